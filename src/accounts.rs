@@ -0,0 +1,190 @@
+use std::error::Error;
+
+use crate::sync::sha256_hex;
+use crate::{Pop3Connection, Pop3ConnectionBuilder, Pop3MessageMeta, SyncOptions};
+
+/// Result of an operation on a single account of an [`AccountSet`].
+pub struct Pop3AccountResult<T> {
+    /// name of the account
+    pub account: String,
+
+    /// result of the operation
+    pub result: Result<T, Box<dyn Error>>,
+}
+
+/// Set of POP3 accounts with combined operations.
+///
+/// Operations are performed on each account in turn using a new connection.
+/// Errors of one account do not affect the others; they are reported in the
+/// per-account results.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{AccountSet, Pop3ConnectionBuilder};
+///
+/// let mut accounts = AccountSet::new();
+/// accounts.add("work", Pop3ConnectionBuilder::new("pop.example.com").login("me@example.com", "secret"));
+/// accounts.add("home", Pop3ConnectionBuilder::new("pop.example.org").login("me@example.org", "secret"));
+///
+/// for account in accounts.list_all() {
+///     match account.result {
+///         Ok(messages) => println!("{}: {} messages", account.account, messages.len()),
+///         Err(err) => println!("{}: {}", account.account, err),
+///     }
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct AccountSet {
+    accounts: Vec<(String, Pop3ConnectionBuilder)>,
+    options: SyncOptions,
+}
+
+impl AccountSet {
+
+    /// Returns a new, empty set of accounts.
+    pub fn new() -> Self {
+        AccountSet { accounts: vec!(), options: SyncOptions::default() }
+    }
+
+    /// Sets the options used to fetch messages.
+    pub fn set_sync_options(&mut self, options: SyncOptions) {
+        self.options = options;
+    }
+
+    /// Adds an account. An account with the same name is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`    - name of the account
+    /// * `builder` - builder used to connect and authenticate the account
+    pub fn add(&mut self, name: &str, builder: Pop3ConnectionBuilder) {
+        self.accounts.retain(|(account, _)| account != name);
+        self.accounts.push((name.to_string(), builder));
+    }
+
+    /// Returns the names of all accounts.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.accounts.iter().map(|(name, _)| name.as_str())
+    }
+
+    fn for_each_account<T>(&self, mut operation: impl FnMut(&str, &mut Pop3Connection) -> Result<T, Box<dyn Error>>) -> Vec<Pop3AccountResult<T>> {
+        self.accounts.iter().map(|(name, builder)| {
+            let result = builder.clone().connect()
+                .and_then(|mut connection| {
+                    let value = operation(name, &mut connection)?;
+                    connection.quit()?;
+                    Ok(value)
+                });
+
+            Pop3AccountResult { account: name.clone(), result }
+        }).collect()
+    }
+
+    /// Returns id, size and unique id of the messages of all accounts.
+    pub fn list_all(&self) -> Vec<Pop3AccountResult<Vec<Pop3MessageMeta>>> {
+        self.for_each_account(|_, connection| connection.list_meta())
+    }
+
+    /// Returns the messages of all accounts, which are not known yet.
+    ///
+    /// Messages without unique id are always considered new.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_known` - returns true, if the message with the given account and unique id is already known
+    pub fn list_new(&self, mut is_known: impl FnMut(&str, &str) -> bool) -> Vec<Pop3AccountResult<Vec<Pop3MessageMeta>>> {
+        self.for_each_account(|name, connection| {
+            let messages = connection.list_meta()?;
+            Ok(messages.into_iter()
+                .filter(|message| !matches!(&message.unique_id, Some(unique_id) if is_known(name, unique_id)))
+                .collect())
+        })
+    }
+
+    /// Retrieves the messages of all accounts and returns the count of fetched messages per account.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - invoked with account name, message metadata and exact message octets
+    pub fn fetch_all(&self, handler: impl FnMut(&str, &Pop3MessageMeta, &[u8]) -> Result<(), Box<dyn Error>>) -> Vec<Pop3AccountResult<usize>> {
+        self.fetch_new(|_, _| false, handler)
+    }
+
+    /// Retrieves the messages of all accounts, which are not known yet,
+    /// and returns the count of fetched messages per account.
+    ///
+    /// If the handler fails, fetching of the affected account stops.
+    /// Messages are deleted after the handler succeeded, if enabled by the
+    /// sync options. If the sync options contain a shared hash store,
+    /// messages already delivered from any account are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `is_known` - returns true, if the message with the given account and unique id is already known
+    /// * `handler`  - invoked with account name, message metadata and exact message octets
+    pub fn fetch_new(&self, mut is_known: impl FnMut(&str, &str) -> bool, mut handler: impl FnMut(&str, &Pop3MessageMeta, &[u8]) -> Result<(), Box<dyn Error>>) -> Vec<Pop3AccountResult<usize>> {
+        self.for_each_account(|name, connection| {
+            let mut count = 0;
+            for message in connection.list_meta()? {
+                if matches!(&message.unique_id, Some(unique_id) if is_known(name, unique_id)) {
+                    continue;
+                }
+
+                let mut content = vec!();
+                connection.retrieve_raw(message.message_id, &mut content)?;
+                match self.options.shared_hash_store() {
+                    Some(store) => {
+                        let hash = sha256_hex(&content);
+                        if !store.contains(&hash) {
+                            handler(name, &message, &content)?;
+                            count += 1;
+                        }
+                        store.insert(&hash)?;
+                    },
+                    None => {
+                        handler(name, &message, &content)?;
+                        count += 1;
+                    }
+                }
+
+                if self.options.is_delete_after_fetch() {
+                    connection.delete(message.message_id)?;
+                }
+            }
+
+            Ok(count)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::unreachable_account;
+
+    #[test]
+    fn test_add_replaces_account() {
+        let mut accounts = AccountSet::new();
+        accounts.add("a", unreachable_account());
+        accounts.add("b", unreachable_account());
+        accounts.add("a", unreachable_account());
+
+        let names: Vec<&str> = accounts.names().collect();
+        assert_eq!(vec!("b", "a"), names);
+    }
+
+    #[test]
+    fn test_errors_are_isolated_per_account() {
+        let mut accounts = AccountSet::new();
+        accounts.add("a", unreachable_account());
+        accounts.add("b", unreachable_account());
+
+        let results = accounts.list_all();
+        assert_eq!(2, results.len());
+        assert_eq!("a", results[0].account);
+        assert!(results[0].result.is_err());
+        assert_eq!("b", results[1].account);
+        assert!(results[1].result.is_err());
+    }
+}
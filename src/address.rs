@@ -0,0 +1,158 @@
+use std::fmt;
+
+use crate::encoding;
+
+/// Email address with optional display name, e.g. `Alice <alice@example.com>`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Mailbox {
+    /// display name, if specified; encoded words are decoded
+    pub display_name: Option<String>,
+
+    /// email address, e.g. `alice@example.com`
+    pub address: String,
+}
+
+impl Mailbox {
+
+    /// Parses an address header value, e.g. of From, To or Cc, into its mailboxes.
+    ///
+    /// Groups are flattened into their members and comments are removed;
+    /// a comment is used as display name, if there is no other one, e.g.
+    /// `alice@example.com (Alice)`. Invalid entries are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - value of the header
+    pub fn parse_list(value: &str) -> Vec<Mailbox> {
+        let mut result = vec!();
+        let mut entry = Entry::default();
+        let mut chars = value.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '"' => break,
+                            '\\' => entry.phrase.extend(chars.next()),
+                            c => entry.phrase.push(c)
+                        }
+                    }
+                },
+                '(' => {
+                    let mut comment = String::new();
+                    let mut depth = 1;
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '(' => depth += 1,
+                            ')' => { depth -= 1; if depth == 0 { break; } },
+                            '\\' => { comment.extend(chars.next()); continue; },
+                            _ => {}
+                        }
+                        comment.push(c);
+                    }
+                    entry.comment.get_or_insert(comment);
+                    entry.phrase.push(' ');
+                },
+                '<' => {
+                    let mut address = String::new();
+                    for c in chars.by_ref() {
+                        if c == '>' {
+                            break;
+                        }
+                        address.push(c);
+                    }
+                    entry.address = Some(address);
+                },
+                ':' if entry.address.is_none() => {
+                    entry = Entry::default();
+                },
+                ',' | ';' => {
+                    result.extend(entry.into_mailbox());
+                    entry = Entry::default();
+                },
+                c => entry.phrase.push(c)
+            }
+        }
+        result.extend(entry.into_mailbox());
+
+        result
+    }
+
+    /// Returns the domain of the address in lower case, e.g. `example.com`.
+    pub fn domain(&self) -> Option<String> {
+        self.address.rsplit_once('@').map(|(_, domain)| domain.to_lowercase())
+    }
+}
+
+impl fmt::Display for Mailbox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.display_name {
+            Some(name) => write!(f, "\"{}\" <{}>", name.replace('\\', "\\\\").replace('"', "\\\""), self.address),
+            None => write!(f, "{}", self.address)
+        }
+    }
+}
+
+#[derive(Default)]
+struct Entry {
+    phrase: String,
+    address: Option<String>,
+    comment: Option<String>,
+}
+
+impl Entry {
+    fn into_mailbox(self) -> Option<Mailbox> {
+        let (address, display_name) = match self.address {
+            Some(address) => (address, Some(self.phrase).filter(|phrase| !phrase.trim().is_empty()).or(self.comment)),
+            None => (self.phrase, self.comment)
+        };
+
+        let address: String = address.chars().filter(|c| !c.is_whitespace()).collect();
+        if address.is_empty() {
+            return None;
+        }
+
+        let display_name = display_name
+            .map(|name| encoding::decode_encoded_words(&name))
+            .map(|name| name.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|name| !name.is_empty());
+
+        Some(Mailbox { display_name, address })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mailbox(display_name: Option<&str>, address: &str) -> Mailbox {
+        Mailbox { display_name: display_name.map(String::from), address: address.into() }
+    }
+
+    #[test]
+    fn test_parse_list() {
+        assert_eq!(vec!(
+            mailbox(Some("Alice Liddell"), "alice@example.com"),
+            mailbox(None, "bob@example.com"),
+            mailbox(Some("Carol"), "carol@example.com"),
+            mailbox(Some("Doe, John"), "john@example.com"),
+        ), Mailbox::parse_list("Alice  Liddell <alice@example.com>, bob@example.com, carol@example.com (Carol), \"Doe, John\" <john@example.com>"));
+    }
+
+    #[test]
+    fn test_parse_list_groups_and_encoded_words() {
+        assert_eq!(vec!(
+            mailbox(Some("Jürgen"), "juergen@example.com"),
+            mailbox(None, "eve@example.com"),
+            mailbox(None, "mallory@example.com"),
+        ), Mailbox::parse_list("=?UTF-8?Q?J=C3=BCrgen?= <juergen@example.com>, Team: eve@example.com, <mallory@example.com>;, undisclosed-recipients:;"));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("\"Doe, \\\"J\\\"\" <john@example.com>", mailbox(Some("Doe, \"J\""), "john@example.com").to_string());
+        assert_eq!("bob@example.com", mailbox(None, "bob@example.com").to_string());
+        assert_eq!(Some("example.com".into()), mailbox(None, "bob@EXAMPLE.com").domain());
+    }
+}
@@ -0,0 +1,1852 @@
+use std::error::Error;
+use std::future::Future;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::pin::{pin, Pin};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+use futures_util::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use futures_util::future::{self, Either};
+use futures_util::stream::{self, Stream};
+
+use crate::clock::{Clock, SystemClock};
+use crate::error::{ConnectionLost, PolicyViolation};
+use crate::health::{Pop3HealthReport, Pop3HealthState};
+use crate::interceptor::Pop3Interceptor;
+use crate::logging::{self, WireLog};
+use crate::metrics::{CommandMetrics, MetricsSink};
+use crate::response::ParseError;
+use crate::session::{self, Pop3SessionEvent, Pop3SessionObserver, TlsInfo};
+use crate::stats::Pop3SessionStats;
+use crate::trace::OperationSpan;
+use crate::throttle::Throttle;
+use crate::transcript::Transcript;
+use crate::{oauth, response, sink};
+use crate::{MessageDigest, Pop3Headers, Pop3MessageInfo, Pop3MessageMeta, Pop3MessageSummary, Pop3NegativeResponse, Pop3SizeCheck};
+use crate::{Pop3MessageUidInfo, Pop3SplitMessage, Pop3Stat, Pop3TransferSize, Pop3UsageReport, TokenProvider};
+
+/// Error reported by an [`AsyncPop3Connection`]; can be sent between tasks.
+pub type Pop3AsyncError = Box<dyn Error + Send + Sync>;
+
+/// Maximum length of a status line including CRLF (RFC 1939).
+const MAX_STATUS_LINE_LENGTH: usize = 512;
+
+/// Maximum length of other lines; lines of messages often exceed the 1000 octets of RFC 5322.
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+pub(crate) const POISONED_MESSAGE: &str = "connection poisoned: a previous operation was cancelled or failed while receiving a response";
+
+/// Timer of an async runtime used to enforce timeouts.
+///
+/// See [`AsyncPop3Connection::set_timeout`].
+pub trait AsyncTimer: Send + Sync {
+    /// Returns a future, which completes after the given duration.
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+/// Resolver of host names used by async connections instead of the resolver of the runtime.
+///
+/// Closures taking host name and port and returning a future of the
+/// socket addresses implement this trait, e.g. to use a caching resolver.
+///
+/// See [`crate::Pop3ConnectionBuilder::resolver`].
+pub trait AsyncResolver: Send + Sync {
+    /// Returns the socket addresses of a host; each address is tried in turn.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - host name to resolve
+    /// * `port` - port of the POP3 server
+    fn resolve(&self, host: &str, port: u16) -> Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send>>;
+}
+
+impl<F, U> AsyncResolver for F
+where
+    F: Fn(String, u16) -> U + Send + Sync,
+    U: Future<Output = io::Result<Vec<SocketAddr>>> + Send + 'static
+{
+    fn resolve(&self, host: &str, port: u16) -> Pin<Box<dyn Future<Output = io::Result<Vec<SocketAddr>>> + Send>> {
+        Box::pin(self(host.to_string(), port))
+    }
+}
+
+/// Runs an I/O operation, which fails when the timeout elapses first.
+async fn with_timeout<T>(timeout: Option<(Duration, Arc<dyn AsyncTimer>)>, operation: impl Future<Output = io::Result<T>>) -> io::Result<T> {
+    match timeout {
+        Some((duration, timer)) => {
+            match future::select(pin!(operation), timer.sleep(duration)).await {
+                Either::Left((result, _)) => result,
+                Either::Right(_) => {
+                    logging::anomaly("operation timed out");
+                    Err(io::Error::new(io::ErrorKind::TimedOut, "timeout"))
+                }
+            }
+        },
+        None => operation.await
+    }
+}
+
+/// Async POP3 connection over any stream implementing the `futures` I/O traits.
+///
+/// Provides the same commands as [`crate::Pop3Connection`] as async
+/// functions. The connection does not depend on a specific runtime: any
+/// stream, e.g. a TLS stream of async-std or smol, can be used by
+/// [`AsyncPop3Connection::from_stream`]. Using the `tokio` feature,
+/// [`crate::Pop3ConnectionBuilder::connect_async`] connects using tokio.
+///
+/// In contrast to the blocking connection, the session is not ended on
+/// drop, since this would require to await the response; use
+/// [`AsyncPop3Connection::quit`] to commit deletions.
+///
+/// # Cancellation
+///
+/// All operations are cancel-safe in the sense that dropping a future,
+/// e.g. by `select!` or a timeout of the runtime, never leads to a
+/// misinterpreted response. If a future is dropped while a response is
+/// received, the connection is poisoned: since the state of the session is
+/// unknown, all further commands fail and the connection should be replaced.
+/// The same applies to I/O errors and timeouts set by
+/// [`AsyncPop3Connection::set_timeout`]. Negative responses of the server
+/// do not poison the connection.
+///
+/// # Examples
+///
+/// ```no_run
+/// use futures_util::io::{AsyncRead, AsyncWrite};
+/// use rust_pop3_client::{AsyncPop3Connection, Pop3AsyncError};
+///
+/// async fn fetch(stream: impl AsyncRead + AsyncWrite + Unpin) -> Result<(), Pop3AsyncError> {
+///     let mut connection = AsyncPop3Connection::from_stream(stream).await?;
+///     connection.login("user@example.com", "secret").await?;
+///
+///     for message in connection.list_meta().await? {
+///         let mut content = vec!();
+///         connection.retrieve_raw(message.message_id, &mut content).await?;
+///     }
+///     connection.quit().await?;
+///     Ok(())
+/// }
+/// ```
+pub struct AsyncPop3Connection<S> {
+    stream: Option<BufReader<S>>,
+    keep_alive: Option<Duration>,
+    last_command: Instant,
+    dry_run: bool,
+    pipelining: bool,
+    deleted: Vec<u32>,
+    pending_response: bool,
+    timeout: Option<Duration>,
+    timer: Option<Arc<dyn AsyncTimer>>,
+    clock: Arc<dyn Clock>,
+    command_span: Option<OperationSpan>,
+    greeting: String,
+    transcript: Option<Transcript>,
+    wire_dump: Option<Transcript>,
+    wire_log: WireLog,
+    metrics: Option<CommandMetrics>,
+    interceptors: Vec<Arc<dyn Pop3Interceptor>>,
+    command: String,
+    observer: Option<Arc<dyn Pop3SessionObserver>>,
+    command_started: Instant,
+    stats: Pop3SessionStats,
+    download_rate: Option<u64>,
+    strict_size_check: bool,
+}
+
+impl<S> AsyncPop3Connection<S> {
+
+    /// Completes the response of the current command.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - error message of a negative response
+    fn complete_response(&mut self, error: Option<String>) {
+        self.pending_response = false;
+        if let Some(span) = self.command_span.take() {
+            span.finish(&error.as_ref().map_or(Ok(()), Err));
+        }
+        if let Some(metrics) = self.metrics.as_mut() {
+            metrics.finish(error.is_none());
+        }
+        if self.command.is_empty() {
+            return;     // greeting
+        }
+
+        let command = session::command_name(&self.command);
+        let latency = self.clock.now().saturating_duration_since(self.command_started);
+        self.stats.record(command.clone(), latency, error.is_none());
+        if self.observer.is_some() {
+            self.notify(Pop3SessionEvent::ResponseReceived { command, success: error.is_none() });
+        }
+    }
+
+    /// Passes an event to the observer of the session.
+    pub(crate) fn notify(&self, event: Pop3SessionEvent) {
+        if let Some(observer) = &self.observer {
+            observer.on_event(&event);
+        }
+    }
+
+    /// Records a line sent to the transcript, the wire dump and the log.
+    fn record_client(&mut self, line: &[u8]) {
+        for transcript in [&mut self.transcript, &mut self.wire_dump].into_iter().flatten() {
+            transcript.client(line);
+        }
+        self.wire_log.client(line);
+        if let Some(metrics) = &self.metrics {
+            metrics.sent(line.len());
+        }
+    }
+
+    /// Passes a line received to the interceptors.
+    fn intercept_response_line(&self, line: &mut Vec<u8>) {
+        for interceptor in &self.interceptors {
+            interceptor.after_response_line(&self.command, line);
+        }
+    }
+
+    /// Records a line received to the transcript, the wire dump and the log.
+    fn record_server(&mut self, line: &[u8]) {
+        for transcript in [&mut self.transcript, &mut self.wire_dump].into_iter().flatten() {
+            transcript.server(line);
+        }
+        self.wire_log.server(line);
+        if let Some(metrics) = &self.metrics {
+            metrics.received(line.len());
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncPop3Connection<S> {
+
+    /// Returns a connection using an established stream and reads the greeting of the server.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - connected stream, e.g. a TLS stream
+    pub async fn from_stream(stream: S) -> Result<AsyncPop3Connection<S>, Pop3AsyncError> {
+        let mut connection = AsyncPop3Connection {
+            stream: Some(BufReader::new(stream)),
+            keep_alive: None,
+            last_command: Instant::now(),
+            dry_run: false,
+            pipelining: false,
+            deleted: vec!(),
+            pending_response: false,
+            timeout: None,
+            timer: None,
+            clock: Arc::new(SystemClock),
+            command_span: None,
+            greeting: String::new(),
+            transcript: None,
+            wire_dump: None,
+            wire_log: WireLog::default(),
+            metrics: None,
+            interceptors: vec!(),
+            command: String::new(),
+            observer: None,
+            command_started: Instant::now(),
+            stats: Pop3SessionStats::default(),
+            download_rate: None,
+            strict_size_check: false,
+        };
+        connection.greeting = connection.read_status_line().await?;
+
+        Ok(connection)
+    }
+
+    /// Upgrades the connection to TLS using STLS (RFC 2595).
+    ///
+    /// After the server accepted STLS, the stream is passed to the given
+    /// function, which performs the TLS handshake and returns the TLS stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `upgrade` - performs the TLS handshake on the plain stream
+    pub async fn start_tls_with<T, F, U>(mut self, upgrade: F) -> Result<AsyncPop3Connection<T>, Pop3AsyncError>
+    where
+        T: AsyncRead + AsyncWrite + Unpin,
+        F: FnOnce(S) -> U,
+        U: Future<Output = Result<T, Pop3AsyncError>>
+    {
+        self.invoke_single_line("STLS\r\n").await?;
+        let stream = self.stream.take().ok_or(ConnectionLost("stream closed"))?.into_inner();
+
+        Ok(AsyncPop3Connection {
+            stream: Some(BufReader::new(upgrade(stream).await?)),
+            keep_alive: self.keep_alive,
+            last_command: self.last_command,
+            dry_run: self.dry_run,
+            pipelining: self.pipelining,
+            deleted: self.deleted,
+            pending_response: false,
+            timeout: self.timeout,
+            timer: self.timer,
+            clock: self.clock,
+            command_span: None,
+            greeting: self.greeting,
+            transcript: self.transcript,
+            wire_dump: self.wire_dump,
+            wire_log: self.wire_log,
+            metrics: self.metrics,
+            interceptors: self.interceptors,
+            command: String::new(),
+            observer: self.observer,
+            command_started: self.command_started,
+            stats: self.stats,
+            download_rate: self.download_rate,
+            strict_size_check: self.strict_size_check,
+        })
+    }
+
+    /// Records the session to a transcript; see [`crate::Pop3Connection::set_transcript`].
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - receives the transcript; `None` stops recording
+    pub fn set_transcript(&mut self, writer: Option<Box<dyn Write + Send>>) {
+        self.transcript = writer.map(Transcript::new);
+        if let Some(transcript) = self.transcript.as_mut() {
+            transcript.server(self.greeting.as_bytes());
+        }
+    }
+
+    /// Writes all traffic to a wire dump; see [`crate::Pop3Connection::set_wire_dump`].
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - receives the wire dump; `None` stops dumping
+    pub fn set_wire_dump(&mut self, writer: Option<Box<dyn Write + Send>>) {
+        self.wire_dump = writer.map(Transcript::wire_dump);
+    }
+
+    /// Reports metrics of all further commands; see [`crate::Pop3Connection::set_metrics`].
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - receives the metrics; `None` stops reporting
+    pub fn set_metrics(&mut self, sink: Option<Arc<dyn MetricsSink>>) {
+        self.metrics = sink.map(CommandMetrics::new);
+    }
+
+    /// Sets the observer of the events of the session; see [`crate::Pop3Connection::set_observer`].
+    ///
+    /// # Arguments
+    ///
+    /// * `observer` - receives the events; `None` stops observing
+    pub fn set_observer(&mut self, observer: Option<Arc<dyn Pop3SessionObserver>>) {
+        self.observer = observer;
+    }
+
+    /// Sets the observer of a new connection and reports its connection and TLS establishment.
+    pub(crate) fn start_observing(&mut self, observer: Arc<dyn Pop3SessionObserver>, host: &str, port: u16)
+    where
+        S: TlsInfo
+    {
+        self.observer = Some(observer);
+        self.notify(Pop3SessionEvent::Connected { host: host.to_string(), port });
+        if let Some(cipher) = self.stream.as_ref().and_then(|stream| stream.get_ref().cipher_suite()) {
+            self.notify(Pop3SessionEvent::TlsEstablished { cipher });
+        }
+    }
+
+    /// Adds an interceptor of commands and responses; see [`crate::Pop3Connection::add_interceptor`].
+    ///
+    /// # Arguments
+    ///
+    /// * `interceptor` - interceptor, which is called after the interceptors added before
+    pub fn add_interceptor(&mut self, interceptor: Arc<dyn Pop3Interceptor>) {
+        self.interceptors.push(interceptor);
+    }
+
+    /// Sets the timeout of each read and write operation.
+    ///
+    /// When the timeout elapses, the operation fails and the connection is
+    /// poisoned. Timeouts require a timer, which is set by the constructors
+    /// of the runtime specific connections; see [`AsyncPop3Connection::set_timer`].
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - timeout of each operation; `None` disables timeouts
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    /// Sets the timer used to enforce timeouts.
+    ///
+    /// # Arguments
+    ///
+    /// * `timer` - timer of the async runtime
+    pub fn set_timer(&mut self, timer: impl AsyncTimer + 'static) {
+        self.timer = Some(Arc::new(timer));
+    }
+
+    /// Sets the clock used by keep-alive.
+    #[cfg(test)]
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.last_command = clock.now();
+        self.clock = clock;
+    }
+
+    /// Returns the latency statistics of the commands of this session.
+    pub fn stats(&self) -> &Pop3SessionStats {
+        &self.stats
+    }
+
+    /// Returns true, if the connection is poisoned and can not be used anymore.
+    ///
+    /// See [Cancellation](AsyncPop3Connection#cancellation).
+    pub fn is_poisoned(&self) -> bool {
+        self.pending_response
+    }
+
+    /// Returns the underlying stream, e.g. to upgrade it to TLS in place.
+    pub(crate) fn stream_mut(&mut self) -> Option<&mut S> {
+        self.stream.as_mut().map(BufReader::get_mut)
+    }
+
+    fn stream(&mut self) -> Result<&mut BufReader<S>, Pop3AsyncError> {
+        self.stream.as_mut().ok_or_else(|| ConnectionLost("stream closed").into())
+    }
+
+    fn timeout(&self) -> Option<(Duration, Arc<dyn AsyncTimer>)> {
+        self.timeout.zip(self.timer.clone())
+    }
+
+    /// Reads a line including its line terminator.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_length` - maximum length of the line including its line terminator
+    async fn read_raw_line(&mut self, max_length: usize) -> Result<Vec<u8>, Pop3AsyncError> {
+        let timeout = self.timeout();
+        let stream = self.stream()?;
+        let mut line = vec!();
+        with_timeout(timeout, stream.take(max_length as u64).read_until(b'\n', &mut line)).await?;
+        if !line.ends_with(b"\n") {
+            if line.len() >= max_length {
+                logging::anomaly(format!("line exceeds {} octets", max_length));
+                return Err(format!("line exceeds {} octets", max_length).into());
+            }
+            logging::anomaly("connection closed by server");
+            self.notify(Pop3SessionEvent::Disconnected { reason: "connection closed by server".into() });
+            return Err(ConnectionLost("connection closed").into());
+        }
+
+        if let Some(span) = self.command_span.as_mut() {
+            span.add_bytes(line.len());
+        }
+        self.record_server(&line);
+        self.intercept_response_line(&mut line);
+        Ok(line)
+    }
+
+    async fn read_line(&mut self, max_length: usize) -> Result<String, Pop3AsyncError> {
+        let line = self.read_raw_line(max_length).await?;
+        Ok(String::from_utf8_lossy(&line).trim().to_string())
+    }
+
+    /// Reads the status line of a response; a negative response completes the response.
+    async fn read_status_line(&mut self) -> Result<String, Pop3AsyncError> {
+        let line = self.read_line(MAX_STATUS_LINE_LENGTH).await?;
+        if !line.starts_with("+OK") && !line.starts_with("-ERR") {
+            logging::anomaly(format!("invalid status line: {}", line));
+        }
+        let status = response::check_status(line);
+        if let Err(err) = &status {
+            self.complete_response(Some(err.to_string()));
+        }
+
+        status
+    }
+
+    async fn write_line(&mut self, line: &str) -> Result<(), Pop3AsyncError> {
+        let timeout = self.timeout();
+        let stream = self.stream()?;
+        with_timeout(timeout, async {
+            stream.write_all(line.as_bytes()).await?;
+            stream.flush().await
+        }).await?;
+        self.record_client(line.as_bytes());
+        self.last_command = self.clock.now();
+        Ok(())
+    }
+
+    /// Writes a command; the connection stays poisoned until the response is received completely.
+    async fn write_command(&mut self, command: &str) -> Result<(), Pop3AsyncError> {
+        self.check_command(command)?;
+        self.start_command(command);
+        self.write_line(command).await
+    }
+
+    /// Fails, if a response is pending or an interceptor rejects the command.
+    fn check_command(&self, command: &str) -> Result<(), Pop3AsyncError> {
+        if self.pending_response {
+            logging::anomaly(POISONED_MESSAGE);
+            return Err(ConnectionLost(POISONED_MESSAGE).into());
+        }
+
+        let name = command.trim_end_matches(['\r', '\n']);
+        for interceptor in &self.interceptors {
+            interceptor.before_command(name).map_err(|err| PolicyViolation(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Makes a command the current one, whose response is read next.
+    fn start_command(&mut self, command: &str) {
+        let name = command.trim_end_matches(['\r', '\n']);
+        self.command = name.to_string();
+        self.command_started = self.clock.now();
+        if self.observer.is_some() {
+            self.notify(Pop3SessionEvent::CommandSent { command: session::command_name(name) });
+        }
+
+        self.pending_response = true;
+        self.command_span = Some(OperationSpan::command(command));
+        if let Some(metrics) = self.metrics.as_mut() {
+            metrics.start(command);
+        }
+    }
+
+    async fn send_command(&mut self, command: &str) -> Result<(), Pop3AsyncError> {
+        self.keep_alive().await?;
+        self.write_command(command).await
+    }
+
+    /// Sends a command and reads the status line of its response.
+    async fn begin_response(&mut self, command: &str) -> Result<String, Pop3AsyncError> {
+        self.send_command(command).await?;
+        self.read_status_line().await
+    }
+
+    pub(crate) async fn invoke_single_line(&mut self, command: &str) -> Result<String, Pop3AsyncError> {
+        let status = self.begin_response(command).await?;
+        self.complete_response(None);
+        Ok(status)
+    }
+
+    async fn invoke_multi_line(&mut self, command: &str) -> Result<Vec<String>, Pop3AsyncError> {
+        self.send_command(command).await?;
+        self.read_multi_line().await
+    }
+
+    /// Reads status line and lines of a multi-line response.
+    async fn read_multi_line(&mut self) -> Result<Vec<String>, Pop3AsyncError> {
+        self.read_status_line().await?;
+
+        let mut response = vec!();
+        while let Some(line) = self.read_multi_line_entry().await? {
+            response.push(line);
+        }
+
+        Ok(response)
+    }
+
+    /// Reads a single line of a multi-line response; returns `None` at the terminating line.
+    async fn read_multi_line_entry(&mut self) -> Result<Option<String>, Pop3AsyncError> {
+        let line = self.read_raw_line(MAX_LINE_LENGTH).await?;
+        match response::unstuff_line(&line) {
+            Some(content) => Ok(Some(String::from_utf8_lossy(content).trim().to_string())),
+            None => { self.complete_response(None); Ok(None) }
+        }
+    }
+
+    /// Returns a stream of the parsed lines of a multi-line response.
+    ///
+    /// The command is sent when the stream is polled the first time.
+    fn multi_line_stream<T: 'static>(&mut self, command: &'static str, parse: fn(&str) -> Result<T, ParseError>) -> impl Stream<Item = Result<T, Pop3AsyncError>> + '_ {
+        stream::unfold(Some((self, false)), move |state| async move {
+            let (connection, started) = state?;
+            if !started {
+                if let Err(err) = connection.begin_response(command).await {
+                    return Some((Err(err), None));
+                }
+            }
+
+            match connection.read_multi_line_entry().await {
+                Ok(Some(line)) => Some((parse(&line).map_err(Into::into), Some((connection, true)))),
+                Ok(None) => None,
+                Err(err) => Some((Err(err), None))
+            }
+        })
+    }
+
+    /// Reads the lines of a multi-line response, writes them without byte-stuffing and returns their sizes.
+    async fn read_raw_multi_line(&mut self, writer: &mut (impl AsyncWrite + Unpin)) -> Result<Pop3TransferSize, Pop3AsyncError> {
+        let mut size = Pop3TransferSize::default();
+        let mut throttle = self.download_rate.map(Throttle::new);
+        loop {
+            let line = self.read_raw_line(MAX_LINE_LENGTH).await?;
+            let content = match response::unstuff_line(&line) {
+                Some(content) => content,
+                None => { self.complete_response(None); break }
+            };
+
+            writer.write_all(content).await?;
+            if let Some(sleep) = self.throttle(&mut throttle, content.len()) {
+                sleep.await;
+            }
+            size.wire_size += line.len() as u64;
+            size.decoded_size += content.len() as u64;
+            size.normalized_size += match content.ends_with(b"\r\n") {
+                true => content.len() as u64 - 1,
+                false => content.len() as u64
+            };
+        }
+
+        writer.flush().await?;
+        Ok(size)
+    }
+
+    /// Returns a future, which waits until the download rate is met, if needed.
+    fn throttle(&self, throttle: &mut Option<Throttle>, bytes: usize) -> Option<Pin<Box<dyn Future<Output = ()> + Send>>> {
+        let delay = throttle.as_mut()?.consume(bytes);
+        let timer = self.timer.as_ref().filter(|_| !delay.is_zero())?;
+        Some(timer.sleep(delay))
+    }
+
+    /// Enables or disables keep-alive. See [`crate::Pop3Connection::set_keep_alive`].
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - idle interval after which a NOOP is sent; `None` disables keep-alive
+    pub fn set_keep_alive(&mut self, interval: Option<Duration>) {
+        self.keep_alive = interval;
+    }
+
+    /// Sends a NOOP, if keep-alive is enabled and the session was idle for the keep-alive interval.
+    pub async fn keep_alive(&mut self) -> Result<(), Pop3AsyncError> {
+        if let Some(interval) = self.keep_alive {
+            if self.clock.now().saturating_duration_since(self.last_command) >= interval {
+                self.write_command("NOOP\r\n").await?;
+                self.read_status_line().await?;
+                self.complete_response(None);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Limits the download rate of retrieved messages. See [`crate::Pop3Connection::set_download_rate`].
+    ///
+    /// The rate is limited by waiting using the timer of the connection; see
+    /// [`AsyncPop3Connection::set_timer`]. Without timer, the rate is not limited.
+    pub fn set_download_rate(&mut self, bytes_per_second: Option<u64>) {
+        self.download_rate = bytes_per_second;
+    }
+
+    /// Enables or disables strict size checks. See [`crate::Pop3Connection::set_strict_size_check`].
+    pub fn set_strict_size_check(&mut self, strict: bool) {
+        self.strict_size_check = strict;
+    }
+
+    /// Enables or disables dry-run mode. See [`crate::Pop3Connection::set_dry_run`].
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    /// Returns true, if dry-run mode is enabled.
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Enables or disables pipelining. See [`crate::Pop3Connection::set_pipelining`].
+    pub fn set_pipelining(&mut self, pipelining: bool) {
+        self.pipelining = pipelining;
+    }
+
+    /// Returns true, if commands are pipelined.
+    pub fn is_pipelining(&self) -> bool {
+        self.pipelining
+    }
+
+    /// Returns the ids of messages marked as deleted in this session.
+    pub fn marked_for_deletion(&self) -> &[u32] {
+        &self.deleted
+    }
+
+    /// Authenticate a POP3 session using username and password.
+    ///
+    /// # Arguments
+    ///
+    /// * `user`     - Name of the user, typically it's e-mail address.
+    /// * `password` - Password of the user.
+    pub async fn login(&mut self, user: &str, password: &str) -> Result<(), Pop3AsyncError> {
+        OperationSpan::auth("USER").run(async {
+            self.invoke_single_line(&format!("USER {}\r\n", user)).await?;
+            self.invoke_single_line(&format!("PASS {}\r\n", password)).await?;
+            self.notify(Pop3SessionEvent::Authenticated { mechanism: "USER".into() });
+            Ok(())
+        }).await
+    }
+
+    /// Authenticate a POP3 session using an OAuth 2.0 access token (XOAUTH2).
+    ///
+    /// See [`crate::Pop3Connection::login_oauth2`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `user`     - Name of the user, typically it's e-mail address.
+    /// * `provider` - Provider of the access token.
+    pub async fn login_oauth2(&mut self, user: &str, provider: &(dyn TokenProvider + Sync)) -> Result<(), Pop3AsyncError> {
+        self.login_oauth2_with(user, provider).await
+    }
+
+    /// Authenticates using XOAUTH2; the provider is generic, so that blocking callers need no `Sync` provider.
+    pub(crate) async fn login_oauth2_with<P: TokenProvider + ?Sized>(&mut self, user: &str, provider: &P) -> Result<(), Pop3AsyncError> {
+        OperationSpan::auth("XOAUTH2").run(async {
+            let access_token = provider.access_token().map_err(|err| err.to_string())?;
+            match self.authenticate_xoauth2(user, &access_token).await {
+                Err(err) if oauth::is_auth_failure(err.as_ref()) => {
+                    let access_token = provider.refresh_access_token().map_err(|err| err.to_string())?;
+                    self.authenticate_xoauth2(user, &access_token).await
+                },
+                result => result
+            }?;
+            self.notify(Pop3SessionEvent::Authenticated { mechanism: "XOAUTH2".into() });
+            Ok(())
+        }).await
+    }
+
+    async fn authenticate_xoauth2(&mut self, user: &str, access_token: &str) -> Result<(), Pop3AsyncError> {
+        let response = oauth::xoauth2_initial_response(user, access_token);
+        self.send_command(&format!("AUTH XOAUTH2 {}\r\n", response)).await?;
+
+        let mut line = self.read_line(MAX_LINE_LENGTH).await?;
+        if line.starts_with('+') && !line.starts_with("+OK") {
+            // server sent error details as challenge; answer with an empty response
+            self.write_line("\r\n").await?;
+            line = self.read_line(MAX_LINE_LENGTH).await?;
+        }
+
+        let status = response::check_status(line);
+        self.complete_response(status.as_ref().err().map(ToString::to_string));
+        status?;
+        Ok(())
+    }
+
+    /// Returns maildrop statistics.
+    pub async fn stat(&mut self) -> Result<Pop3Stat, Pop3AsyncError> {
+        let stat = self.invoke_single_line("STAT\r\n").await?;
+        Ok(response::parse_stat(&stat)?)
+    }
+
+    /// Returns id and size of each message.
+    pub async fn list(&mut self) -> Result<Vec<Pop3MessageInfo>, Pop3AsyncError> {
+        let lines = self.invoke_multi_line("LIST\r\n").await?;
+        let mut result = vec!();
+        for line in lines {
+            result.push(response::parse_list_line(&line)?);
+        }
+
+        Ok(result)
+    }
+
+    /// Returns a stream of id and size of each message.
+    ///
+    /// In contrast to [`AsyncPop3Connection::list`], entries are yielded as
+    /// soon as they are received, so huge maildrops can be processed without
+    /// buffering the whole listing. The stream must be consumed completely
+    /// before further commands are issued.
+    pub fn list_stream(&mut self) -> impl Stream<Item = Result<Pop3MessageInfo, Pop3AsyncError>> + '_ {
+        self.multi_line_stream("LIST\r\n", response::parse_list_line)
+    }
+
+    /// Returns the size of a given message.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to query
+    pub async fn get_message_size(&mut self, message_id: u32) -> Result<u32, Pop3AsyncError> {
+        let line = self.invoke_single_line(&format!("LIST {}\r\n", message_id)).await?;
+        Ok(response::parse_message_size(&line)?)
+    }
+
+    /// Downloads a given message. Lines are trimmed and terminated by LF.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to download
+    /// * `writer`     - writer to store message
+    pub async fn retrieve(&mut self, message_id: u32, writer: &mut (impl AsyncWrite + Unpin)) -> Result<(), Pop3AsyncError> {
+        let mut throttle = self.download_rate.map(Throttle::new);
+        for line in self.invoke_multi_line(&format!("RETR {}\r\n", message_id)).await? {
+            writer.write_all(line.as_bytes()).await?;
+            writer.write_all(b"\n").await?;
+            if let Some(sleep) = self.throttle(&mut throttle, line.len() + 1) {
+                sleep.await;
+            }
+        }
+
+        writer.flush().await?;
+        Ok(())
+    }
+
+    /// Downloads the exact octets of a given message.
+    ///
+    /// See [`crate::Pop3Connection::retrieve_raw`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to download
+    /// * `writer`     - writer to store message
+    pub async fn retrieve_raw(&mut self, message_id: u32, writer: &mut (impl AsyncWrite + Unpin)) -> Result<(), Pop3AsyncError> {
+        self.retrieve_sized(message_id, writer).await?;
+        Ok(())
+    }
+
+    /// Downloads the exact octets of a given message and returns its sizes.
+    ///
+    /// See [`crate::Pop3Connection::retrieve_sized`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to download
+    /// * `writer`     - writer to store message
+    pub async fn retrieve_sized(&mut self, message_id: u32, writer: &mut (impl AsyncWrite + Unpin)) -> Result<Pop3TransferSize, Pop3AsyncError> {
+        self.begin_response(&format!("RETR {}\r\n", message_id)).await?;
+        self.read_raw_multi_line(writer).await
+    }
+
+    /// Downloads the exact octets of a given message and compares their count to the size reported by LIST.
+    ///
+    /// See [`crate::Pop3Connection::retrieve_verified`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - metadata of the message to download
+    /// * `writer`  - writer to store message
+    pub async fn retrieve_verified(&mut self, message: &Pop3MessageMeta, writer: &mut (impl AsyncWrite + Unpin)) -> Result<Pop3SizeCheck, Pop3AsyncError> {
+        let received_size = self.retrieve_sized(message.message_id, writer).await?.wire_size;
+        let check = Pop3SizeCheck { expected_size: message.message_size, received_size };
+        if self.strict_size_check && !check.is_match() {
+            return Err(format!("size mismatch of message {}: expected {} octets, received {}",
+                message.message_id, check.expected_size, check.received_size).into());
+        }
+
+        Ok(check)
+    }
+
+    /// Downloads the exact octets of a given message and returns their digest.
+    ///
+    /// See [`crate::Pop3Connection::retrieve_with_digest`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to download
+    /// * `writer`     - writer to store message
+    /// * `digest`     - digest to compute, e.g. [`crate::Sha256Digest`]
+    pub async fn retrieve_with_digest<D>(&mut self, message_id: u32, writer: &mut (impl AsyncWrite + Unpin), digest: &mut D) -> Result<Vec<u8>, Pop3AsyncError>
+    where
+        D: MessageDigest + ?Sized
+    {
+        digest.finalize_reset();
+        let mut writer = DigestWriter { writer, digest };
+        self.retrieve_raw(message_id, &mut writer).await?;
+        Ok(writer.digest.finalize_reset())
+    }
+
+    /// Downloads the exact octets of a given message separated into header section and body.
+    ///
+    /// See [`crate::Pop3Connection::retrieve_split`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to download
+    pub async fn retrieve_split(&mut self, message_id: u32) -> Result<Pop3SplitMessage, Pop3AsyncError> {
+        let mut content = vec!();
+        self.retrieve_raw(message_id, &mut content).await?;
+        let (raw_headers, body) = sink::split_message(&content);
+        let body = body.to_vec();
+        content.truncate(raw_headers.len());
+        Ok(Pop3SplitMessage { raw_headers: content, body })
+    }
+
+    /// Returns a reader of the exact octets of a given message.
+    ///
+    /// In contrast to [`AsyncPop3Connection::retrieve_raw`], the message is
+    /// not written to a writer, but read from the returned reader as it is
+    /// received, so it can be piped to files or parsers without buffering.
+    /// The reader must be read to its end before further commands are issued.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to download
+    pub async fn retrieve_reader(&mut self, message_id: u32) -> Result<Pop3AsyncMessageReader<'_, S>, Pop3AsyncError> {
+        self.begin_response(&format!("RETR {}\r\n", message_id)).await?;
+        self.stream()?;
+        Ok(Pop3AsyncMessageReader { connection: self, line: vec!(), pos: 0, line_complete: false, done: false, sleep: None })
+    }
+
+    /// Deletes a given message. In dry-run mode, the deletion is only simulated.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to delete
+    pub async fn delete(&mut self, message_id: u32) -> Result<(), Pop3AsyncError> {
+        if self.dry_run {
+            if self.deleted.contains(&message_id) {
+                return Err(format!("message {} already deleted", message_id).into());
+            }
+        }
+        else {
+            self.invoke_single_line(&format!("DELE {}\r\n", message_id)).await?;
+        }
+
+        self.deleted.push(message_id);
+        Ok(())
+    }
+
+    /// Does nothing but checking the connection.
+    pub async fn noop(&mut self) -> Result<(), Pop3AsyncError> {
+        self.invoke_single_line("NOOP\r\n").await?;
+        Ok(())
+    }
+
+    /// Checks the connection by NOOP and returns its state and round-trip latency.
+    ///
+    /// The check never fails; a failed NOOP is reported by the returned
+    /// state. A poisoned or closed connection is reported without sending
+    /// NOOP. Keep-alive is not triggered.
+    pub async fn health_check(&mut self) -> Pop3HealthReport {
+        let state = match (self.is_open(), self.is_poisoned()) {
+            (false, _) => Some(Pop3HealthState::Closed),
+            (true, true) => Some(Pop3HealthState::Poisoned),
+            (true, false) => None
+        };
+        if let Some(state) = state {
+            return Pop3HealthReport { state, latency: None, error: None };
+        }
+
+        let started = self.clock.now();
+        let result = match self.write_command("NOOP\r\n").await {
+            Ok(()) => self.read_status_line().await,
+            Err(err) => Err(err)
+        };
+        let latency = self.clock.now().saturating_duration_since(started);
+
+        match result {
+            Ok(_) => {
+                self.complete_response(None);
+                Pop3HealthReport { state: Pop3HealthState::Ready, latency: Some(latency), error: None }
+            },
+            Err(err) => Pop3HealthReport {
+                state: if self.is_poisoned() { Pop3HealthState::Poisoned } else { Pop3HealthState::Unresponsive },
+                latency: None,
+                error: Some(err.to_string())
+            }
+        }
+    }
+
+    /// Ends the session. Messages marked as deleted are removed by the server.
+    pub async fn quit(mut self) -> Result<(), Pop3AsyncError> {
+        self.close().await
+    }
+
+    /// Returns true, if the session was not ended yet.
+    pub(crate) fn is_open(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Closes the stream without QUIT.
+    ///
+    /// The server does not enter the UPDATE state, so messages marked as
+    /// deleted are kept (RFC 1939, section 6).
+    pub(crate) async fn abort(&mut self) {
+        if let Some(mut stream) = self.stream.take() {
+            let _ = stream.close().await;
+            self.notify(Pop3SessionEvent::Disconnected { reason: "aborted".into() });
+        }
+    }
+
+    /// Ends the session by QUIT and closes the stream, even if QUIT failed.
+    pub(crate) async fn close(&mut self) -> Result<(), Pop3AsyncError> {
+        let result = match self.write_command("QUIT\r\n").await {
+            Ok(()) => self.read_status_line().await.map(|_| self.complete_response(None)),
+            Err(err) => Err(err)
+        };
+        if let Some(mut stream) = self.stream.take() {
+            let _ = stream.close().await;
+            self.notify(Pop3SessionEvent::Disconnected { reason: "QUIT".into() });
+        }
+
+        result
+    }
+
+    /// Unmark any messages marked as delete.
+    pub async fn reset(&mut self) -> Result<(), Pop3AsyncError> {
+        self.invoke_single_line("RSET\r\n").await?;
+        self.deleted.clear();
+        Ok(())
+    }
+
+    /// Returns the message header an a given number of lines from the message.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    /// * `line_count` - count of lines to return from the message body
+    pub async fn top(&mut self, message_id: u32, line_count: u32) -> Result<String, Pop3AsyncError> {
+        let lines = self.invoke_multi_line(&format!("TOP {} {}\r\n", message_id, line_count)).await?;
+        let mut message = String::new();
+        for line in lines {
+            message.push_str(&line);
+            message.push('\n');
+        }
+
+        Ok(message)
+    }
+
+    /// Returns the message header and a given number of lines from the message as exact octets.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    /// * `line_count` - count of lines to return from the message body
+    pub async fn top_raw(&mut self, message_id: u32, line_count: u32) -> Result<Vec<u8>, Pop3AsyncError> {
+        self.begin_response(&format!("TOP {} {}\r\n", message_id, line_count)).await?;
+
+        let mut message = vec!();
+        self.read_raw_multi_line(&mut message).await?;
+        Ok(message)
+    }
+
+    /// Returns the headers of a given message.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    pub async fn headers(&mut self, message_id: u32) -> Result<Pop3Headers, Pop3AsyncError> {
+        let content = self.top_raw(message_id, 0).await?;
+        Ok(Pop3Headers::parse(&content))
+    }
+
+    /// Returns metadata and headers of each message.
+    pub async fn summaries(&mut self) -> Result<Vec<Pop3MessageSummary>, Pop3AsyncError> {
+        let messages = self.list_meta().await?;
+        let mut result = vec!();
+        for meta in messages {
+            let headers = self.headers(meta.message_id).await?;
+            result.push(Pop3MessageSummary { meta, headers });
+        }
+
+        Ok(result)
+    }
+
+    /// Returns the unique ids of all messages.
+    pub async fn list_unique_ids(&mut self) -> Result<Vec<Pop3MessageUidInfo>, Pop3AsyncError> {
+        let lines = self.invoke_multi_line("UIDL\r\n").await?;
+        let mut result = vec!();
+        for line in lines {
+            result.push(response::parse_uidl_line(&line)?);
+        }
+
+        Ok(result)
+    }
+
+    /// Returns a stream of id and unique id of each message.
+    ///
+    /// Entries are yielded as soon as they are received; the stream must be
+    /// consumed completely before further commands are issued.
+    pub fn uidl_stream(&mut self) -> impl Stream<Item = Result<Pop3MessageUidInfo, Pop3AsyncError>> + '_ {
+        self.multi_line_stream("UIDL\r\n", response::parse_uidl_line)
+    }
+
+    /// Returns id, size and unique id of each message.
+    ///
+    /// If the server does not support UIDL, unique ids are omitted. If
+    /// pipelining is enabled, LIST and UIDL are sent back-to-back, so both
+    /// responses arrive within a single round trip.
+    pub async fn list_meta(&mut self) -> Result<Vec<Pop3MessageMeta>, Pop3AsyncError> {
+        if self.pipelining {
+            return self.list_meta_pipelined().await;
+        }
+
+        let infos = self.list().await?;
+        let unique_ids = match self.list_unique_ids().await {
+            Ok(unique_ids) => unique_ids,
+            Err(err) if err.is::<Pop3NegativeResponse>() => vec!(),
+            Err(err) => return Err(err)
+        };
+
+        Ok(response::merge_meta(infos, unique_ids))
+    }
+
+    async fn list_meta_pipelined(&mut self) -> Result<Vec<Pop3MessageMeta>, Pop3AsyncError> {
+        self.keep_alive().await?;
+        self.check_command("UIDL\r\n")?;
+        self.write_command("LIST\r\n").await?;
+        self.write_line("UIDL\r\n").await?;
+
+        let infos = self.read_multi_line().await;
+        if self.pending_response {
+            // LIST failed while reading; the response of UIDL cannot be read
+            return Err(infos.err().unwrap_or_else(|| ConnectionLost(POISONED_MESSAGE).into()));
+        }
+        self.start_command("UIDL\r\n");
+        let unique_ids = match self.read_multi_line().await {
+            Ok(lines) => lines,
+            Err(err) if err.is::<Pop3NegativeResponse>() => vec!(),
+            Err(err) => return Err(err)
+        };
+
+        let infos = infos?.iter()
+            .map(|line| response::parse_list_line(line))
+            .collect::<Result<Vec<_>, _>>()?;
+        let unique_ids = unique_ids.iter()
+            .map(|line| response::parse_uidl_line(line))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(response::merge_meta(infos, unique_ids))
+    }
+
+    /// Returns the capabilities announced by the server (RFC 2449).
+    ///
+    /// Pipelining is enabled, if the server announces PIPELINING.
+    pub async fn capabilities(&mut self) -> Result<Vec<String>, Pop3AsyncError> {
+        let capabilities = self.invoke_multi_line("CAPA\r\n").await?;
+        if capabilities.iter().any(|capability| capability.eq_ignore_ascii_case("PIPELINING")) {
+            self.pipelining = true;
+        }
+
+        Ok(capabilities)
+    }
+
+    /// Enables pipelining, if the server announces PIPELINING.
+    ///
+    /// Servers refusing CAPA do not announce any capability, so pipelining stays disabled.
+    pub(crate) async fn probe_pipelining(&mut self) -> Result<(), Pop3AsyncError> {
+        match self.capabilities().await {
+            Ok(_) => Ok(()),
+            Err(err) if err.is::<Pop3NegativeResponse>() => Ok(()),
+            Err(err) => Err(err)
+        }
+    }
+
+    /// Returns a report about the usage of the maildrop.
+    pub async fn usage_report(&mut self) -> Result<Pop3UsageReport, Pop3AsyncError> {
+        let stat = self.stat().await?;
+        let messages = self.list_meta().await?;
+        Ok(Pop3UsageReport::new(&stat, &messages))
+    }
+
+    /// Returns the unique id of a given message.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    pub async fn get_unique_id(&mut self, message_id: u32) -> Result<String, Pop3AsyncError> {
+        let line = self.invoke_single_line(&format!("UIDL {}\r\n", message_id)).await?;
+        Ok(response::parse_unique_id(&line)?)
+    }
+}
+
+/// Reader of a message retrieved by [`AsyncPop3Connection::retrieve_reader`].
+///
+/// Yields the exact octets of the message without byte-stuffing; the end of
+/// the message is reached when the terminating line was received.
+/// Dropping the reader before its end poisons the connection.
+pub struct Pop3AsyncMessageReader<'a, S> {
+    connection: &'a mut AsyncPop3Connection<S>,
+    line: Vec<u8>,
+    pos: usize,
+    line_complete: bool,
+    done: bool,
+    sleep: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
+
+/// Writer, which feeds the written data into a digest.
+struct DigestWriter<'a, W, D: ?Sized> {
+    writer: &'a mut W,
+    digest: &'a mut D,
+}
+
+impl<W: AsyncWrite + Unpin, D: MessageDigest + ?Sized> AsyncWrite for DigestWriter<'_, W, D> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let len = ready!(Pin::new(&mut *self.writer).poll_write(cx, buf))?;
+        self.digest.update(&buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.writer).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut *self.writer).poll_close(cx)
+    }
+}
+
+impl<S: AsyncRead + Unpin> Pop3AsyncMessageReader<'_, S> {
+
+    /// Returns true, if the whole message was read.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Fails, if no data was received within the timeout of the connection.
+    fn poll_timeout(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let timeout = self.connection.timeout.zip(self.connection.timer.clone());
+        let sleep = match (&mut self.sleep, timeout) {
+            (Some(sleep), _) => sleep,
+            (None, Some((duration, timer))) => self.sleep.insert(timer.sleep(duration)),
+            (None, None) => return Poll::Pending
+        };
+
+        ready!(sleep.as_mut().poll(cx));
+        Poll::Ready(Err(io::Error::new(io::ErrorKind::TimedOut, "timeout")))
+    }
+
+    /// Receives the next line of the response; marks the reader done at the terminating line.
+    fn poll_next_line(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.line_complete {
+            self.line.clear();
+            self.pos = 0;
+            self.line_complete = false;
+        }
+
+        while !self.line_complete {
+            let reader = self.connection.stream.as_mut().ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "stream closed"))?;
+            let available = ready!(Pin::new(&mut *reader).poll_fill_buf(cx))?;
+            if available.is_empty() {
+                self.connection.notify(Pop3SessionEvent::Disconnected { reason: "connection closed by server".into() });
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed")));
+            }
+
+            let length = match available.iter().position(|&c| c == b'\n') {
+                Some(index) => { self.line_complete = true; index + 1 },
+                None => available.len()
+            };
+            if self.line.len() + length > MAX_LINE_LENGTH {
+                return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, format!("line exceeds {} octets", MAX_LINE_LENGTH))));
+            }
+            self.line.extend_from_slice(&available[..length]);
+            if let Some(span) = self.connection.command_span.as_mut() {
+                span.add_bytes(length);
+            }
+            Pin::new(reader).consume(length);
+        }
+
+        self.connection.record_server(&self.line);
+        self.connection.intercept_response_line(&mut self.line);
+
+        match response::unstuff_line(&self.line) {
+            Some(content) => { self.pos = self.line.len() - content.len(); },
+            None => { self.done = true; self.line.clear(); self.connection.complete_response(None); }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Pop3AsyncMessageReader<'_, S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if this.line_complete && this.pos < this.line.len() {
+                let count = buf.len().min(this.line.len() - this.pos);
+                buf[..count].copy_from_slice(&this.line[this.pos..this.pos + count]);
+                this.pos += count;
+                return Poll::Ready(Ok(count));
+            }
+
+            if this.done || buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+
+            match this.poll_next_line(cx) {
+                Poll::Ready(result) => { this.sleep = None; result?; },
+                Poll::Pending => { ready!(this.poll_timeout(cx))?; }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use futures_util::io::AsyncReadExt;
+    use futures_util::{FutureExt, StreamExt};
+
+    /// Stream, which answers with a fixed response and records the commands.
+    ///
+    /// Since it is always ready, futures using it complete on first poll.
+    struct ScriptedStream {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl ScriptedStream {
+        fn new(response: &str) -> Self {
+            ScriptedStream { input: io::Cursor::new(response.as_bytes().to_vec()), output: vec!() }
+        }
+    }
+
+    /// Simulates a stalled server: once the response is consumed, reads stay pending.
+    impl AsyncRead for ScriptedStream {
+        fn poll_read(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            match io::Read::read(&mut self.get_mut().input, buf) {
+                Ok(0) if !buf.is_empty() => Poll::Pending,
+                result => Poll::Ready(result)
+            }
+        }
+    }
+
+    struct ImmediateTimer;
+
+    impl AsyncTimer for ImmediateTimer {
+        fn sleep(&self, _: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            Box::pin(async { })
+        }
+    }
+
+    impl AsyncWrite for ScriptedStream {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            self.get_mut().output.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn test_session() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK\r\n1 20\r\n.\r\n-ERR not supported\r\n\
+            +OK\r\nSubject: hi\r\n\r\n..dot\r\n.\r\n+OK\r\n");
+
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        let messages = connection.list_meta().now_or_never().unwrap().unwrap();
+        assert_eq!(vec!(Pop3MessageMeta { message_id: 1, message_size: 20, unique_id: None }), messages);
+
+        let mut content = vec!();
+        connection.retrieve_raw(1, &mut content).now_or_never().unwrap().unwrap();
+        assert_eq!(b"Subject: hi\r\n\r\n.dot\r\n".to_vec(), content);
+
+        connection.delete(1).now_or_never().unwrap().unwrap();
+        let commands = connection.stream.take().unwrap().into_inner().output;
+        assert_eq!(b"LIST\r\nUIDL\r\nRETR 1\r\nDELE 1\r\n".to_vec(), commands);
+    }
+
+    /// Stream, which returns one chunk per read and the end of the stream afterwards.
+    struct ChunkedStream(std::collections::VecDeque<Vec<u8>>);
+
+    impl AsyncRead for ChunkedStream {
+        fn poll_read(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            let chunks = &mut self.get_mut().0;
+            let Some(chunk) = chunks.front_mut() else {
+                return Poll::Ready(Ok(0));
+            };
+            let count = buf.len().min(chunk.len());
+            buf[..count].copy_from_slice(&chunk[..count]);
+            chunk.drain(..count);
+            if chunk.is_empty() {
+                chunks.pop_front();
+            }
+            Poll::Ready(Ok(count))
+        }
+    }
+
+    impl AsyncWrite for ChunkedStream {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Returns a connection, which received the greeting and the given chunks afterwards.
+    fn chunked_connection(chunks: &[&[u8]]) -> AsyncPop3Connection<ChunkedStream> {
+        let chunks = [b"+OK ready\r\n".as_slice()].iter().chain(chunks).map(|chunk| chunk.to_vec()).collect();
+        AsyncPop3Connection::from_stream(ChunkedStream(chunks)).now_or_never().unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_read_single_line() {
+        let mut connection = chunked_connection(&[b"Hello\n"]);
+        let line = connection.read_line(MAX_LINE_LENGTH).now_or_never().unwrap().unwrap();
+        assert_eq!("Hello", line);
+    }
+
+    #[test]
+    fn test_read_multiple_lines() {
+        let mut connection = chunked_connection(&[b"Hello\nWorld\n"]);
+        assert_eq!("Hello", connection.read_line(MAX_LINE_LENGTH).now_or_never().unwrap().unwrap());
+        assert_eq!("World", connection.read_line(MAX_LINE_LENGTH).now_or_never().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_read_split_lines() {
+        let mut connection = chunked_connection(&[b"Hello\nWor", b"ld\n"]);
+        assert_eq!("Hello", connection.read_line(MAX_LINE_LENGTH).now_or_never().unwrap().unwrap());
+        assert_eq!("World", connection.read_line(MAX_LINE_LENGTH).now_or_never().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_read_status_line_exceeded() {
+        let mut connection = chunked_connection(&[&[b'x'; 512], b"\r\n"]);
+        let err = connection.read_status_line().now_or_never().unwrap().err().unwrap();
+        assert_eq!("line exceeds 512 octets", err.to_string());
+    }
+
+    #[test]
+    fn test_read_line_exceeded() {
+        let data = vec![b'x'; MAX_LINE_LENGTH + 1];
+        let mut connection = chunked_connection(&[&data]);
+        assert!(connection.read_raw_line(MAX_LINE_LENGTH).now_or_never().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_read_closed() {
+        let mut connection = chunked_connection(&[b"Hello"]);
+        let err = connection.read_line(MAX_LINE_LENGTH).now_or_never().unwrap().err().unwrap();
+        assert_eq!("connection closed", err.to_string());
+    }
+
+    #[test]
+    fn test_read_raw_lines() {
+        let mut connection = chunked_connection(&[b"  Hello \r\nWorld\n"]);
+        assert_eq!(b"  Hello \r\n".to_vec(), connection.read_raw_line(MAX_LINE_LENGTH).now_or_never().unwrap().unwrap());
+        assert_eq!(b"World\n".to_vec(), connection.read_raw_line(MAX_LINE_LENGTH).now_or_never().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_read_long_raw_line() {
+        let mut data = vec![b'x'; 2000];
+        data.extend_from_slice(b"\r\nnext\r\n");
+        let mut connection = chunked_connection(&[&data]);
+        assert_eq!(2002, connection.read_raw_line(MAX_LINE_LENGTH).now_or_never().unwrap().unwrap().len());
+        assert_eq!(b"next\r\n".to_vec(), connection.read_raw_line(MAX_LINE_LENGTH).now_or_never().unwrap().unwrap());
+    }
+
+    #[test]
+    fn test_retrieve_reader_line_exceeded() {
+        let mut data = b"+OK\r\n".to_vec();
+        data.extend(vec![b'x'; MAX_LINE_LENGTH + 1]);
+        let mut connection = chunked_connection(&[&data]);
+        let mut reader = connection.retrieve_reader(1).now_or_never().unwrap().unwrap();
+        let mut content = vec!();
+        let err = reader.read_to_end(&mut content).now_or_never().unwrap().err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    /// Withholds the responses after the greeting until both LIST and UIDL were sent.
+    struct PipelinedStream {
+        greeting: io::Cursor<Vec<u8>>,
+        responses: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl AsyncRead for PipelinedStream {
+        fn poll_read(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            let this = self.get_mut();
+            if (this.greeting.position() as usize) < this.greeting.get_ref().len() {
+                return Poll::Ready(io::Read::read(&mut this.greeting, buf));
+            }
+            match this.output.ends_with(b"LIST\r\nUIDL\r\n") || this.responses.position() > 0 {
+                true => Poll::Ready(io::Read::read(&mut this.responses, buf)),
+                false => Poll::Pending
+            }
+        }
+    }
+
+    impl AsyncWrite for PipelinedStream {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            self.get_mut().output.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn pipelined_connection(responses: &str) -> AsyncPop3Connection<PipelinedStream> {
+        let stream = PipelinedStream {
+            greeting: io::Cursor::new(b"+OK ready\r\n".to_vec()),
+            responses: io::Cursor::new(responses.as_bytes().to_vec()),
+            output: vec!(),
+        };
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        connection.set_pipelining(true);
+        connection
+    }
+
+    #[test]
+    fn test_list_meta_pipelined() {
+        let mut connection = pipelined_connection("+OK\r\n1 20\r\n2 30\r\n.\r\n+OK\r\n1 a\r\n2 b\r\n.\r\n+OK\r\n");
+
+        let messages = connection.list_meta().now_or_never().unwrap().unwrap();
+        assert_eq!(vec!(
+            Pop3MessageMeta { message_id: 1, message_size: 20, unique_id: Some("a".into()) },
+            Pop3MessageMeta { message_id: 2, message_size: 30, unique_id: Some("b".into()) },
+        ), messages);
+        assert_eq!(1, connection.stats().get("LIST").unwrap().count);
+        assert_eq!(1, connection.stats().get("UIDL").unwrap().count);
+
+        connection.noop().now_or_never().unwrap().unwrap();
+        assert_eq!(b"LIST\r\nUIDL\r\nNOOP\r\n".to_vec(), connection.stream.as_ref().unwrap().get_ref().output);
+    }
+
+    #[test]
+    fn test_list_meta_pipelined_without_uidl() {
+        let mut connection = pipelined_connection("+OK\r\n1 20\r\n.\r\n-ERR not supported\r\n");
+
+        let messages = connection.list_meta().now_or_never().unwrap().unwrap();
+        assert_eq!(vec!(Pop3MessageMeta { message_id: 1, message_size: 20, unique_id: None }), messages);
+    }
+
+    #[test]
+    fn test_list_meta_pipelined_consumes_uidl_after_error() {
+        let mut connection = pipelined_connection("-ERR busy\r\n+OK\r\n1 a\r\n.\r\n+OK\r\n");
+
+        let err = connection.list_meta().now_or_never().unwrap().err().unwrap();
+        assert_eq!("-ERR busy", err.to_string());
+        assert!(!connection.is_poisoned());
+        connection.noop().now_or_never().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_capabilities_enable_pipelining() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK\r\nUSER\r\nPIPELINING\r\nSASL PLAIN\r\n.\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        assert!(!connection.is_pipelining());
+
+        let capabilities = connection.capabilities().now_or_never().unwrap().unwrap();
+        assert_eq!(vec!("USER", "PIPELINING", "SASL PLAIN"), capabilities);
+        assert!(connection.is_pipelining());
+    }
+
+    #[derive(Default)]
+    struct MetricsRecorder {
+        commands: std::sync::Mutex<Vec<(String, bool)>>,
+        latencies: std::sync::Mutex<Vec<String>>,
+        bytes: std::sync::Mutex<(u64, u64)>,
+    }
+
+    impl MetricsSink for MetricsRecorder {
+        fn increment_command(&self, command: &str, success: bool) {
+            self.commands.lock().unwrap().push((command.to_string(), success));
+        }
+
+        fn record_latency(&self, command: &str, _latency: Duration) {
+            self.latencies.lock().unwrap().push(command.to_string());
+        }
+
+        fn add_bytes(&self, sent: u64, received: u64) {
+            let mut bytes = self.bytes.lock().unwrap();
+            bytes.0 += sent;
+            bytes.1 += received;
+        }
+    }
+
+    #[test]
+    fn test_metrics() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK\r\n-ERR locked\r\n+OK\r\nHello\r\n.\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        let recorder = Arc::new(MetricsRecorder::default());
+        connection.set_metrics(Some(recorder.clone()));
+
+        connection.noop().now_or_never().unwrap().unwrap();
+        assert!(connection.stat().now_or_never().unwrap().is_err());
+        let mut content = vec!();
+        connection.retrieve_raw(1, &mut content).now_or_never().unwrap().unwrap();
+
+        assert_eq!(vec!(
+            ("NOOP".to_string(), true),
+            ("STAT".to_string(), false),
+            ("RETR".to_string(), true),
+        ), *recorder.commands.lock().unwrap());
+        assert_eq!(vec!("NOOP", "STAT", "RETR"), *recorder.latencies.lock().unwrap());
+        assert_eq!((20, 33), *recorder.bytes.lock().unwrap());
+    }
+
+    struct ReadOnly;
+
+    impl Pop3Interceptor for ReadOnly {
+        fn before_command(&self, command: &str) -> Result<(), Pop3AsyncError> {
+            match command.starts_with("DELE") {
+                true => Err("deletion is not allowed".into()),
+                false => Ok(())
+            }
+        }
+
+        fn after_response_line(&self, command: &str, line: &mut Vec<u8>) {
+            if command == "RETR 1" && line.starts_with(b"Subject:") {
+                *line = b"Subject: [external] hi\r\n".to_vec();
+            }
+        }
+    }
+
+    #[test]
+    fn test_interceptor() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK\r\nSubject: hi\r\n.\r\n+OK\r\nSubject: hi\r\n.\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        connection.add_interceptor(Arc::new(ReadOnly));
+
+        let err = connection.delete(1).now_or_never().unwrap().unwrap_err();
+        assert_eq!("deletion is not allowed", err.to_string());
+        assert!(!connection.is_poisoned());
+
+        let mut content = vec!();
+        connection.retrieve_raw(1, &mut content).now_or_never().unwrap().unwrap();
+        assert_eq!(b"Subject: [external] hi\r\n".to_vec(), content);
+
+        let mut reader = connection.retrieve_reader(1).now_or_never().unwrap().unwrap();
+        let mut content = vec!();
+        reader.read_to_end(&mut content).now_or_never().unwrap().unwrap();
+        assert_eq!(b"Subject: [external] hi\r\n".to_vec(), content);
+
+        let commands = connection.stream.take().unwrap().into_inner().output;
+        assert_eq!(b"RETR 1\r\nRETR 1\r\n".to_vec(), commands);
+    }
+
+    #[test]
+    fn test_list_stream() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK\r\n1 20\r\n2 30\r\n.\r\n+OK\r\n1 a\r\nbroken\r\n.\r\n+OK\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+
+        let messages: Vec<_> = connection.list_stream().collect::<Vec<_>>().now_or_never().unwrap();
+        let messages: Vec<(u32, u32)> = messages.into_iter()
+            .map(|info| info.map(|info| (info.message_id, info.message_size)).unwrap())
+            .collect();
+        assert_eq!(vec!((1, 20), (2, 30)), messages);
+
+        let mut unique_ids = Box::pin(connection.uidl_stream());
+        let first = unique_ids.next().now_or_never().unwrap().unwrap().unwrap();
+        assert_eq!("a", first.unique_id);
+        assert!(unique_ids.next().now_or_never().unwrap().unwrap().is_err());
+        assert!(unique_ids.next().now_or_never().unwrap().is_none());
+        drop(unique_ids);
+
+        connection.noop().now_or_never().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_retrieve_reader() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK\r\nSubject: hi\r\n\r\n..dot\r\n.\r\n+OK\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+
+        let mut reader = connection.retrieve_reader(1).now_or_never().unwrap().unwrap();
+        let mut start = [0; 4];
+        reader.read_exact(&mut start).now_or_never().unwrap().unwrap();
+        assert_eq!(b"Subj", &start);
+        let mut rest = vec!();
+        reader.read_to_end(&mut rest).now_or_never().unwrap().unwrap();
+        assert_eq!(b"ect: hi\r\n\r\n.dot\r\n".to_vec(), rest);
+        assert!(reader.is_done());
+
+        connection.noop().now_or_never().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_cancelled_command_poisons_connection() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK\r\n1 20\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        assert!(!connection.is_poisoned());
+
+        assert!(connection.list().now_or_never().is_none());
+        assert!(connection.is_poisoned());
+
+        let err = connection.stat().now_or_never().unwrap().unwrap_err();
+        assert_eq!(POISONED_MESSAGE, err.to_string());
+    }
+
+    #[test]
+    fn test_negative_response_does_not_poison() {
+        let stream = ScriptedStream::new("+OK ready\r\n-ERR no such message\r\n+OK 1 20\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+
+        assert!(connection.list_unique_ids().now_or_never().unwrap().is_err());
+        assert!(!connection.is_poisoned());
+        assert_eq!(1, connection.stat().now_or_never().unwrap().unwrap().message_count);
+    }
+
+    #[test]
+    fn test_dropped_reader_poisons_connection() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK\r\nSubject: hi\r\n.\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+
+        let mut reader = connection.retrieve_reader(1).now_or_never().unwrap().unwrap();
+        let mut start = [0; 4];
+        reader.read_exact(&mut start).now_or_never().unwrap().unwrap();
+        drop(reader);
+
+        assert!(connection.is_poisoned());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK\r\nSubject: hi\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        connection.set_timer(ImmediateTimer);
+        connection.set_timeout(Some(Duration::from_secs(1)));
+
+        let mut reader = connection.retrieve_reader(1).now_or_never().unwrap().unwrap();
+        let mut content = vec!();
+        let err = reader.read_to_end(&mut content).now_or_never().unwrap().unwrap_err();
+        assert_eq!(io::ErrorKind::TimedOut, err.kind());
+        drop(reader);
+
+        let err = connection.stat().now_or_never().unwrap().unwrap_err();
+        assert_eq!(POISONED_MESSAGE, err.to_string());
+    }
+
+    #[test]
+    fn test_timeout_with_manual_clock() {
+        let stream = ScriptedStream::new("+OK ready\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        let clock = ManualClock::new();
+        connection.set_timer(clock.clone());
+        connection.set_timeout(Some(Duration::from_secs(30)));
+
+        let mut stat = Box::pin(connection.stat());
+        assert!((&mut stat).now_or_never().is_none());
+        clock.advance(Duration::from_secs(29));
+        assert!((&mut stat).now_or_never().is_none());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!("timeout", stat.now_or_never().unwrap().unwrap_err().to_string());
+    }
+
+    #[test]
+    fn test_download_rate_with_manual_clock() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK\r\nSubject: hi\r\n.\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        let clock = ManualClock::new();
+        connection.set_timer(clock.clone());
+        connection.set_download_rate(Some(1));
+
+        let mut content = vec!();
+        let mut retrieve = Box::pin(connection.retrieve_raw(1, &mut content));
+        assert!((&mut retrieve).now_or_never().is_none());
+        assert_eq!(1, clock.sleepers());
+        clock.advance(Duration::from_secs(14));
+        retrieve.now_or_never().unwrap().unwrap();
+        assert_eq!(b"Subject: hi\r\n".to_vec(), content);
+    }
+
+    #[test]
+    fn test_retrieve_verified_and_digest() {
+        let message = "+OK\r\nSubject: hi\r\n.\r\n";
+        let stream = ScriptedStream::new(&format!("+OK ready\r\n{}{}{}", message, message, message));
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+
+        let meta = Pop3MessageMeta { message_id: 1, message_size: 13, unique_id: None };
+        let check = connection.retrieve_verified(&meta, &mut vec!()).now_or_never().unwrap().unwrap();
+        assert!(check.is_match());
+
+        connection.set_strict_size_check(true);
+        let meta = Pop3MessageMeta { message_id: 1, message_size: 20, unique_id: None };
+        assert!(connection.retrieve_verified(&meta, &mut vec!()).now_or_never().unwrap().is_err());
+
+        let mut content = vec!();
+        let hash = connection.retrieve_with_digest(1, &mut content, &mut crate::Sha256Digest::new()).now_or_never().unwrap().unwrap();
+        assert_eq!(crate::sync::sha256_hex(&content), crate::to_hex(&hash));
+    }
+
+    #[test]
+    fn test_keep_alive_with_manual_clock() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK\r\n+OK\r\n+OK\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        let clock = ManualClock::new();
+        connection.set_clock(Arc::new(clock.clone()));
+        connection.set_keep_alive(Some(Duration::from_secs(60)));
+
+        clock.advance(Duration::from_secs(59));
+        connection.reset().now_or_never().unwrap().unwrap();
+        clock.advance(Duration::from_secs(60));
+        connection.reset().now_or_never().unwrap().unwrap();
+
+        let commands = connection.stream.take().unwrap().into_inner().output;
+        assert_eq!(b"RSET\r\nNOOP\r\nRSET\r\n".to_vec(), commands);
+    }
+
+    #[test]
+    fn test_health_check() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK\r\n-ERR busy\r\n+OK\r\nSubject: hi\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        let clock = ManualClock::new();
+        connection.set_clock(Arc::new(clock.clone()));
+
+        let report = connection.health_check().now_or_never().unwrap();
+        assert!(report.is_ready());
+        assert_eq!(Some(Duration::ZERO), report.latency);
+
+        let report = connection.health_check().now_or_never().unwrap();
+        assert_eq!(Pop3HealthState::Unresponsive, report.state);
+        assert_eq!(Some("-ERR busy".to_string()), report.error);
+
+        let reader = connection.retrieve_reader(1).now_or_never().unwrap().unwrap();
+        drop(reader);
+        let report = connection.health_check().now_or_never().unwrap();
+        assert_eq!(Pop3HealthReport { state: Pop3HealthState::Poisoned, latency: None, error: None }, report);
+
+        connection.stream = None;
+        assert_eq!(Pop3HealthState::Closed, connection.health_check().now_or_never().unwrap().state);
+    }
+
+    /// Lets each response take as many seconds as the message id of the command.
+    struct Latency(ManualClock);
+
+    impl Pop3Interceptor for Latency {
+        fn after_response_line(&self, command: &str, _line: &mut Vec<u8>) {
+            let seconds = command.split(' ').nth(1).map_or(0, |id| id.parse().unwrap());
+            self.0.advance(Duration::from_secs(seconds));
+        }
+    }
+
+    #[test]
+    fn test_stats() {
+        let stream = ScriptedStream::new("+OK ready\r\n+OK 1\r\n-ERR no such message\r\n+OK 3\r\n+OK\r\n");
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        let clock = ManualClock::new();
+        connection.set_clock(Arc::new(clock.clone()));
+        connection.add_interceptor(Arc::new(Latency(clock)));
+
+        for message_id in 1..=3 {
+            let _ = connection.get_message_size(message_id).now_or_never().unwrap();
+        }
+        connection.noop().now_or_never().unwrap().unwrap();
+
+        let list = connection.stats().get("list").unwrap();
+        assert_eq!((3, 1), (list.count, list.failures));
+        assert_eq!(Duration::from_secs(1), list.min);
+        assert_eq!(Duration::from_secs(3), list.max);
+        assert_eq!(Duration::from_secs(2), list.average());
+        let commands: Vec<&str> = connection.stats().iter().map(|(command, _)| command).collect();
+        assert_eq!(vec!("LIST", "NOOP"), commands);
+    }
+
+    #[test]
+    fn test_start_tls_with() {
+        let connection = AsyncPop3Connection::from_stream(ScriptedStream::new("+OK ready\r\n+OK begin TLS\r\n"))
+            .now_or_never().unwrap().unwrap();
+
+        let upgraded = connection.start_tls_with(|stream| async move {
+            assert_eq!(b"STLS\r\n".to_vec(), stream.output);
+            Ok(ScriptedStream::new("-ERR not now\r\n"))
+        }).now_or_never().unwrap();
+        let mut connection = upgraded.unwrap();
+
+        let err = connection.stat().now_or_never().unwrap().unwrap_err();
+        assert_eq!("-ERR not now", err.to_string());
+    }
+}
@@ -0,0 +1,528 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::stream::{self, StreamExt};
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+
+use crate::{Pop3AsyncError, Pop3ConnectionBuilder, Pop3MessageMeta, TokioPop3Connection};
+
+/// Idle connection of a pool.
+struct IdleConnection {
+    connection: TokioPop3Connection,
+    created: Instant,
+}
+
+/// Account of a pool with its idle connections.
+struct PoolAccount {
+    builder: Pop3ConnectionBuilder,
+    permits: Arc<Semaphore>,
+    idle: Mutex<Vec<IdleConnection>>,
+}
+
+/// Failed operation of [`AsyncPop3Pool::fetch_all`].
+#[derive(Debug)]
+pub struct Pop3FetchFailure {
+    /// name of the account
+    pub account: String,
+
+    /// message, which failed; `None` if the messages of the account could not be listed
+    pub message: Option<Pop3MessageMeta>,
+
+    /// reason of the failure
+    pub error: Pop3AsyncError,
+}
+
+/// Aggregated result of [`AsyncPop3Pool::fetch_all`].
+#[derive(Debug, Default)]
+pub struct Pop3FetchReport {
+    /// count of messages delivered to the sink
+    pub fetched: usize,
+
+    /// total size of the delivered messages in octets
+    pub fetched_bytes: u64,
+
+    /// failed listings and messages
+    pub failures: Vec<Pop3FetchFailure>,
+}
+
+impl Pop3FetchReport {
+
+    /// Returns true, if all messages were fetched.
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Phase of [`AsyncPop3Pool::fetch_all`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Pop3FetchPhase {
+    /// no fetch was started yet
+    #[default]
+    Idle,
+
+    /// messages of the accounts are listed
+    Listing,
+
+    /// messages are downloaded
+    Fetching,
+
+    /// fetch is complete
+    Done,
+}
+
+/// Single step of [`AsyncPop3Pool::fetch_all`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Pop3FetchEvent {
+    /// messages of an account were listed
+    Listed { account: String, message_count: usize },
+
+    /// message was delivered to the sink
+    Fetched { account: String, message_id: u32, size: u64 },
+
+    /// listing of an account (`message_id` is `None`) or download of a message failed
+    Failed { account: String, message_id: Option<u32>, error: String },
+}
+
+/// Progress of [`AsyncPop3Pool::fetch_all`], see [`AsyncPop3Pool::progress`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pop3FetchProgress {
+    /// current phase
+    pub phase: Pop3FetchPhase,
+
+    /// count of listed messages
+    pub total_messages: usize,
+
+    /// total size of the listed messages in octets, as reported by LIST
+    pub total_bytes: u64,
+
+    /// count of messages delivered to the sink
+    pub fetched: usize,
+
+    /// size of the delivered messages in octets
+    pub fetched_bytes: u64,
+
+    /// count of failed listings and messages
+    pub failed: usize,
+
+    /// most recent step; `None` before the first step
+    pub last_event: Option<Pop3FetchEvent>,
+}
+
+impl Pop3FetchProgress {
+
+    fn apply(&mut self, event: Pop3FetchEvent) {
+        match &event {
+            Pop3FetchEvent::Listed { .. } => { },
+            Pop3FetchEvent::Fetched { size, .. } => {
+                self.fetched += 1;
+                self.fetched_bytes += size;
+            },
+            Pop3FetchEvent::Failed { .. } => { self.failed += 1; }
+        }
+        self.last_event = Some(event);
+    }
+}
+
+/// Pool of authenticated async POP3 connections, keyed by account.
+///
+/// Connections are created on demand and reused after they were returned to
+/// the pool. Before an idle connection is handed out, its health is checked
+/// by NOOP; broken connections and connections exceeding the maximum
+/// lifetime are replaced. Each account has a limited count of connections;
+/// tasks waiting for a connection are served in the order of their requests.
+///
+/// Since most servers lock the maildrop during a session, the default
+/// count of connections per account is 1. Deletions are committed when a
+/// connection is ended by QUIT, i.e. when it exceeds its maximum lifetime,
+/// is detached, or the pool is closed.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use rust_pop3_client::{AsyncPop3Pool, Pop3ConnectionBuilder};
+///
+/// # async fn run() -> Result<(), rust_pop3_client::Pop3AsyncError> {
+/// let mut pool = AsyncPop3Pool::new();
+/// pool.set_max_lifetime(Some(Duration::from_secs(300)));
+/// pool.add("work", Pop3ConnectionBuilder::new("pop.example.com").login("me@example.com", "secret"));
+///
+/// let mut connection = pool.get("work").await?;
+/// let stat = connection.stat().await?;
+/// println!("{} messages", stat.message_count);
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncPop3Pool {
+    accounts: HashMap<String, Arc<PoolAccount>>,
+    max_size: usize,
+    max_lifetime: Option<Duration>,
+    progress: watch::Sender<Pop3FetchProgress>,
+}
+
+impl AsyncPop3Pool {
+
+    /// Returns a new pool without accounts.
+    pub fn new() -> Self {
+        AsyncPop3Pool { accounts: HashMap::new(), max_size: 1, max_lifetime: None, progress: watch::channel(Pop3FetchProgress::default()).0 }
+    }
+
+    /// Sets the maximum count of connections per account; applies to accounts added afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - maximum count of connections per account; at least 1
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size.max(1);
+    }
+
+    /// Sets the maximum lifetime of connections.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_lifetime` - time after which connections are ended; `None` keeps connections forever
+    pub fn set_max_lifetime(&mut self, max_lifetime: Option<Duration>) {
+        self.max_lifetime = max_lifetime;
+    }
+
+    /// Adds an account. An account with the same name is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`    - name of the account
+    /// * `builder` - builder used to connect and authenticate the account
+    pub fn add(&mut self, name: &str, builder: Pop3ConnectionBuilder) {
+        self.accounts.insert(name.to_string(), Arc::new(PoolAccount {
+            builder,
+            permits: Arc::new(Semaphore::new(self.max_size)),
+            idle: Mutex::new(vec!()),
+        }));
+    }
+
+    fn is_expired(&self, created: Instant) -> bool {
+        matches!(self.max_lifetime, Some(max_lifetime) if created.elapsed() >= max_lifetime)
+    }
+
+    /// Returns a connection of the given account.
+    ///
+    /// Waits until a connection of the account is available. The connection
+    /// is returned to the pool when dropped, unless it is poisoned.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - name of the account
+    pub async fn get(&self, name: &str) -> Result<Pop3PooledConnection, Pop3AsyncError> {
+        let account = self.accounts.get(name).ok_or_else(|| format!("unknown account: {}", name))?.clone();
+        let permit = account.permits.clone().acquire_owned().await?;
+
+        loop {
+            let idle = account.idle.lock().map_err(|_| "pool poisoned")?.pop();
+            match idle {
+                Some(idle) if self.is_expired(idle.created) => {
+                    let _ = idle.connection.quit().await;
+                },
+                Some(mut idle) => {
+                    if idle.connection.noop().await.is_ok() {
+                        return Ok(Pop3PooledConnection { connection: Some(idle.connection), created: idle.created, account, _permit: permit });
+                    }
+                },
+                None => {
+                    let connection = account.builder.clone().connect_async().await?;
+                    return Ok(Pop3PooledConnection { connection: Some(connection), created: Instant::now(), account, _permit: permit });
+                }
+            }
+        }
+    }
+
+    /// Returns a receiver of the progress of [`AsyncPop3Pool::fetch_all`].
+    ///
+    /// The receiver is notified on each step, so UIs can await changes
+    /// instead of polling. Since only the latest progress is kept, steps
+    /// may be skipped by slow receivers; the counters are always complete.
+    pub fn progress(&self) -> watch::Receiver<Pop3FetchProgress> {
+        self.progress.subscribe()
+    }
+
+    fn report_progress(&self, update: impl FnOnce(&mut Pop3FetchProgress)) {
+        self.progress.send_modify(update);
+    }
+
+    /// Downloads the messages of all accounts with a bounded count of concurrent downloads.
+    ///
+    /// The messages of each account are listed first. Afterwards, up to
+    /// `concurrency` messages are downloaded at the same time, each using a
+    /// connection of the pool; the effective concurrency per account is
+    /// limited by [`AsyncPop3Pool::set_max_size`]. Since message ids may
+    /// differ between sessions, the unique id of a message is verified before
+    /// it is downloaded, if known. A failing message does not affect the
+    /// others; failures are collected in the returned report. Progress is
+    /// reported by [`AsyncPop3Pool::progress`].
+    ///
+    /// # Arguments
+    ///
+    /// * `concurrency` - maximum count of concurrent downloads; at least 1
+    /// * `sink`        - invoked with account name, message metadata and exact message octets
+    pub async fn fetch_all<F, U>(&self, concurrency: usize, sink: F) -> Pop3FetchReport
+    where
+        F: Fn(String, Pop3MessageMeta, Vec<u8>) -> U,
+        U: Future<Output = Result<(), Pop3AsyncError>>
+    {
+        let mut report = Pop3FetchReport::default();
+        let mut names: Vec<&String> = self.accounts.keys().collect();
+        names.sort();
+        self.report_progress(|progress| *progress = Pop3FetchProgress { phase: Pop3FetchPhase::Listing, ..Default::default() });
+
+        let mut jobs = vec!();
+        for name in names {
+            match self.list_account(name).await {
+                Ok(messages) => {
+                    self.report_progress(|progress| {
+                        progress.total_messages += messages.len();
+                        progress.total_bytes += messages.iter().map(|message| message.message_size as u64).sum::<u64>();
+                        progress.apply(Pop3FetchEvent::Listed { account: name.clone(), message_count: messages.len() });
+                    });
+                    jobs.extend(messages.into_iter().map(|message| (name.clone(), message)));
+                },
+                Err(error) => {
+                    self.report_progress(|progress| progress.apply(Pop3FetchEvent::Failed { account: name.clone(), message_id: None, error: error.to_string() }));
+                    report.failures.push(Pop3FetchFailure { account: name.clone(), message: None, error });
+                }
+            }
+        }
+        self.report_progress(|progress| progress.phase = Pop3FetchPhase::Fetching);
+
+        let sink = &sink;
+        let mut results = stream::iter(jobs)
+            .map(|(account, message)| async move {
+                let result = match self.fetch_message(&account, &message).await {
+                    Ok(content) => {
+                        let size = content.len() as u64;
+                        sink(account.clone(), message.clone(), content).await.map(|_| size)
+                    },
+                    Err(err) => Err(err)
+                };
+                (account, message, result)
+            })
+            .buffer_unordered(concurrency.max(1));
+
+        while let Some((account, message, result)) = results.next().await {
+            match result {
+                Ok(size) => {
+                    report.fetched += 1;
+                    report.fetched_bytes += size;
+                    self.report_progress(|progress| progress.apply(Pop3FetchEvent::Fetched { account, message_id: message.message_id, size }));
+                },
+                Err(error) => {
+                    self.report_progress(|progress| progress.apply(Pop3FetchEvent::Failed { account: account.clone(), message_id: Some(message.message_id), error: error.to_string() }));
+                    report.failures.push(Pop3FetchFailure { account, message: Some(message), error });
+                }
+            }
+        }
+        self.report_progress(|progress| progress.phase = Pop3FetchPhase::Done);
+
+        report
+    }
+
+    async fn list_account(&self, name: &str) -> Result<Vec<Pop3MessageMeta>, Pop3AsyncError> {
+        self.get(name).await?.list_meta().await
+    }
+
+    async fn fetch_message(&self, name: &str, message: &Pop3MessageMeta) -> Result<Vec<u8>, Pop3AsyncError> {
+        let mut connection = self.get(name).await?;
+        if let Some(unique_id) = &message.unique_id {
+            if &connection.get_unique_id(message.message_id).await? != unique_id {
+                return Err(format!("message {} changed its unique id", message.message_id).into());
+            }
+        }
+
+        let mut content = vec!();
+        connection.retrieve_raw(message.message_id, &mut content).await?;
+        Ok(content)
+    }
+
+    /// Ends all idle connections by QUIT.
+    pub async fn close(&self) {
+        for account in self.accounts.values() {
+            let idle: Vec<IdleConnection> = match account.idle.lock() {
+                Ok(mut idle) => idle.drain(..).collect(),
+                Err(_) => vec!()
+            };
+            for idle in idle {
+                let _ = idle.connection.quit().await;
+            }
+        }
+    }
+}
+
+impl Default for AsyncPop3Pool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Connection borrowed from an [`AsyncPop3Pool`].
+///
+/// Dereferences to [`TokioPop3Connection`]; the connection is returned to
+/// the pool when dropped.
+pub struct Pop3PooledConnection {
+    connection: Option<TokioPop3Connection>,
+    created: Instant,
+    account: Arc<PoolAccount>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Pop3PooledConnection {
+
+    /// Removes the connection from the pool, e.g. to commit deletions by QUIT.
+    pub fn detach(mut self) -> TokioPop3Connection {
+        self.connection.take().expect("connection present until dropped")
+    }
+}
+
+impl Deref for Pop3PooledConnection {
+    type Target = TokioPop3Connection;
+
+    fn deref(&self) -> &TokioPop3Connection {
+        self.connection.as_ref().expect("connection present until dropped")
+    }
+}
+
+impl DerefMut for Pop3PooledConnection {
+    fn deref_mut(&mut self) -> &mut TokioPop3Connection {
+        self.connection.as_mut().expect("connection present until dropped")
+    }
+}
+
+impl Drop for Pop3PooledConnection {
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.take() {
+            if !connection.is_poisoned() {
+                if let Ok(mut idle) = self.account.idle.lock() {
+                    idle.push(IdleConnection { connection, created: self.created });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use crate::TlsMode;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    fn account(port: u16) -> Pop3ConnectionBuilder {
+        Pop3ConnectionBuilder::new("127.0.0.1").tls_mode(TlsMode::Plain).port(port).login("user", "secret")
+    }
+
+    #[test]
+    fn test_reuses_healthy_connection() {
+        let (port, server) = test_util::serve(&[
+            ("USER user", "+OK\r\n"),
+            ("PASS secret", "+OK\r\n"),
+            ("STAT", "+OK 2 40\r\n"),
+            ("NOOP", "+OK\r\n"),
+            ("STAT", "+OK 2 40\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        block_on(async {
+            let mut pool = AsyncPop3Pool::new();
+            pool.add("a", account(port));
+
+            for _ in 0..2 {
+                let mut connection = pool.get("a").await.unwrap();
+                assert_eq!(2, connection.stat().await.unwrap().message_count);
+            }
+            pool.close().await;
+
+            assert!(pool.get("unknown").await.is_err());
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_fetch_all() {
+        let (port_a, server_a) = test_util::serve(&[
+            ("USER user", "+OK\r\n"),
+            ("PASS secret", "+OK\r\n"),
+            ("LIST", "+OK\r\n1 5\r\n2 5\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 a1\r\n2 a2\r\n.\r\n"),
+            ("NOOP", "+OK\r\n"),
+            ("UIDL 1", "+OK 1 a1\r\n"),
+            ("RETR 1", "+OK\r\nabc\r\n.\r\n"),
+            ("NOOP", "+OK\r\n"),
+            ("UIDL 2", "+OK 2 other\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+        let (port_b, server_b) = test_util::serve(&[
+            ("USER user", "+OK\r\n"),
+            ("PASS secret", "+OK\r\n"),
+            ("LIST", "+OK\r\n1 7\r\n.\r\n"),
+            ("UIDL", "-ERR not supported\r\n"),
+            ("NOOP", "+OK\r\n"),
+            ("RETR 1", "+OK\r\nhello\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        block_on(async {
+            let mut pool = AsyncPop3Pool::new();
+            pool.add("a", account(port_a));
+            pool.add("b", account(port_b));
+            pool.add("c", account(test_util::serve(&[]).0));
+
+            let progress = pool.progress();
+            let fetched = Mutex::new(vec!());
+            let report = pool.fetch_all(2, |account, message, content| {
+                fetched.lock().unwrap().push((account, message.message_id, content));
+                async { Ok(()) }
+            }).await;
+            pool.close().await;
+
+            assert_eq!(2, report.fetched);
+            assert_eq!(12, report.fetched_bytes);
+            assert_eq!(2, report.failures.len());
+            assert!(report.failures.iter().any(|failure| failure.account == "c" && failure.message.is_none()));
+            assert!(report.failures.iter().any(|failure| failure.account == "a" && failure.message.as_ref().unwrap().message_id == 2));
+
+            let progress = progress.borrow().clone();
+            assert_eq!(Pop3FetchPhase::Done, progress.phase);
+            assert_eq!(3, progress.total_messages);
+            assert_eq!(17, progress.total_bytes);
+            assert_eq!(2, progress.fetched);
+            assert_eq!(12, progress.fetched_bytes);
+            assert_eq!(2, progress.failed);
+
+            let mut fetched = fetched.into_inner().unwrap();
+            fetched.sort();
+            assert_eq!(vec!(("a".to_string(), 1, b"abc\r\n".to_vec()), ("b".to_string(), 1, b"hello\r\n".to_vec())), fetched);
+        });
+        server_a.join().unwrap();
+        server_b.join().unwrap();
+    }
+
+    #[test]
+    fn test_replaces_expired_connection() {
+        let (port, server) = test_util::serve_sessions(&[
+            &[("USER user", "+OK\r\n"), ("PASS secret", "+OK\r\n"), ("QUIT", "+OK\r\n")],
+            &[("USER user", "+OK\r\n"), ("PASS secret", "+OK\r\n")],
+        ]);
+
+        block_on(async {
+            let mut pool = AsyncPop3Pool::new();
+            pool.set_max_lifetime(Some(Duration::ZERO));
+            pool.add("a", account(port));
+
+            drop(pool.get("a").await.unwrap());
+            pool.get("a").await.unwrap().detach();
+        });
+        assert_eq!(2, server.join().unwrap().len());
+    }
+}
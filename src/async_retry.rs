@@ -0,0 +1,451 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::is_transient;
+use crate::{AsyncTimer, Pop3AsyncError, Pop3ConnectionBuilder, Pop3MessageInfo, Pop3MessageMeta, Pop3MessageUidInfo, Pop3Stat, TokioPop3Connection, TokioTimer};
+
+/// Future of an operation of a [`RetryingPop3Connection`].
+pub type Pop3OperationFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, Pop3AsyncError>> + Send + 'a>>;
+
+/// Class of an operation, which determines whether it is retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OperationClass {
+    /// operations without side effects, e.g. STAT, LIST, UIDL, TOP and NOOP
+    Query,
+
+    /// download of messages by RETR
+    Retrieve,
+
+    /// operations changing the maildrop, e.g. DELE and RSET
+    Mutation,
+}
+
+/// Policy of a [`RetryingPop3Connection`].
+///
+/// By default, queries and downloads are retried up to 3 times with an
+/// exponential backoff starting at 100 milliseconds. Mutations are never
+/// retried, since marks of deleted messages are lost when the session
+/// breaks.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    retried_classes: Vec<OperationClass>,
+}
+
+impl RetryPolicy {
+
+    /// Returns the default policy.
+    pub fn new() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            retried_classes: vec!(OperationClass::Query, OperationClass::Retrieve),
+        }
+    }
+
+    /// Sets how often an operation is retried.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the backoff before the first retry, which is doubled for each further retry.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial` - delay before the first retry
+    /// * `max`     - maximum delay
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Enables or disables retries of a class of operations.
+    ///
+    /// Note that [`OperationClass::Mutation`] can not be enabled, since
+    /// retrying DELE in a new session may delete a different message.
+    pub fn retry(mut self, class: OperationClass, enabled: bool) -> Self {
+        self.retried_classes.retain(|retried| *retried != class);
+        if enabled && class != OperationClass::Mutation {
+            self.retried_classes.push(class);
+        }
+        self
+    }
+
+    /// Returns true, if operations of the given class are retried.
+    pub fn is_retried(&self, class: OperationClass) -> bool {
+        self.retried_classes.contains(&class)
+    }
+
+    /// Returns the delay before the given retry, starting at 0.
+    fn backoff_for(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.min(31));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Async POP3 connection, which reconnects and retries operations on transient failures.
+///
+/// Failed operations are retried in a new, authenticated session according
+/// to the [`RetryPolicy`]. Since a new session starts without deletion marks,
+/// messages marked as deleted in a broken session are not deleted.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{Pop3ConnectionBuilder, RetryingPop3Connection, RetryPolicy};
+///
+/// # async fn run() -> Result<(), rust_pop3_client::Pop3AsyncError> {
+/// let builder = Pop3ConnectionBuilder::new("pop.example.com").login("user@example.com", "secret");
+/// let mut connection = RetryingPop3Connection::connect(builder, RetryPolicy::new()).await?;
+///
+/// for message in connection.list_meta().await? {
+///     let content = connection.retrieve(message.message_id).await?;
+///     println!("{}: {} octets", message.message_id, content.len());
+/// }
+/// connection.quit().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct RetryingPop3Connection {
+    builder: Pop3ConnectionBuilder,
+    policy: RetryPolicy,
+    connection: Option<TokioPop3Connection>,
+    reconnects: u32,
+    timer: Arc<dyn AsyncTimer>,
+}
+
+impl RetryingPop3Connection {
+
+    /// Connects and authenticates using the given builder.
+    ///
+    /// # Arguments
+    ///
+    /// * `builder` - builder used to connect and authenticate, also on reconnects
+    /// * `policy`  - retry policy
+    pub async fn connect(builder: Pop3ConnectionBuilder, policy: RetryPolicy) -> Result<RetryingPop3Connection, Pop3AsyncError> {
+        Self::connect_with_timer(builder, policy, Arc::new(TokioTimer)).await
+    }
+
+    /// Connects and authenticates; the backoff sleeps using the given timer.
+    pub(crate) async fn connect_with_timer(builder: Pop3ConnectionBuilder, policy: RetryPolicy, timer: Arc<dyn AsyncTimer>) -> Result<RetryingPop3Connection, Pop3AsyncError> {
+        let mut connection = RetryingPop3Connection { builder, policy, connection: None, reconnects: 0, timer };
+        connection.call(OperationClass::Query, |_| Box::pin(async { Ok(()) })).await?;
+        Ok(connection)
+    }
+
+    /// Returns the count of reconnects after failures.
+    pub fn reconnects(&self) -> u32 {
+        self.reconnects
+    }
+
+    async fn connection(&mut self) -> Result<&mut TokioPop3Connection, Pop3AsyncError> {
+        if self.connection.is_none() {
+            self.connection = Some(self.builder.clone().connect_async().await?);
+        }
+
+        self.connection.as_mut().ok_or_else(|| "not connected".into())
+    }
+
+    /// Runs an operation and retries it on transient failures, if its class is retried.
+    ///
+    /// Message numbers are only valid within a session, so operations on
+    /// a given message should use [`RetryingPop3Connection::call_message`].
+    ///
+    /// # Arguments
+    ///
+    /// * `class`     - class of the operation
+    /// * `operation` - operation to run on the current connection
+    pub async fn call<T>(&mut self, class: OperationClass, mut operation: impl for<'c> FnMut(&'c mut TokioPop3Connection) -> Pop3OperationFuture<'c, T>) -> Result<T, Pop3AsyncError> {
+        let mut retry = 0;
+        loop {
+            let result = match self.connection().await {
+                Ok(connection) => operation(connection).await,
+                Err(err) => Err(err)
+            };
+
+            match result {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    let transient = is_transient(err.as_ref());
+                    if transient {
+                        self.connection = None;
+                    }
+                    if !transient || !self.policy.is_retried(class) || retry >= self.policy.max_retries {
+                        return Err(err);
+                    }
+
+                    self.timer.sleep(self.policy.backoff_for(retry)).await;
+                    retry += 1;
+                    self.reconnects += 1;
+                }
+            }
+        }
+    }
+
+    /// Runs an operation on a given message and retries it on transient failures, if its class is retried.
+    ///
+    /// Message numbers are only valid within a session, so the unique id of
+    /// the message is recorded before the first attempt and verified after
+    /// each reconnect. If it differs, the operation fails instead of being
+    /// retried on a different message. If the unique id is not known, e.g.
+    /// since the server does not support UIDL, the operation is not retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `class`      - class of the operation
+    /// * `message_id` - id of the message in the current session
+    /// * `operation`  - operation to run on the current connection
+    pub async fn call_message<T>(&mut self, class: OperationClass, message_id: u32, mut operation: impl for<'c> FnMut(&'c mut TokioPop3Connection) -> Pop3OperationFuture<'c, T>) -> Result<T, Pop3AsyncError> {
+        let unique_id = match self.connection().await {
+            Ok(connection) => connection.get_unique_id(message_id).await.ok(),
+            Err(_) => None
+        };
+
+        let mut retry = 0;
+        loop {
+            let result = match self.connection().await {
+                Ok(connection) if retry == 0 => operation(connection).await,
+                Ok(connection) => match connection.get_unique_id(message_id).await {
+                    Ok(current) if unique_id.as_ref() == Some(&current) => operation(connection).await,
+                    Ok(_) => Err(format!("message {} changed its unique id after reconnect", message_id).into()),
+                    Err(err) => Err(err)
+                },
+                Err(err) => Err(err)
+            };
+
+            match result {
+                Ok(value) => return Ok(value),
+                Err(err) => {
+                    let transient = is_transient(err.as_ref());
+                    if transient {
+                        self.connection = None;
+                    }
+                    if !transient || unique_id.is_none() || !self.policy.is_retried(class) || retry >= self.policy.max_retries {
+                        return Err(err);
+                    }
+
+                    self.timer.sleep(self.policy.backoff_for(retry)).await;
+                    retry += 1;
+                    self.reconnects += 1;
+                }
+            }
+        }
+    }
+
+    /// Returns maildrop statistics.
+    pub async fn stat(&mut self) -> Result<Pop3Stat, Pop3AsyncError> {
+        self.call(OperationClass::Query, |connection| Box::pin(connection.stat())).await
+    }
+
+    /// Returns id and size of each message.
+    pub async fn list(&mut self) -> Result<Vec<Pop3MessageInfo>, Pop3AsyncError> {
+        self.call(OperationClass::Query, |connection| Box::pin(connection.list())).await
+    }
+
+    /// Returns the unique ids of all messages.
+    pub async fn list_unique_ids(&mut self) -> Result<Vec<Pop3MessageUidInfo>, Pop3AsyncError> {
+        self.call(OperationClass::Query, |connection| Box::pin(connection.list_unique_ids())).await
+    }
+
+    /// Returns id, size and unique id of each message.
+    pub async fn list_meta(&mut self) -> Result<Vec<Pop3MessageMeta>, Pop3AsyncError> {
+        self.call(OperationClass::Query, |connection| Box::pin(connection.list_meta())).await
+    }
+
+    /// Returns the message header and a given number of lines from the message as exact octets.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    /// * `line_count` - count of lines to return from the message body
+    pub async fn top_raw(&mut self, message_id: u32, line_count: u32) -> Result<Vec<u8>, Pop3AsyncError> {
+        self.call_message(OperationClass::Query, message_id, |connection| Box::pin(connection.top_raw(message_id, line_count))).await
+    }
+
+    /// Downloads the exact octets of a given message.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to download
+    pub async fn retrieve(&mut self, message_id: u32) -> Result<Vec<u8>, Pop3AsyncError> {
+        self.call_message(OperationClass::Retrieve, message_id, |connection| Box::pin(async move {
+            let mut content = vec!();
+            connection.retrieve_raw(message_id, &mut content).await?;
+            Ok(content)
+        })).await
+    }
+
+    /// Deletes a given message; never retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to delete
+    pub async fn delete(&mut self, message_id: u32) -> Result<(), Pop3AsyncError> {
+        self.call(OperationClass::Mutation, |connection| Box::pin(connection.delete(message_id))).await
+    }
+
+    /// Ends the session. Messages marked as deleted are removed by the server.
+    pub async fn quit(mut self) -> Result<(), Pop3AsyncError> {
+        match self.connection.take() {
+            Some(connection) => connection.quit().await,
+            None => Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::{test_util, Pop3NegativeResponse, TlsMode};
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    fn account(port: u16) -> Pop3ConnectionBuilder {
+        Pop3ConnectionBuilder::new("127.0.0.1").tls_mode(TlsMode::Plain).port(port).login("user", "secret")
+    }
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy::new().backoff(Duration::ZERO, Duration::ZERO)
+    }
+
+    #[test]
+    fn test_retries_query_in_new_session() {
+        let (port, server) = test_util::serve_sessions(&[
+            &[("USER user", "+OK\r\n"), ("PASS secret", "+OK\r\n")],
+            &[("USER user", "+OK\r\n"), ("PASS secret", "+OK\r\n"), ("STAT", "+OK 1 10\r\n"), ("QUIT", "+OK\r\n")],
+        ]);
+
+        block_on(async {
+            let mut connection = RetryingPop3Connection::connect(account(port), policy()).await.unwrap();
+            assert_eq!(1, connection.stat().await.unwrap().message_count);
+            assert_eq!(1, connection.reconnects());
+            connection.quit().await.unwrap();
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_backoff_with_manual_clock() {
+        let (port, server) = test_util::serve_sessions(&[
+            &[("USER user", "+OK\r\n"), ("PASS secret", "+OK\r\n")],
+            &[("USER user", "+OK\r\n"), ("PASS secret", "+OK\r\n"), ("STAT", "+OK 1 10\r\n"), ("QUIT", "+OK\r\n")],
+        ]);
+
+        let clock = ManualClock::new();
+        let policy = RetryPolicy::new().backoff(Duration::from_secs(60), Duration::from_secs(60));
+        block_on(async {
+            let mut connection = RetryingPop3Connection::connect_with_timer(account(port), policy, Arc::new(clock.clone())).await.unwrap();
+            let advance = async {
+                while clock.sleepers() == 0 {
+                    tokio::task::yield_now().await;
+                }
+                clock.advance(Duration::from_secs(60));
+            };
+            let (stat, ()) = futures_util::future::join(connection.stat(), advance).await;
+            assert_eq!(1, stat.unwrap().message_count);
+            connection.quit().await.unwrap();
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_retries_retrieve_of_same_message() {
+        let (port, server) = test_util::serve_sessions(&[
+            &[("USER user", "+OK\r\n"), ("PASS secret", "+OK\r\n"), ("UIDL 1", "+OK 1 uid1\r\n")],
+            &[("USER user", "+OK\r\n"), ("PASS secret", "+OK\r\n"), ("UIDL 1", "+OK 1 uid1\r\n"),
+              ("RETR 1", "+OK\r\nSubject: hi\r\n.\r\n"), ("QUIT", "+OK\r\n")],
+        ]);
+
+        block_on(async {
+            let mut connection = RetryingPop3Connection::connect(account(port), policy()).await.unwrap();
+            assert_eq!(b"Subject: hi\r\n".to_vec(), connection.retrieve(1).await.unwrap());
+            assert_eq!(1, connection.reconnects());
+            connection.quit().await.unwrap();
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_does_not_retry_changed_message() {
+        let (port, server) = test_util::serve_sessions(&[
+            &[("USER user", "+OK\r\n"), ("PASS secret", "+OK\r\n"), ("UIDL 1", "+OK 1 uid1\r\n")],
+            &[("USER user", "+OK\r\n"), ("PASS secret", "+OK\r\n"), ("UIDL 1", "+OK 1 uid2\r\n")],
+        ]);
+
+        block_on(async {
+            let mut connection = RetryingPop3Connection::connect(account(port), policy()).await.unwrap();
+            let err = connection.retrieve(1).await.unwrap_err();
+            assert_eq!("message 1 changed its unique id after reconnect", err.to_string());
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_does_not_retry_without_unique_id() {
+        let (port, server) = test_util::serve_sessions(&[
+            &[("USER user", "+OK\r\n"), ("PASS secret", "+OK\r\n"), ("UIDL 1", "-ERR not supported\r\n")],
+        ]);
+
+        block_on(async {
+            let mut connection = RetryingPop3Connection::connect(account(port), policy()).await.unwrap();
+            assert!(connection.top_raw(1, 0).await.is_err());
+            assert_eq!(0, connection.reconnects());
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_never_retries_delete() {
+        let (port, server) = test_util::serve_sessions(&[
+            &[("USER user", "+OK\r\n"), ("PASS secret", "+OK\r\n")],
+        ]);
+
+        block_on(async {
+            let mut connection = RetryingPop3Connection::connect(account(port), policy()).await.unwrap();
+            assert!(connection.delete(1).await.is_err());
+            assert_eq!(0, connection.reconnects());
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = RetryPolicy::new()
+            .backoff(Duration::from_millis(100), Duration::from_millis(300))
+            .retry(OperationClass::Retrieve, false)
+            .retry(OperationClass::Mutation, true);
+        assert!(policy.is_retried(OperationClass::Query));
+        assert!(!policy.is_retried(OperationClass::Retrieve));
+        assert!(!policy.is_retried(OperationClass::Mutation));
+        assert_eq!(Duration::from_millis(200), policy.backoff_for(1));
+        assert_eq!(Duration::from_millis(300), policy.backoff_for(5));
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let transient: Pop3AsyncError = Box::new(crate::error::ConnectionLost("connection closed"));
+        assert!(is_transient(transient.as_ref()));
+        let temporary: Pop3AsyncError = Box::new(Pop3NegativeResponse::new("-ERR [SYS/TEMP] try again"));
+        assert!(is_transient(temporary.as_ref()));
+        let timeout: Pop3AsyncError = Box::new(std::io::Error::new(std::io::ErrorKind::TimedOut, "timeout"));
+        assert!(is_transient(timeout.as_ref()));
+        let permanent: Pop3AsyncError = Box::new(Pop3NegativeResponse::new("-ERR no such message"));
+        assert!(!is_transient(permanent.as_ref()));
+    }
+}
@@ -0,0 +1,203 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use async_net::TcpStream;
+use futures_rustls::TlsConnector;
+use futures_rustls::client::TlsStream;
+use futures_util::io::{AsyncRead, AsyncWrite};
+use rustls::RootCertStore;
+
+use crate::builder::native_root_store;
+use crate::session::{self, TlsInfo};
+use crate::stream;
+use crate::trace::OperationSpan;
+use crate::{AsyncPop3Connection, AsyncResolver, AsyncTimer, Pop3AsyncError, Pop3ConnectionBuilder, TlsMode};
+
+/// Async POP3 connection based on smol.
+///
+/// Since the connection uses `async-io`, it can be used by async-std as well.
+pub type SmolPop3Connection = AsyncPop3Connection<SmolStream>;
+
+/// Network stream of a smol based POP3 connection, either plain or TLS protected.
+pub struct SmolStream {
+    inner: SmolStreamKind,
+}
+
+enum SmolStreamKind {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl TlsInfo for SmolStream {
+    fn cipher_suite(&self) -> Option<String> {
+        match &self.inner {
+            SmolStreamKind::Plain(_) => None,
+            SmolStreamKind::Tls(stream) => stream.get_ref().1.negotiated_cipher_suite().map(session::cipher_suite_name),
+        }
+    }
+}
+
+impl AsyncRead for SmolStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        match &mut self.get_mut().inner {
+            SmolStreamKind::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            SmolStreamKind::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for SmolStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match &mut self.get_mut().inner {
+            SmolStreamKind::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            SmolStreamKind::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().inner {
+            SmolStreamKind::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            SmolStreamKind::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match &mut self.get_mut().inner {
+            SmolStreamKind::Plain(stream) => Pin::new(stream).poll_close(cx),
+            SmolStreamKind::Tls(stream) => Pin::new(stream.as_mut()).poll_close(cx),
+        }
+    }
+}
+
+/// Timer of the smol runtime, used to enforce timeouts.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SmolTimer;
+
+impl AsyncTimer for SmolTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move { async_io::Timer::after(duration).await; })
+    }
+}
+
+/// Wraps a TCP stream into TLS.
+async fn connect_tls(stream: TcpStream, host: &str, root_store: RootCertStore) -> Result<SmolStream, Pop3AsyncError> {
+    OperationSpan::tls_handshake(host).run(async {
+        let connector = TlsConnector::from(Arc::new(stream::client_config(root_store)));
+        let server_name = host.try_into()?;
+        let stream = connector.connect(server_name, stream).await?;
+        Ok(SmolStream { inner: SmolStreamKind::Tls(Box::new(stream)) })
+    }).await
+}
+
+impl AsyncPop3Connection<SmolStream> {
+
+    /// Returns a new POP3 connection using implicit TLS.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - IP-Address or host name of the POP3 server to connect
+    /// * `port` - Port of the POP3 server to connect
+    pub async fn new(host: &str, port: u16) -> Result<SmolPop3Connection, Pop3AsyncError> {
+        Pop3ConnectionBuilder::new(host)
+            .port(port)
+            .connect_smol()
+            .await
+    }
+
+    /// Returns a new POP3 connection using implicit TLS with custom certificates.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - IP-Address or host name of the POP3 server to connect
+    /// * `port` - Port of the POP3 server to connect
+    /// * `root_store` - Store of trusted (root) certificates.
+    pub async fn with_custom_certs(host: &str, port: u16, root_store: RootCertStore) -> Result<SmolPop3Connection, Pop3AsyncError> {
+        Pop3ConnectionBuilder::new(host)
+            .port(port)
+            .root_store(root_store)
+            .connect_smol()
+            .await
+    }
+
+    /// Connects to a POP3 server and reads its greeting.
+    ///
+    /// The connection uses the timer of the runtime to enforce timeouts.
+    ///
+    /// # Arguments
+    ///
+    /// * `host`       - IP-Address or host name of the POP3 server to connect
+    /// * `port`       - Port of the POP3 server to connect
+    /// * `tls_mode`   - transport layer security mode
+    /// * `root_store` - trusted certificates; `None` to use the system certificates
+    /// * `resolver`   - resolver of the host name; `None` to use the resolver of the runtime
+    pub(crate) async fn open(host: &str, port: u16, tls_mode: TlsMode, root_store: Option<RootCertStore>, resolver: Option<&dyn AsyncResolver>) -> Result<SmolPop3Connection, Pop3AsyncError> {
+        OperationSpan::connect(host, port).run(async {
+            let root_store = match (tls_mode, root_store) {
+                (TlsMode::Plain, _) => RootCertStore::empty(),
+                (_, Some(root_store)) => root_store,
+                (_, None) => native_root_store().map_err(|err| err.to_string())?
+            };
+
+            let stream = match resolver {
+                Some(resolver) => TcpStream::connect(&resolver.resolve(host, port).await?[..]).await?,
+                None => TcpStream::connect((host, port)).await?
+            };
+            let mut connection = match tls_mode {
+                TlsMode::Implicit => AsyncPop3Connection::from_stream(connect_tls(stream, host, root_store).await?).await,
+                TlsMode::StartTls => {
+                    let connection = AsyncPop3Connection::from_stream(SmolStream { inner: SmolStreamKind::Plain(stream) }).await?;
+                    connection.start_tls_with(|stream| async move {
+                        match stream.inner {
+                            SmolStreamKind::Plain(stream) => connect_tls(stream, host, root_store).await,
+                            SmolStreamKind::Tls(_) => Err("TLS already active".into())
+                        }
+                    }).await
+                },
+                TlsMode::Plain => AsyncPop3Connection::from_stream(SmolStream { inner: SmolStreamKind::Plain(stream) }).await
+            }?;
+            connection.set_timer(SmolTimer);
+
+            Ok(connection)
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_session() {
+        let (port, server) = test_util::serve(&[
+            ("USER user", "+OK\r\n"),
+            ("PASS secret", "+OK\r\n"),
+            ("STAT", "+OK 1 20\r\n"),
+            ("RETR 1", "+OK\r\nSubject: hi\r\n\r\n..dot\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        async_io::block_on(async {
+            let mut connection = Pop3ConnectionBuilder::new("127.0.0.1")
+                .tls_mode(TlsMode::Plain)
+                .port(port)
+                .login("user", "secret")
+                .connect_smol()
+                .await
+                .unwrap();
+
+            assert_eq!(1, connection.stat().await.unwrap().message_count);
+
+            let mut content = vec!();
+            connection.retrieve_raw(1, &mut content).await.unwrap();
+            assert_eq!(b"Subject: hi\r\n\r\n.dot\r\n".to_vec(), content);
+
+            connection.quit().await.unwrap();
+        });
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,307 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+
+use futures_util::io::{AsyncRead, AsyncWrite};
+use rustls::RootCertStore;
+use tokio::io::{AsyncRead as TokioRead, AsyncWrite as TokioWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+
+use crate::builder::native_root_store;
+use crate::session::{self, TlsInfo};
+use crate::stream;
+use crate::trace::OperationSpan;
+use crate::{AsyncPop3Connection, AsyncResolver, AsyncTimer, Pop3AsyncError, Pop3AsyncMessageReader, Pop3ConnectionBuilder, TlsMode};
+
+/// Async POP3 connection based on tokio.
+pub type TokioPop3Connection = AsyncPop3Connection<TokioStream>;
+
+/// Network stream of a tokio based POP3 connection, either plain or TLS protected.
+///
+/// Adapts the tokio I/O traits to the `futures` I/O traits used by
+/// [`AsyncPop3Connection`].
+pub struct TokioStream {
+    inner: TokioStreamKind,
+}
+
+enum TokioStreamKind {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl TokioStream {
+    fn io(&mut self) -> Pin<&mut (dyn TokioIo + Unpin)> {
+        match &mut self.inner {
+            TokioStreamKind::Plain(stream) => Pin::new(stream),
+            TokioStreamKind::Tls(stream) => Pin::new(stream.as_mut()),
+        }
+    }
+}
+
+impl TlsInfo for TokioStream {
+    fn cipher_suite(&self) -> Option<String> {
+        match &self.inner {
+            TokioStreamKind::Plain(_) => None,
+            TokioStreamKind::Tls(stream) => stream.get_ref().1.negotiated_cipher_suite().map(session::cipher_suite_name),
+        }
+    }
+}
+
+trait TokioIo: TokioRead + TokioWrite {}
+
+impl<T: TokioRead + TokioWrite> TokioIo for T {}
+
+impl AsyncRead for TokioStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let mut buf = ReadBuf::new(buf);
+        match self.get_mut().io().poll_read(cx, &mut buf) {
+            Poll::Ready(Ok(())) => Poll::Ready(Ok(buf.filled().len())),
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl AsyncWrite for TokioStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        self.get_mut().io().poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().io().poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().io().poll_shutdown(cx)
+    }
+}
+
+/// Allows to use the reader with tokio, e.g. by `tokio::io::copy`.
+impl<S: AsyncRead + Unpin> TokioRead for Pop3AsyncMessageReader<'_, S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let count = ready!(AsyncRead::poll_read(self, cx, buf.initialize_unfilled()))?;
+        buf.advance(count);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Timer of the tokio runtime, used to enforce timeouts.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioTimer;
+
+impl AsyncTimer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Wraps a TCP stream into TLS.
+async fn connect_tls(stream: TcpStream, host: &str, root_store: RootCertStore) -> Result<TokioStream, Pop3AsyncError> {
+    OperationSpan::tls_handshake(host).run(async {
+        let connector = TlsConnector::from(Arc::new(stream::client_config(root_store)));
+        let server_name = host.try_into()?;
+        let stream = connector.connect(server_name, stream).await?;
+        Ok(TokioStream { inner: TokioStreamKind::Tls(Box::new(stream)) })
+    }).await
+}
+
+impl AsyncPop3Connection<TokioStream> {
+
+    /// Returns a new POP3 connection using implicit TLS.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - IP-Address or host name of the POP3 server to connect
+    /// * `port` - Port of the POP3 server to connect
+    pub async fn new(host: &str, port: u16) -> Result<TokioPop3Connection, Pop3AsyncError> {
+        Pop3ConnectionBuilder::new(host)
+            .port(port)
+            .connect_async()
+            .await
+    }
+
+    /// Returns a new POP3 connection using implicit TLS with custom certificates.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - IP-Address or host name of the POP3 server to connect
+    /// * `port` - Port of the POP3 server to connect
+    /// * `root_store` - Store of trusted (root) certificates.
+    pub async fn with_custom_certs(host: &str, port: u16, root_store: RootCertStore) -> Result<TokioPop3Connection, Pop3AsyncError> {
+        Pop3ConnectionBuilder::new(host)
+            .port(port)
+            .root_store(root_store)
+            .connect_async()
+            .await
+    }
+
+    /// Connects to a POP3 server and reads its greeting.
+    ///
+    /// The connection uses the timer of the runtime to enforce timeouts.
+    ///
+    /// # Arguments
+    ///
+    /// * `host`       - IP-Address or host name of the POP3 server to connect
+    /// * `port`       - Port of the POP3 server to connect
+    /// * `tls_mode`   - transport layer security mode
+    /// * `root_store` - trusted certificates; `None` to use the system certificates
+    /// * `resolver`   - resolver of the host name; `None` to use the resolver of the runtime
+    pub(crate) async fn open(host: &str, port: u16, tls_mode: TlsMode, root_store: Option<RootCertStore>, resolver: Option<&dyn AsyncResolver>) -> Result<TokioPop3Connection, Pop3AsyncError> {
+        OperationSpan::connect(host, port).run(async {
+            let root_store = match (tls_mode, root_store) {
+                (TlsMode::Plain, _) => RootCertStore::empty(),
+                (_, Some(root_store)) => root_store,
+                (_, None) => native_root_store().map_err(|err| err.to_string())?
+            };
+
+            let stream = match resolver {
+                Some(resolver) => TcpStream::connect(&resolver.resolve(host, port).await?[..]).await?,
+                None => TcpStream::connect((host, port)).await?
+            };
+            let mut connection = match tls_mode {
+                TlsMode::Implicit => AsyncPop3Connection::from_stream(connect_tls(stream, host, root_store).await?).await,
+                TlsMode::StartTls => {
+                    let connection = AsyncPop3Connection::from_stream(TokioStream { inner: TokioStreamKind::Plain(stream) }).await?;
+                    connection.start_tls_with(|stream| async move {
+                        match stream.inner {
+                            TokioStreamKind::Plain(stream) => connect_tls(stream, host, root_store).await,
+                            TokioStreamKind::Tls(_) => Err("TLS already active".into())
+                        }
+                    }).await
+                },
+                TlsMode::Plain => AsyncPop3Connection::from_stream(TokioStream { inner: TokioStreamKind::Plain(stream) }).await
+            }?;
+            connection.set_timer(TokioTimer);
+
+            Ok(connection)
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+    use crate::Pop3MessageMeta;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn test_session() {
+        let (port, server) = test_util::serve(&[
+            ("USER user", "+OK\r\n"),
+            ("PASS secret", "+OK\r\n"),
+            ("LIST", "+OK\r\n1 20\r\n.\r\n"),
+            ("UIDL", "-ERR not supported\r\n"),
+            ("RETR 1", "+OK\r\nSubject: hi\r\n\r\n..dot\r\n.\r\n"),
+            ("RETR 1", "+OK\r\nSubject: hi\r\n\r\n..dot\r\n.\r\n"),
+            ("DELE 1", "+OK\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        block_on(async {
+            let mut connection = Pop3ConnectionBuilder::new("127.0.0.1")
+                .tls_mode(TlsMode::Plain)
+                .port(port)
+                .login("user", "secret")
+                .connect_async()
+                .await
+                .unwrap();
+
+            let messages = connection.list_meta().await.unwrap();
+            assert_eq!(vec!(Pop3MessageMeta { message_id: 1, message_size: 20, unique_id: None }), messages);
+
+            let mut content = vec!();
+            connection.retrieve_raw(1, &mut content).await.unwrap();
+            assert_eq!(b"Subject: hi\r\n\r\n.dot\r\n".to_vec(), content);
+
+            let mut reader = connection.retrieve_reader(1).await.unwrap();
+            let mut content = vec!();
+            tokio::io::copy(&mut reader, &mut content).await.unwrap();
+            assert_eq!(b"Subject: hi\r\n\r\n.dot\r\n".to_vec(), content);
+
+            connection.delete(1).await.unwrap();
+            connection.quit().await.unwrap();
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_builder_options() {
+        let (port, server) = test_util::serve(&[
+            ("RETR 1", "+OK\r\nSubject: hi\r\n.\r\n"),
+            ("LIST", "+OK\r\n1 13\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uid1\r\n.\r\n"),
+            ("RETR 1", "+OK\r\nSubject: hi\r\n\r\nHello\r\n.\r\n"),
+            ("RETR 1", "+OK\r\nSubject: hi\r\n\r\nHello\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        block_on(async {
+            let mut connection = Pop3ConnectionBuilder::new("127.0.0.1")
+                .tls_mode(TlsMode::Plain)
+                .port(port)
+                .strict_size_check(true)
+                .download_rate(1_000_000)
+                .connect_async()
+                .await
+                .unwrap();
+
+            let meta = Pop3MessageMeta { message_id: 1, message_size: 20, unique_id: None };
+            assert!(connection.retrieve_verified(&meta, &mut vec!()).await.is_err());
+
+            let mut state = crate::MemoryStateStore::default();
+            let mut fetched = vec!();
+            let count = connection.fetch_new_messages(&mut state, |message, _| {
+                fetched.push(message.message_id);
+                Ok(())
+            }).await.unwrap();
+            assert_eq!((1, vec!(1)), (count, fetched));
+
+            assert_eq!("Hello", connection.preview_text(1, 80).await.unwrap());
+            connection.quit().await.unwrap();
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_negative_response() {
+        let (port, server) = test_util::serve(&[
+            ("STAT", "-ERR not now\r\n"),
+        ]);
+
+        block_on(async {
+            let mut connection = TokioPop3Connection::open("127.0.0.1", port, TlsMode::Plain, None, None).await.unwrap();
+            let err = connection.stat().await.unwrap_err();
+            assert_eq!("-ERR not now", err.to_string());
+        });
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_custom_resolver() {
+        let (port, server) = test_util::serve(&[
+            ("STAT", "+OK 0 0\r\n"),
+        ]);
+
+        block_on(async {
+            let mut connection = Pop3ConnectionBuilder::new("pop.invalid")
+                .tls_mode(TlsMode::Plain)
+                .resolver(|host: String, port: u16| async move {
+                    assert_eq!("pop.invalid", host);
+                    Ok(vec!(std::net::SocketAddr::from(([127, 0, 0, 1], port))))
+                })
+                .port(port)
+                .connect_async().await.unwrap();
+            assert_eq!(0, connection.stat().await.unwrap().message_count);
+        });
+        server.join().unwrap();
+    }
+}
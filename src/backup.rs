@@ -0,0 +1,312 @@
+//! Backup of complete maildrops.
+//!
+//! A backup directory contains each message as `messages/<uid>.eml` file
+//! with its exact octets and a manifest `manifest.json`, which records
+//! unique id, size and SHA-256 hash of each message:
+//!
+//! ````json
+//! {"version":1,"messages":{"<uid>":{"file":"<uid>.eml","size":1234,"sha256":"..."}}}
+//! ````
+//!
+//! Each stored message is appended to the journal `manifest.journal`, which
+//! is merged into the manifest once the backup is complete. So an
+//! interrupted backup continues where it stopped when [`create`] is called
+//! again, without rewriting the manifest for each message.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::eml;
+use crate::json::{self, Value};
+use crate::sync::sha256_hex;
+use crate::Pop3Connection;
+
+const FILE_FORMAT_VERSION: u64 = 1;
+const MANIFEST_FILE: &str = "manifest.json";
+const JOURNAL_FILE: &str = "manifest.journal";
+const MESSAGES_DIR: &str = "messages";
+
+/// Manifest entry of a single message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupEntry {
+    /// unique id of the message
+    pub unique_id: String,
+
+    /// name of the message file in the `messages` directory
+    pub file_name: String,
+
+    /// size of the message in octets, as reported by the server
+    pub message_size: u32,
+
+    /// SHA-256 hash of the message as hex string
+    pub sha256: String,
+}
+
+/// Result of the verification of a backup.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BackupVerification {
+    /// unique ids of messages, which are intact
+    pub verified: Vec<String>,
+
+    /// unique ids of messages, whose file is missing
+    pub missing: Vec<String>,
+
+    /// unique ids of messages, whose file does not match the hash
+    pub corrupted: Vec<String>,
+}
+
+impl BackupVerification {
+
+    /// Returns true, if all messages are intact.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.corrupted.is_empty()
+    }
+}
+
+/// Stores all messages of the maildrop in a backup directory.
+///
+/// Messages, which are already contained in the backup, are skipped.
+/// Messages, which were removed from the server, stay in the backup.
+/// Returns the entries of all messages contained in the backup.
+///
+/// # Arguments
+///
+/// * `connection` - connection to the server
+/// * `path`       - path of the backup directory; a missing directory is created
+pub fn create(connection: &mut Pop3Connection, path: impl AsRef<Path>) -> Result<Vec<BackupEntry>, Box<dyn Error>> {
+    let path = path.as_ref();
+    let messages_path = path.join(MESSAGES_DIR);
+    fs::create_dir_all(&messages_path)?;
+    let mut entries = load(path)?;
+    let mut journal = OpenOptions::new().create(true).append(true).open(path.join(JOURNAL_FILE))?;
+
+    for meta in connection.list_meta()? {
+        let unique_id = meta.unique_id.ok_or("server does not provide unique ids")?;
+        if let Some(entry) = entries.get(&unique_id) {
+            if messages_path.join(&entry.file_name).exists() {
+                continue;
+            }
+        }
+
+        let mut content = vec!();
+        connection.retrieve_raw(meta.message_id, &mut content)?;
+        let file_name = eml::file_name(&unique_id);
+        write_atomic(&messages_path.join(&file_name), &content)?;
+
+        let entry = BackupEntry { unique_id: unique_id.clone(), file_name, message_size: meta.message_size, sha256: sha256_hex(&content) };
+        journal.write_all(format!("{}\n", entry_to_json(&entry)).as_bytes())?;
+        journal.sync_all()?;
+        entries.insert(unique_id, entry);
+    }
+
+    // compact the journal into the manifest
+    write_atomic(&path.join(MANIFEST_FILE), to_json(&entries).to_string().as_bytes())?;
+    drop(journal);
+    fs::remove_file(path.join(JOURNAL_FILE))?;
+
+    Ok(entries.into_values().collect())
+}
+
+/// Returns the entries of all messages contained in a backup.
+///
+/// # Arguments
+///
+/// * `path` - path of the backup directory
+pub fn read_manifest(path: impl AsRef<Path>) -> Result<Vec<BackupEntry>, Box<dyn Error>> {
+    Ok(load(path.as_ref())?.into_values().collect())
+}
+
+/// Checks each message of a backup against the hash recorded in the manifest.
+///
+/// # Arguments
+///
+/// * `path` - path of the backup directory
+pub fn verify(path: impl AsRef<Path>) -> Result<BackupVerification, Box<dyn Error>> {
+    let path = path.as_ref();
+    let mut result = BackupVerification::default();
+    for (unique_id, entry) in load(path)? {
+        match fs::read(path.join(MESSAGES_DIR).join(&entry.file_name)) {
+            Ok(content) if sha256_hex(&content) == entry.sha256 => result.verified.push(unique_id),
+            Ok(_) => result.corrupted.push(unique_id),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => result.missing.push(unique_id),
+            Err(err) => return Err(err.into())
+        }
+    }
+
+    Ok(result)
+}
+
+/// Loads the manifest and the entries of the journal of an interrupted backup.
+fn load(path: &Path) -> Result<BTreeMap<String, BackupEntry>, Box<dyn Error>> {
+    let mut entries = match read_optional(&path.join(MANIFEST_FILE))? {
+        Some(text) => from_json(&json::parse(&text)?)?,
+        None => BTreeMap::new()
+    };
+
+    for line in read_optional(&path.join(JOURNAL_FILE))?.unwrap_or_default().lines() {
+        // a line may be incomplete, if the backup was interrupted while writing it
+        if let Ok(value) = json::parse(line) {
+            let unique_id = value.get("uid").and_then(Value::as_str).ok_or("invalid journal")?;
+            entries.insert(unique_id.to_string(), entry_from_json(unique_id, &value)?);
+        }
+    }
+
+    Ok(entries)
+}
+
+fn read_optional(path: &Path) -> Result<Option<String>, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(Some(text)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into())
+    }
+}
+
+fn write_atomic(path: &Path, content: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut tmp_path = path.to_path_buf().into_os_string();
+    tmp_path.push(".tmp");
+
+    let result = File::create(&tmp_path)
+        .and_then(|mut file| {
+            file.write_all(content)?;
+            file.sync_all()
+        })
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if let Err(err) = result {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err.into());
+    }
+
+    Ok(())
+}
+
+fn to_json(entries: &BTreeMap<String, BackupEntry>) -> Value {
+    let messages = entries.iter().map(|(unique_id, entry)| {
+        (unique_id.clone(), Value::Object(entry_fields(entry)))
+    }).collect();
+
+    Value::Object(vec!(
+        ("version".into(), Value::Number(FILE_FORMAT_VERSION as f64)),
+        ("messages".into(), Value::Object(messages)),
+    ))
+}
+
+fn entry_fields(entry: &BackupEntry) -> Vec<(String, Value)> {
+    vec!(
+        ("file".into(), Value::String(entry.file_name.clone())),
+        ("size".into(), Value::Number(entry.message_size as f64)),
+        ("sha256".into(), Value::String(entry.sha256.clone())),
+    )
+}
+
+/// Returns the journal line of an entry, which contains the unique id in addition.
+fn entry_to_json(entry: &BackupEntry) -> Value {
+    let mut fields = vec!(("uid".into(), Value::String(entry.unique_id.clone())));
+    fields.extend(entry_fields(entry));
+    Value::Object(fields)
+}
+
+fn entry_from_json(unique_id: &str, entry: &Value) -> Result<BackupEntry, Box<dyn Error>> {
+    let file_name = entry.get("file").and_then(Value::as_str).ok_or("invalid manifest")?;
+    let message_size = entry.get("size").and_then(Value::as_u64).ok_or("invalid manifest")?;
+    let sha256 = entry.get("sha256").and_then(Value::as_str).ok_or("invalid manifest")?;
+    Ok(BackupEntry {
+        unique_id: unique_id.to_string(),
+        file_name: file_name.into(),
+        message_size: u32::try_from(message_size)?,
+        sha256: sha256.into(),
+    })
+}
+
+fn from_json(value: &Value) -> Result<BTreeMap<String, BackupEntry>, Box<dyn Error>> {
+    if value.get("version").and_then(Value::as_u64) != Some(FILE_FORMAT_VERSION) {
+        return Err("unsupported manifest version".into());
+    }
+
+    let messages = value.get("messages").and_then(Value::as_object).ok_or("invalid manifest")?;
+    let mut entries = BTreeMap::new();
+    for (unique_id, entry) in messages {
+        entries.insert(unique_id.clone(), entry_from_json(unique_id, entry)?);
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_create_resume_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let (mut connection, server) = test_util::connect(&[
+            ("LIST", "+OK\r\n1 5\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uid1\r\n.\r\n"),
+            ("RETR 1", "+OK\r\nabc\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+        let entries = create(&mut connection, dir.path()).unwrap();
+        connection.quit().unwrap();
+        server.join().unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(b"abc\r\n".to_vec(), fs::read(dir.path().join("messages/uid1.eml")).unwrap());
+
+        let (mut connection, server) = test_util::connect(&[
+            ("LIST", "+OK\r\n1 5\r\n2 5\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uid1\r\n2 uid2\r\n.\r\n"),
+            ("RETR 2", "+OK\r\ndef\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+        let entries = create(&mut connection, dir.path()).unwrap();
+        connection.quit().unwrap();
+        server.join().unwrap();
+        assert_eq!(vec!["uid1", "uid2"], entries.iter().map(|entry| entry.unique_id.as_str()).collect::<Vec<_>>());
+        assert_eq!(entries, read_manifest(dir.path()).unwrap());
+        assert!(verify(dir.path()).unwrap().is_ok());
+
+        fs::write(dir.path().join("messages/uid1.eml"), "changed").unwrap();
+        fs::remove_file(dir.path().join("messages/uid2.eml")).unwrap();
+        let verification = verify(dir.path()).unwrap();
+        assert_eq!(vec!["uid1"], verification.corrupted);
+        assert_eq!(vec!["uid2"], verification.missing);
+    }
+
+    #[test]
+    fn test_resume_from_journal() {
+        let dir = tempfile::tempdir().unwrap();
+
+        // the server fails after the first message, so the backup is interrupted
+        let (mut connection, server) = test_util::connect(&[
+            ("LIST", "+OK\r\n1 5\r\n2 5\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uidA\r\n2 uida\r\n.\r\n"),
+            ("RETR 1", "+OK\r\nabc\r\n.\r\n"),
+            ("RETR 2", "-ERR [SYS/TEMP] try again\r\n"),
+        ]);
+        assert!(create(&mut connection, dir.path()).is_err());
+        drop(connection);
+        server.join().unwrap();
+        assert!(!dir.path().join(MANIFEST_FILE).exists());
+        assert_eq!(vec!["uidA"], read_manifest(dir.path()).unwrap().iter().map(|entry| entry.unique_id.as_str()).collect::<Vec<_>>());
+
+        let (mut connection, server) = test_util::connect(&[
+            ("LIST", "+OK\r\n1 5\r\n2 5\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uidA\r\n2 uida\r\n.\r\n"),
+            ("RETR 2", "+OK\r\ndef\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+        let entries = create(&mut connection, dir.path()).unwrap();
+        connection.quit().unwrap();
+        server.join().unwrap();
+
+        assert!(!dir.path().join(JOURNAL_FILE).exists());
+        assert_eq!(entries, read_manifest(dir.path()).unwrap());
+        // unique ids differing in case are stored in distinct files
+        assert_ne!(entries[0].file_name.to_lowercase(), entries[1].file_name.to_lowercase());
+        assert!(verify(dir.path()).unwrap().is_ok());
+    }
+}
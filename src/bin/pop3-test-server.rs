@@ -0,0 +1,114 @@
+//! POP3 server serving a directory of `.eml` files, e.g. for end-to-end tests in CI.
+//!
+//! Requires the `test-util` feature:
+//!
+//! ```text
+//! cargo run --features test-util --bin pop3-test-server -- --dir tests/maildrop --port 1110
+//! ```
+
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::process;
+
+use rust_pop3_client::test_util::{MaildropServer, MaildropServerBuilder};
+use rust_pop3_client::TlsMode;
+
+const USAGE: &str = "\
+Usage: pop3-test-server --dir <path> [options]
+
+Serves the .eml files of a directory as maildrop on localhost.
+
+Options:
+    --dir <path>               directory of .eml files (required)
+    --port <port>              port to listen on (default: 1110)
+    --tls <mode>               plain, implicit or starttls (default: plain)
+    --cert <path>              writes the generated certificate in PEM format
+    --user <user>              accepts only this user (requires --password)
+    --password <password>      accepts only this password (requires --user)
+    --capa <list>              comma separated capabilities (default: USER,UIDL,TOP)
+    --fail <command>=<status>  answers a command always by a status line,
+                               e.g. RETR=-ERR [SYS/TEMP] try again later
+    --disconnect-after <count> closes each connection after count commands
+    --help                     prints this help";
+
+const OPTIONS: [&str; 9] = ["--dir", "--port", "--tls", "--cert", "--user", "--password", "--capa", "--fail", "--disconnect-after"];
+
+fn parse_tls_mode(value: &str) -> Result<TlsMode, Box<dyn Error>> {
+    match value {
+        "plain" => Ok(TlsMode::Plain),
+        "implicit" => Ok(TlsMode::Implicit),
+        "starttls" => Ok(TlsMode::StartTls),
+        _ => Err(format!("invalid TLS mode: {}", value).into())
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(MaildropServerBuilder, Option<String>), Box<dyn Error>> {
+    let mut builder = MaildropServer::builder().port(1110);
+    let mut dir = None;
+    let mut cert = None;
+    let mut user = None;
+    let mut password = None;
+
+    while let Some(arg) = args.next() {
+        if arg == "--help" {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        if !OPTIONS.contains(&arg.as_str()) {
+            return Err(format!("unknown option: {}", arg).into());
+        }
+        let value = args.next().ok_or_else(|| format!("missing value of {}", arg))?;
+        builder = match arg.as_str() {
+            "--dir" => { dir = Some(value); builder },
+            "--port" => builder.port(value.parse()?),
+            "--tls" => builder.tls_mode(parse_tls_mode(&value)?),
+            "--cert" => { cert = Some(value); builder },
+            "--user" => { user = Some(value); builder },
+            "--password" => { password = Some(value); builder },
+            "--capa" => builder.capabilities(&value.split(',').map(str::trim).collect::<Vec<_>>()),
+            "--fail" => {
+                let (command, status) = value.split_once('=').ok_or("expected --fail <command>=<status>")?;
+                builder.fail(command, status)
+            },
+            "--disconnect-after" => builder.disconnect_after(value.parse()?),
+            _ => unreachable!()
+        };
+    }
+
+    builder = match (user, password) {
+        (Some(user), Some(password)) => builder.credentials(&user, &password),
+        (None, None) => builder,
+        _ => return Err("--user and --password must be used together".into())
+    };
+    let dir = dir.ok_or("missing --dir")?;
+
+    Ok((builder.load_dir(dir)?, cert))
+}
+
+fn main() {
+    let (builder, cert) = match parse_args(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let server = match builder.start() {
+        Ok(server) => server,
+        Err(err) => {
+            eprintln!("error: failed to start server: {}", err);
+            process::exit(1);
+        }
+    };
+    if let Some(cert) = cert {
+        if let Err(err) = fs::write(&cert, server.certificate_pem()) {
+            eprintln!("error: failed to write certificate: {}", err);
+            process::exit(1);
+        }
+    }
+
+    println!("listening on 127.0.0.1:{} ({} messages)", server.port(), server.unique_ids().len());
+    server.wait();
+}
@@ -0,0 +1,313 @@
+//! Account profiles of the configuration file.
+//!
+//! ```toml
+//! default = "work"
+//!
+//! [accounts.work]
+//! host = "pop.example.com"
+//! tls = "starttls"
+//! user = "me@example.com"
+//!
+//! [accounts.work.sync]
+//! maildir = "~/Mail/work"
+//! delete = true
+//!
+//! [accounts.home]
+//! host = "pop.example.org"
+//! user = "me@example.org"
+//! keyring = true
+//! pipelining = true
+//!
+//! [accounts.gmail]
+//! host = "pop.gmail.com"
+//! user = "me@gmail.com"
+//! auth = "xoauth2"
+//!
+//! [accounts.gmail.oauth]
+//! provider = "google"
+//! client_id = "1234.apps.googleusercontent.com"
+//! client_secret = "secret"
+//! ```
+
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use toml_edit::{DocumentMut, Item, Table, value};
+
+use crate::TlsArg;
+
+/// Authentication mechanism of an account.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Auth {
+    /// USER and PASS
+    #[default]
+    User,
+
+    /// XOAUTH2; the password is used as OAuth 2.0 access token
+    Xoauth2,
+}
+
+/// Well-known OAuth 2.0 providers.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    /// Gmail
+    Google,
+
+    /// Outlook and Microsoft 365
+    Microsoft,
+}
+
+/// OAuth 2.0 client of an account, used by `pop3 login --oauth` and XOAUTH2.
+///
+/// Endpoints and scope default to those of the provider.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OAuthProfile {
+    pub provider: Option<Provider>,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub scope: Option<String>,
+    pub auth_url: Option<String>,
+    pub token_url: Option<String>,
+    pub device_url: Option<String>,
+
+    /// file of the refresh token [default: the keyring, if available, `~/.config/pop3/tokens/<user>@<host>` otherwise]
+    pub token_file: Option<PathBuf>,
+}
+
+/// Sync policy of an account, used by `pop3 fetch`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SyncProfile {
+    /// path of the Maildir
+    pub maildir: Option<PathBuf>,
+
+    /// file of the synchronization state
+    pub state: Option<PathBuf>,
+
+    /// deletes fetched messages from the server
+    #[serde(default)]
+    pub delete: bool,
+
+    /// maximum size of messages to fetch in octets
+    pub max_message_size: Option<u32>,
+
+    /// skips messages with the same content as a message fetched before
+    #[serde(default)]
+    pub skip_duplicates: bool,
+}
+
+/// Profile of an account; options of the command line take precedence.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    pub tls: Option<TlsArg>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+
+    /// the password is stored in the keyring, e.g. by `pop3 account add`
+    #[serde(default)]
+    pub keyring: bool,
+
+    /// send LIST and UIDL back-to-back, if the server announces PIPELINING
+    #[serde(default)]
+    pub pipelining: bool,
+
+    #[serde(default)]
+    pub auth: Auth,
+
+    #[serde(default)]
+    pub sync: SyncProfile,
+
+    pub oauth: Option<OAuthProfile>,
+}
+
+/// Configuration file, e.g. `~/.config/pop3/config.toml`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// account used without `--account`
+    pub default: Option<String>,
+
+    #[serde(default)]
+    pub accounts: BTreeMap<String, Profile>,
+}
+
+impl Config {
+
+    /// Parses a configuration in TOML format.
+    pub fn parse(text: &str) -> Result<Self, Box<dyn Error>> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Loads a configuration file.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path).map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        Self::parse(&text).map_err(|err| format!("invalid configuration {}: {}", path.display(), err).into())
+    }
+
+    /// Returns the profile of an account; without name, the profile of the default account, if any.
+    pub fn profile(&self, name: Option<&str>) -> Result<Option<&Profile>, Box<dyn Error>> {
+        match name.or(self.default.as_deref()) {
+            Some(name) => self.accounts.get(name).map(Some).ok_or_else(|| format!("unknown account: {}", name).into()),
+            None => Ok(None)
+        }
+    }
+}
+
+/// Adds an account, which uses the keyring, to a configuration file; other contents are kept.
+///
+/// An existing account of the same name is replaced.
+///
+/// # Arguments
+///
+/// * `path`    - path of the configuration file; it is created, if it does not exist
+/// * `name`    - name of the account
+/// * `profile` - host, port, TLS mode, user and authentication mechanism of the account
+/// * `default` - makes the account the default account
+pub fn add_account(path: &Path, name: &str, profile: &Profile, default: bool) -> Result<(), Box<dyn Error>> {
+    let mut document = match path.exists() {
+        true => fs::read_to_string(path)?.parse::<DocumentMut>().map_err(|err| format!("invalid configuration {}: {}", path.display(), err))?,
+        false => DocumentMut::new()
+    };
+
+    let mut account = Table::new();
+    if let Some(host) = &profile.host {
+        account["host"] = value(host);
+    }
+    if let Some(port) = profile.port {
+        account["port"] = value(port as i64);
+    }
+    if let Some(tls) = profile.tls.and_then(|tls| tls.to_possible_value()) {
+        account["tls"] = value(tls.get_name());
+    }
+    if let Some(user) = &profile.user {
+        account["user"] = value(user);
+    }
+    if let Some(auth) = profile.auth.to_possible_value().filter(|_| profile.auth != Auth::User) {
+        account["auth"] = value(auth.get_name());
+    }
+    account["keyring"] = value(true);
+
+    if default {
+        document["default"] = value(name);
+    }
+    let accounts = document.entry("accounts").or_insert_with(|| {
+        let mut accounts = Table::new();
+        accounts.set_implicit(true);
+        Item::Table(accounts)
+    });
+    accounts.as_table_mut().ok_or("invalid configuration: accounts is not a table")?.insert(name, Item::Table(account));
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, document.to_string())?;
+    Ok(())
+}
+
+/// Returns the directory of the configuration, e.g. `~/.config/pop3`.
+pub fn config_dir() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("pop3"))
+}
+
+/// Returns the default path of the configuration file, e.g. `~/.config/pop3/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("config.toml"))
+}
+
+/// Replaces a leading `~` of a path by the home directory.
+pub fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(r#"
+            default = "work"
+
+            [accounts.work]
+            host = "pop.example.com"
+            port = 1995
+            tls = "starttls"
+            user = "me@example.com"
+            auth = "xoauth2"
+
+            [accounts.work.sync]
+            maildir = "~/Mail/work"
+            delete = true
+
+            [accounts.home]
+            host = "pop.example.org"
+
+            [accounts.home.oauth]
+            provider = "microsoft"
+            client_id = "client"
+        "#).unwrap();
+
+        let work = config.profile(None).unwrap().unwrap();
+        assert_eq!(Some("pop.example.com"), work.host.as_deref());
+        assert_eq!(Some(1995), work.port);
+        assert!(matches!(work.tls, Some(TlsArg::StartTls)));
+        assert_eq!(Auth::Xoauth2, work.auth);
+        assert_eq!(Some(PathBuf::from("~/Mail/work")), work.sync.maildir);
+        assert!(work.sync.delete);
+
+        let home = config.profile(Some("home")).unwrap().unwrap();
+        assert_eq!(Auth::User, home.auth);
+        assert!(work.oauth.is_none());
+        let oauth = home.oauth.as_ref().unwrap();
+        assert_eq!(Some(Provider::Microsoft), oauth.provider);
+        assert_eq!("client", oauth.client_id);
+        assert!(!home.sync.delete);
+        assert!(config.profile(Some("other")).is_err());
+
+        assert!(Config::parse("[accounts.work]\nhots = \"typo\"").is_err());
+        assert!(!work.keyring);
+        assert!(Config::default().profile(None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_add_account() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pop3").join("config.toml");
+        let profile = Profile {
+            host: Some("pop.example.com".into()),
+            tls: Some(TlsArg::StartTls),
+            user: Some("me@example.com".into()),
+            ..Default::default()
+        };
+        add_account(&path, "work", &profile, false).unwrap();
+        fs::write(&path, format!("# my accounts\n{}", fs::read_to_string(&path).unwrap())).unwrap();
+        add_account(&path, "home", &Profile { auth: Auth::Xoauth2, ..profile }, true).unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("# my accounts\n[accounts.work]\n"));
+        let config = Config::parse(&text).unwrap();
+        let work = config.profile(Some("work")).unwrap().unwrap();
+        assert_eq!(Some("pop.example.com"), work.host.as_deref());
+        assert!(matches!(work.tls, Some(TlsArg::StartTls)));
+        assert!(work.keyring);
+        assert!(work.password.is_none());
+        let home = config.profile(None).unwrap().unwrap();
+        assert_eq!(Auth::Xoauth2, home.auth);
+    }
+}
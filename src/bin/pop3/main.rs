@@ -0,0 +1,1132 @@
+//! Command line POP3 client, e.g. for scripting and debugging.
+//!
+//! Requires the `cli` feature:
+//!
+//! ```text
+//! cargo run --features cli --bin pop3 -- --host pop.example.com --user me@example.com list
+//! ```
+//!
+//! Recurring accounts can be configured as profiles in `~/.config/pop3/config.toml`
+//! and selected by `--account`; see [`config`]. Accounts authenticating by XOAUTH2
+//! are authorized once by `pop3 login --oauth`; see [`oauth`].
+
+mod config;
+mod oauth;
+mod progress;
+mod report;
+mod shell;
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::ops::ControlFlow;
+use std::process::{self, ExitCode};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use config::{Auth, Config, Profile, SyncProfile};
+use progress::{FetchProgress, ProgressWriter};
+#[cfg(feature = "keyring")]
+use rust_pop3_client::credentials;
+use rust_pop3_client::{AttachmentFilter, JsonFileStateStore, MaildirSink, MboxSink, MemoryStateStore, MessageSink, Pop3Connection, Pop3ConnectionBuilder, Pop3Headers, Pop3MessageInfo, Pop3MessageSummary, Pop3MessageUidInfo, Pop3Proxy, Pop3Watcher, RuleMatcher, SyncOptions, SyncStateStore, TlsMode};
+
+/// File of the synchronization state within a Maildir, unless configured otherwise.
+const STATE_FILE: &str = ".pop3-state.json";
+
+/// Command line POP3 client.
+#[derive(Parser)]
+#[command(name = "pop3", version, about)]
+struct Cli {
+    #[command(flatten)]
+    account: AccountArgs,
+
+    /// prints results as JSON, e.g. for scripts
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Settings of the commands of a session.
+struct Context {
+    /// sync policy of the account profile
+    sync: SyncProfile,
+
+    /// prints results as JSON
+    json: bool,
+}
+
+/// Message printed by `top` and `retr` in JSON format.
+#[derive(Serialize)]
+struct MessageContent {
+    message_id: u32,
+    content: String,
+}
+
+/// Summary of a message printed by `purge` and `watch`.
+#[derive(Serialize)]
+struct MessagePreview<'a> {
+    message_id: u32,
+    message_size: u32,
+    unique_id: Option<&'a str>,
+    from: Option<&'a str>,
+    subject: Option<&'a str>,
+    date: Option<&'a str>,
+}
+
+impl<'a> From<&'a Pop3MessageSummary> for MessagePreview<'a> {
+    fn from(summary: &'a Pop3MessageSummary) -> Self {
+        MessagePreview {
+            message_id: summary.meta.message_id,
+            message_size: summary.meta.message_size,
+            unique_id: summary.meta.unique_id.as_deref(),
+            from: summary.from(),
+            subject: summary.subject(),
+            date: summary.date(),
+        }
+    }
+}
+
+impl fmt::Display for MessagePreview<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.message_id, self.message_size, self.from.unwrap_or("-"), self.subject.unwrap_or("-"))
+    }
+}
+
+/// Transport layer security mode; see [`TlsMode`].
+#[derive(Clone, Copy, Debug, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TlsArg {
+    /// TLS right after connecting (POP3S)
+    Implicit,
+
+    /// upgrade to TLS using STLS
+    #[value(name = "starttls")]
+    StartTls,
+
+    /// no encryption at all
+    Plain,
+}
+
+impl From<TlsArg> for TlsMode {
+    fn from(tls: TlsArg) -> Self {
+        match tls {
+            TlsArg::Implicit => TlsMode::Implicit,
+            TlsArg::StartTls => TlsMode::StartTls,
+            TlsArg::Plain => TlsMode::Plain,
+        }
+    }
+}
+
+/// Options of the account to connect; they take precedence over the account profile.
+#[derive(Args)]
+struct AccountArgs {
+    /// configuration file [default: ~/.config/pop3/config.toml]
+    #[arg(long, env = "POP3_CONFIG", global = true)]
+    config: Option<PathBuf>,
+
+    /// name of the account profile [default: the default account of the configuration file]
+    #[arg(long, env = "POP3_ACCOUNT", global = true)]
+    account: Option<String>,
+
+    /// IP-Address or host name of the POP3 server
+    #[arg(long, env = "POP3_HOST", global = true)]
+    host: Option<String>,
+
+    /// port of the POP3 server [default: 995 for implicit TLS, 110 otherwise]
+    #[arg(long, env = "POP3_PORT", global = true)]
+    port: Option<u16>,
+
+    /// transport layer security mode [default: implicit]
+    #[arg(long, value_enum, global = true)]
+    tls: Option<TlsArg>,
+
+    /// name of the user to login
+    #[arg(long, env = "POP3_USER", global = true)]
+    user: Option<String>,
+
+    /// password of the user
+    #[arg(long, env = "POP3_PASSWORD", hide_env_values = true, global = true)]
+    password: Option<String>,
+
+    /// SOCKS5 proxy to connect through, e.g. `127.0.0.1:1080`
+    #[arg(long, env = "POP3_SOCKS5", value_parser = parse_address, conflicts_with = "http_proxy", global = true)]
+    socks5: Option<(String, u16)>,
+
+    /// HTTP proxy to connect through using CONNECT, e.g. `proxy.example.com:3128`
+    #[arg(long, env = "POP3_HTTP_PROXY", value_parser = parse_address, global = true)]
+    http_proxy: Option<(String, u16)>,
+
+    /// credentials of the proxy as `user:password`
+    #[arg(long, env = "POP3_PROXY_AUTH", hide_env_values = true, global = true)]
+    proxy_auth: Option<String>,
+}
+
+impl AccountArgs {
+
+    /// Returns the selected account profile; an empty profile, if none is selected.
+    ///
+    /// A missing configuration file is only reported, if it or an account was requested explicitly.
+    fn profile(&self) -> Result<Profile, Box<dyn Error>> {
+        let config = match (&self.config, config::default_path()) {
+            (Some(path), _) => Config::load(path)?,
+            (None, Some(path)) if path.exists() => Config::load(&path)?,
+            _ => Config::default()
+        };
+
+        Ok(config.profile(self.account.as_deref())?.cloned().unwrap_or_default())
+    }
+
+    fn host<'a>(&'a self, profile: &'a Profile) -> Result<&'a str, Box<dyn Error>> {
+        Ok(self.host.as_ref().or(profile.host.as_ref()).ok_or("missing --host or POP3_HOST")?)
+    }
+
+    fn user<'a>(&'a self, profile: &'a Profile) -> Result<&'a str, Box<dyn Error>> {
+        Ok(self.user.as_ref().or(profile.user.as_ref()).ok_or("missing --user or POP3_USER")?)
+    }
+
+    /// Returns the proxy to connect through, if any.
+    fn proxy(&self) -> Result<Option<Pop3Proxy>, Box<dyn Error>> {
+        let proxy = match (&self.socks5, &self.http_proxy) {
+            (Some((host, port)), _) => Pop3Proxy::socks5(host, *port),
+            (None, Some((host, port))) => Pop3Proxy::http(host, *port),
+            (None, None) if self.proxy_auth.is_some() => return Err("--proxy-auth requires --socks5 or --http-proxy".into()),
+            (None, None) => return Ok(None)
+        };
+        Ok(Some(match &self.proxy_auth {
+            Some(auth) => {
+                let (user, password) = auth.split_once(':').ok_or("expected --proxy-auth user:password")?;
+                proxy.credentials(user, password)
+            },
+            None => proxy
+        }))
+    }
+
+    /// Returns a builder of authenticated connections.
+    ///
+    /// Without password, XOAUTH2 uses access tokens issued on each connect for the refresh token stored by `pop3 login --oauth`.
+    fn builder(&self, profile: &Profile) -> Result<Pop3ConnectionBuilder, Box<dyn Error>> {
+        let host = self.host(profile)?;
+        let user = self.user(profile)?;
+        let password = match (self.password.as_ref().or(profile.password.as_ref()), profile.keyring) {
+            (Some(password), _) => Some(password.clone()),
+            (None, true) => Some(keyring_password(host, user)?),
+            (None, false) => None
+        };
+        let tls = self.tls.or(profile.tls).unwrap_or(TlsArg::Implicit);
+
+        let mut builder = Pop3ConnectionBuilder::new(host).tls_mode(tls.into()).pipelining(profile.pipelining);
+        if let Some(port) = self.port.or(profile.port) {
+            builder = builder.port(port);
+        }
+        if let Some(proxy) = self.proxy()? {
+            builder = builder.proxy(proxy);
+        }
+        Ok(match (profile.auth, password, &profile.oauth) {
+            (Auth::User, Some(password), _) => builder.login(user, &password),
+            (Auth::Xoauth2, Some(access_token), _) => builder.login_oauth2(user, &access_token),
+            (Auth::Xoauth2, None, Some(oauth)) => {
+                // refresh on each connect, so that reconnects, e.g. of `watch`, do not use an expired token
+                let (settings, host, token_user) = (oauth.clone(), host.to_string(), user.to_string());
+                builder.login_oauth2_with(user, Arc::new(move || oauth::access_token(&settings, &host, &token_user)))
+            },
+            (_, None, _) => return Err("missing --password or POP3_PASSWORD".into())
+        })
+    }
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints count and total size of the messages
+    Stat,
+
+    /// Prints id and size of all messages or of a given message
+    List {
+        /// id of the message
+        id: Option<u32>,
+    },
+
+    /// Prints id and unique id of all messages or of a given message
+    Uidl {
+        /// id of the message
+        id: Option<u32>,
+    },
+
+    /// Prints total size, size and age distribution, largest messages and largest senders
+    Report,
+
+    /// Prints the header and the first lines of the body of a message
+    Top {
+        /// id of the message
+        id: u32,
+
+        /// count of body lines
+        #[arg(default_value_t = 0)]
+        lines: u32,
+    },
+
+    /// Prints a message
+    Retr {
+        /// id of the message
+        id: u32,
+    },
+
+    /// Deletes messages
+    Dele {
+        /// ids of the messages
+        #[arg(required = true)]
+        ids: Vec<u32>,
+    },
+
+    /// Deletes all messages or the messages matching all filters
+    ///
+    /// Without --yes, the messages are only listed.
+    Purge {
+        /// sender address or glob pattern, e.g. `*@spammer.example`; may be repeated
+        #[arg(long)]
+        from: Vec<String>,
+
+        /// minimum age according to the Date header, e.g. `30d`; units are s, m, h, d and w
+        #[arg(long, value_parser = parse_age)]
+        older_than: Option<Duration>,
+
+        /// minimum size, e.g. `5M`; units are K, M and G
+        #[arg(long, value_parser = parse_size)]
+        larger_than: Option<u32>,
+
+        /// lists the messages, which would be deleted
+        #[arg(long, conflicts_with = "yes")]
+        dry_run: bool,
+
+        /// deletes the messages without listing them
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Retrieves messages, which were not fetched before, into a Maildir
+    Fetch {
+        /// path of the Maildir; missing directories are created
+        #[arg(long)]
+        maildir: Option<PathBuf>,
+
+        /// deletes fetched messages from the server
+        #[arg(long)]
+        delete: bool,
+
+        /// file of the synchronization state [default: .pop3-state.json within the Maildir]
+        #[arg(long)]
+        state: Option<PathBuf>,
+    },
+
+    /// Appends messages to an mbox file, e.g. for backups
+    Export {
+        /// path of the mbox file; it is created, if it does not exist
+        #[arg(long)]
+        mbox: PathBuf,
+
+        /// file of a synchronization state; only messages, which were not exported before, are appended
+        #[arg(long)]
+        since_uidl_state: Option<PathBuf>,
+    },
+
+    /// Saves the matching attachments of all messages and prints their paths
+    ExtractAttachments {
+        /// pattern of the file names, e.g. `*.pdf`; may be repeated
+        #[arg(long = "name")]
+        names: Vec<String>,
+
+        /// pattern of the MIME types, e.g. `image/*`; may be repeated
+        #[arg(long = "type")]
+        content_types: Vec<String>,
+
+        /// template of the directory with the placeholders {id}, {uid}, {from} and {date}
+        #[arg(long, default_value = "attachments/{from}/{date}")]
+        dir: String,
+    },
+
+    /// Authorizes the account and stores the refresh token used by XOAUTH2
+    Login {
+        /// runs the OAuth 2.0 flow configured in the `oauth` section of the account profile
+        #[arg(long, required = true)]
+        oauth: bool,
+
+        /// uses a code entered on another device instead of a browser redirected to a local port
+        #[arg(long)]
+        device: bool,
+    },
+
+    /// Polls for new messages and prints them or runs a command for each
+    Watch {
+        /// seconds between checks
+        #[arg(long, default_value_t = 60)]
+        interval: u64,
+
+        /// command run by the shell for each new message; the message is described
+        /// by POP3_MESSAGE_ID, POP3_UID, POP3_SIZE, POP3_FROM, POP3_SUBJECT and POP3_DATE
+        #[arg(long)]
+        exec: Option<String>,
+    },
+
+    /// Runs commands interactively in one session, e.g. `top 3 10` or `retr 5 > message.eml`
+    Shell,
+
+    /// Manages account profiles of the configuration file
+    Account {
+        #[command(subcommand)]
+        command: AccountCommand,
+    },
+
+    /// Prints the completion script of a shell, e.g. `pop3 completions bash > /etc/bash_completion.d/pop3`
+    Completions {
+        /// shell to complete commands in
+        shell: clap_complete::Shell,
+    },
+
+    /// Prints the man page in roff format, e.g. `pop3 man > /usr/local/share/man/man1/pop3.1`
+    Man,
+}
+
+#[derive(Subcommand)]
+enum AccountCommand {
+    /// Adds an account, e.g. `pop3 --host pop.example.com --user me@example.com account add work`
+    ///
+    /// The password is read from --password, POP3_PASSWORD or the terminal and stored in the keyring.
+    Add {
+        /// name of the account
+        name: String,
+
+        /// authentication mechanism; the password of XOAUTH2 is an access token
+        #[arg(long, value_enum, default_value_t = Auth::User)]
+        auth: Auth,
+
+        /// makes the account the default account
+        #[arg(long)]
+        default: bool,
+    },
+}
+
+/// Runs a command in a new session.
+fn run(cli: Cli, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Command::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "pop3", out);
+            return Ok(());
+        },
+        Command::Man => return Ok(clap_mangen::Man::new(Cli::command()).render(out)?),
+        Command::Account { command: AccountCommand::Add { name, auth, default } } => return add_account(&cli.account, &name, auth, default, cli.json, out),
+        _ => { }
+    }
+    let profile = cli.account.profile()?;
+    match cli.command {
+        Command::Login { device, .. } => return login(&cli.account, &profile, device, cli.json, out),
+        Command::Watch { interval, exec } => return watch(&cli.account, &profile, Duration::from_secs(interval), exec.as_deref(), cli.json, out),
+        _ => { }
+    }
+    let mut connection = cli.account.builder(&profile)?.connect()?;
+    let context = Context { sync: profile.sync, json: cli.json };
+    execute(&mut connection, cli.command, &context, out)?;
+    match connection.is_open() {
+        true => connection.quit(),
+        false => Ok(())
+    }
+}
+
+/// Adds an account profile to the configuration file and stores its password in the keyring.
+fn add_account(account: &AccountArgs, name: &str, auth: Auth, default: bool, json: bool, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    let path = account.config.clone().or_else(config::default_path).ok_or("missing --config or POP3_CONFIG")?;
+    let profile = Profile {
+        host: Some(account.host.clone().ok_or("missing --host or POP3_HOST")?),
+        port: account.port,
+        tls: account.tls,
+        user: Some(account.user.clone().ok_or("missing --user or POP3_USER")?),
+        auth,
+        ..Default::default()
+    };
+    let password = match &account.password {
+        Some(password) => password.clone(),
+        None => rpassword::prompt_password(format!("password of {}: ", name))?
+    };
+
+    store_keyring_password(profile.host.as_deref().unwrap_or_default(), profile.user.as_deref().unwrap_or_default(), &password)?;
+    config::add_account(&path, name, &profile, default)?;
+    match json {
+        true => write_json(out, &serde_json::json!({ "account": name, "config": path })),
+        false => Ok(writeln!(out, "added account {} to {}; the password is stored in the keyring", name, path.display())?)
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_password(host: &str, user: &str) -> Result<String, Box<dyn Error>> {
+    credentials::load_password(host, user).map_err(|err| format!("failed to load the password of {} from the keyring: {}", user, err).into())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn keyring_password(_host: &str, _user: &str) -> Result<String, Box<dyn Error>> {
+    Err("passwords in the keyring require the keyring feature".into())
+}
+
+#[cfg(feature = "keyring")]
+fn store_keyring_password(host: &str, user: &str, password: &str) -> Result<(), Box<dyn Error>> {
+    credentials::store_password(host, user, password).map_err(|err| format!("failed to store the password in the keyring: {}", err).into())
+}
+
+#[cfg(not(feature = "keyring"))]
+fn store_keyring_password(_host: &str, _user: &str, _password: &str) -> Result<(), Box<dyn Error>> {
+    Err("passwords in the keyring require the keyring feature".into())
+}
+
+/// Runs the OAuth 2.0 flow of an account and stores the refresh token.
+fn login(account: &AccountArgs, profile: &Profile, device: bool, json: bool, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    let host = account.host(profile)?;
+    let user = account.user(profile)?;
+    let settings = profile.oauth.as_ref().ok_or("missing oauth section in the account profile")?;
+    let client = oauth::Client::new(settings)?;
+    let tokens = match device {
+        true => client.authorize_device(|code| eprintln!("To authorize pop3, open {} and enter the code {}", code.verification_uri, code.user_code))?,
+        false => client.authorize_redirect(|url| eprintln!("To authorize pop3, open the following URL in a browser:\n\n{}\n", url))?
+    };
+    let refresh_token = tokens.refresh_token.ok_or("no refresh token issued; check the scope of the account profile")?;
+    oauth::TokenStore::new(settings, host, user)?.store(&refresh_token)?;
+    match json {
+        true => write_json(out, &serde_json::json!({ "user": user, "host": host })),
+        false => Ok(writeln!(out, "authorized {} at {}", user, host)?)
+    }
+}
+
+/// Polls for new messages until an error occurs.
+fn watch(account: &AccountArgs, profile: &Profile, interval: Duration, exec: Option<&str>, json: bool, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    let mut watcher = Pop3Watcher::new(account.builder(profile)?);
+    let mut result = Ok(());
+    watcher.poll(interval, |messages| {
+        result = messages.iter().try_for_each(|message| notify(message, exec, json, out));
+        match result {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(())
+        }
+    })?;
+    result
+}
+
+/// Prints a new message or runs the command of `watch --exec` for it.
+///
+/// A failing command is reported, but does not stop watching.
+fn notify(message: &Pop3MessageSummary, exec: Option<&str>, json: bool, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    let preview = MessagePreview::from(message);
+    let command = match exec {
+        Some(command) => command,
+        None => {
+            match json {
+                true => write_json(out, &preview)?,
+                false => writeln!(out, "{}", preview)?
+            }
+            return Ok(out.flush()?);
+        }
+    };
+
+    #[cfg(windows)]
+    let mut shell = process::Command::new("cmd");
+    #[cfg(windows)]
+    shell.arg("/C");
+    #[cfg(not(windows))]
+    let mut shell = process::Command::new("sh");
+    #[cfg(not(windows))]
+    shell.arg("-c");
+
+    let status = shell.arg(command)
+        .env("POP3_MESSAGE_ID", preview.message_id.to_string())
+        .env("POP3_UID", preview.unique_id.unwrap_or_default())
+        .env("POP3_SIZE", preview.message_size.to_string())
+        .env("POP3_FROM", preview.from.unwrap_or_default())
+        .env("POP3_SUBJECT", preview.subject.unwrap_or_default())
+        .env("POP3_DATE", preview.date.unwrap_or_default())
+        .stdin(process::Stdio::null())
+        .status()
+        .map_err(|err| format!("failed to run {}: {}", command, err))?;
+    if !status.success() {
+        eprintln!("error: command failed for message {}: {}", preview.message_id, status);
+    }
+    Ok(())
+}
+
+fn execute(connection: &mut Pop3Connection, command: Command, context: &Context, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    let sync = &context.sync;
+    match command {
+        Command::Stat => {
+            let stat = connection.stat()?;
+            match context.json {
+                true => write_json(out, &stat)?,
+                false => writeln!(out, "{} messages ({} octets)", stat.message_count, stat.maildrop_size)?
+            }
+        },
+        Command::List { id } => {
+            let infos = match id {
+                Some(id) => vec!(Pop3MessageInfo { message_id: id, message_size: connection.get_message_size(id)? }),
+                None => connection.list()?
+            };
+            match (context.json, id) {
+                (true, Some(_)) => write_json(out, &infos[0])?,
+                (true, None) => write_json(out, &infos)?,
+                (false, _) => for info in infos {
+                    writeln!(out, "{} {}", info.message_id, info.message_size)?;
+                }
+            }
+        },
+        Command::Uidl { id } => {
+            let infos = match id {
+                Some(id) => vec!(Pop3MessageUidInfo { message_id: id, unique_id: connection.get_unique_id(id)? }),
+                None => connection.list_unique_ids()?
+            };
+            match (context.json, id) {
+                (true, Some(_)) => write_json(out, &infos[0])?,
+                (true, None) => write_json(out, &infos)?,
+                (false, _) => for info in infos {
+                    writeln!(out, "{} {}", info.message_id, info.unique_id)?;
+                }
+            }
+        },
+        Command::Top { id, lines } => {
+            let content = connection.top_raw(id, lines)?;
+            match context.json {
+                true => write_json(out, &MessageContent { message_id: id, content: String::from_utf8_lossy(&content).into_owned() })?,
+                false => out.write_all(&content)?
+            }
+        },
+        Command::Retr { id } if context.json => {
+            let mut content = vec!();
+            retrieve(connection, id, &mut content)?;
+            write_json(out, &MessageContent { message_id: id, content: String::from_utf8_lossy(&content).into_owned() })?;
+        },
+        Command::Retr { id } => retrieve(connection, id, out)?,
+        Command::Dele { ids } => {
+            for id in &ids {
+                connection.delete(*id)?;
+            }
+            if context.json {
+                write_json(out, &serde_json::json!({ "deleted": ids }))?;
+            }
+        },
+        Command::Purge { from, older_than, larger_than, dry_run, yes } => {
+            let mut filters = vec!();
+            if !from.is_empty() {
+                filters.push(RuleMatcher::Any(from.into_iter().map(RuleMatcher::FromAddress).collect()));
+            }
+            filters.extend(older_than.map(RuleMatcher::OlderThan));
+            filters.extend(larger_than.map(RuleMatcher::LargerThan));
+
+            // headers are only retrieved, if they are filtered or listed
+            let summaries = match yes && filters.iter().all(|filter| matches!(filter, RuleMatcher::LargerThan(_))) {
+                true => connection.list_meta()?.into_iter().map(|meta| Pop3MessageSummary { meta, headers: Pop3Headers::default() }).collect(),
+                false => connection.summaries()?
+            };
+            let matcher = RuleMatcher::All(filters);
+            let matched: Vec<_> = summaries.iter().filter(|summary| matcher.matches(summary)).collect();
+
+            if !yes {
+                let previews: Vec<_> = matched.iter().map(|summary| MessagePreview::from(*summary)).collect();
+                match context.json {
+                    true => write_json(out, &serde_json::json!({ "matched": previews }))?,
+                    false => for preview in &previews {
+                        writeln!(out, "{}", preview)?;
+                    }
+                }
+                return match dry_run {
+                    true => Ok(()),
+                    false => Err(format!("purge would delete {} messages; repeat with --yes to delete them", matched.len()).into())
+                };
+            }
+
+            let mut transaction = connection.transaction();
+            for summary in &matched {
+                transaction.delete(summary.meta.message_id)?;
+            }
+            transaction.commit()?;
+            match context.json {
+                true => write_json(out, &serde_json::json!({ "deleted": matched.len() }))?,
+                false => writeln!(out, "deleted {} messages", matched.len())?
+            }
+        },
+        Command::Report => {
+            let usage = connection.usage_report()?;
+            let summaries = connection.summaries()?;
+            let report = report::Report::new(usage, &summaries, SystemTime::now());
+            match context.json {
+                true => write_json(out, &report)?,
+                false => report.write_text(out)?
+            }
+        },
+        Command::Fetch { maildir, delete, state } => {
+            let maildir = maildir.or_else(|| sync.maildir.as_deref().map(config::expand_home)).ok_or("missing --maildir")?;
+            let state = state.or_else(|| sync.state.as_deref().map(config::expand_home));
+            let mut state = JsonFileStateStore::new(state.unwrap_or_else(|| maildir.join(STATE_FILE)));
+            let mut maildir = MaildirSink::new(maildir)?;
+            let options = SyncOptions::new()
+                .delete_after_fetch(delete || sync.delete)
+                .max_message_size(sync.max_message_size)
+                .skip_duplicates(sync.skip_duplicates);
+            let count = fetch_into(connection, &mut state, &options, sync.max_message_size, &mut maildir)?;
+            match context.json {
+                true => write_json(out, &serde_json::json!({ "fetched": count }))?,
+                false => writeln!(out, "fetched {} messages", count)?
+            }
+        },
+        Command::Export { mbox, since_uidl_state } => {
+            let mut sink = MboxSink::new(mbox);
+            let count = match since_uidl_state {
+                Some(state) => fetch_into(connection, &mut JsonFileStateStore::new(state), &SyncOptions::new(), None, &mut sink)?,
+                None => fetch_into(connection, &mut MemoryStateStore::new(), &SyncOptions::new(), None, &mut sink)?
+            };
+            match context.json {
+                true => write_json(out, &serde_json::json!({ "exported": count }))?,
+                false => writeln!(out, "exported {} messages", count)?
+            }
+        },
+        Command::ExtractAttachments { names, content_types, dir } => {
+            let filter = names.iter().fold(AttachmentFilter::new(), |filter, name| filter.name(name));
+            let filter = content_types.iter().fold(filter, |filter, content_type| filter.content_type(content_type));
+            let paths = connection.save_all_attachments(&filter, &dir)?;
+            match context.json {
+                true => write_json(out, &serde_json::json!({ "saved": paths }))?,
+                false => for path in paths {
+                    writeln!(out, "{}", path.display())?;
+                }
+            }
+        },
+        Command::Login { .. } | Command::Watch { .. } | Command::Account { .. } | Command::Completions { .. } | Command::Man => return Err("not available within a session".into()),
+        Command::Shell => shell::run(connection, context, out)?
+    }
+
+    Ok(())
+}
+
+/// Writes a value as a line of JSON.
+/// Parses an address like `proxy.example.com:3128` or `[::1]:1080`.
+fn parse_address(value: &str) -> Result<(String, u16), String> {
+    let (host, port) = value.rsplit_once(':').ok_or("expected host:port")?;
+    let host = host.strip_prefix('[').and_then(|host| host.strip_suffix(']')).unwrap_or(host);
+    let port = port.parse().map_err(|_| format!("invalid port: {}", port))?;
+    match host.is_empty() {
+        true => Err("expected host:port".into()),
+        false => Ok((host.to_string(), port))
+    }
+}
+
+/// Parses an age like `30d`; units are s, m, h, d and w.
+fn parse_age(value: &str) -> Result<Duration, String> {
+    let unit = match value.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        Some('w') => 7 * 24 * 60 * 60,
+        _ => return Err("expected a number followed by s, m, h, d or w, e.g. 30d".into())
+    };
+    let count: u64 = value[..value.len() - 1].parse().map_err(|_| format!("invalid age: {}", value))?;
+    Ok(Duration::from_secs(count * unit))
+}
+
+/// Parses a size like `5M`; units are K, M and G.
+fn parse_size(value: &str) -> Result<u32, String> {
+    let (count, unit) = match value.char_indices().last() {
+        Some((index, 'K' | 'k')) => (&value[..index], 1 << 10),
+        Some((index, 'M' | 'm')) => (&value[..index], 1 << 20),
+        Some((index, 'G' | 'g')) => (&value[..index], 1 << 30),
+        _ => (value, 1)
+    };
+    count.parse::<u32>().ok()
+        .and_then(|count| count.checked_mul(unit))
+        .ok_or_else(|| format!("invalid size: {}", value))
+}
+
+/// Delivers messages, which were not fetched before, to a sink and shows the progress.
+fn fetch_into(connection: &mut Pop3Connection, state: &mut dyn SyncStateStore, options: &SyncOptions, max_message_size: Option<u32>, sink: &mut dyn MessageSink) -> Result<usize, Box<dyn Error>> {
+    let mut progress = match progress::enabled() {
+        true => {
+            let (count, size) = progress::pending(&connection.list_meta()?, &state.load()?, max_message_size);
+            FetchProgress::new(count, size)
+        },
+        false => FetchProgress::hidden()
+    };
+    connection.fetch_new_messages_with(state, options, |message, content| {
+        progress.fetched(message);
+        sink.deliver(message, content)
+    })
+}
+
+/// Retrieves a message and shows the progress.
+fn retrieve(connection: &mut Pop3Connection, id: u32, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    match progress::enabled() {
+        true => {
+            let bar = progress::message_bar(connection.get_message_size(id)? as u64);
+            connection.retrieve_raw(id, &mut ProgressWriter::new(out, bar))
+        },
+        false => connection.retrieve_raw(id, out)
+    }
+}
+
+fn write_json(out: &mut impl Write, value: &impl Serialize) -> Result<(), Box<dyn Error>> {
+    serde_json::to_writer(&mut *out, value)?;
+    writeln!(out)?;
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let json = cli.json;
+    match run(cli, &mut io::stdout().lock()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            match json {
+                true => eprintln!("{}", serde_json::json!({ "error": err.to_string() })),
+                false => eprintln!("error: {}", err)
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from(["pop3", "top", "3", "10", "--host", "pop.example.com", "--tls", "starttls"]).unwrap();
+        assert!(matches!(cli.command, Command::Top { id: 3, lines: 10 }));
+        assert_eq!(Some("pop.example.com"), cli.account.host.as_deref());
+        assert!(matches!(cli.account.tls, Some(TlsArg::StartTls)));
+
+        assert!(Cli::try_parse_from(["pop3", "dele"]).is_err());
+        assert!(Cli::try_parse_from(["pop3", "purge", "--dry-run", "--yes"]).is_err());
+        let cli = Cli::try_parse_from(["pop3", "account", "add", "work", "--auth", "xoauth2", "--default"]).unwrap();
+        assert!(matches!(cli.command, Command::Account { command: AccountCommand::Add { auth: Auth::Xoauth2, default: true, .. } }));
+
+        assert_eq!(Ok(("::1".to_string(), 1080)), parse_address("[::1]:1080"));
+        assert_eq!(Ok(("proxy.example.com".to_string(), 3128)), parse_address("proxy.example.com:3128"));
+        assert!(parse_address("proxy.example.com").is_err());
+        assert!(parse_address(":3128").is_err());
+        assert!(Cli::try_parse_from(["pop3", "stat", "--socks5", "a:1", "--http-proxy", "b:2"]).is_err());
+
+        assert_eq!(Ok(Duration::from_secs(30 * 24 * 60 * 60)), parse_age("30d"));
+        assert_eq!(Ok(Duration::from_secs(90)), parse_age("90s"));
+        assert!(parse_age("30").is_err());
+        assert!(parse_age("d").is_err());
+        assert_eq!(Ok(5 * 1024 * 1024), parse_size("5M"));
+        assert_eq!(Ok(100), parse_size("100"));
+        assert!(parse_size("5T").is_err());
+        assert!(parse_size("8G").is_err());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_commands() {
+        use rust_pop3_client::test_util::MaildropServer;
+
+        let server = MaildropServer::builder()
+            .message("a", b"Subject: first\r\n\r\nHello\r\n")
+            .message("b", b"Subject: second\r\n\r\nWorld\r\n")
+            .start()
+            .unwrap();
+        let port = server.port().to_string();
+        let invoke = |args: &[&str]| {
+            let mut command = vec!("pop3", "--host", "127.0.0.1", "--port", &port, "--tls", "plain", "--user", "me", "--password", "secret");
+            command.extend_from_slice(args);
+            let mut out = vec!();
+            run(Cli::try_parse_from(command).unwrap(), &mut out).map(|()| String::from_utf8(out).unwrap())
+        };
+
+        assert_eq!("2 messages (51 octets)\n", invoke(&["stat"]).unwrap());
+        assert_eq!("1 a\n2 b\n", invoke(&["uidl"]).unwrap());
+        assert_eq!("2 26\n", invoke(&["list", "2"]).unwrap());
+        assert_eq!("Subject: second\r\n\r\nWorld\r\n", invoke(&["retr", "2"]).unwrap());
+        assert_eq!("Subject: first\r\n\r\n", invoke(&["top", "1"]).unwrap());
+
+        invoke(&["dele", "1"]).unwrap();
+        assert_eq!(vec!("b"), server.unique_ids());
+        assert!(invoke(&["purge"]).is_err());
+        assert_eq!("deleted 1 messages\n", invoke(&["purge", "--yes"]).unwrap());
+        assert!(server.unique_ids().is_empty());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_json() {
+        use rust_pop3_client::test_util::MaildropServer;
+
+        let server = MaildropServer::builder()
+            .message("a", b"Subject: first\r\n\r\nHello\r\n")
+            .message("b", b"Subject: second\r\n\r\nWorld\r\n")
+            .start()
+            .unwrap();
+        let port = server.port().to_string();
+        let invoke = |args: &[&str]| {
+            let mut command = vec!("pop3", "--host", "127.0.0.1", "--port", &port, "--tls", "plain", "--user", "me", "--password", "secret", "--json");
+            command.extend_from_slice(args);
+            let mut out = vec!();
+            run(Cli::try_parse_from(command).unwrap(), &mut out).unwrap();
+            serde_json::from_slice::<serde_json::Value>(&out).unwrap()
+        };
+
+        assert_eq!(serde_json::json!({ "message_count": 2, "maildrop_size": 51 }), invoke(&["stat"]));
+        assert_eq!(serde_json::json!([{ "message_id": 1, "unique_id": "a" }, { "message_id": 2, "unique_id": "b" }]), invoke(&["uidl"]));
+        assert_eq!(serde_json::json!({ "message_id": 2, "message_size": 26 }), invoke(&["list", "2"]));
+        assert_eq!(serde_json::json!({ "message_id": 1, "content": "Subject: first\r\n\r\nHello\r\n" }), invoke(&["retr", "1"]));
+        assert_eq!(serde_json::json!({ "deleted": [1] }), invoke(&["dele", "1"]));
+        assert_eq!(serde_json::json!({ "deleted": 1 }), invoke(&["purge", "--yes"]));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_purge_filter() {
+        use rust_pop3_client::test_util::MaildropServer;
+
+        let server = MaildropServer::builder()
+            .message("a", b"From: bob@spammer.example\r\nDate: Mon, 1 Jan 2024 10:00:00 +0000\r\nSubject: offer\r\n\r\nBuy\r\n")
+            .message("b", b"From: alice@example.com\r\nDate: Mon, 1 Jan 2024 10:00:00 +0000\r\nSubject: hi\r\n\r\nHello\r\n")
+            .message("c", b"From: eve@spammer.example\r\nSubject: new\r\n\r\nBuy\r\n")
+            .start()
+            .unwrap();
+        let port = server.port().to_string();
+        let invoke = |args: &[&str]| {
+            let mut command = vec!("pop3", "--host", "127.0.0.1", "--port", &port, "--tls", "plain", "--user", "me", "--password", "secret",
+                "purge", "--from", "*@spammer.example", "--older-than", "30d");
+            command.extend_from_slice(args);
+            let mut out = vec!();
+            let result = run(Cli::try_parse_from(command).unwrap(), &mut out);
+            (result, String::from_utf8(out).unwrap())
+        };
+
+        let (result, preview) = invoke(&["--dry-run"]);
+        assert!(result.is_ok());
+        assert_eq!("1 88 bob@spammer.example offer\n", preview);
+        let (result, preview) = invoke(&[]);
+        assert_eq!("purge would delete 1 messages; repeat with --yes to delete them", result.unwrap_err().to_string());
+        assert_eq!("1 88 bob@spammer.example offer\n", preview);
+        assert_eq!(vec!("a", "b", "c"), server.unique_ids());
+
+        assert_eq!("deleted 1 messages\n", invoke(&["--yes"]).1);
+        assert_eq!(vec!("b", "c"), server.unique_ids());
+    }
+
+    #[test]
+    fn test_completions() {
+        let invoke = |args: &[&str]| {
+            let mut out = vec!();
+            run(Cli::try_parse_from(args).unwrap(), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        let script = invoke(&["pop3", "completions", "bash"]);
+        assert!(script.contains("_pop3()"));
+        assert!(script.contains("extract-attachments"));
+        let page = invoke(&["pop3", "man"]);
+        assert!(page.starts_with(".ie \\n(.g .ds Aq"));
+        assert!(page.contains(".TH pop3 1"));
+    }
+
+    #[test]
+    fn test_notify() {
+        let message = Pop3MessageSummary {
+            meta: rust_pop3_client::Pop3MessageMeta { message_id: 3, message_size: 42, unique_id: Some("c".into()) },
+            headers: Pop3Headers::parse(b"From: alice@example.com\r\nSubject: hello\r\n\r\n"),
+        };
+
+        let mut out = vec!();
+        notify(&message, None, false, &mut out).unwrap();
+        notify(&message, None, true, &mut out).unwrap();
+        let expected = "3 42 alice@example.com hello\n\
+            {\"message_id\":3,\"message_size\":42,\"unique_id\":\"c\",\"from\":\"alice@example.com\",\"subject\":\"hello\",\"date\":null}\n";
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+
+        #[cfg(unix)]
+        {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("notified");
+            let command = format!("printf '%s %s' \"$POP3_UID\" \"$POP3_SUBJECT\" > {}; exit 1", path.display());
+            let mut out = vec!();
+            notify(&message, Some(&command), false, &mut out).unwrap();
+            assert!(out.is_empty());
+            assert_eq!("c hello", std::fs::read_to_string(&path).unwrap());
+        }
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_report() {
+        use rust_pop3_client::test_util::MaildropServer;
+
+        let server = MaildropServer::builder()
+            .message("a", b"From: alice@example.com\r\nSubject: first\r\n\r\nHello\r\n")
+            .message("b", b"From: alice@example.com\r\nSubject: second\r\n\r\nWorld!\r\n")
+            .start()
+            .unwrap();
+        let port = server.port().to_string();
+        let command = ["pop3", "--host", "127.0.0.1", "--port", &port, "--tls", "plain", "--user", "me", "--password", "secret", "--json", "report"];
+
+        let mut out = vec!();
+        run(Cli::try_parse_from(command).unwrap(), &mut out).unwrap();
+
+        let report: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(2, report["message_count"]);
+        assert_eq!(serde_json::json!([{ "address": "alice@example.com", "message_count": 2, "total_size": 102 }]), report["senders"]);
+        assert_eq!("second", report["largest_messages"][0]["subject"]);
+        assert_eq!(2, report["ages"][5]["message_count"]);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_export() {
+        use rust_pop3_client::test_util::MaildropServer;
+
+        let server = MaildropServer::builder()
+            .message("a", b"Subject: first\r\n\r\nHello\r\n")
+            .message("b", b"Subject: second\r\n\r\nWorld\r\n")
+            .start()
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mbox = dir.path().join("backup.mbox");
+        let state = dir.path().join("state.json");
+        let port = server.port().to_string();
+        let invoke = |args: &[&str]| {
+            let mut command = vec!("pop3", "--host", "127.0.0.1", "--port", &port, "--tls", "plain", "--user", "me", "--password", "secret",
+                "export", "--mbox", mbox.to_str().unwrap());
+            command.extend_from_slice(args);
+            let mut out = vec!();
+            run(Cli::try_parse_from(command).unwrap(), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!("exported 2 messages\n", invoke(&["--since-uidl-state", state.to_str().unwrap()]));
+        assert_eq!("exported 0 messages\n", invoke(&["--since-uidl-state", state.to_str().unwrap()]));
+        assert_eq!("exported 2 messages\n", invoke(&[]));
+
+        let content = std::fs::read_to_string(&mbox).unwrap();
+        assert_eq!(4, content.matches("\nFrom ").count() + content.starts_with("From ") as usize);
+        assert_eq!(2, content.matches("Subject: first\n").count());
+        assert_eq!(2, content.matches("Subject: second\n").count());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_extract_attachments() {
+        use rust_pop3_client::test_util::MaildropServer;
+
+        let server = MaildropServer::builder()
+            .message("a", b"From: alice@example.com\r\n\
+Date: Mon, 1 Jan 2024 10:00:00 +0000\r\n\
+MIME-Version: 1.0\r\n\
+Content-Type: multipart/mixed; boundary=\"b\"\r\n\
+\r\n\
+--b\r\n\
+Content-Type: text/plain\r\n\
+\r\n\
+Hello\r\n\
+--b\r\n\
+Content-Type: application/pdf; name=\"invoice.pdf\"\r\n\
+Content-Disposition: attachment; filename=\"invoice.pdf\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+JVBERi0=\r\n\
+--b\r\n\
+Content-Type: image/png; name=\"logo.png\"\r\n\
+Content-Disposition: attachment; filename=\"logo.png\"\r\n\
+\r\n\
+png\r\n\
+--b--\r\n")
+            .start()
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let template = format!("{}/{{from}}", dir.path().display());
+        let port = server.port().to_string();
+        let command = ["pop3", "--host", "127.0.0.1", "--port", &port, "--tls", "plain", "--user", "me", "--password", "secret",
+            "extract-attachments", "--name", "*.pdf", "--dir", &template];
+
+        let mut out = vec!();
+        run(Cli::try_parse_from(command).unwrap(), &mut out).unwrap();
+
+        let path = dir.path().join("alice@example.com").join("invoice.pdf");
+        assert_eq!(format!("{}\n", path.display()), String::from_utf8(out).unwrap());
+        assert_eq!(b"%PDF-".to_vec(), std::fs::read(&path).unwrap());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_fetch() {
+        use rust_pop3_client::test_util::MaildropServer;
+
+        let server = MaildropServer::builder()
+            .message("a", b"Subject: first\r\n\r\nHello\r\n")
+            .message("b", b"Subject: second\r\n\r\nWorld\r\n")
+            .start()
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let maildir = dir.path().join("inbox");
+        let port = server.port().to_string();
+        let fetch = |delete: bool| {
+            let mut command = vec!("pop3", "--host", "127.0.0.1", "--port", &port, "--tls", "plain", "--user", "me", "--password", "secret",
+                "fetch", "--maildir", maildir.to_str().unwrap());
+            if delete {
+                command.push("--delete");
+            }
+            let mut out = vec!();
+            run(Cli::try_parse_from(command).unwrap(), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+
+        assert_eq!("fetched 2 messages\n", fetch(false));
+        assert_eq!(2, std::fs::read_dir(maildir.join("new")).unwrap().count());
+        assert!(maildir.join(STATE_FILE).exists());
+        assert_eq!("fetched 0 messages\n", fetch(false));
+
+        std::fs::remove_file(maildir.join(STATE_FILE)).unwrap();
+        assert_eq!("fetched 2 messages\n", fetch(true));
+        assert!(server.unique_ids().is_empty());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_account_profile() {
+        use rust_pop3_client::test_util::MaildropServer;
+
+        let server = MaildropServer::builder()
+            .credentials("me", "secret")
+            .message("a", b"Subject: first\r\n\r\nHello\r\n")
+            .start()
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let maildir = dir.path().join("inbox");
+        let config = dir.path().join("config.toml");
+        std::fs::write(&config, format!(r#"
+            [accounts.test]
+            host = "127.0.0.1"
+            port = {}
+            tls = "plain"
+            user = "me"
+            password = "secret"
+
+            [accounts.test.sync]
+            maildir = "{}"
+            delete = true
+        "#, server.port(), maildir.display())).unwrap();
+
+        let invoke = |args: &[&str]| {
+            let mut command = vec!("pop3", "--config", config.to_str().unwrap());
+            command.extend_from_slice(args);
+            let mut out = vec!();
+            run(Cli::try_parse_from(command).unwrap(), &mut out).map(|()| String::from_utf8(out).unwrap())
+        };
+
+        assert!(invoke(&["stat"]).unwrap_err().to_string().contains("missing --host"));
+        assert!(invoke(&["--account", "other", "stat"]).unwrap_err().to_string().contains("unknown account"));
+        assert!(invoke(&["--account", "test", "--password", "wrong", "stat"]).is_err());
+        assert_eq!("1 messages (25 octets)\n", invoke(&["--account", "test", "stat"]).unwrap());
+        assert_eq!("fetched 1 messages\n", invoke(&["--account", "test", "fetch"]).unwrap());
+        assert!(server.unique_ids().is_empty());
+    }
+}
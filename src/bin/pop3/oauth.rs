@@ -0,0 +1,508 @@
+//! OAuth 2.0 authorization of accounts authenticating by XOAUTH2.
+//!
+//! `pop3 login --oauth` obtains a refresh token, either by a browser redirected
+//! to a local port (RFC 8252) or by the device authorization grant (RFC 8628),
+//! and stores it. Each session exchanges the refresh token for an access token.
+
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64URL;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::{self, OAuthProfile, Provider};
+
+/// Timeout of requests to the provider.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Endpoints and default scope of a provider.
+struct Endpoints {
+    auth_url: &'static str,
+    token_url: &'static str,
+    device_url: &'static str,
+    scope: &'static str,
+}
+
+impl Provider {
+    fn endpoints(self) -> Endpoints {
+        match self {
+            Provider::Google => Endpoints {
+                auth_url: "https://accounts.google.com/o/oauth2/v2/auth",
+                token_url: "https://oauth2.googleapis.com/token",
+                device_url: "https://oauth2.googleapis.com/device/code",
+                scope: "https://mail.google.com/",
+            },
+            Provider::Microsoft => Endpoints {
+                auth_url: "https://login.microsoftonline.com/common/oauth2/v2.0/authorize",
+                token_url: "https://login.microsoftonline.com/common/oauth2/v2.0/token",
+                device_url: "https://login.microsoftonline.com/common/oauth2/v2.0/devicecode",
+                scope: "https://outlook.office.com/POP.AccessAsUser.All offline_access",
+            },
+        }
+    }
+}
+
+/// Tokens issued by the provider.
+#[derive(Debug, Deserialize)]
+pub struct Tokens {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+}
+
+/// Response of the token endpoint; either tokens or an error (RFC 6749, section 5.2).
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TokenResponse {
+    Tokens(Tokens),
+    Error {
+        error: String,
+        error_description: Option<String>,
+    },
+}
+
+/// Response of the device authorization endpoint (RFC 8628, section 3.2).
+#[derive(Debug, Deserialize)]
+pub struct DeviceCode {
+    device_code: String,
+
+    /// code the user enters at the verification URI
+    pub user_code: String,
+
+    /// page to enter the user code; Google calls it `verification_url`
+    #[serde(alias = "verification_url")]
+    pub verification_uri: String,
+
+    #[serde(default = "default_interval")]
+    interval: u64,
+
+    expires_in: u64,
+}
+
+fn default_interval() -> u64 {
+    5
+}
+
+/// OAuth 2.0 client of an account.
+pub struct Client {
+    agent: ureq::Agent,
+    auth_url: Option<String>,
+    token_url: String,
+    device_url: Option<String>,
+    client_id: String,
+    client_secret: Option<String>,
+    scope: String,
+}
+
+impl Client {
+
+    /// Returns a client using the endpoints of the profile or of its provider.
+    pub fn new(profile: &OAuthProfile) -> Result<Self, Box<dyn Error>> {
+        let endpoints = profile.provider.map(Provider::endpoints);
+        let endpoint = |url: &Option<String>, default: fn(&Endpoints) -> &'static str| {
+            url.clone().or_else(|| endpoints.as_ref().map(|endpoints| default(endpoints).to_string()))
+        };
+
+        Ok(Client {
+            agent: ureq::AgentBuilder::new().timeout(TIMEOUT).build(),
+            auth_url: endpoint(&profile.auth_url, |endpoints| endpoints.auth_url),
+            token_url: endpoint(&profile.token_url, |endpoints| endpoints.token_url).ok_or("missing provider or token_url")?,
+            device_url: endpoint(&profile.device_url, |endpoints| endpoints.device_url),
+            client_id: profile.client_id.clone(),
+            client_secret: profile.client_secret.clone(),
+            scope: endpoint(&profile.scope, |endpoints| endpoints.scope).unwrap_or_default(),
+        })
+    }
+
+    /// Authorizes by a browser, which is redirected to a local port afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `open` - Function showing the authorization URL to the user.
+    pub fn authorize_redirect(&self, open: impl FnOnce(&str)) -> Result<Tokens, Box<dyn Error>> {
+        let auth_url = self.auth_url.as_deref().ok_or("missing provider or auth_url")?;
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        let redirect_uri = format!("http://127.0.0.1:{}/", listener.local_addr()?.port());
+        let verifier = random_string()?;
+        let state = random_string()?;
+        let challenge = BASE64URL.encode(Sha256::digest(verifier.as_bytes()));
+
+        let params = [
+            ("response_type", "code"),
+            ("client_id", &self.client_id),
+            ("redirect_uri", &redirect_uri),
+            ("scope", &self.scope),
+            ("state", &state),
+            ("code_challenge", &challenge),
+            ("code_challenge_method", "S256"),
+            ("access_type", "offline"),
+            ("prompt", "consent"),
+        ];
+        let query: Vec<String> = params.iter().map(|(name, value)| format!("{}={}", name, encode(value))).collect();
+        open(&format!("{}?{}", auth_url, query.join("&")));
+
+        let code = receive_code(&listener, &state)?;
+        self.request_token(&[
+            ("grant_type", "authorization_code"),
+            ("code", &code),
+            ("redirect_uri", &redirect_uri),
+            ("code_verifier", &verifier),
+        ])
+    }
+
+    /// Authorizes by a code, which the user enters on another device.
+    ///
+    /// # Arguments
+    ///
+    /// * `prompt` - Function showing the user code and the verification URI to the user.
+    pub fn authorize_device(&self, prompt: impl FnOnce(&DeviceCode)) -> Result<Tokens, Box<dyn Error>> {
+        let device_url = self.device_url.as_deref().ok_or("missing provider or device_url")?;
+        let body = post(&self.agent, device_url, &[("client_id", &self.client_id), ("scope", &self.scope)])?;
+        let code: DeviceCode = serde_json::from_str(&body).map_err(|_| format!("device authorization failed: {}", body))?;
+        prompt(&code);
+
+        let deadline = Instant::now() + Duration::from_secs(code.expires_in);
+        let mut interval = Duration::from_secs(code.interval);
+        loop {
+            thread::sleep(interval);
+            if Instant::now() > deadline {
+                return Err("device code expired".into());
+            }
+            match self.token_response(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                ("device_code", &code.device_code),
+            ])? {
+                TokenResponse::Tokens(tokens) => return Ok(tokens),
+                TokenResponse::Error { error, .. } if error == "authorization_pending" => { },
+                TokenResponse::Error { error, .. } if error == "slow_down" => interval += Duration::from_secs(5),
+                TokenResponse::Error { error, error_description } => return Err(token_error(error, error_description))
+            }
+        }
+    }
+
+    /// Returns new tokens for a refresh token.
+    pub fn refresh(&self, refresh_token: &str) -> Result<Tokens, Box<dyn Error>> {
+        self.request_token(&[("grant_type", "refresh_token"), ("refresh_token", refresh_token)])
+    }
+
+    fn request_token(&self, params: &[(&str, &str)]) -> Result<Tokens, Box<dyn Error>> {
+        match self.token_response(params)? {
+            TokenResponse::Tokens(tokens) => Ok(tokens),
+            TokenResponse::Error { error, error_description } => Err(token_error(error, error_description))
+        }
+    }
+
+    fn token_response(&self, params: &[(&str, &str)]) -> Result<TokenResponse, Box<dyn Error>> {
+        let mut params = params.to_vec();
+        params.push(("client_id", &self.client_id));
+        if let Some(client_secret) = &self.client_secret {
+            params.push(("client_secret", client_secret));
+        }
+        let body = post(&self.agent, &self.token_url, &params)?;
+        serde_json::from_str(&body).map_err(|_| format!("invalid token response: {}", body).into())
+    }
+}
+
+/// Storage of the refresh token of an account.
+pub enum TokenStore {
+    File(PathBuf),
+    #[cfg(feature = "keyring")]
+    Keyring(keyring::Entry),
+}
+
+impl TokenStore {
+
+    /// Returns the storage configured by the profile, the keyring or the default file.
+    pub fn new(profile: &OAuthProfile, host: &str, user: &str) -> Result<Self, Box<dyn Error>> {
+        if let Some(path) = &profile.token_file {
+            return Ok(TokenStore::File(config::expand_home(path)));
+        }
+        #[cfg(feature = "keyring")]
+        return Ok(TokenStore::Keyring(keyring::Entry::new(&format!("pop3-oauth://{}", host), user)?));
+        #[cfg(not(feature = "keyring"))]
+        {
+            let dir = config::config_dir().ok_or("missing token_file")?;
+            Ok(TokenStore::File(dir.join("tokens").join(format!("{}@{}", user, host))))
+        }
+    }
+
+    /// Returns the stored refresh token.
+    pub fn load(&self) -> Result<String, Box<dyn Error>> {
+        let token = match self {
+            TokenStore::File(path) => fs::read_to_string(path).map(|token| token.trim().to_string()).ok(),
+            #[cfg(feature = "keyring")]
+            TokenStore::Keyring(entry) => entry.get_password().ok(),
+        };
+        token.ok_or_else(|| "no refresh token stored; run pop3 login --oauth".into())
+    }
+
+    /// Stores a refresh token; files are only readable by the owner.
+    pub fn store(&self, refresh_token: &str) -> Result<(), Box<dyn Error>> {
+        match self {
+            TokenStore::File(path) => {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                let mut options = OpenOptions::new();
+                options.write(true).create(true).truncate(true);
+                #[cfg(unix)]
+                std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+                options.open(path)?.write_all(refresh_token.as_bytes())?;
+            },
+            #[cfg(feature = "keyring")]
+            TokenStore::Keyring(entry) => entry.set_password(refresh_token)?,
+        }
+        Ok(())
+    }
+}
+
+/// Returns an access token of an account by its stored refresh token.
+///
+/// A refresh token rotated by the provider replaces the stored one.
+pub fn access_token(profile: &OAuthProfile, host: &str, user: &str) -> Result<String, Box<dyn Error>> {
+    let store = TokenStore::new(profile, host, user)?;
+    let tokens = Client::new(profile)?.refresh(&store.load()?)?;
+    if let Some(refresh_token) = tokens.refresh_token {
+        store.store(&refresh_token)?;
+    }
+    Ok(tokens.access_token)
+}
+
+fn token_error(error: String, description: Option<String>) -> Box<dyn Error> {
+    match description {
+        Some(description) => format!("authorization failed: {} ({})", error, description).into(),
+        None => format!("authorization failed: {}", error).into()
+    }
+}
+
+/// Posts a form and returns the response body, also of error responses.
+fn post(agent: &ureq::Agent, url: &str, params: &[(&str, &str)]) -> Result<String, Box<dyn Error>> {
+    match agent.post(url).send_form(params) {
+        Ok(response) | Err(ureq::Error::Status(_, response)) => Ok(response.into_string()?),
+        Err(err) => Err(err.into())
+    }
+}
+
+/// Waits for the redirected browser and returns the authorization code.
+fn receive_code(listener: &TcpListener, state: &str) -> Result<String, Box<dyn Error>> {
+    for stream in listener.incoming() {
+        let mut stream = stream?;
+        let mut request_line = String::new();
+        BufReader::new(&stream).read_line(&mut request_line)?;
+        let query = request_line.split_whitespace().nth(1)
+            .and_then(|target| target.split_once('?'))
+            .map(|(_, query)| query)
+            .unwrap_or_default();
+        let param = |name: &str| query.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| decode(value));
+
+        let result = match (param("code"), param("error")) {
+            (_, Some(error)) => Err(format!("authorization failed: {}", error)),
+            (Some(_), _) if param("state").as_deref() != Some(state) => Err("authorization failed: state mismatch".to_string()),
+            (Some(code), _) => Ok(code),
+            (None, None) => {
+                // e.g. a request of the favicon
+                stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")?;
+                continue;
+            }
+        };
+        let text = match &result {
+            Ok(_) => "Authorization completed; you may close this window.\n",
+            Err(_) => "Authorization failed; see the output of pop3.\n",
+        };
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", text.len(), text)?;
+        return Ok(result?);
+    }
+    Err("no authorization received".into())
+}
+
+/// Returns 32 random bytes encoded in base64url, e.g. a PKCE code verifier.
+fn random_string() -> Result<String, Box<dyn Error>> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(BASE64URL.encode(bytes))
+}
+
+/// Percent-encodes all but unreserved characters (RFC 3986).
+fn encode(value: &str) -> String {
+    value.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte)
+    }).collect()
+}
+
+/// Decodes a percent-encoded value of a query.
+fn decode(value: &str) -> String {
+    let mut bytes = vec!();
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail.get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(escaped)) => {
+                bytes.push(escaped);
+                rest = &tail[2..];
+            },
+            (b'+', _) => {
+                bytes.push(b' ');
+                rest = tail;
+            },
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpStream;
+    use std::thread::JoinHandle;
+
+    /// Serves canned JSON responses and returns the bodies of the requests.
+    fn serve(responses: Vec<(u16, &'static str)>) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let url = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+        let handle = thread::spawn(move || responses.into_iter().map(|(status, body)| {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                if line.trim().is_empty() {
+                    break;
+                }
+            }
+            let mut request = vec![0; length];
+            reader.read_exact(&mut request).unwrap();
+            write!(reader.get_mut(), "HTTP/1.1 {} X\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body).unwrap();
+            String::from_utf8(request).unwrap()
+        }).collect());
+        (url, handle)
+    }
+
+    fn profile(url: &str) -> OAuthProfile {
+        OAuthProfile {
+            provider: None,
+            client_id: "client".into(),
+            client_secret: None,
+            scope: Some("mail".into()),
+            auth_url: Some(format!("{}/auth", url)),
+            token_url: Some(format!("{}/token", url)),
+            device_url: Some(format!("{}/device", url)),
+            token_file: None,
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!("a-b_c.d~e%20f%2Fg%40h", encode("a-b_c.d~e f/g@h"));
+        assert_eq!("a b/g@h%", decode("a+b%2fg%40h%"));
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let mut profile = profile("http://localhost");
+        profile.provider = Some(Provider::Google);
+        profile.scope = None;
+        profile.auth_url = None;
+        let client = Client::new(&profile).unwrap();
+        assert_eq!(Some("https://accounts.google.com/o/oauth2/v2/auth"), client.auth_url.as_deref());
+        assert_eq!("http://localhost/token", client.token_url);
+        assert_eq!("https://mail.google.com/", client.scope);
+
+        profile.provider = None;
+        profile.token_url = None;
+        assert!(Client::new(&profile).is_err());
+    }
+
+    #[test]
+    fn test_authorize_device() {
+        let (url, server) = serve(vec!(
+            (200, r#"{"device_code":"dc","user_code":"ABCD","verification_url":"https://example.com/device","interval":0,"expires_in":60}"#),
+            (400, r#"{"error":"authorization_pending"}"#),
+            (200, r#"{"access_token":"at","refresh_token":"rt"}"#),
+        ));
+
+        let mut prompted = None;
+        let tokens = Client::new(&profile(&url)).unwrap()
+            .authorize_device(|code| prompted = Some((code.user_code.clone(), code.verification_uri.clone())))
+            .unwrap();
+
+        assert_eq!(Some(("ABCD".to_string(), "https://example.com/device".to_string())), prompted);
+        assert_eq!("at", tokens.access_token);
+        assert_eq!(Some("rt"), tokens.refresh_token.as_deref());
+        let requests = server.join().unwrap();
+        assert_eq!("client_id=client&scope=mail", requests[0]);
+        assert!(requests[2].contains("device_code=dc"));
+    }
+
+    #[test]
+    fn test_authorize_redirect() {
+        let (url, server) = serve(vec!((200, r#"{"access_token":"at","refresh_token":"rt"}"#)));
+
+        let tokens = Client::new(&profile(&url)).unwrap().authorize_redirect(|auth_url| {
+            let query = auth_url.split_once('?').unwrap().1;
+            let param = |name: &str| query.split('&').find_map(|pair| pair.strip_prefix(&format!("{}=", name))).map(decode).unwrap();
+            let redirect_uri = param("redirect_uri");
+            let state = param("state");
+            thread::spawn(move || {
+                let mut browser = TcpStream::connect(redirect_uri.trim_start_matches("http://").trim_end_matches('/')).unwrap();
+                write!(browser, "GET /?code=c%2F1&state={} HTTP/1.1\r\n\r\n", state).unwrap();
+                let mut response = String::new();
+                browser.read_to_string(&mut response).unwrap();
+                assert!(response.starts_with("HTTP/1.1 200 OK"));
+            });
+        }).unwrap();
+
+        assert_eq!("at", tokens.access_token);
+        let request = &server.join().unwrap()[0];
+        assert!(request.contains("grant_type=authorization_code&code=c%2F1&"));
+        assert!(request.contains("code_verifier="));
+    }
+
+    #[test]
+    fn test_refresh_error() {
+        let (url, server) = serve(vec!((400, r#"{"error":"invalid_grant","error_description":"revoked"}"#)));
+        let err = Client::new(&profile(&url)).unwrap().refresh("rt").unwrap_err();
+        assert_eq!("authorization failed: invalid_grant (revoked)", err.to_string());
+        assert!(server.join().unwrap()[0].starts_with("grant_type=refresh_token&refresh_token=rt&"));
+    }
+
+    #[test]
+    fn test_token_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut profile = profile("http://localhost");
+        profile.token_file = Some(dir.path().join("tokens").join("me"));
+        let store = TokenStore::new(&profile, "pop.example.com", "me").unwrap();
+        assert!(store.load().is_err());
+
+        store.store("rt").unwrap();
+        assert_eq!("rt", store.load().unwrap());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(profile.token_file.unwrap()).unwrap().permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+    }
+}
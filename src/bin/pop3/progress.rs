@@ -0,0 +1,151 @@
+//! Progress bars of downloads.
+//!
+//! Bars are drawn to stderr, only if it is a terminal; otherwise they are hidden
+//! and no commands are issued to determine their length.
+
+use std::collections::HashMap;
+use std::io::{self, IsTerminal, Write};
+
+use indicatif::{ProgressBar, ProgressStyle};
+
+use rust_pop3_client::{Pop3MessageMeta, UidState};
+
+/// Returns true, if progress bars are drawn.
+pub fn enabled() -> bool {
+    io::stderr().is_terminal()
+}
+
+/// Returns a progress bar of the octets of a single message.
+pub fn message_bar(size: u64) -> ProgressBar {
+    let style = ProgressStyle::with_template("{bytes}/{total_bytes} [{bar:40}] {bytes_per_sec}, {eta}")
+        .expect("valid template")
+        .progress_chars("=> ");
+    ProgressBar::new(size).with_style(style)
+}
+
+/// Aggregate progress of a bulk fetch.
+pub struct FetchProgress {
+    bar: ProgressBar,
+    count: usize,
+    fetched: usize,
+}
+
+impl FetchProgress {
+
+    /// Returns the progress of fetching messages of the given count and total size.
+    pub fn new(count: usize, size: u64) -> Self {
+        let style = ProgressStyle::with_template("{msg} {bytes}/{total_bytes} [{bar:40}] {bytes_per_sec}, {eta}")
+            .expect("valid template")
+            .progress_chars("=> ");
+        let bar = ProgressBar::new(size).with_style(style);
+        bar.set_message(format!("0/{} messages", count));
+        FetchProgress { bar, count, fetched: 0 }
+    }
+
+    /// Returns a progress, which is not shown.
+    pub fn hidden() -> Self {
+        FetchProgress { bar: ProgressBar::hidden(), count: 0, fetched: 0 }
+    }
+
+    /// Advances the progress by a fetched message.
+    pub fn fetched(&mut self, message: &Pop3MessageMeta) {
+        self.fetched += 1;
+        self.bar.inc(message.message_size as u64);
+        self.bar.set_message(format!("{}/{} messages", self.fetched, self.count));
+    }
+}
+
+impl Drop for FetchProgress {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+/// Returns count and total size of the messages, which were not fetched before.
+///
+/// Messages larger than the maximum size are deferred by the sync and not counted.
+pub fn pending(messages: &[Pop3MessageMeta], states: &HashMap<String, UidState>, max_message_size: Option<u32>) -> (usize, u64) {
+    messages.iter()
+        .filter(|message| message.unique_id.as_ref().and_then(|unique_id| states.get(unique_id)).is_none_or(|state| state.fetched_at.is_none()))
+        .filter(|message| max_message_size.is_none_or(|max_size| message.message_size <= max_size))
+        .fold((0, 0), |(count, size), message| (count + 1, size + message.message_size as u64))
+}
+
+/// Writer advancing a progress bar by the octets written.
+pub struct ProgressWriter<'a, W: Write> {
+    inner: &'a mut W,
+    bar: ProgressBar,
+}
+
+impl<'a, W: Write> ProgressWriter<'a, W> {
+    pub fn new(inner: &'a mut W, bar: ProgressBar) -> Self {
+        ProgressWriter { inner, bar }
+    }
+}
+
+impl<W: Write> Write for ProgressWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.bar.inc(written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for ProgressWriter<'_, W> {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn meta(message_id: u32, message_size: u32, unique_id: &str) -> Pop3MessageMeta {
+        Pop3MessageMeta { message_id, message_size, unique_id: Some(unique_id.to_string()) }
+    }
+
+    #[test]
+    fn test_pending() {
+        let messages = vec!(meta(1, 10, "a"), meta(2, 20, "b"), meta(3, 300, "c"), meta(4, 40, "d"));
+        let mut fetched = UidState::new(SystemTime::now());
+        fetched.fetched_at = Some(SystemTime::now());
+        let states = HashMap::from([
+            ("a".to_string(), fetched),
+            ("b".to_string(), UidState::new(SystemTime::now())),
+        ]);
+
+        assert_eq!((3, 360), pending(&messages, &states, None));
+        assert_eq!((2, 60), pending(&messages, &states, Some(100)));
+    }
+
+    #[test]
+    fn test_fetch_progress() {
+        let mut progress = FetchProgress::new(2, 30);
+        progress.bar.set_draw_target(indicatif::ProgressDrawTarget::hidden());
+        progress.fetched(&meta(1, 10, "a"));
+        assert_eq!(10, progress.bar.position());
+        assert_eq!("1/2 messages", progress.bar.message());
+    }
+
+    #[test]
+    fn test_progress_writer() {
+        let bar = ProgressBar::hidden();
+        bar.set_length(11);
+        let mut out = vec!();
+        {
+            let mut writer = ProgressWriter::new(&mut out, bar.clone());
+            writer.write_all(b"Hello").unwrap();
+            writer.write_all(b" World").unwrap();
+            assert_eq!(11, bar.position());
+        }
+
+        assert!(bar.is_finished());
+        assert_eq!(b"Hello World".to_vec(), out);
+    }
+}
@@ -0,0 +1,190 @@
+//! Usage report of a maildrop, printed by `pop3 report`.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+
+use rust_pop3_client::{Pop3MessageSummary, Pop3UsageReport};
+
+use crate::MessagePreview;
+
+/// Count of senders contained in a report.
+const SENDERS_COUNT: usize = 10;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Upper bounds (exclusive) of the age buckets and their labels.
+const AGE_BOUNDS: [(u64, &str); 4] = [(DAY, "< 1 day"), (7 * DAY, "< 1 week"), (30 * DAY, "< 30 days"), (365 * DAY, "< 1 year")];
+
+/// Usage of the maildrop by a sender.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct SenderUsage {
+    /// address of the sender; `unknown`, if the message has no valid From header
+    pub address: String,
+    pub message_count: u32,
+    pub total_size: u64,
+}
+
+/// Bucket of the age distribution by the Date header.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct AgeBucket {
+    /// range of ages, e.g. `< 1 week`; `unknown` for messages without valid Date header
+    pub age: &'static str,
+    pub message_count: u32,
+    pub total_size: u64,
+}
+
+/// Usage report extended by the senders and ages of the messages.
+#[derive(Serialize)]
+pub struct Report<'a> {
+    #[serde(flatten)]
+    usage: Pop3UsageReport,
+
+    /// largest messages by size, sorted in descending order
+    largest_messages: Vec<MessagePreview<'a>>,
+
+    /// senders using the most space, sorted in descending order
+    senders: Vec<SenderUsage>,
+
+    ages: Vec<AgeBucket>,
+}
+
+impl<'a> Report<'a> {
+
+    /// Returns a report of the messages.
+    ///
+    /// # Arguments
+    ///
+    /// * `usage`     - usage report of the maildrop
+    /// * `summaries` - metadata and headers of the messages
+    /// * `now`       - time the ages of the messages relate to
+    pub fn new(usage: Pop3UsageReport, summaries: &'a [Pop3MessageSummary], now: SystemTime) -> Self {
+        let largest_messages = usage.largest_messages.iter()
+            .filter_map(|meta| summaries.iter().find(|summary| summary.meta.message_id == meta.message_id))
+            .map(MessagePreview::from)
+            .collect();
+
+        let mut senders: HashMap<String, SenderUsage> = HashMap::new();
+        for summary in summaries {
+            let address = summary.from_mailboxes().first()
+                .map(|mailbox| mailbox.address.to_lowercase())
+                .unwrap_or_else(|| "unknown".to_string());
+            let sender = senders.entry(address.clone()).or_insert(SenderUsage { address, message_count: 0, total_size: 0 });
+            sender.message_count += 1;
+            sender.total_size += summary.meta.message_size as u64;
+        }
+        let mut senders: Vec<SenderUsage> = senders.into_values().collect();
+        senders.sort_by(|a, b| b.total_size.cmp(&a.total_size).then_with(|| a.address.cmp(&b.address)));
+        senders.truncate(SENDERS_COUNT);
+
+        let mut ages: Vec<AgeBucket> = AGE_BOUNDS.iter().map(|(_, age)| *age)
+            .chain(["older", "unknown"])
+            .map(|age| AgeBucket { age, message_count: 0, total_size: 0 })
+            .collect();
+        for summary in summaries {
+            let index = match summary.timestamp() {
+                Some(timestamp) => {
+                    let age = now.duration_since(timestamp).unwrap_or(Duration::ZERO);
+                    AGE_BOUNDS.iter().position(|(bound, _)| age < Duration::from_secs(*bound)).unwrap_or(AGE_BOUNDS.len())
+                },
+                None => AGE_BOUNDS.len() + 1
+            };
+            ages[index].message_count += 1;
+            ages[index].total_size += summary.meta.message_size as u64;
+        }
+
+        Report { usage, largest_messages, senders, ages }
+    }
+
+    /// Writes the report as text.
+    pub fn write_text(&self, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+        writeln!(out, "{} messages ({} octets)", self.usage.message_count, self.usage.total_size)?;
+
+        writeln!(out, "\nsizes:")?;
+        for bucket in &self.usage.size_histogram {
+            let range = match bucket.max_size {
+                Some(max_size) if bucket.min_size == 0 => format!("< {}", format_size(max_size)),
+                Some(max_size) => format!("{} - {}", format_size(bucket.min_size), format_size(max_size)),
+                None => format!(">= {}", format_size(bucket.min_size))
+            };
+            writeln!(out, "  {}: {} messages ({} octets)", range, bucket.message_count, bucket.total_size)?;
+        }
+
+        writeln!(out, "\nlargest messages:")?;
+        for message in &self.largest_messages {
+            writeln!(out, "  {}", message)?;
+        }
+
+        writeln!(out, "\nlargest senders:")?;
+        for sender in &self.senders {
+            writeln!(out, "  {}: {} messages ({} octets)", sender.address, sender.message_count, sender.total_size)?;
+        }
+
+        writeln!(out, "\nages:")?;
+        for bucket in &self.ages {
+            writeln!(out, "  {}: {} messages ({} octets)", bucket.age, bucket.message_count, bucket.total_size)?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats a size in binary units, e.g. `10 KiB`.
+fn format_size(size: u32) -> String {
+    match size {
+        size if size >= 1 << 20 && size % (1 << 20) == 0 => format!("{} MiB", size >> 20),
+        size if size >= 1 << 10 && size % (1 << 10) == 0 => format!("{} KiB", size >> 10),
+        size => format!("{} B", size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_pop3_client::{Pop3Headers, Pop3MessageMeta};
+
+    fn summary(message_id: u32, message_size: u32, headers: &str) -> Pop3MessageSummary {
+        Pop3MessageSummary {
+            meta: Pop3MessageMeta { message_id, message_size, unique_id: None },
+            headers: Pop3Headers::parse(headers.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let summaries = vec!(
+            summary(1, 100, "From: Alice <alice@example.com>\r\nDate: Mon, 1 Jan 2024 10:00:00 +0000\r\n\r\n"),
+            summary(2, 300, "From: bob@example.com\r\nDate: Sun, 31 Dec 2023 10:00:00 +0000\r\n\r\n"),
+            summary(3, 200, "From: ALICE@example.com\r\nDate: Tue, 1 Jan 2019 10:00:00 +0000\r\n\r\n"),
+            summary(4, 50, "Subject: no sender\r\n\r\n"),
+        );
+        let usage = Pop3UsageReport {
+            message_count: 4,
+            maildrop_size: 650,
+            total_size: 650,
+            largest_messages: summaries.iter().map(|summary| summary.meta.clone()).collect(),
+            size_histogram: vec!(),
+        };
+        let now = summaries[0].timestamp().unwrap() + Duration::from_secs(60);
+
+        let report = Report::new(usage, &summaries, now);
+
+        assert_eq!(4, report.largest_messages.len());
+        assert_eq!(vec!(
+            SenderUsage { address: "alice@example.com".into(), message_count: 2, total_size: 300 },
+            SenderUsage { address: "bob@example.com".into(), message_count: 1, total_size: 300 },
+            SenderUsage { address: "unknown".into(), message_count: 1, total_size: 50 },
+        ), report.senders);
+        let ages: Vec<(&str, u32)> = report.ages.iter().map(|bucket| (bucket.age, bucket.message_count)).collect();
+        assert_eq!(vec!(("< 1 day", 1), ("< 1 week", 1), ("< 30 days", 0), ("< 1 year", 0), ("older", 1), ("unknown", 1)), ages);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!("10 KiB", format_size(10 * 1024));
+        assert_eq!("10 MiB", format_size(10 * 1024 * 1024));
+        assert_eq!("1000 B", format_size(1000));
+    }
+}
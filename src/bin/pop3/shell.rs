@@ -0,0 +1,180 @@
+//! Interactive shell running commands in one authenticated session.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+
+use clap::{Parser, Subcommand};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use rust_pop3_client::Pop3Connection;
+
+use crate::{execute, Command, Context};
+
+const PROMPT: &str = "pop3> ";
+
+/// Line split into words and the file of an output redirection, e.g. `retr 5 > message.eml`.
+#[derive(Debug, PartialEq)]
+struct ParsedLine<'a> {
+    words: Vec<&'a str>,
+    redirect: Option<&'a str>,
+}
+
+/// Command line of the shell.
+#[derive(Parser)]
+#[command(name = "", no_binary_name = true, disable_version_flag = true)]
+struct ShellLine {
+    #[command(subcommand)]
+    command: ShellCommand,
+}
+
+#[derive(Subcommand)]
+enum ShellCommand {
+    #[command(flatten)]
+    Command(Command),
+
+    /// Unmarks all messages marked as deleted
+    Rset,
+
+    /// Does nothing but checking the connection
+    Noop,
+
+    /// Ends the session; messages marked as deleted are removed
+    #[command(alias = "exit")]
+    Quit,
+}
+
+/// Runs commands read by a line editor until `quit` or the end of input.
+///
+/// Messages marked as deleted are removed, when the session ends afterwards.
+pub fn run(connection: &mut Pop3Connection, context: &Context, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    let mut editor = DefaultEditor::new()?;
+    let read_line = || loop {
+        match editor.readline(PROMPT) {
+            Ok(line) => {
+                let _ = editor.add_history_entry(line.as_str());
+                return Ok(Some(line));
+            },
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => return Ok(None),
+            Err(err) => return Err(err.into())
+        }
+    };
+
+    run_with(connection, context, read_line, out)
+}
+
+/// Runs commands until `quit` or until no more lines are read.
+fn run_with(connection: &mut Pop3Connection, context: &Context, mut read_line: impl FnMut() -> Result<Option<String>, Box<dyn Error>>, out: &mut impl Write) -> Result<(), Box<dyn Error>> {
+    while let Some(line) = read_line()? {
+        let ParsedLine { words, redirect } = match parse(&line) {
+            Ok(parsed) if parsed.words.is_empty() => continue,
+            Ok(parsed) => parsed,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                continue;
+            }
+        };
+        let command = match ShellLine::try_parse_from(words) {
+            Ok(line) => line.command,
+            Err(err) => {
+                let _ = err.print();
+                continue;
+            }
+        };
+
+        let result = match redirect {
+            Some(path) => File::create(path)
+                .map_err(|err| format!("failed to create {}: {}", path, err).into())
+                .and_then(|mut file| perform(connection, command, context, &mut file)),
+            None => perform(connection, command, context, out)
+        };
+        match result {
+            Ok(true) => { },
+            Ok(false) => break,
+            Err(err) => eprintln!("error: {}", err)
+        }
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Performs a command; returns false, if the shell ends.
+fn perform(connection: &mut Pop3Connection, command: ShellCommand, context: &Context, out: &mut impl Write) -> Result<bool, Box<dyn Error>> {
+    match command {
+        ShellCommand::Command(Command::Shell) => return Err("already running a shell".into()),
+        ShellCommand::Command(command) => execute(connection, command, context, out)?,
+        ShellCommand::Rset => connection.reset()?,
+        ShellCommand::Noop => connection.noop()?,
+        ShellCommand::Quit => return Ok(false)
+    }
+
+    Ok(connection.is_open())
+}
+
+/// Splits a line into words and the file of an output redirection.
+fn parse(line: &str) -> Result<ParsedLine<'_>, Box<dyn Error>> {
+    match line.split_once('>') {
+        Some((command, path)) => {
+            let path = path.trim();
+            if path.is_empty() || path.contains(char::is_whitespace) {
+                return Err("expected a file name after >".into());
+            }
+            Ok(ParsedLine { words: command.split_whitespace().collect(), redirect: Some(path) })
+        },
+        None => Ok(ParsedLine { words: line.split_whitespace().collect(), redirect: None })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(ParsedLine { words: vec!("top", "3", "10"), redirect: None }, parse(" top 3  10 ").unwrap());
+        let redirected = ParsedLine { words: vec!("retr", "5"), redirect: Some("message.eml") };
+        assert_eq!(redirected, parse("retr 5 > message.eml").unwrap());
+        assert_eq!(redirected, parse("retr 5 >message.eml").unwrap());
+        assert!(parse("retr 5 >").is_err());
+        assert!(parse("retr 5 > a b").is_err());
+    }
+
+    #[cfg(feature = "test-util")]
+    #[test]
+    fn test_shell() {
+        use rust_pop3_client::test_util::MaildropServer;
+
+        let server = MaildropServer::builder()
+            .message("a", b"Subject: first\r\n\r\nHello\r\n")
+            .message("b", b"Subject: second\r\n\r\nWorld\r\n")
+            .start()
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("message.eml");
+
+        let mut lines = vec!(
+            "uidl".to_string(),
+            format!("retr 2 > {}", path.display()),
+            "dele 1".to_string(),
+            "rset".to_string(),
+            "".to_string(),
+            "bogus".to_string(),
+            "shell".to_string(),
+            "dele 2".to_string(),
+            "quit".to_string(),
+            "stat".to_string(),
+        ).into_iter();
+        let mut connection = server.connection_builder().login("me", "secret").connect().unwrap();
+        let mut out = vec!();
+        let context = Context { sync: Default::default(), json: false };
+        run_with(&mut connection, &context, || Ok(lines.next()), &mut out).unwrap();
+        connection.quit().unwrap();
+
+        assert_eq!("1 a\n2 b\n", String::from_utf8(out).unwrap());
+        assert_eq!(b"Subject: second\r\n\r\nWorld\r\n".to_vec(), std::fs::read(&path).unwrap());
+        assert_eq!(vec!("a"), server.unique_ids());
+    }
+}
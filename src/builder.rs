@@ -0,0 +1,555 @@
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use rustls::RootCertStore;
+
+use crate::{MetricsSink, Pop3AccountKey, Pop3Connection, Pop3Proxy, Pop3SessionObserver, TokenProvider};
+#[cfg(any(feature = "tokio", feature = "smol"))]
+use crate::{AsyncPop3Connection, AsyncResolver, Pop3AsyncError};
+#[cfg(feature = "smol")]
+use crate::SmolPop3Connection;
+#[cfg(feature = "tokio")]
+use crate::TokioPop3Connection;
+#[cfg(any(feature = "tokio", feature = "smol"))]
+use crate::session::TlsInfo;
+use crate::stream::Stream;
+use crate::trace::OperationSpan;
+
+/// Transport layer security mode of a POP3 connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TlsMode {
+    /// TLS is established right after connecting (POP3S).
+    Implicit,
+
+    /// Connection starts unencrypted and is upgraded using STLS (RFC 2595).
+    StartTls,
+
+    /// Connection is not encrypted at all; credentials are sent in plain text.
+    Plain,
+}
+
+impl TlsMode {
+    /// Returns the well-known port used with this mode.
+    pub fn default_port(&self) -> u16 {
+        match self {
+            TlsMode::Implicit => 995,
+            _ => 110
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Credentials {
+    Password(String, String),
+    AccessToken(String, String),
+    TokenProvider(String, Arc<dyn TokenProvider + Send + Sync>),
+}
+
+/// Builder of POP3 connections.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{Pop3ConnectionBuilder, TlsMode};
+///
+/// let connection = Pop3ConnectionBuilder::new("pop.example.com")
+///     .tls_mode(TlsMode::StartTls)
+///     .login("user@example.com", "secret")
+///     .connect();
+/// ```
+#[derive(Clone)]
+pub struct Pop3ConnectionBuilder {
+    host: String,
+    port: Option<u16>,
+    tls_mode: TlsMode,
+    root_store: Option<RootCertStore>,
+    credentials: Option<Credentials>,
+    keep_alive: Option<Duration>,
+    download_rate: Option<u64>,
+    dry_run: bool,
+    pipelining: bool,
+    strict_size_check: bool,
+    transcript: Option<PathBuf>,
+    metrics: Option<Arc<dyn MetricsSink>>,
+    observer: Option<Arc<dyn Pop3SessionObserver>>,
+    proxy: Option<Pop3Proxy>,
+    #[cfg(any(feature = "tokio", feature = "smol"))]
+    resolver: Option<Arc<dyn AsyncResolver>>,
+}
+
+impl Pop3ConnectionBuilder {
+
+    /// Returns a new builder using implicit TLS on the default port.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - IP-Address or host name of the POP3 server to connect
+    pub fn new(host: &str) -> Self {
+        Pop3ConnectionBuilder {
+            host: host.to_string(),
+            port: None,
+            tls_mode: TlsMode::Implicit,
+            root_store: None,
+            credentials: None,
+            keep_alive: None,
+            download_rate: None,
+            dry_run: false,
+            pipelining: false,
+            strict_size_check: false,
+            transcript: None,
+            metrics: None,
+            observer: None,
+            proxy: None,
+            #[cfg(any(feature = "tokio", feature = "smol"))]
+            resolver: None,
+        }
+    }
+
+    /// Returns a new builder configured by environment variables.
+    ///
+    /// The following variables are recognized:
+    ///
+    /// * `POP3_HOST`         - IP-Address or host name of the POP3 server (required)
+    /// * `POP3_PORT`         - Port of the POP3 server
+    /// * `POP3_STARTTLS`     - Use STLS instead of implicit TLS (`true` or `false`)
+    /// * `POP3_USER`         - Name of the user to login
+    /// * `POP3_PASSWORD`     - Password of the user
+    /// * `POP3_ACCESS_TOKEN` - OAuth 2.0 access token of the user (used instead of a password)
+    /// * `POP3_DRY_RUN`      - Only simulate deletions (`true` or `false`)
+    pub fn from_env() -> Result<Self, Box<dyn Error>> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, Box<dyn Error>> {
+        let host = var("POP3_HOST").ok_or("missing POP3_HOST")?;
+        let mut builder = Pop3ConnectionBuilder::new(&host);
+
+        if let Some(port) = var("POP3_PORT") {
+            builder = builder.port(port.parse::<u16>()?);
+        }
+
+        if let Some(starttls) = var("POP3_STARTTLS") {
+            if parse_bool(&starttls)? {
+                builder = builder.tls_mode(TlsMode::StartTls);
+            }
+        }
+
+        if let Some(dry_run) = var("POP3_DRY_RUN") {
+            builder = builder.dry_run(parse_bool(&dry_run)?);
+        }
+
+        if let Some(user) = var("POP3_USER") {
+            builder = match (var("POP3_PASSWORD"), var("POP3_ACCESS_TOKEN")) {
+                (Some(password), None) => builder.login(&user, &password),
+                (None, Some(access_token)) => builder.login_oauth2(&user, &access_token),
+                (None, None) => return Err("missing POP3_PASSWORD or POP3_ACCESS_TOKEN".into()),
+                (Some(_), Some(_)) => return Err("POP3_PASSWORD and POP3_ACCESS_TOKEN are mutually exclusive".into()),
+            };
+        }
+
+        Ok(builder)
+    }
+
+    /// Sets the port to connect. Defaults to the well-known port of the TLS mode.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = Some(port);
+        self
+    }
+
+    /// Sets the TLS mode. Defaults to [`TlsMode::Implicit`].
+    pub fn tls_mode(mut self, tls_mode: TlsMode) -> Self {
+        self.tls_mode = tls_mode;
+        self
+    }
+
+    /// Sets the store of trusted (root) certificates. Defaults to system certificates.
+    pub fn root_store(mut self, root_store: RootCertStore) -> Self {
+        self.root_store = Some(root_store);
+        self
+    }
+
+    /// Authenticate using username and password after connecting.
+    ///
+    /// # Arguments
+    ///
+    /// * `user`     - Name of the user, typically it's e-mail address.
+    /// * `password` - Password of the user.
+    pub fn login(mut self, user: &str, password: &str) -> Self {
+        self.credentials = Some(Credentials::Password(user.to_string(), password.to_string()));
+        self
+    }
+
+    /// Authenticate using an OAuth 2.0 access token (XOAUTH2) after connecting.
+    ///
+    /// # Arguments
+    ///
+    /// * `user`         - Name of the user, typically it's e-mail address.
+    /// * `access_token` - OAuth 2.0 access token of the user.
+    pub fn login_oauth2(mut self, user: &str, access_token: &str) -> Self {
+        self.credentials = Some(Credentials::AccessToken(user.to_string(), access_token.to_string()));
+        self
+    }
+
+    /// Authenticate using OAuth 2.0 access tokens of a provider (XOAUTH2) after connecting.
+    ///
+    /// The provider is asked for a token on each connect, so that clones
+    /// of the builder, e.g. used to reconnect by [`crate::Pop3Watcher`],
+    /// never authenticate with an expired token.
+    ///
+    /// # Arguments
+    ///
+    /// * `user`     - Name of the user, typically it's e-mail address.
+    /// * `provider` - Provider of the access tokens of the user.
+    pub fn login_oauth2_with(mut self, user: &str, provider: Arc<dyn TokenProvider + Send + Sync>) -> Self {
+        self.credentials = Some(Credentials::TokenProvider(user.to_string(), provider));
+        self
+    }
+
+    /// Enables keep-alive. See [`Pop3Connection::set_keep_alive`].
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    /// Limits the download rate. See [`Pop3Connection::set_download_rate`].
+    pub fn download_rate(mut self, bytes_per_second: u64) -> Self {
+        self.download_rate = Some(bytes_per_second);
+        self
+    }
+
+    /// Enables dry-run mode. See [`Pop3Connection::set_dry_run`].
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Enables pipelining, if the server announces PIPELINING.
+    ///
+    /// After authentication, the capabilities of the server are queried
+    /// using CAPA; pipelining stays disabled, if PIPELINING is not announced.
+    /// See [`Pop3Connection::set_pipelining`].
+    pub fn pipelining(mut self, pipelining: bool) -> Self {
+        self.pipelining = pipelining;
+        self
+    }
+
+    /// Enables strict size checks. See [`Pop3Connection::set_strict_size_check`].
+    pub fn strict_size_check(mut self, strict: bool) -> Self {
+        self.strict_size_check = strict;
+        self
+    }
+
+    /// Records the session to a transcript file. See [`Pop3Connection::set_transcript`].
+    ///
+    /// Recording starts after TLS was established, so that the transcript
+    /// can be replayed without TLS.
+    pub fn record_transcript(mut self, path: impl AsRef<Path>) -> Self {
+        self.transcript = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Reports metrics of the connection. See [`Pop3Connection::set_metrics`].
+    ///
+    /// Metrics are reported from the authentication on.
+    pub fn metrics(mut self, sink: Arc<dyn MetricsSink>) -> Self {
+        self.metrics = Some(sink);
+        self
+    }
+
+    /// Connects through a SOCKS5 or HTTP proxy.
+    ///
+    /// Proxies are only supported by [`Pop3ConnectionBuilder::connect`];
+    /// async connections fail, if a proxy is set.
+    pub fn proxy(mut self, proxy: Pop3Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Sets the observer of the events of the session. See [`Pop3Connection::set_observer`].
+    pub fn observer(mut self, observer: impl Pop3SessionObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    /// Sets the resolver of the host name used by async connections.
+    ///
+    /// Defaults to the resolver of the runtime, which uses the blocking
+    /// system resolver. The host name is still used to verify the
+    /// certificate of the server.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::net::SocketAddr;
+    /// use rust_pop3_client::Pop3ConnectionBuilder;
+    ///
+    /// let builder = Pop3ConnectionBuilder::new("pop.example.com")
+    ///     .resolver(|_host: String, port: u16| async move {
+    ///         Ok(vec!(SocketAddr::from(([192, 0, 2, 1], port))))
+    ///     });
+    /// ```
+    #[cfg(any(feature = "tokio", feature = "smol"))]
+    pub fn resolver(mut self, resolver: impl AsyncResolver + 'static) -> Self {
+        self.resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Returns the key identifying the account of this builder.
+    pub(crate) fn account_key(&self) -> Pop3AccountKey {
+        let user = match &self.credentials {
+            Some(Credentials::Password(user, _)) => Some(user.clone()),
+            Some(Credentials::AccessToken(user, _)) => Some(user.clone()),
+            Some(Credentials::TokenProvider(user, _)) => Some(user.clone()),
+            None => None
+        };
+
+        Pop3AccountKey {
+            host: self.host.clone(),
+            port: self.port.unwrap_or(self.tls_mode.default_port()),
+            user
+        }
+    }
+
+    /// Connects to the POP3 server and authenticates, if credentials were specified.
+    pub fn connect(self) -> Result<Pop3Connection, Box<dyn Error>> {
+        let port = self.port.unwrap_or(self.tls_mode.default_port());
+        OperationSpan::connect(&self.host, port).run_blocking(|| {
+            let stream = match &self.proxy {
+                Some(proxy) => proxy.connect(&self.host, port)?,
+                None => TcpStream::connect((self.host.as_str(), port))?
+            };
+
+            let mut connection = match self.tls_mode {
+                TlsMode::Implicit => {
+                    let root_store = Self::root_store_or_native(self.root_store)?;
+                    Pop3Connection::open(Stream::tls(stream, &self.host, root_store)?)?
+                },
+                TlsMode::StartTls => {
+                    let root_store = Self::root_store_or_native(self.root_store)?;
+                    let mut connection = Pop3Connection::open(Stream::Plain(stream))?;
+                    connection.start_tls(&self.host, root_store)?;
+                    connection
+                },
+                TlsMode::Plain => Pop3Connection::open(Stream::Plain(stream))?
+            };
+            connection.set_keep_alive(self.keep_alive);
+            connection.set_download_rate(self.download_rate);
+            connection.set_dry_run(self.dry_run);
+            connection.set_strict_size_check(self.strict_size_check);
+            if let Some(path) = &self.transcript {
+                connection.set_transcript(Some(Box::new(File::create(path)?)));
+            }
+            connection.set_metrics(self.metrics);
+            if let Some(observer) = self.observer {
+                connection.inner.start_observing(observer, &self.host, port);
+            }
+
+            match self.credentials {
+                Some(Credentials::Password(user, password)) => {
+                    connection.login(&user, &password)?;
+                },
+                Some(Credentials::AccessToken(user, access_token)) => {
+                    let provider = move || -> Result<String, Box<dyn Error>> { Ok(access_token.clone()) };
+                    connection.login_oauth2(&user, &provider)?;
+                },
+                Some(Credentials::TokenProvider(user, provider)) => {
+                    connection.login_oauth2(&user, provider.as_ref())?;
+                },
+                None => { }
+            }
+            if self.pipelining {
+                crate::block_on(connection.inner.probe_pipelining())?;
+            }
+
+            Ok(connection)
+        })
+    }
+
+    /// Connects to the POP3 server asynchronously using tokio and authenticates, if credentials were specified.
+    ///
+    /// Proxies are not supported by async connections.
+    #[cfg(feature = "tokio")]
+    pub async fn connect_async(mut self) -> Result<TokioPop3Connection, Pop3AsyncError> {
+        if self.proxy.is_some() {
+            return Err("proxies are not supported by async connections".into());
+        }
+        let port = self.port.unwrap_or(self.tls_mode.default_port());
+        let connection = TokioPop3Connection::open(&self.host, port, self.tls_mode, self.root_store.take(), self.resolver.as_deref()).await?;
+        self.setup_async(connection, port).await
+    }
+
+    /// Connects to the POP3 server asynchronously using smol and authenticates, if credentials were specified.
+    ///
+    /// The connection is based on `async-io`, so it can be used by async-std as well.
+    /// Proxies are not supported by async connections.
+    #[cfg(feature = "smol")]
+    pub async fn connect_smol(mut self) -> Result<SmolPop3Connection, Pop3AsyncError> {
+        if self.proxy.is_some() {
+            return Err("proxies are not supported by async connections".into());
+        }
+        let port = self.port.unwrap_or(self.tls_mode.default_port());
+        let connection = SmolPop3Connection::open(&self.host, port, self.tls_mode, self.root_store.take(), self.resolver.as_deref()).await?;
+        self.setup_async(connection, port).await
+    }
+
+    #[cfg(any(feature = "tokio", feature = "smol"))]
+    async fn setup_async<S>(self, mut connection: AsyncPop3Connection<S>, port: u16) -> Result<AsyncPop3Connection<S>, Pop3AsyncError>
+    where
+        S: futures_util::io::AsyncRead + futures_util::io::AsyncWrite + TlsInfo + Unpin
+    {
+        if let Some(observer) = self.observer {
+            connection.start_observing(observer, &self.host, port);
+        }
+        connection.set_keep_alive(self.keep_alive);
+        connection.set_download_rate(self.download_rate);
+        connection.set_strict_size_check(self.strict_size_check);
+        connection.set_dry_run(self.dry_run);
+        if let Some(path) = self.transcript {
+            connection.set_transcript(Some(Box::new(File::create(path)?)));
+        }
+        connection.set_metrics(self.metrics);
+
+        match self.credentials {
+            Some(Credentials::Password(user, password)) => {
+                connection.login(&user, &password).await?;
+            },
+            Some(Credentials::AccessToken(user, access_token)) => {
+                let provider = move || -> Result<String, Box<dyn Error>> { Ok(access_token.clone()) };
+                connection.login_oauth2(&user, &provider).await?;
+            },
+            Some(Credentials::TokenProvider(user, provider)) => {
+                connection.login_oauth2(&user, provider.as_ref()).await?;
+            },
+            None => { }
+        }
+        if self.pipelining {
+            connection.probe_pipelining().await?;
+        }
+
+        Ok(connection)
+    }
+
+    fn root_store_or_native(root_store: Option<RootCertStore>) -> Result<RootCertStore, Box<dyn Error>> {
+        match root_store {
+            Some(root_store) => Ok(root_store),
+            None => native_root_store()
+        }
+    }
+}
+
+/// Returns a store containing the system certificates.
+pub(crate) fn native_root_store() -> Result<RootCertStore, Box<dyn Error>> {
+    let mut root_store = RootCertStore::empty();
+    for cert in rustls_native_certs::load_native_certs()? {
+        root_store.add(&rustls::Certificate(cert.0))?;
+    }
+
+    Ok(root_store)
+}
+
+fn parse_bool(value: &str) -> Result<bool, Box<dyn Error>> {
+    match value.to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Ok(true),
+        "0" | "false" | "no" | "off" | "" => Ok(false),
+        _ => Err(format!("invalid boolean value: {}", value).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::test_util;
+
+    fn from_map(vars: &[(&str, &str)]) -> Result<Pop3ConnectionBuilder, Box<dyn Error>> {
+        let vars: HashMap<String, String> = vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        Pop3ConnectionBuilder::from_vars(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn test_from_env_requires_host() {
+        assert!(from_map(&[]).is_err());
+    }
+
+    #[test]
+    fn test_from_env_defaults() {
+        let builder = from_map(&[("POP3_HOST", "pop.example.com")]).unwrap();
+        assert_eq!("pop.example.com", builder.host);
+        assert_eq!(None, builder.port);
+        assert_eq!(TlsMode::Implicit, builder.tls_mode);
+        assert!(builder.credentials.is_none());
+        assert!(!builder.dry_run);
+    }
+
+    #[test]
+    fn test_from_env_dry_run() {
+        let builder = from_map(&[("POP3_HOST", "pop.example.com"), ("POP3_DRY_RUN", "1")]).unwrap();
+        assert!(builder.dry_run);
+    }
+
+    #[test]
+    fn test_from_env_starttls_with_password() {
+        let builder = from_map(&[
+            ("POP3_HOST", "pop.example.com"),
+            ("POP3_PORT", "1110"),
+            ("POP3_STARTTLS", "yes"),
+            ("POP3_USER", "user@example.com"),
+            ("POP3_PASSWORD", "secret"),
+        ]).unwrap();
+        assert_eq!(Some(1110), builder.port);
+        assert_eq!(TlsMode::StartTls, builder.tls_mode);
+        assert!(matches!(builder.credentials, Some(Credentials::Password(user, password)) if user == "user@example.com" && password == "secret"));
+    }
+
+    #[test]
+    fn test_from_env_access_token() {
+        let builder = from_map(&[
+            ("POP3_HOST", "pop.example.com"),
+            ("POP3_USER", "user@example.com"),
+            ("POP3_ACCESS_TOKEN", "token"),
+        ]).unwrap();
+        assert!(matches!(builder.credentials, Some(Credentials::AccessToken(_, token)) if token == "token"));
+    }
+
+    #[test]
+    fn test_from_env_invalid_values() {
+        assert!(from_map(&[("POP3_HOST", "pop.example.com"), ("POP3_PORT", "pop3")]).is_err());
+        assert!(from_map(&[("POP3_HOST", "pop.example.com"), ("POP3_STARTTLS", "maybe")]).is_err());
+        assert!(from_map(&[("POP3_HOST", "pop.example.com"), ("POP3_USER", "user")]).is_err());
+    }
+
+    #[test]
+    fn test_default_port() {
+        assert_eq!(995, TlsMode::Implicit.default_port());
+        assert_eq!(110, TlsMode::StartTls.default_port());
+        assert_eq!(110, TlsMode::Plain.default_port());
+    }
+
+    #[test]
+    fn test_pipelining_requires_capability() {
+        let cases = [
+            ("+OK\r\nUSER\r\nPIPELINING\r\n.\r\n", true),
+            ("+OK\r\nUSER\r\n.\r\n", false),
+            ("-ERR unknown command\r\n", false),
+        ];
+
+        for (response, pipelining) in cases {
+            let (port, server) = test_util::serve(&[("CAPA", response)]);
+            let connection = Pop3ConnectionBuilder::new("127.0.0.1")
+                .tls_mode(TlsMode::Plain)
+                .port(port)
+                .pipelining(true)
+                .connect()
+                .unwrap();
+            assert_eq!(pipelining, connection.is_pipelining(), "{}", response);
+            assert_eq!(vec!("CAPA"), server.join().unwrap());
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+#[cfg(test)]
+use std::task::{Context, Poll, Waker};
+
+use crate::AsyncTimer;
+
+/// Source of the current time used by keep-alive and idle tracking.
+///
+/// Sleeping, e.g. for timeouts and backoff, is done by an [`crate::AsyncTimer`].
+pub(crate) trait Clock: Send + Sync {
+
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+}
+
+/// Clock of the system.
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Timer of blocking code, which sleeps the current thread.
+pub(crate) struct ThreadTimer;
+
+impl AsyncTimer for ThreadTimer {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(async move { thread::sleep(duration) })
+    }
+}
+
+/// Clock and timer, whose time only passes when advanced manually.
+///
+/// Sleeps complete, once the clock was advanced beyond their deadline.
+#[cfg(test)]
+#[derive(Clone)]
+pub(crate) struct ManualClock(Arc<Mutex<ManualState>>);
+
+#[cfg(test)]
+struct ManualState {
+    now: Instant,
+    sleepers: Vec<Waker>,
+}
+
+#[cfg(test)]
+impl ManualClock {
+
+    pub(crate) fn new() -> Self {
+        ManualClock(Arc::new(Mutex::new(ManualState { now: Instant::now(), sleepers: vec!() })))
+    }
+
+    /// Advances the time and wakes all sleeps.
+    pub(crate) fn advance(&self, duration: Duration) {
+        let sleepers = {
+            let mut state = self.0.lock().unwrap();
+            state.now += duration;
+            std::mem::take(&mut state.sleepers)
+        };
+        sleepers.into_iter().for_each(Waker::wake);
+    }
+
+    /// Returns the count of pending sleeps.
+    pub(crate) fn sleepers(&self) -> usize {
+        self.0.lock().unwrap().sleepers.len()
+    }
+}
+
+#[cfg(test)]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.0.lock().unwrap().now
+    }
+}
+
+#[cfg(test)]
+impl AsyncTimer for ManualClock {
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(ManualSleep { clock: self.clone(), deadline: self.now() + duration })
+    }
+}
+
+#[cfg(test)]
+struct ManualSleep {
+    clock: ManualClock,
+    deadline: Instant,
+}
+
+#[cfg(test)]
+impl Future for ManualSleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.clock.0.lock().unwrap();
+        if state.now >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        state.sleepers.push(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::new();
+        let start = clock.now();
+        let mut sleep = clock.sleep(Duration::from_secs(10));
+        assert!((&mut sleep).now_or_never().is_none());
+        assert_eq!(1, clock.sleepers());
+
+        clock.advance(Duration::from_secs(5));
+        assert!((&mut sleep).now_or_never().is_none());
+        clock.advance(Duration::from_secs(5));
+        assert!(sleep.now_or_never().is_some());
+        assert_eq!(Duration::from_secs(10), clock.now() - start);
+    }
+}
@@ -0,0 +1,92 @@
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::Pop3MessageMeta;
+use crate::sink::{self, MessageSink};
+
+/// Delivers messages by piping them to an external command, e.g. `procmail`.
+///
+/// The command is spawned for each message and receives the message on its
+/// standard input; line endings are converted to LF. The delivery fails, if
+/// the command exits with a non-zero status, so synchronization does not
+/// record or delete the message.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::CommandSink;
+///
+/// let sink = CommandSink::new("/usr/bin/procmail").arg("-d").arg("user");
+/// ```
+pub struct CommandSink {
+    program: OsString,
+    args: Vec<OsString>,
+}
+
+impl CommandSink {
+
+    /// Returns a new command sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `program` - path or name of the command to spawn
+    pub fn new(program: impl AsRef<OsStr>) -> Self {
+        CommandSink { program: program.as_ref().to_os_string(), args: vec!() }
+    }
+
+    /// Adds an argument passed to the command.
+    pub fn arg(mut self, arg: impl AsRef<OsStr>) -> Self {
+        self.args.push(arg.as_ref().to_os_string());
+        self
+    }
+}
+
+impl MessageSink for CommandSink {
+    fn deliver(&mut self, _message: &Pop3MessageMeta, content: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()?;
+
+        let mut stdin = child.stdin.take().ok_or("failed to open stdin of delivery command")?;
+        let written = stdin.write_all(&sink::to_lf(content));
+        drop(stdin);
+
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(format!("delivery command failed: {}", status).into());
+        }
+        written?;
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn meta() -> Pop3MessageMeta {
+        Pop3MessageMeta { message_id: 1, message_size: 10, unique_id: Some("uid1".into()) }
+    }
+
+    #[test]
+    fn test_deliver_pipes_message_to_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("message");
+        let mut sink = CommandSink::new("sh").arg("-c").arg("cat > \"$0\"").arg(&path);
+
+        sink.deliver(&meta(), b"Subject: hi\r\n\r\nbody\r\n").unwrap();
+        assert_eq!("Subject: hi\n\nbody\n", fs::read_to_string(&path).unwrap());
+    }
+
+    #[test]
+    fn test_deliver_fails_on_non_zero_exit() {
+        let mut sink = CommandSink::new("sh").arg("-c").arg("exit 75");
+        assert!(sink.deliver(&meta(), b"Subject: hi\r\n\r\n").is_err());
+    }
+}
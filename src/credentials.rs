@@ -0,0 +1,47 @@
+//! Storage of POP3 passwords in the platform secret service.
+//!
+//! Passwords are stored in the macOS Keychain, the Windows Credential Manager
+//! or the Secret Service (libsecret) on Linux and BSD, keyed by host and user.
+
+use std::error::Error;
+
+use keyring::Entry;
+
+fn entry(host: &str, user: &str) -> Result<Entry, Box<dyn Error>> {
+    Ok(Entry::new(&format!("pop3://{}", host), user)?)
+}
+
+/// Returns the password stored for the given host and user.
+///
+/// # Arguments
+///
+/// * `host` - IP-Address or host name of the POP3 server
+/// * `user` - Name of the user, typically it's e-mail address.
+pub fn load_password(host: &str, user: &str) -> Result<String, Box<dyn Error>> {
+    Ok(entry(host, user)?.get_password()?)
+}
+
+/// Stores the password for the given host and user.
+///
+/// An already stored password is replaced.
+///
+/// # Arguments
+///
+/// * `host`     - IP-Address or host name of the POP3 server
+/// * `user`     - Name of the user, typically it's e-mail address.
+/// * `password` - Password to store.
+pub fn store_password(host: &str, user: &str, password: &str) -> Result<(), Box<dyn Error>> {
+    entry(host, user)?.set_password(password)?;
+    Ok(())
+}
+
+/// Removes the password stored for the given host and user.
+///
+/// # Arguments
+///
+/// * `host` - IP-Address or host name of the POP3 server
+/// * `user` - Name of the user, typically it's e-mail address.
+pub fn delete_password(host: &str, user: &str) -> Result<(), Box<dyn Error>> {
+    entry(host, user)?.delete_password()?;
+    Ok(())
+}
@@ -0,0 +1,74 @@
+//! CSV export of message listings.
+
+use std::error::Error;
+use std::io::Write;
+
+use crate::Pop3MessageSummary;
+
+/// Writes message summaries as CSV (RFC 4180).
+///
+/// The columns are `id`, `uid`, `size`, `from`, `subject` and `date`;
+/// the first line contains the column names.
+///
+/// # Arguments
+///
+/// * `writer`    - writer of the CSV data
+/// * `summaries` - summaries of the messages to write
+pub fn write_summaries(writer: &mut impl Write, summaries: &[Pop3MessageSummary]) -> Result<(), Box<dyn Error>> {
+    writer.write_all(b"id,uid,size,from,subject,date\r\n")?;
+    for summary in summaries {
+        let fields = [
+            summary.meta.message_id.to_string(),
+            summary.meta.unique_id.clone().unwrap_or_default(),
+            summary.meta.message_size.to_string(),
+            summary.from().unwrap_or_default().to_string(),
+            summary.subject().unwrap_or_default().to_string(),
+            summary.date().unwrap_or_default().to_string(),
+        ];
+
+        let line: Vec<String> = fields.iter().map(|field| quote(field)).collect();
+        writer.write_all(line.join(",").as_bytes())?;
+        writer.write_all(b"\r\n")?;
+    }
+
+    Ok(())
+}
+
+fn quote(field: &str) -> String {
+    match field.contains([',', '"', '\r', '\n']) {
+        true => format!("\"{}\"", field.replace('"', "\"\"")),
+        _ => field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Pop3Headers, Pop3MessageMeta};
+
+    #[test]
+    fn test_write_summaries() {
+        let summaries = [
+            Pop3MessageSummary {
+                meta: Pop3MessageMeta { message_id: 1, message_size: 1234, unique_id: Some("uid1".into()) },
+                headers: vec!(
+                    ("From".into(), "\"Doe, John\" <john@example.com>".into()),
+                    ("Subject".into(), "Hello".into()),
+                    ("Date".into(), "Thu, 1 Jan 1970 00:00:00 +0000".into()),
+                ).into(),
+            },
+            Pop3MessageSummary {
+                meta: Pop3MessageMeta { message_id: 2, message_size: 42, unique_id: None },
+                headers: Pop3Headers::default(),
+            },
+        ];
+
+        let mut output = vec!();
+        write_summaries(&mut output, &summaries).unwrap();
+        assert_eq!(
+            "id,uid,size,from,subject,date\r\n\
+             1,uid1,1234,\"\"\"Doe, John\"\" <john@example.com>\",Hello,\"Thu, 1 Jan 1970 00:00:00 +0000\"\r\n\
+             2,,42,,,\r\n",
+            String::from_utf8(output).unwrap());
+    }
+}
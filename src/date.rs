@@ -0,0 +1,160 @@
+//! Parsing of RFC 2822 date-time values, e.g. of the Date header, and conversion of calendar dates.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+
+/// Parses an RFC 2822 date-time, e.g. `Tue, 1 Jul 2003 10:52:37 +0200`.
+///
+/// Common deviations are tolerated: a missing day of week, missing seconds,
+/// two-digit years, obsolete zone names and trailing comments.
+/// Returns `None`, if the value can not be parsed.
+pub(crate) fn parse_rfc2822(value: &str) -> Option<SystemTime> {
+    let (seconds, _) = parse(value)?;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(seconds).ok()?))
+}
+
+/// Parses an RFC 2822 date-time, keeping its offset to UTC.
+///
+/// See [`parse_rfc2822`] for the tolerated deviations.
+#[cfg(feature = "chrono")]
+pub(crate) fn parse_rfc2822_chrono(value: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    let (seconds, offset) = parse(value)?;
+    let offset = chrono::FixedOffset::east_opt(i32::try_from(offset).ok()?)?;
+    Some(chrono::DateTime::from_timestamp(seconds, 0)?.with_timezone(&offset))
+}
+
+/// Returns the calendar date of an RFC 2822 date-time in its own zone as `YYYY-MM-DD`.
+///
+/// See [`parse_rfc2822`] for the tolerated deviations.
+#[cfg(feature = "mail-parser")]
+pub(crate) fn format_date(value: &str) -> Option<String> {
+    let (seconds, offset) = parse(value)?;
+    let (year, month, day) = civil_from_days((seconds + offset).div_euclid(86400));
+    Some(format!("{:04}-{:02}-{:02}", year, month, day))
+}
+
+/// Returns the seconds since the UNIX epoch and the offset to UTC in seconds.
+fn parse(value: &str) -> Option<(i64, i64)> {
+    let value = value.split('(').next()?;
+    let mut tokens = value.split(|c: char| c.is_whitespace() || c == ',').filter(|token| !token.is_empty()).peekable();
+    if tokens.peek()?.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        tokens.next();
+    }
+
+    let day: u32 = tokens.next()?.parse().ok()?;
+    let month = tokens.next()?.get(..3)?.to_ascii_lowercase();
+    let month = MONTHS.iter().position(|name| *name == month)? as u32 + 1;
+    let year: i64 = match tokens.next()? {
+        year if year.len() <= 2 => match year.parse::<i64>().ok()? {
+            year @ 0..=49 => 2000 + year,
+            year => 1900 + year,
+        },
+        year => year.parse().ok()?,
+    };
+
+    let mut time = tokens.next()?.split(':');
+    let hour: i64 = time.next()?.parse().ok()?;
+    let minute: i64 = time.next()?.parse().ok()?;
+    let second: i64 = time.next().map(|second| second.parse()).unwrap_or(Ok(0)).ok()?;
+    let offset = tokens.next().map(zone_offset).unwrap_or(Some(0))?;
+
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let seconds = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    Some((seconds, offset))
+}
+
+/// Returns the offset of a zone to UTC in seconds.
+fn zone_offset(zone: &str) -> Option<i64> {
+    let hours = match zone.to_ascii_uppercase().as_str() {
+        "UT" | "UTC" | "GMT" | "Z" => 0,
+        "EDT" => -4,
+        "EST" | "CDT" => -5,
+        "CST" | "MDT" => -6,
+        "MST" | "PDT" => -7,
+        "PST" => -8,
+        _ => {
+            let (sign, digits) = match zone.as_bytes().first()? {
+                b'+' => (1, &zone[1..]),
+                b'-' => (-1, &zone[1..]),
+                _ => return Some(0)
+            };
+            if digits.len() != 4 {
+                return None;
+            }
+            let value: i64 = digits.parse().ok()?;
+            return Some(sign * ((value / 100) * 3600 + (value % 100) * 60));
+        }
+    };
+
+    Some(hours * 3600)
+}
+
+/// Returns the number of days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Returns year, month and day of a number of days since 1970-01-01 (inverse of [`days_from_civil`]).
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamp(value: &str) -> Option<u64> {
+        parse_rfc2822(value).map(|time| time.duration_since(UNIX_EPOCH).unwrap().as_secs())
+    }
+
+    #[test]
+    fn test_parse_rfc2822() {
+        assert_eq!(Some(1057049557), timestamp("Tue, 1 Jul 2003 10:52:37 +0200"));
+        assert_eq!(Some(1057049557), timestamp("1 Jul 2003 08:52:37 GMT"));
+        assert_eq!(Some(1057049520), timestamp("Tue, 01 jul 03 08:52 +0000 (UTC)"));
+        assert_eq!(Some(1057049557), timestamp("Tue,1 July 2003 04:52:37 EDT"));
+        assert_eq!(Some(951782400), timestamp("Tue, 29 Feb 2000 00:00:00 +0000"));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_parse_rfc2822_chrono() {
+        let date_time = parse_rfc2822_chrono("Tue, 1 Jul 2003 10:52:37 +0200").unwrap();
+        assert_eq!("2003-07-01T10:52:37+02:00", date_time.to_rfc3339());
+    }
+
+    #[cfg(feature = "mail-parser")]
+    #[test]
+    fn test_format_date() {
+        assert_eq!(Some("2003-07-01".into()), format_date("Tue, 1 Jul 2003 00:52:37 +0200"));
+        assert_eq!(Some("2000-02-29".into()), format_date("Tue, 29 Feb 2000 23:00:00 -0100"));
+        assert_eq!(None, format_date("yesterday"));
+    }
+
+    #[test]
+    fn test_parse_rfc2822_invalid() {
+        assert_eq!(None, timestamp(""));
+        assert_eq!(None, timestamp("yesterday"));
+        assert_eq!(None, timestamp("Tue, 1 Foo 2003 10:52:37 +0200"));
+        assert_eq!(None, timestamp("Tue, 1 Jul 2003 25:52:37 +0200"));
+    }
+}
@@ -0,0 +1,90 @@
+use sha2::{Digest, Sha256};
+
+/// Digest computed over the exact octets of retrieved messages.
+pub trait MessageDigest {
+    /// Feeds data into the digest.
+    fn update(&mut self, data: &[u8]);
+
+    /// Returns the digest of all data fed so far and resets the digest.
+    fn finalize_reset(&mut self) -> Vec<u8>;
+}
+
+/// SHA-256 message digest.
+#[derive(Clone, Default)]
+pub struct Sha256Digest {
+    hasher: Sha256,
+}
+
+impl Sha256Digest {
+
+    /// Returns a new SHA-256 digest.
+    pub fn new() -> Self {
+        Sha256Digest::default()
+    }
+}
+
+impl MessageDigest for Sha256Digest {
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(&mut self.hasher, data);
+    }
+
+    fn finalize_reset(&mut self) -> Vec<u8> {
+        self.hasher.finalize_reset().to_vec()
+    }
+}
+
+/// BLAKE3 message digest.
+#[cfg(feature = "blake3")]
+#[derive(Clone, Default)]
+pub struct Blake3Digest {
+    hasher: blake3::Hasher,
+}
+
+#[cfg(feature = "blake3")]
+impl Blake3Digest {
+
+    /// Returns a new BLAKE3 digest.
+    pub fn new() -> Self {
+        Blake3Digest::default()
+    }
+}
+
+#[cfg(feature = "blake3")]
+impl MessageDigest for Blake3Digest {
+    fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    fn finalize_reset(&mut self) -> Vec<u8> {
+        let hash = self.hasher.finalize();
+        self.hasher.reset();
+        hash.as_bytes().to_vec()
+    }
+}
+
+/// Returns a digest as lower case hex string.
+pub fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256() {
+        let mut digest = Sha256Digest::new();
+        digest.update(b"a");
+        digest.update(b"bc");
+        assert_eq!("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad", to_hex(&digest.finalize_reset()));
+        assert_eq!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855", to_hex(&digest.finalize_reset()));
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn test_blake3() {
+        let mut digest = Blake3Digest::new();
+        digest.update(b"abc");
+        assert_eq!("6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85", to_hex(&digest.finalize_reset()));
+    }
+}
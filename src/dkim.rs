@@ -0,0 +1,149 @@
+use std::error::Error;
+
+use mail_auth::{AuthenticatedMessage, DkimResult, MessageAuthenticator};
+
+use futures_util::{AsyncRead, AsyncWrite};
+
+use crate::{AsyncPop3Connection, Pop3AsyncError, Pop3Connection};
+
+/// Outcome of the verification of a DKIM signature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DkimStatus {
+    /// The signature is valid.
+    Pass,
+
+    /// The signature could not be evaluated, e.g. due to an unsupported algorithm.
+    Neutral(String),
+
+    /// The signature is invalid, e.g. the message was modified.
+    Fail(String),
+
+    /// The signature can not be verified, e.g. the public key is missing.
+    PermError(String),
+
+    /// The signature could not be verified due to a temporary error, e.g. of DNS.
+    TempError(String),
+
+    /// The message is not signed.
+    None,
+}
+
+/// Result of the verification of a single DKIM signature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DkimSignatureResult {
+    /// signing domain (`d=` tag), if the signature could be parsed
+    pub domain: Option<String>,
+
+    /// selector of the public key (`s=` tag), if the signature could be parsed
+    pub selector: Option<String>,
+
+    /// outcome of the verification
+    pub status: DkimStatus,
+}
+
+/// Verifies the DKIM signatures of a message and returns a result per signature.
+///
+/// Public keys are looked up using the DNS configuration of the system.
+/// For unsigned messages, a single result with [`DkimStatus::None`] is returned.
+///
+/// The lookups are performed by a tokio runtime, which is created for the
+/// call, so this function must not be called from async code; an error is
+/// returned within a tokio runtime. Use [`verify_dkim_async`] instead.
+///
+/// # Arguments
+///
+/// * `content` - exact octets of the message
+pub fn verify_dkim(content: &[u8]) -> Result<Vec<DkimSignatureResult>, Box<dyn Error>> {
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return Err("verify_dkim must not be called within an async runtime, use verify_dkim_async".into());
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+    runtime.block_on(verify_dkim_async(content)).map_err(|err| err as Box<dyn Error>)
+}
+
+/// Verifies the DKIM signatures of a message and returns a result per signature.
+///
+/// See [`verify_dkim`] for details. The DNS lookups require a tokio runtime.
+///
+/// # Arguments
+///
+/// * `content` - exact octets of the message
+pub async fn verify_dkim_async(content: &[u8]) -> Result<Vec<DkimSignatureResult>, Pop3AsyncError> {
+    let message = AuthenticatedMessage::parse(content).ok_or("failed to parse message")?;
+    let authenticator = MessageAuthenticator::new_system_conf()?;
+    let outputs = authenticator.verify_dkim(&message).await;
+    let mut results: Vec<DkimSignatureResult> = outputs.iter()
+        .map(|output| DkimSignatureResult {
+            domain: output.signature().map(|signature| signature.d.clone()),
+            selector: output.signature().map(|signature| signature.s.clone()),
+            status: match output.result() {
+                DkimResult::Pass => DkimStatus::Pass,
+                DkimResult::Neutral(err) => DkimStatus::Neutral(err.to_string()),
+                DkimResult::Fail(err) => DkimStatus::Fail(err.to_string()),
+                DkimResult::PermError(err) => DkimStatus::PermError(err.to_string()),
+                DkimResult::TempError(err) => DkimStatus::TempError(err.to_string()),
+                DkimResult::None => DkimStatus::None,
+            },
+        })
+        .collect();
+
+    if results.is_empty() {
+        results.push(DkimSignatureResult { domain: None, selector: None, status: DkimStatus::None });
+    }
+
+    Ok(results)
+}
+
+impl Pop3Connection {
+
+    /// Retrieves a message and verifies its DKIM signatures.
+    ///
+    /// See [`verify_dkim`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    pub fn verify_dkim(&mut self, message_id: u32) -> Result<Vec<DkimSignatureResult>, Box<dyn Error>> {
+        let mut content = vec!();
+        self.retrieve_raw(message_id, &mut content)?;
+        verify_dkim(&content)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncPop3Connection<S> {
+
+    /// Retrieves a message and verifies its DKIM signatures.
+    ///
+    /// See [`verify_dkim_async`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    pub async fn verify_dkim(&mut self, message_id: u32) -> Result<Vec<DkimSignatureResult>, Pop3AsyncError> {
+        let mut content = vec!();
+        self.retrieve_raw(message_id, &mut content).await?;
+        verify_dkim_async(&content).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verify_unsigned_message() {
+        let results = verify_dkim(b"From: alice@example.com\r\nSubject: hi\r\n\r\nbody\r\n").unwrap();
+        assert_eq!(vec!(DkimSignatureResult { domain: None, selector: None, status: DkimStatus::None }), results);
+    }
+
+    #[test]
+    fn test_verify_within_runtime() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let content = b"From: alice@example.com\r\nSubject: hi\r\n\r\nbody\r\n";
+
+        let results = runtime.block_on(verify_dkim_async(content)).unwrap();
+        assert_eq!(DkimStatus::None, results[0].status);
+        assert!(runtime.block_on(async { verify_dkim(content) }).is_err());
+    }
+}
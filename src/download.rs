@@ -0,0 +1,294 @@
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::clock::ThreadTimer;
+use crate::{AsyncTimer, Pop3ConnectionBuilder, Pop3MessageMeta, SyncOptions, SyncStateStore};
+
+/// Downloads all new messages of an account and resumes after connection failures.
+///
+/// The completion of each message is recorded in the state store right after
+/// it was delivered. When the connection breaks, the downloader reconnects,
+/// maps the unique ids to the message numbers of the new session and
+/// continues with the messages, which are still missing.
+///
+/// Before each retry, the downloader waits for an exponential backoff,
+/// which starts at 1 second and is limited to 1 minute. The backoff is
+/// randomized by up to a half (jitter), so clients failing at the same time
+/// do not retry at the same time.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use rust_pop3_client::{JsonFileStateStore, MaildirSink, MessageSink, Pop3ConnectionBuilder, Pop3Downloader};
+///
+/// let builder = Pop3ConnectionBuilder::new("pop.example.com").login("user@example.com", "secret");
+/// let mut downloader = Pop3Downloader::new(builder, JsonFileStateStore::new("state.json"))
+///     .max_retries(3)
+///     .backoff(Duration::from_secs(2), Duration::from_secs(30));
+///
+/// let mut maildir = MaildirSink::new("Maildir").unwrap();
+/// downloader.run(|message, content| maildir.deliver(message, content)).unwrap();
+/// ```
+pub struct Pop3Downloader<S: SyncStateStore> {
+    builder: Pop3ConnectionBuilder,
+    state: S,
+    options: SyncOptions,
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    jitter: bool,
+    timer: Arc<dyn AsyncTimer>,
+}
+
+impl<S: SyncStateStore> Pop3Downloader<S> {
+
+    /// Creates a new downloader, which does not retry failed downloads.
+    ///
+    /// # Arguments
+    ///
+    /// * `builder` - builder used to connect to the server
+    /// * `state`   - store of the synchronization state
+    pub fn new(builder: Pop3ConnectionBuilder, state: S) -> Self {
+        Pop3Downloader {
+            builder,
+            state,
+            options: SyncOptions::default(),
+            max_retries: 0,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            jitter: true,
+            timer: Arc::new(ThreadTimer),
+        }
+    }
+
+    /// Sets the options of the synchronization.
+    pub fn options(mut self, options: SyncOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets how often [`Pop3Downloader::run`] resumes after a failure.
+    ///
+    /// Failures of the handler are never retried.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the backoff before the first retry, which is doubled for each further retry.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial` - delay before the first retry
+    /// * `max`     - maximum delay
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Enables or disables randomizing the backoff by up to a half.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the timer used to wait for the backoff.
+    #[cfg(test)]
+    fn timer(mut self, timer: impl AsyncTimer + 'static) -> Self {
+        self.timer = Arc::new(timer);
+        self
+    }
+
+    /// Returns the delay before the given retry, starting at 0.
+    fn backoff_for(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.min(31));
+        let backoff = self.initial_backoff.saturating_mul(factor).min(self.max_backoff);
+        match self.jitter {
+            true => backoff / 2 + backoff.mul_f64(random_fraction() / 2.0),
+            false => backoff
+        }
+    }
+
+    /// Returns the state store.
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    /// Returns the state store, consuming the downloader.
+    pub fn into_state(self) -> S {
+        self.state
+    }
+
+    /// Downloads all new messages and returns their count.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - invoked with metadata and exact octets of each new message
+    pub fn run(&mut self, mut handler: impl FnMut(&Pop3MessageMeta, &[u8]) -> Result<(), Box<dyn Error>>) -> Result<usize, Box<dyn Error>> {
+        let mut count = 0;
+        let mut retries = 0;
+        loop {
+            let mut handler_failed = false;
+            let result = self.resume(|message, content| {
+                handler(message, content).inspect_err(|_| handler_failed = true)?;
+                count += 1;
+                Ok(())
+            });
+
+            match result {
+                Ok(_) => return Ok(count),
+                Err(err) if handler_failed || retries >= self.max_retries => return Err(err),
+                Err(_) => {
+                    futures_executor::block_on(self.timer.sleep(self.backoff_for(retries)));
+                    retries += 1;
+                }
+            }
+        }
+    }
+
+    /// Connects and downloads the messages, which are still missing, once.
+    ///
+    /// Use this to continue a download, which was interrupted before, e.g.
+    /// after the application was restarted.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - invoked with metadata and exact octets of each new message
+    pub fn resume(&mut self, handler: impl FnMut(&Pop3MessageMeta, &[u8]) -> Result<(), Box<dyn Error>>) -> Result<usize, Box<dyn Error>> {
+        let mut connection = self.builder.clone().connect()?;
+        let count = connection.fetch_new_messages_with(&mut self.state, &self.options, handler)?;
+        if connection.is_open() {
+            connection.quit()?;
+        }
+
+        Ok(count)
+    }
+}
+
+/// Returns a random number in the range [0, 1).
+fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Instant;
+
+    use crate::clock::ManualClock;
+    use crate::{MemoryStateStore, TlsMode, test_util};
+
+    /// Waits until the downloader sleeps.
+    fn wait_for_sleep(clock: &ManualClock) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while clock.sleepers() == 0 {
+            assert!(Instant::now() < deadline, "downloader does not sleep");
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn test_run_resumes_after_connection_failure() {
+        let (port, server) = test_util::serve_sessions(&[
+            &[
+                ("LIST", "+OK\r\n1 10\r\n2 20\r\n.\r\n"),
+                ("UIDL", "+OK\r\n1 uid1\r\n2 uid2\r\n.\r\n"),
+                ("RETR 1", "+OK\r\none\r\n.\r\n"),
+                ("DELE 1", "+OK\r\n"),
+            ],
+            &[
+                ("LIST", "+OK\r\n1 20\r\n2 10\r\n.\r\n"),
+                ("UIDL", "+OK\r\n1 uid2\r\n2 uid1\r\n.\r\n"),
+                ("RETR 1", "+OK\r\ntwo\r\n.\r\n"),
+                ("DELE 1", "+OK\r\n"),
+                ("DELE 2", "+OK\r\n"),
+                ("QUIT", "+OK\r\n"),
+            ],
+        ]);
+
+        let builder = Pop3ConnectionBuilder::new("127.0.0.1").tls_mode(TlsMode::Plain).port(port);
+        let mut downloader = Pop3Downloader::new(builder, MemoryStateStore::new())
+            .options(SyncOptions::new().delete_after_fetch(true))
+            .max_retries(1)
+            .backoff(Duration::ZERO, Duration::ZERO);
+
+        let mut messages = vec!();
+        let count = downloader.run(|_, content| {
+            messages.push(content.to_vec());
+            Ok(())
+        }).unwrap();
+        server.join().unwrap();
+
+        assert_eq!(2, count);
+        assert_eq!(vec!(b"one\r\n".to_vec(), b"two\r\n".to_vec()), messages);
+    }
+
+    #[test]
+    fn test_run_does_not_retry_handler_failure() {
+        let (port, server) = test_util::serve(&[
+            ("LIST", "+OK\r\n1 10\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uid1\r\n.\r\n"),
+            ("RETR 1", "+OK\r\none\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let builder = Pop3ConnectionBuilder::new("127.0.0.1").tls_mode(TlsMode::Plain).port(port);
+        let mut downloader = Pop3Downloader::new(builder, MemoryStateStore::new()).max_retries(3);
+        assert!(downloader.run(|_, _| Err("disk full".into())).is_err());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_backoff_with_manual_clock() {
+        let (port, server) = test_util::serve_sessions(&[
+            &[("LIST", "+OK\r\n1 10\r\n.\r\n")],
+            &[
+                ("LIST", "+OK\r\n1 10\r\n.\r\n"),
+                ("UIDL", "+OK\r\n1 uid1\r\n.\r\n"),
+                ("RETR 1", "+OK\r\none\r\n.\r\n"),
+                ("QUIT", "+OK\r\n"),
+            ],
+        ]);
+
+        let clock = ManualClock::new();
+        let builder = Pop3ConnectionBuilder::new("127.0.0.1").tls_mode(TlsMode::Plain).port(port);
+        let mut downloader = Pop3Downloader::new(builder, MemoryStateStore::new())
+            .max_retries(1)
+            .backoff(Duration::from_secs(60), Duration::from_secs(60))
+            .jitter(false)
+            .timer(clock.clone());
+        let download = thread::spawn(move || downloader.run(|_, _| Ok(())).unwrap());
+
+        wait_for_sleep(&clock);
+        clock.advance(Duration::from_secs(59));
+        wait_for_sleep(&clock);
+        assert!(!download.is_finished());
+        clock.advance(Duration::from_secs(1));
+
+        assert_eq!(1, download.join().unwrap());
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_backoff() {
+        let builder = Pop3ConnectionBuilder::new("127.0.0.1");
+        let downloader = Pop3Downloader::new(builder, MemoryStateStore::new())
+            .backoff(Duration::from_millis(100), Duration::from_millis(300));
+        for retry in [0, 1, 5] {
+            let backoff = [100, 200, 300][retry.min(2) as usize];
+            let delay = downloader.backoff_for(retry);
+            assert!(delay >= Duration::from_millis(backoff / 2) && delay <= Duration::from_millis(backoff), "{:?}", delay);
+        }
+
+        let downloader = downloader.jitter(false);
+        assert_eq!(Duration::from_millis(200), downloader.backoff_for(1));
+        assert_eq!(Duration::from_millis(300), downloader.backoff_for(5));
+    }
+}
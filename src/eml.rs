@@ -0,0 +1,148 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::Pop3MessageMeta;
+use crate::sink::MessageSink;
+
+const INDEX_FILE: &str = "index.tsv";
+
+/// Stores each message as `<uid>.eml` file in a directory.
+///
+/// Messages are stored with their exact octets, including CRLF line endings.
+/// Messages, which are already stored, are skipped. Characters of the unique
+/// id, which are not safe for file names, are percent-encoded; the file
+/// `index.tsv` maps each unique id to its file name (separated by a tab).
+/// Since unique ids are case-sensitive, uppercase letters are percent-encoded
+/// as well, so that file names do not collide on case-insensitive file systems.
+pub struct EmlDirectorySink {
+    path: PathBuf,
+}
+
+impl EmlDirectorySink {
+
+    /// Returns a new EML directory sink. A missing directory is created.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path of the directory
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(&path)?;
+        Ok(EmlDirectorySink { path })
+    }
+
+    /// Returns the path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns true, if the message with the given unique id is already stored.
+    pub fn contains(&self, unique_id: &str) -> bool {
+        self.path.join(file_name(unique_id)).exists()
+    }
+}
+
+impl MessageSink for EmlDirectorySink {
+    fn deliver(&mut self, message: &Pop3MessageMeta, content: &[u8]) -> Result<(), Box<dyn Error>> {
+        let unique_id = message.unique_id.as_deref().ok_or("missing unique id")?;
+        let name = file_name(unique_id);
+        let path = self.path.join(&name);
+        if path.exists() {
+            return Ok(());
+        }
+
+        let tmp_path = self.path.join(format!("{}.tmp", name));
+        let result = File::create(&tmp_path)
+            .and_then(|mut file| {
+                file.write_all(content)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp_path, &path));
+        if let Err(err) = result {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err.into());
+        }
+
+        let mut index = OpenOptions::new().create(true).append(true).open(self.path.join(INDEX_FILE))?;
+        index.write_all(format!("{}\t{}\n", unique_id, name).as_bytes())?;
+        index.sync_all()?;
+        Ok(())
+    }
+}
+
+/// Returns the file name of a message, percent-encoding unsafe characters of the unique id.
+///
+/// Uppercase letters are encoded as well, so that unique ids differing only
+/// in case get distinct names on case-insensitive file systems.
+pub(crate) fn file_name(unique_id: &str) -> String {
+    let mut name = String::new();
+    for byte in unique_id.bytes() {
+        match byte {
+            b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' => name.push(byte as char),
+            _ => name.push_str(&format!("%{:02X}", byte))
+        }
+    }
+
+    name.push_str(".eml");
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(unique_id: &str) -> Pop3MessageMeta {
+        Pop3MessageMeta { message_id: 1, message_size: 0, unique_id: Some(unique_id.to_string()) }
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!("abc-123_%58.eml", file_name("abc-123_X"));
+        assert_eq!("a%2Fb%2E%25.eml", file_name("a/b.%"));
+    }
+
+    #[test]
+    fn test_deliver_preserves_octets() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = EmlDirectorySink::new(dir.path()).unwrap();
+        sink.deliver(&meta("uid/1"), b"Subject: hi\r\n\r\nbody\r\n").unwrap();
+
+        assert!(sink.contains("uid/1"));
+        assert_eq!(b"Subject: hi\r\n\r\nbody\r\n".to_vec(), fs::read(dir.path().join("uid%2F1.eml")).unwrap());
+        assert_eq!("uid/1\tuid%2F1.eml\n", fs::read_to_string(dir.path().join(INDEX_FILE)).unwrap());
+    }
+
+    #[test]
+    fn test_skip_existing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = EmlDirectorySink::new(dir.path()).unwrap();
+        sink.deliver(&meta("uid1"), b"first").unwrap();
+        sink.deliver(&meta("uid1"), b"second").unwrap();
+
+        assert_eq!(b"first".to_vec(), fs::read(dir.path().join("uid1.eml")).unwrap());
+        assert_eq!("uid1\tuid1.eml\n", fs::read_to_string(dir.path().join(INDEX_FILE)).unwrap());
+    }
+
+    #[test]
+    fn test_unique_ids_differing_in_case() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = EmlDirectorySink::new(dir.path()).unwrap();
+        sink.deliver(&meta("uidA"), b"first").unwrap();
+        sink.deliver(&meta("uida"), b"second").unwrap();
+
+        assert_ne!(file_name("uidA").to_lowercase(), file_name("uida").to_lowercase());
+        assert_eq!(b"first".to_vec(), fs::read(dir.path().join(file_name("uidA"))).unwrap());
+        assert_eq!(b"second".to_vec(), fs::read(dir.path().join(file_name("uida"))).unwrap());
+        assert_eq!("uidA\tuid%41.eml\nuida\tuida.eml\n", fs::read_to_string(dir.path().join(INDEX_FILE)).unwrap());
+    }
+
+    #[test]
+    fn test_missing_unique_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = EmlDirectorySink::new(dir.path()).unwrap();
+        let message = Pop3MessageMeta { message_id: 1, message_size: 0, unique_id: None };
+        assert!(sink.deliver(&message, b"content").is_err());
+    }
+}
@@ -0,0 +1,386 @@
+//! Decoding of content transfer encodings (RFC 2045).
+
+use std::error::Error;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+use crate::headers;
+use crate::sink;
+use crate::text;
+
+/// Decodes quoted-printable data.
+///
+/// Soft line breaks are removed and trailing whitespace of lines is ignored.
+/// Invalid escape sequences are kept as they are.
+///
+/// # Arguments
+///
+/// * `data` - quoted-printable encoded data
+pub fn decode_quoted_printable(data: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(data.len());
+    for line in data.split_inclusive(|&byte| byte == b'\n') {
+        let (content, line_break) = match line.strip_suffix(b"\r\n").or_else(|| line.strip_suffix(b"\n")) {
+            Some(content) => (content, &line[content.len()..]),
+            None => (line, &b""[..])
+        };
+        let content = match content.iter().rposition(|&byte| byte != b' ' && byte != b'\t') {
+            Some(end) => &content[..=end],
+            None => &b""[..]
+        };
+
+        let (content, soft_break) = match content.strip_suffix(b"=") {
+            Some(content) => (content, true),
+            None => (content, false)
+        };
+
+        unescape(content, &mut result);
+        if !soft_break {
+            result.extend_from_slice(line_break);
+        }
+    }
+
+    result
+}
+
+/// Appends data with `=XX` escape sequences replaced by the byte values.
+fn unescape(data: &[u8], result: &mut Vec<u8>) {
+    let mut pos = 0;
+    while pos < data.len() {
+        let byte = data[pos];
+        if byte == b'=' {
+            if let Some(value) = data.get(pos + 1..pos + 3).and_then(hex_value) {
+                result.push(value);
+                pos += 3;
+                continue;
+            }
+        }
+        result.push(byte);
+        pos += 1;
+    }
+}
+
+fn hex_value(digits: &[u8]) -> Option<u8> {
+    let text = std::str::from_utf8(digits).ok()?;
+    u8::from_str_radix(text, 16).ok()
+}
+
+/// Decodes base64 data, ignoring line breaks and other whitespace.
+///
+/// # Arguments
+///
+/// * `data` - base64 encoded data
+pub fn decode_base64(data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let data: Vec<u8> = data.iter().copied().filter(|byte| !byte.is_ascii_whitespace()).collect();
+    Ok(BASE64.decode(data)?)
+}
+
+/// Decodes data according to the value of a Content-Transfer-Encoding header.
+///
+/// Data without encoding or with an identity encoding (`7bit`, `8bit`,
+/// `binary`) is returned unchanged.
+///
+/// # Arguments
+///
+/// * `encoding` - value of the Content-Transfer-Encoding header, if any
+/// * `data`     - encoded data
+pub fn decode_transfer_encoding(encoding: Option<&str>, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    match encoding.map(|encoding| encoding.trim().to_ascii_lowercase()).as_deref() {
+        None | Some("7bit") | Some("8bit") | Some("binary") => Ok(data.to_vec()),
+        Some("quoted-printable") => Ok(decode_quoted_printable(data)),
+        Some("base64") => decode_base64(data),
+        Some(encoding) => Err(format!("unsupported content transfer encoding: {}", encoding).into())
+    }
+}
+
+/// Decoder of a content transfer encoding, which is fed line by line.
+///
+/// Unlike [`decode_transfer_encoding`], the encoded data does not need to be
+/// buffered. Unknown encodings are treated as identity encoding. The line
+/// break before a multipart boundary belongs to the boundary, so the last
+/// line break is only written, if the part is not followed by a boundary.
+#[cfg(feature = "mail-parser")]
+pub(crate) struct TransferDecoder {
+    encoding: TransferEncoding,
+    pending_break: Vec<u8>,
+    base64: Vec<u8>,
+}
+
+#[cfg(feature = "mail-parser")]
+enum TransferEncoding {
+    Identity,
+    QuotedPrintable,
+    Base64,
+}
+
+#[cfg(feature = "mail-parser")]
+impl TransferDecoder {
+
+    /// Returns a decoder of the value of a Content-Transfer-Encoding header.
+    pub(crate) fn new(encoding: Option<&str>) -> Self {
+        let encoding = match encoding.map(|encoding| encoding.trim().to_ascii_lowercase()).as_deref() {
+            Some("quoted-printable") => TransferEncoding::QuotedPrintable,
+            Some("base64") => TransferEncoding::Base64,
+            _ => TransferEncoding::Identity
+        };
+
+        TransferDecoder { encoding, pending_break: vec!(), base64: vec!() }
+    }
+
+    /// Decodes a line including its line break.
+    pub(crate) fn decode_line(&mut self, line: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let (content, line_break) = match line.strip_suffix(b"\r\n").or_else(|| line.strip_suffix(b"\n")) {
+            Some(content) => (content, &line[content.len()..]),
+            None => (line, &b""[..])
+        };
+
+        let mut result = std::mem::take(&mut self.pending_break);
+        match self.encoding {
+            TransferEncoding::Identity => {
+                result.extend_from_slice(content);
+                self.pending_break = line_break.to_vec();
+            },
+            TransferEncoding::QuotedPrintable => {
+                let content = match content.iter().rposition(|&byte| byte != b' ' && byte != b'\t') {
+                    Some(end) => &content[..=end],
+                    None => &b""[..]
+                };
+                match content.strip_suffix(b"=") {
+                    Some(content) => unescape(content, &mut result),
+                    None => {
+                        unescape(content, &mut result);
+                        self.pending_break = line_break.to_vec();
+                    }
+                }
+            },
+            TransferEncoding::Base64 => {
+                self.base64.extend(content.iter().copied().filter(|byte| !byte.is_ascii_whitespace() && *byte != b'='));
+                let complete = self.base64.len() - self.base64.len() % 4;
+                result = base64::engine::general_purpose::STANDARD_NO_PAD.decode(&self.base64[..complete])?;
+                self.base64.drain(..complete);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Decodes the remaining data at the end of the part.
+    ///
+    /// # Arguments
+    ///
+    /// * `boundary` - true, if the part is followed by a boundary
+    pub(crate) fn finish(&mut self, boundary: bool) -> Result<Vec<u8>, Box<dyn Error>> {
+        let pending_break = std::mem::take(&mut self.pending_break);
+        let base64 = std::mem::take(&mut self.base64);
+        match self.encoding {
+            TransferEncoding::Base64 if base64.is_empty() => Ok(vec!()),
+            TransferEncoding::Base64 => Ok(base64::engine::general_purpose::STANDARD_NO_PAD.decode(base64)?),
+            _ if boundary => Ok(vec!()),
+            _ => Ok(pending_break)
+        }
+    }
+}
+
+/// Returns the decoded body of a single part message.
+///
+/// The body is decoded according to the Content-Transfer-Encoding header
+/// of the message. Multipart messages are not split into their parts.
+///
+/// # Arguments
+///
+/// * `message` - exact octets of the message
+pub fn decode_body(message: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (header_data, body) = sink::split_message(message);
+    let headers = headers::parse_headers(header_data);
+    decode_transfer_encoding(headers::find(&headers, "Content-Transfer-Encoding"), body)
+}
+
+/// Decodes encoded words (RFC 2047) in a header value, e.g. `=?UTF-8?Q?Gr=C3=BC=C3=9Fe?=`.
+///
+/// Whitespace between adjacent encoded words is removed. UTF-8, US-ASCII
+/// and ISO-8859-1 are supported; other charsets are decoded as UTF-8,
+/// replacing invalid sequences. Malformed encoded words are kept as they are.
+///
+/// # Arguments
+///
+/// * `value` - header value
+pub fn decode_encoded_words(value: &str) -> String {
+    let mut result = String::new();
+    let mut pending_whitespace = String::new();
+    let mut after_encoded_word = false;
+    let mut rest = value;
+
+    while !rest.is_empty() {
+        if let Some((decoded, remaining)) = rest.strip_prefix("=?").and_then(encoded_word) {
+            if !after_encoded_word {
+                result.push_str(&pending_whitespace);
+            }
+            pending_whitespace.clear();
+            result.push_str(&decoded);
+            after_encoded_word = true;
+            rest = remaining;
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap_or_default();
+        rest = &rest[c.len_utf8()..];
+        if c.is_whitespace() {
+            pending_whitespace.push(c);
+        }
+        else {
+            result.push_str(&pending_whitespace);
+            pending_whitespace.clear();
+            result.push(c);
+            after_encoded_word = false;
+        }
+    }
+    result.push_str(&pending_whitespace);
+
+    result
+}
+
+/// Decodes an encoded word without its leading `=?` and returns the remaining text.
+fn encoded_word(text: &str) -> Option<(String, &str)> {
+    let (charset, text) = text.split_once('?')?;
+    let (encoding, text) = text.split_once('?')?;
+    let end = text.find("?=")?;
+    let (encoded, remaining) = (&text[..end], &text[end + 2..]);
+    if encoded.contains(char::is_whitespace) {
+        return None;
+    }
+
+    let data = match encoding {
+        "B" | "b" => decode_base64(encoded.as_bytes()).ok()?,
+        "Q" | "q" => {
+            let mut data = vec!();
+            unescape(encoded.replace('_', " ").as_bytes(), &mut data);
+            data
+        },
+        _ => return None
+    };
+
+    let charset = charset.split('*').next().unwrap_or_default();
+    Some((decode_charset(&data, Some(charset)), remaining))
+}
+
+/// Decodes text in the given charset.
+///
+/// UTF-8, US-ASCII and ISO-8859-1 are supported; other charsets are decoded
+/// as UTF-8, replacing invalid sequences.
+#[cfg(not(feature = "charset"))]
+pub(crate) fn decode_charset(data: &[u8], charset: Option<&str>) -> String {
+    match charset.map(|charset| charset.trim().to_ascii_lowercase()).as_deref() {
+        Some("iso-8859-1") | Some("latin1") => data.iter().map(|&byte| byte as char).collect(),
+        _ => String::from_utf8_lossy(data).into_owned()
+    }
+}
+
+/// Decodes text in the given charset.
+///
+/// All charsets of the WHATWG Encoding Standard are supported. If the
+/// charset is missing or unknown, valid UTF-8 is used as it is; otherwise
+/// the charset is guessed from the data.
+#[cfg(feature = "charset")]
+pub(crate) fn decode_charset(data: &[u8], charset: Option<&str>) -> String {
+    let encoding = match charset.and_then(|charset| encoding_rs::Encoding::for_label(charset.trim().as_bytes())) {
+        Some(encoding) => encoding,
+        None => match std::str::from_utf8(data) {
+            Ok(text) => return text.to_string(),
+            Err(_) => {
+                let mut detector = chardetng::EncodingDetector::new();
+                detector.feed(data, true);
+                detector.guess(None, true)
+            }
+        }
+    };
+
+    encoding.decode_without_bom_handling(data).0.into_owned()
+}
+
+/// Returns a message as UTF-8 text.
+///
+/// The message is decoded using the charset of its Content-Type header.
+/// See [`decode_charset`] for messages without charset.
+pub(crate) fn message_to_string(content: &[u8]) -> String {
+    let (header_data, _) = sink::split_message(content);
+    let headers = headers::parse_headers(header_data);
+    let (_, parameters) = text::parse_content_type(headers::find(&headers, "Content-Type").unwrap_or_default());
+    let charset = parameters.iter().find(|(name, _)| name == "charset").map(|(_, value)| value.as_str());
+
+    decode_charset(content, charset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_quoted_printable() {
+        assert_eq!(b"caf\xc3\xa9 = ok\r\n".to_vec(), decode_quoted_printable(b"caf=C3=A9 =3D ok  \r\n"));
+        assert_eq!(b"a long line\r\n".to_vec(), decode_quoted_printable(b"a lo=\r\nng line\r\n"));
+        assert_eq!(b"soft end".to_vec(), decode_quoted_printable(b"soft end="));
+        assert_eq!(b"=ZZ and =4".to_vec(), decode_quoted_printable(b"=ZZ and =4"));
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(b"Hello, World!".to_vec(), decode_base64(b"SGVsbG8s\r\nIFdvcmxkIQ==\r\n").unwrap());
+        assert!(decode_base64(b"!!!").is_err());
+    }
+
+    #[test]
+    fn test_decode_encoded_words() {
+        assert_eq!("Grüße aus Köln", decode_encoded_words("=?UTF-8?Q?Gr=C3=BC=C3=9Fe_aus_?= =?iso-8859-1?B?S/Zsbg==?="));
+        assert_eq!("Re: Café menu", decode_encoded_words("Re: =?utf-8?q?Caf=C3=A9?= menu"));
+        assert_eq!("=?invalid?= text", decode_encoded_words("=?invalid?= text"));
+    }
+
+    #[test]
+    fn test_decode_charset() {
+        assert_eq!("Grüße", decode_charset(b"Gr\xfc\xdfe", Some("ISO-8859-1")));
+        assert_eq!("Grüße", decode_charset("Grüße".as_bytes(), None));
+    }
+
+    #[cfg(feature = "charset")]
+    #[test]
+    fn test_decode_charset_sniffing() {
+        assert_eq!("Привет", decode_charset(b"\xf0\xd2\xc9\xd7\xc5\xd4", Some("koi8-r")));
+        assert_eq!("Grüße aus Köln", decode_charset(b"Gr\xfc\xdfe aus K\xf6ln", None));
+    }
+
+    #[test]
+    fn test_message_to_string() {
+        let message = b"Content-Type: text/plain; charset=\"iso-8859-1\"\r\n\r\nK\xf6ln\r\n";
+        assert_eq!("Content-Type: text/plain; charset=\"iso-8859-1\"\r\n\r\nKöln\r\n", message_to_string(message));
+    }
+
+    #[test]
+    fn test_decode_body() {
+        let message = b"Subject: hi\r\nContent-Transfer-Encoding: Quoted-Printable\r\n\r\nGr=C3=BC=C3=9Fe\r\n";
+        assert_eq!("Grüße\r\n", String::from_utf8(decode_body(message).unwrap()).unwrap());
+
+        let message = b"Subject: hi\r\n\r\nplain\r\n";
+        assert_eq!(b"plain\r\n".to_vec(), decode_body(message).unwrap());
+
+        assert!(decode_transfer_encoding(Some("x-uuencode"), b"").is_err());
+    }
+
+    #[cfg(feature = "mail-parser")]
+    #[test]
+    fn test_transfer_decoder() {
+        let decode = |encoding, lines: &[&[u8]], boundary| {
+            let mut decoder = TransferDecoder::new(encoding);
+            let mut result = vec!();
+            for line in lines {
+                result.extend(decoder.decode_line(line).unwrap());
+            }
+            result.extend(decoder.finish(boundary).unwrap());
+            result
+        };
+
+        assert_eq!(b"Hello, World!".to_vec(), decode(Some("Base64"), &[b"SGVsbG8sIF\r\n", b"dvcmxkIQ==\r\n"], true));
+        assert_eq!(b"a long line\r\nend".to_vec(), decode(Some("quoted-printable"), &[b"a lo=\r\n", b"ng line\r\n", b"end\r\n"], true));
+        assert_eq!(b"plain\r\ntext\r\n".to_vec(), decode(None, &[b"plain\r\n", b"text\r\n"], false));
+        assert_eq!(b"plain\r\ntext".to_vec(), decode(Some("x-unknown"), &[b"plain\r\n", b"text\r\n"], true));
+    }
+}
@@ -0,0 +1,301 @@
+use std::collections::VecDeque;
+use std::error::Error;
+
+use crate::{response, Pop3NegativeResponse};
+
+/// Event of a [`Pop3Engine`], produced from received bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Pop3Event {
+    /// greeting of the server, e.g. `+OK POP3 server ready`
+    Greeting(String),
+
+    /// positive status line (`+OK`) of the response to a command
+    Positive {
+        /// command, whose response started
+        command: String,
+
+        /// complete status line
+        status: String,
+    },
+
+    /// negative status line (`-ERR`) of the response to a command; completes the response
+    Negative {
+        /// command, which failed
+        command: String,
+
+        /// complete status line
+        status: String,
+    },
+
+    /// continuation request (`+ `) during authentication, e.g. error details of XOAUTH2
+    Continuation(String),
+
+    /// line of a multi-line response without byte-stuffing, including its line terminator
+    Line(Vec<u8>),
+
+    /// end of a multi-line response
+    End,
+}
+
+/// Response expected from the server.
+#[derive(Debug)]
+enum Expected {
+    Greeting,
+    SingleLine(String),
+    MultiLine(String),
+}
+
+/// Protocol engine of POP3 without any I/O (sans-IO).
+///
+/// The engine encodes commands into bytes to send and decodes received
+/// bytes into [`Pop3Event`]s. The caller moves bytes between the engine and
+/// an arbitrary transport, e.g. WASI sockets or a custom tunnel. Commands
+/// can be pipelined: responses are assigned to commands in the order they
+/// were sent.
+///
+/// Whether a response is multi-line is derived from the command: RETR,
+/// TOP and CAPA as well as LIST and UIDL without argument expect a
+/// multi-line response.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::io::{Read, Write};
+/// use std::net::TcpStream;
+/// use rust_pop3_client::{Pop3Engine, Pop3Event};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+/// let mut transport = TcpStream::connect("localhost:110")?;
+/// let mut engine = Pop3Engine::new();
+/// engine.send("USER user@example.com");
+/// engine.send("PASS secret");
+/// engine.send("LIST");
+///
+/// let mut buffer = [0; 4096];
+/// while !engine.is_idle() {
+///     transport.write_all(engine.output())?;
+///     engine.consume_output(engine.output().len());
+///
+///     let count = transport.read(&mut buffer)?;
+///     engine.receive(&buffer[..count]);
+///     while let Some(event) = engine.next_event()? {
+///         if let Pop3Event::Line(line) = event {
+///             print!("{}", String::from_utf8_lossy(&line));
+///         }
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Pop3Engine {
+    input: Vec<u8>,
+    output: Vec<u8>,
+    expected: VecDeque<Expected>,
+    in_body: bool,
+}
+
+impl Pop3Engine {
+
+    /// Returns a new engine, which expects the greeting of the server.
+    pub fn new() -> Self {
+        Pop3Engine {
+            input: vec!(),
+            output: vec!(),
+            expected: VecDeque::from([Expected::Greeting]),
+            in_body: false,
+        }
+    }
+
+    /// Encodes a command and expects its response.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - command without line terminator, e.g. `RETR 1`
+    pub fn send(&mut self, command: &str) {
+        self.output.extend_from_slice(command.as_bytes());
+        self.output.extend_from_slice(b"\r\n");
+
+        let command = command.to_string();
+        self.expected.push_back(match is_multi_line(&command) {
+            true => Expected::MultiLine(command),
+            false => Expected::SingleLine(command)
+        });
+    }
+
+    /// Encodes a line, which is not a command, e.g. the response to a continuation request.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - line without line terminator
+    pub fn send_line(&mut self, line: &str) {
+        self.output.extend_from_slice(line.as_bytes());
+        self.output.extend_from_slice(b"\r\n");
+    }
+
+    /// Returns the bytes to send.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Removes bytes, which were sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - count of bytes sent from the start of [`Pop3Engine::output`]
+    pub fn consume_output(&mut self, count: usize) {
+        self.output.drain(..count.min(self.output.len()));
+    }
+
+    /// Adds received bytes.
+    pub fn receive(&mut self, data: &[u8]) {
+        self.input.extend_from_slice(data);
+    }
+
+    /// Returns true, if no response is pending.
+    pub fn is_idle(&self) -> bool {
+        self.expected.is_empty()
+    }
+
+    /// Returns the next event; returns `None`, if more bytes must be received.
+    ///
+    /// Fails, if data is received, which was not expected.
+    pub fn next_event(&mut self) -> Result<Option<Pop3Event>, Box<dyn Error + Send + Sync>> {
+        let length = match self.input.iter().position(|&c| c == b'\n') {
+            Some(index) => index + 1,
+            None => return Ok(None)
+        };
+        let line: Vec<u8> = self.input.drain(..length).collect();
+
+        if self.in_body {
+            return Ok(Some(match response::unstuff_line(&line) {
+                Some(content) => Pop3Event::Line(content.to_vec()),
+                None => {
+                    self.in_body = false;
+                    self.expected.pop_front();
+                    Pop3Event::End
+                }
+            }));
+        }
+
+        let status = String::from_utf8_lossy(&line).trim().to_string();
+        let expected = self.expected.pop_front().ok_or_else(|| format!("unexpected response: {}", status))?;
+        if status.starts_with('+') && !status.starts_with("+OK") {
+            self.expected.push_front(expected);
+            return Ok(Some(Pop3Event::Continuation(status.trim_start_matches('+').trim_start().to_string())));
+        }
+
+        let positive = status.starts_with("+OK");
+        if !positive && !status.starts_with("-ERR") {
+            return Err(format!("invalid response: {}", status).into());
+        }
+
+        Ok(Some(match expected {
+            Expected::Greeting if positive => Pop3Event::Greeting(status),
+            Expected::Greeting => return Err(Box::new(Pop3NegativeResponse::new(status))),
+            Expected::MultiLine(command) if positive => {
+                self.in_body = true;
+                self.expected.push_front(Expected::MultiLine(command.clone()));
+                Pop3Event::Positive { command, status }
+            },
+            Expected::SingleLine(command) | Expected::MultiLine(command) if positive => Pop3Event::Positive { command, status },
+            Expected::SingleLine(command) | Expected::MultiLine(command) => Pop3Event::Negative { command, status },
+        }))
+    }
+}
+
+impl Default for Pop3Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns true, if the response of a command is multi-line.
+fn is_multi_line(command: &str) -> bool {
+    let mut words = command.split_whitespace();
+    let name = words.next().unwrap_or_default().to_ascii_uppercase();
+    match name.as_str() {
+        "RETR" | "TOP" | "CAPA" => true,
+        "LIST" | "UIDL" => words.next().is_none(),
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(engine: &mut Pop3Engine) -> Vec<Pop3Event> {
+        let mut events = vec!();
+        while let Some(event) = engine.next_event().unwrap() {
+            events.push(event);
+        }
+        events
+    }
+
+    #[test]
+    fn test_pipelined_commands() {
+        let mut engine = Pop3Engine::new();
+        engine.send("STAT");
+        engine.send("RETR 1");
+        assert_eq!(b"STAT\r\nRETR 1\r\n", engine.output());
+        engine.consume_output(6);
+        assert_eq!(b"RETR 1\r\n", engine.output());
+
+        engine.receive(b"+OK ready\r\n+OK 1 8\r\n+OK\r\n..dot\r\nHel");
+        assert_eq!(vec!(
+            Pop3Event::Greeting("+OK ready".into()),
+            Pop3Event::Positive { command: "STAT".into(), status: "+OK 1 8".into() },
+            Pop3Event::Positive { command: "RETR 1".into(), status: "+OK".into() },
+            Pop3Event::Line(b".dot\r\n".to_vec()),
+        ), events(&mut engine));
+        assert!(!engine.is_idle());
+
+        engine.receive(b"lo\r\n.\r\n");
+        assert_eq!(vec!(Pop3Event::Line(b"Hello\r\n".to_vec()), Pop3Event::End), events(&mut engine));
+        assert!(engine.is_idle());
+    }
+
+    #[test]
+    fn test_negative_multi_line_response() {
+        let mut engine = Pop3Engine::new();
+        engine.send("LIST");
+        engine.send("LIST 1");
+        engine.receive(b"+OK\r\n-ERR locked\r\n+OK 1 120\r\n");
+        assert_eq!(vec!(
+            Pop3Event::Greeting("+OK".into()),
+            Pop3Event::Negative { command: "LIST".into(), status: "-ERR locked".into() },
+            Pop3Event::Positive { command: "LIST 1".into(), status: "+OK 1 120".into() },
+        ), events(&mut engine));
+        assert!(engine.is_idle());
+    }
+
+    #[test]
+    fn test_continuation() {
+        let mut engine = Pop3Engine::new();
+        engine.receive(b"+OK\r\n");
+        engine.send("AUTH XOAUTH2 dG9rZW4=");
+        engine.receive(b"+ eyJzdGF0dXMiOiI0MDEifQ==\r\n");
+        assert_eq!(vec!(
+            Pop3Event::Greeting("+OK".into()),
+            Pop3Event::Continuation("eyJzdGF0dXMiOiI0MDEifQ==".into()),
+        ), events(&mut engine));
+
+        engine.send_line("");
+        engine.receive(b"-ERR invalid token\r\n");
+        assert!(matches!(engine.next_event().unwrap(), Some(Pop3Event::Negative { .. })));
+        assert!(engine.is_idle());
+    }
+
+    #[test]
+    fn test_unexpected_response() {
+        let mut engine = Pop3Engine::new();
+        engine.receive(b"+OK\r\n+OK\r\n");
+        assert_eq!(Some(Pop3Event::Greeting("+OK".into())), engine.next_event().unwrap());
+        assert!(engine.next_event().is_err());
+
+        let mut engine = Pop3Engine::new();
+        engine.receive(b"-ERR busy\r\n");
+        assert!(engine.next_event().is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Classification of errors reported by connections, e.g. for alerting rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pop3ErrorKind {
+    /// transport failed, e.g. I/O errors, timeouts and closed or poisoned connections
+    Network,
+
+    /// negative or malformed response of the server
+    Protocol,
+
+    /// authentication was rejected (response code `[AUTH]`, RFC 3206)
+    Auth,
+
+    /// operation was refused by a policy, e.g. a locked maildrop (`[IN-USE]`),
+    /// a login delay (`[LOGIN-DELAY]`) or a veto of an interceptor
+    Policy,
+}
+
+/// Classification of the errors of connections.
+///
+/// Errors of this crate are boxed errors, so the classification is
+/// implemented for `dyn Error` and can be used for [`crate::Pop3AsyncError`]
+/// as well as for `Box<dyn Error>`.
+///
+/// # Examples
+///
+/// ```
+/// use rust_pop3_client::{Pop3AsyncError, Pop3ErrorExt, Pop3ErrorKind};
+///
+/// use rust_pop3_client::Pop3NegativeResponse;
+///
+/// let err: Pop3AsyncError = Box::new(Pop3NegativeResponse::new("-ERR [IN-USE] maildrop locked"));
+/// assert_eq!(Pop3ErrorKind::Policy, err.kind());
+/// assert!(err.is_transient());
+/// ```
+pub trait Pop3ErrorExt {
+
+    /// Returns the kind of the error.
+    fn kind(&self) -> Pop3ErrorKind;
+
+    /// Returns true, if the error is transient, i.e. a retry in a new session may succeed.
+    ///
+    /// Network errors are transient, as well as negative responses with the
+    /// `[SYS/TEMP]`, `[IN-USE]` or `[LOGIN-DELAY]` response codes. Other
+    /// errors are permanent.
+    fn is_transient(&self) -> bool;
+}
+
+impl Pop3ErrorExt for dyn Error + 'static {
+    fn kind(&self) -> Pop3ErrorKind {
+        error_kind(self)
+    }
+
+    fn is_transient(&self) -> bool {
+        is_transient(self)
+    }
+}
+
+impl Pop3ErrorExt for dyn Error + Send + Sync + 'static {
+    fn kind(&self) -> Pop3ErrorKind {
+        error_kind(self)
+    }
+
+    fn is_transient(&self) -> bool {
+        is_transient(self)
+    }
+}
+
+/// Negative response of the server; the message is the status line.
+///
+/// Errors of negative responses can be found by `downcast_ref`, e.g. to
+/// inspect the response code of RFC 2449.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pop3NegativeResponse(String);
+
+impl Pop3NegativeResponse {
+
+    /// Returns a negative response.
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - status line without line terminator, e.g. `-ERR [IN-USE] maildrop locked`
+    pub fn new(status: impl Into<String>) -> Self {
+        Pop3NegativeResponse(status.into())
+    }
+
+    /// Returns the status line.
+    pub fn status(&self) -> &str {
+        &self.0
+    }
+
+    /// Returns the response code, e.g. `IN-USE`.
+    pub fn code(&self) -> Option<&str> {
+        let text = self.0.strip_prefix("-ERR")?.trim_start();
+        let code = text.strip_prefix('[')?;
+        code.find(']').map(|end| &code[..end])
+    }
+}
+
+impl fmt::Display for Pop3NegativeResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for Pop3NegativeResponse { }
+
+/// Connection, which can no longer be used, e.g. closed by the server or poisoned.
+#[derive(Debug)]
+pub(crate) struct ConnectionLost(pub(crate) &'static str);
+
+impl fmt::Display for ConnectionLost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl Error for ConnectionLost { }
+
+/// Command refused by an interceptor.
+#[derive(Debug)]
+pub(crate) struct PolicyViolation(pub(crate) String);
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for PolicyViolation { }
+
+/// Returns the response code of an error caused by a negative response, e.g. `IN-USE`.
+fn response_code<'a>(err: &'a (dyn Error + 'static)) -> Option<&'a str> {
+    err.downcast_ref::<Pop3NegativeResponse>().and_then(Pop3NegativeResponse::code)
+}
+
+/// Returns the kind of an error; see [`Pop3ErrorExt::kind`].
+pub(crate) fn error_kind(err: &(dyn Error + 'static)) -> Pop3ErrorKind {
+    if err.is::<io::Error>() || err.is::<ConnectionLost>() {
+        return Pop3ErrorKind::Network;
+    }
+    if err.is::<PolicyViolation>() {
+        return Pop3ErrorKind::Policy;
+    }
+
+    match response_code(err) {
+        Some("AUTH") => Pop3ErrorKind::Auth,
+        Some("IN-USE") | Some("LOGIN-DELAY") => Pop3ErrorKind::Policy,
+        _ => Pop3ErrorKind::Protocol
+    }
+}
+
+/// Returns true, if an error is transient, i.e. a retry in a new session may succeed.
+///
+/// See [`Pop3ErrorExt::is_transient`].
+pub fn is_transient(err: &(dyn Error + 'static)) -> bool {
+    match error_kind(err) {
+        Pop3ErrorKind::Network => true,
+        _ => matches!(response_code(err), Some("SYS/TEMP") | Some("IN-USE") | Some("LOGIN-DELAY"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pop3AsyncError;
+
+    #[test]
+    fn test_kind() {
+        let cases: [(Pop3AsyncError, Pop3ErrorKind, bool); 9] = [
+            (Box::new(io::Error::new(io::ErrorKind::TimedOut, "timeout")), Pop3ErrorKind::Network, true),
+            (Box::new(ConnectionLost("connection closed")), Pop3ErrorKind::Network, true),
+            (Box::new(Pop3NegativeResponse::new("-ERR [AUTH] invalid credentials")), Pop3ErrorKind::Auth, false),
+            (Box::new(Pop3NegativeResponse::new("-ERR [IN-USE] maildrop locked")), Pop3ErrorKind::Policy, true),
+            (Box::new(Pop3NegativeResponse::new("-ERR [SYS/TEMP] try again")), Pop3ErrorKind::Protocol, true),
+            (Box::new(Pop3NegativeResponse::new("-ERR no such message")), Pop3ErrorKind::Protocol, false),
+            (Box::new(PolicyViolation("deletion is not allowed".into())), Pop3ErrorKind::Policy, false),
+            // messages are not classified, only the type of an error
+            ("-ERR [IN-USE] maildrop locked".into(), Pop3ErrorKind::Protocol, false),
+            ("connection closed".into(), Pop3ErrorKind::Protocol, false),
+        ];
+
+        for (err, kind, transient) in cases {
+            assert_eq!(kind, err.kind(), "{}", err);
+            assert_eq!(transient, err.is_transient(), "{}", err);
+        }
+    }
+
+    #[test]
+    fn test_kind_of_server_errors() {
+        let (mut connection, server) = crate::test_util::connect(&[
+            ("STAT", "-ERR [IN-USE] maildrop locked\r\n"),
+            ("NOOP", "-ERR [SYS/TEMP] try again\r\n"),
+        ]);
+
+        let err = connection.stat().unwrap_err();
+        assert!(err.is::<Pop3NegativeResponse>());
+        assert_eq!(Pop3ErrorKind::Policy, err.kind());
+        let err = connection.noop().unwrap_err();
+        assert_eq!(Some("SYS/TEMP"), err.downcast_ref::<Pop3NegativeResponse>().and_then(Pop3NegativeResponse::code));
+        assert!(err.is_transient());
+
+        server.join().unwrap();
+        let err = connection.noop().unwrap_err();
+        assert_eq!(Pop3ErrorKind::Network, err.kind());
+    }
+}
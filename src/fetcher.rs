@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use crate::Pop3Connection;
+
+/// Message of a mailbox as listed by a [`MailFetcher`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MailInfo {
+    /// unique id of the message, which stays the same across sessions
+    pub unique_id: String,
+
+    /// size of the message in octets
+    pub size: u64,
+}
+
+/// Retrieval of messages, independent of the protocol.
+///
+/// Messages are identified by unique ids, which stay the same across
+/// sessions, e.g. UIDL values for POP3 or UIDs for IMAP. Applications using
+/// this trait can switch the retrieval protocol without further changes.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::error::Error;
+/// use rust_pop3_client::{MailFetcher, Pop3Connection};
+///
+/// fn archive(fetcher: &mut dyn MailFetcher) -> Result<(), Box<dyn Error>> {
+///     for message in fetcher.list_messages()? {
+///         let content = fetcher.fetch_message(&message.unique_id)?;
+///         std::fs::write(format!("{}.eml", message.unique_id), content)?;
+///     }
+///     Ok(())
+/// }
+///
+/// let mut connection = Pop3Connection::new("pop.example.com", 995).unwrap();
+/// connection.login("user@example.com", "secret").unwrap();
+/// archive(&mut connection.mail_fetcher()).unwrap();
+/// ```
+pub trait MailFetcher {
+    /// Returns unique id and size of all messages.
+    fn list_messages(&mut self) -> Result<Vec<MailInfo>, Box<dyn Error>>;
+
+    /// Returns the exact octets of the message with the given unique id.
+    fn fetch_message(&mut self, unique_id: &str) -> Result<Vec<u8>, Box<dyn Error>>;
+
+    /// Deletes the message with the given unique id.
+    fn delete_message(&mut self, unique_id: &str) -> Result<(), Box<dyn Error>>;
+}
+
+/// Implements [`MailFetcher`] for a [`Pop3Connection`] using UIDL, RETR and DELE.
+///
+/// Message ids do not change during a session, so the adapter lists the
+/// unique ids (UIDL) once and keeps their message ids until the messages
+/// are deleted. Deletions are committed, when the session is ended using
+/// [`Pop3Connection::quit`].
+///
+/// The adapter is created by [`Pop3Connection::mail_fetcher`].
+pub struct Pop3MailFetcher<'a> {
+    connection: &'a mut Pop3Connection,
+    message_ids: Option<HashMap<String, u32>>,
+}
+
+impl<'a> Pop3MailFetcher<'a> {
+
+    pub(crate) fn new(connection: &'a mut Pop3Connection) -> Self {
+        Pop3MailFetcher { connection, message_ids: None }
+    }
+
+    /// Returns the id of the message with the given unique id.
+    fn message_id_of(&mut self, unique_id: &str) -> Result<u32, Box<dyn Error>> {
+        if self.message_ids.is_none() {
+            self.message_ids = Some(self.connection.list_unique_ids()?.into_iter()
+                .map(|info| (info.unique_id, info.message_id))
+                .collect());
+        }
+
+        self.message_ids.as_ref().and_then(|message_ids| message_ids.get(unique_id)).copied()
+            .ok_or_else(|| format!("unknown unique id: {}", unique_id).into())
+    }
+}
+
+impl MailFetcher for Pop3MailFetcher<'_> {
+    fn list_messages(&mut self) -> Result<Vec<MailInfo>, Box<dyn Error>> {
+        let mut messages = vec!();
+        let mut message_ids = HashMap::new();
+        for message in self.connection.list_meta()? {
+            let unique_id = message.unique_id.ok_or("server does not support UIDL")?;
+            message_ids.insert(unique_id.clone(), message.message_id);
+            messages.push(MailInfo { unique_id, size: message.message_size as u64 });
+        }
+
+        self.message_ids = Some(message_ids);
+        Ok(messages)
+    }
+
+    fn fetch_message(&mut self, unique_id: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let message_id = self.message_id_of(unique_id)?;
+        let mut content = vec!();
+        self.connection.retrieve_raw(message_id, &mut content)?;
+        Ok(content)
+    }
+
+    fn delete_message(&mut self, unique_id: &str) -> Result<(), Box<dyn Error>> {
+        let message_id = self.message_id_of(unique_id)?;
+        self.connection.delete(message_id)?;
+        if let Some(message_ids) = &mut self.message_ids {
+            message_ids.remove(unique_id);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_mail_fetcher() {
+        let (mut connection, server) = test_util::connect(&[
+            ("UIDL", "+OK\r\n1 uid1\r\n2 uid2\r\n.\r\n"),
+            ("RETR 2", "+OK\r\nSubject: two\r\n.\r\n"),
+            ("DELE 2", "+OK\r\n"),
+            ("RETR 1", "+OK\r\nSubject: one\r\n.\r\n"),
+            ("LIST", "+OK\r\n1 10\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uid1\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let mut fetcher = connection.mail_fetcher();
+        let fetcher: &mut dyn MailFetcher = &mut fetcher;
+        assert_eq!(b"Subject: two\r\n".to_vec(), fetcher.fetch_message("uid2").unwrap());
+        fetcher.delete_message("uid2").unwrap();
+        assert!(fetcher.fetch_message("uid2").is_err());
+        assert!(fetcher.fetch_message("unknown").is_err());
+        assert_eq!(b"Subject: one\r\n".to_vec(), fetcher.fetch_message("uid1").unwrap());
+        assert_eq!(vec!(MailInfo { unique_id: "uid1".into(), size: 10 }), fetcher.list_messages().unwrap());
+        connection.quit().unwrap();
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,34 @@
+/// Returns true, if the text matches the glob pattern (ignoring case).
+pub(crate) fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => { backtrack = Some((p, t)); p += 1; },
+            Some(&c) if c == '?' || c == text[t] => { p += 1; t += 1; },
+            _ => match backtrack {
+                Some((star, matched)) => { p = star + 1; t = matched + 1; backtrack = Some((star, matched + 1)); },
+                None => return false
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("a*b*c", "aXbYbc"));
+        assert!(!glob_matches("a*b", "ab c"));
+        assert!(glob_matches("*@spammer.example", "Bob@Spammer.example"));
+        assert!(glob_matches("inv?ice.*", "invoice.pdf"));
+        assert!(!glob_matches("", "a"));
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+struct Inner {
+    hashes: HashSet<String>,
+    file: Option<File>,
+}
+
+/// Content hashes of delivered messages, shared between accounts.
+///
+/// When several accounts receive the same messages, e.g. of a mailing list,
+/// pass the same store to the sync options of each account (see
+/// [`crate::SyncOptions::shared_hashes`]) to deliver each message only once.
+/// Clones of a store share their content, so a store can be used by
+/// multiple threads.
+///
+/// A store is either kept in memory or persisted in a file, which contains
+/// one SHA-256 hash per line.
+#[derive(Clone)]
+pub struct SharedHashStore {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl SharedHashStore {
+
+    /// Returns an empty store kept in memory.
+    pub fn new() -> Self {
+        SharedHashStore { inner: Arc::new(Mutex::new(Inner { hashes: HashSet::new(), file: None })) }
+    }
+
+    /// Opens a store persisted in a file. A missing file is created.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path of the file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+        let mut hashes = HashSet::new();
+        for line in BufReader::new(&file).lines() {
+            let line = line?;
+            if !line.is_empty() {
+                hashes.insert(line);
+            }
+        }
+
+        Ok(SharedHashStore { inner: Arc::new(Mutex::new(Inner { hashes, file: Some(file) })) })
+    }
+
+    /// Returns true, if the store contains the given hash.
+    pub fn contains(&self, hash: &str) -> bool {
+        self.lock().hashes.contains(hash)
+    }
+
+    /// Adds a hash to the store and returns true, if it was not contained before.
+    pub fn insert(&self, hash: &str) -> Result<bool, Box<dyn Error>> {
+        let mut inner = self.lock();
+        if inner.hashes.contains(hash) {
+            return Ok(false);
+        }
+
+        if let Some(file) = &mut inner.file {
+            file.write_all(format!("{}\n", hash).as_bytes())?;
+            file.sync_data()?;
+        }
+        inner.hashes.insert(hash.to_string());
+        Ok(true)
+    }
+
+    /// Returns the count of hashes in the store.
+    pub fn len(&self) -> usize {
+        self.lock().hashes.len()
+    }
+
+    /// Returns true, if the store contains no hashes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Default for SharedHashStore {
+    fn default() -> Self {
+        SharedHashStore::new()
+    }
+}
+
+impl fmt::Debug for SharedHashStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedHashStore").field("len", &self.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_hashes() {
+        let store = SharedHashStore::new();
+        let clone = store.clone();
+
+        assert!(store.insert("abc").unwrap());
+        assert!(clone.contains("abc"));
+        assert!(!clone.insert("abc").unwrap());
+        assert_eq!(1, store.len());
+    }
+
+    #[test]
+    fn test_open_persists_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hashes");
+
+        let store = SharedHashStore::open(&path).unwrap();
+        store.insert("abc").unwrap();
+        store.insert("def").unwrap();
+        drop(store);
+
+        let store = SharedHashStore::open(&path).unwrap();
+        assert_eq!(2, store.len());
+        assert!(store.contains("def"));
+    }
+}
@@ -0,0 +1,198 @@
+use std::time::SystemTime;
+
+use crate::sink;
+use crate::{Mailbox, date};
+
+/// Headers of a message as name/value pairs in their original order.
+///
+/// Folded header lines are unfolded and values are trimmed; encoded words
+/// are not decoded. Names are compared case-insensitively.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pop3Headers {
+    headers: Vec<(String, String)>,
+}
+
+impl Pop3Headers {
+
+    /// Parses the header section of a message. Parsing stops at the first empty line.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - octets of the message or its header section
+    pub fn parse(content: &[u8]) -> Self {
+        Pop3Headers { headers: parse_headers(content) }
+    }
+
+    /// Returns the value of the first header with the given name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        find(&self.headers, name)
+    }
+
+    /// Returns the values of all headers with the given name, e.g. of `Received`.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers.iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns all headers as name/value pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Returns all headers as name/value pairs.
+    pub fn as_slice(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Returns the count of headers.
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    /// Returns true, if there are no headers.
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    /// Returns the Subject header.
+    pub fn subject(&self) -> Option<&str> {
+        self.get("Subject")
+    }
+
+    /// Returns the From header.
+    pub fn from(&self) -> Option<&str> {
+        self.get("From")
+    }
+
+    /// Returns the Date header.
+    pub fn date(&self) -> Option<&str> {
+        self.get("Date")
+    }
+
+    /// Returns the Message-ID header, e.g. `<1234@example.com>`.
+    pub fn message_id(&self) -> Option<&str> {
+        self.get("Message-ID")
+    }
+
+    /// Returns the Content-Type header, e.g. `text/plain; charset=utf-8`.
+    pub fn content_type(&self) -> Option<&str> {
+        self.get("Content-Type")
+    }
+
+    /// Returns the MIME type of the Content-Type header in lower case, e.g. `text/plain`.
+    ///
+    /// Messages without Content-Type header are `text/plain` (RFC 2045).
+    pub fn mime_type(&self) -> String {
+        crate::text::parse_content_type(self.content_type().unwrap_or("text/plain")).0
+    }
+
+    /// Returns the parsed Date header; `None` if missing or invalid.
+    ///
+    /// Common deviations from RFC 2822 are tolerated, e.g. missing seconds,
+    /// two-digit years and obsolete zone names.
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        self.date().and_then(date::parse_rfc2822)
+    }
+
+    /// Returns the parsed Date header including its offset to UTC; `None` if missing or invalid.
+    ///
+    /// See [`Pop3Headers::timestamp`] for the tolerated deviations.
+    #[cfg(feature = "chrono")]
+    pub fn date_time(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        self.date().and_then(date::parse_rfc2822_chrono)
+    }
+
+    /// Returns the mailboxes of all headers with the given name, e.g. `To`.
+    pub fn mailboxes(&self, name: &str) -> Vec<Mailbox> {
+        self.get_all(name).flat_map(Mailbox::parse_list).collect()
+    }
+}
+
+impl From<Vec<(String, String)>> for Pop3Headers {
+    fn from(headers: Vec<(String, String)>) -> Self {
+        Pop3Headers { headers }
+    }
+}
+
+impl FromIterator<(String, String)> for Pop3Headers {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Pop3Headers { headers: iter.into_iter().collect() }
+    }
+}
+
+impl IntoIterator for Pop3Headers {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.headers.into_iter()
+    }
+}
+
+/// Parses the header section of a message into name/value pairs.
+///
+/// Folded header lines are unfolded, values are trimmed. Parsing stops
+/// at the first empty line.
+pub(crate) fn parse_headers(content: &[u8]) -> Vec<(String, String)> {
+    let (headers, _) = sink::split_message(content);
+    let mut result: Vec<(String, String)> = vec!();
+
+    for line in headers.split(|&byte| byte == b'\n') {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches('\r');
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = result.last_mut() {
+                value.push_str(line);
+            }
+        }
+        else if let Some((name, value)) = line.split_once(':') {
+            result.push((name.trim().to_string(), value.to_string()));
+        }
+    }
+
+    for (_, value) in result.iter_mut() {
+        *value = value.trim().to_string();
+    }
+
+    result
+}
+
+/// Returns the value of the first header with the given name (case-insensitive).
+pub(crate) fn find<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_headers() {
+        let headers = parse_headers(b"From: Alice <alice@example.com>\r\nSubject: a long\r\n  subject\r\nX-Empty:\r\n\r\nBody: no header\r\n");
+        assert_eq!(vec!(
+            ("From".to_string(), "Alice <alice@example.com>".to_string()),
+            ("Subject".to_string(), "a long  subject".to_string()),
+            ("X-Empty".to_string(), "".to_string()),
+        ), headers);
+    }
+
+    #[test]
+    fn test_pop3_headers() {
+        let headers = Pop3Headers::parse(b"Received: a\r\nMessage-ID: <1@example.com>\r\nReceived: b\r\nContent-Type: Text/HTML; charset=utf-8\r\n\r\n");
+        assert_eq!(vec!["a", "b"], headers.get_all("received").collect::<Vec<_>>());
+        assert_eq!(Some("<1@example.com>"), headers.message_id());
+        assert_eq!("text/html", headers.mime_type());
+        assert_eq!(None, headers.subject());
+        assert_eq!("text/plain", Pop3Headers::default().mime_type());
+    }
+
+    #[test]
+    fn test_find() {
+        let headers = parse_headers(b"Subject: first\nsubject: second\n");
+        assert_eq!(Some("first"), find(&headers, "SUBJECT"));
+        assert_eq!(None, find(&headers, "From"));
+    }
+}
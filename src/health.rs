@@ -0,0 +1,38 @@
+use std::time::Duration;
+
+/// State of a connection determined by a health check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pop3HealthState {
+    /// the server answered NOOP positive
+    Ready,
+
+    /// the server did not answer NOOP positive; see [`Pop3HealthReport::error`]
+    Unresponsive,
+
+    /// the connection is poisoned by a cancelled or failed operation
+    Poisoned,
+
+    /// the session was ended
+    Closed,
+}
+
+/// Result of a health check, e.g. for readiness probes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pop3HealthReport {
+    /// state of the connection
+    pub state: Pop3HealthState,
+
+    /// round-trip time of NOOP, if it was answered
+    pub latency: Option<Duration>,
+
+    /// error of NOOP, if it failed
+    pub error: Option<String>,
+}
+
+impl Pop3HealthReport {
+
+    /// Returns true, if the connection can be used.
+    pub fn is_ready(&self) -> bool {
+        self.state == Pop3HealthState::Ready
+    }
+}
@@ -0,0 +1,57 @@
+use crate::Pop3AsyncError;
+
+/// Middleware, which observes commands and responses of a connection.
+///
+/// Interceptors implement cross-cutting concerns like auditing or policy
+/// enforcement: each command can be vetoed before it is sent and each line
+/// of a response can be rewritten before it is parsed. Transcripts, wire
+/// dumps and logs always show the traffic as sent and received.
+///
+/// Commands are passed as sent, so they contain passwords of `PASS` and
+/// tokens of `AUTH`.
+///
+/// See [`crate::Pop3Connection::add_interceptor`].
+///
+/// # Examples
+///
+/// ```
+/// use rust_pop3_client::{Pop3AsyncError, Pop3Interceptor};
+///
+/// /// Keeps all messages on the server.
+/// struct ReadOnly;
+///
+/// impl Pop3Interceptor for ReadOnly {
+///     fn before_command(&self, command: &str) -> Result<(), Pop3AsyncError> {
+///         match command.to_ascii_uppercase().starts_with("DELE") {
+///             true => Err("deletion is not allowed".into()),
+///             false => Ok(())
+///         }
+///     }
+/// }
+/// ```
+pub trait Pop3Interceptor: Send + Sync {
+
+    /// Inspects a command before it is sent; an error vetoes the command.
+    ///
+    /// A vetoed command is not sent and its error is returned by the
+    /// operation. The connection stays usable.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - command without line terminator, e.g. `RETR 1`
+    fn before_command(&self, _command: &str) -> Result<(), Pop3AsyncError> {
+        Ok(())
+    }
+
+    /// Inspects and optionally rewrites a line of a response.
+    ///
+    /// It is called for the status line as well as for each line of a
+    /// multi-line response, which still contains byte-stuffing.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - command, whose response is received, without line terminator
+    /// * `line` - received line including its line terminator
+    fn after_response_line(&self, _command: &str, _line: &mut Vec<u8>) {
+    }
+}
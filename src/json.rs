@@ -0,0 +1,288 @@
+//! Minimal JSON support used by the file formats of this crate.
+
+use std::error::Error;
+use std::fmt;
+
+/// JSON value
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+
+    /// Returns the member of an object with the given name.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().find(|(key, _)| key == name).map(|(_, value)| value),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Value::Number(value) if *value >= 0.0 && value.fract() == 0.0 => Some(*value as u64),
+            _ => None
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Object(members) => Some(members),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::Number(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", string(value)),
+            Value::Array(values) => {
+                write!(f, "[")?;
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            },
+            Value::Object(members) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", string(key), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Returns the value as quoted JSON string.
+pub(crate) fn string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len() + 2);
+    result.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Parses a JSON document.
+pub(crate) fn parse(text: &str) -> Result<Value, Box<dyn Error>> {
+    let mut parser = Parser { chars: text.chars().collect(), pos: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.pos == parser.chars.len() {
+        true => Ok(value),
+        _ => Err("unexpected trailing characters".into())
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Parser {
+
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn next(&mut self) -> Result<char, Box<dyn Error>> {
+        let c = *self.chars.get(self.pos).ok_or("unexpected end of JSON")?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), Box<dyn Error>> {
+        for c in expected.chars() {
+            if self.next()? != c {
+                return Err(format!("expected {}", expected).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn value(&mut self) -> Result<Value, Box<dyn Error>> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos).ok_or("unexpected end of JSON")? {
+            'n' => { self.expect("null")?; Ok(Value::Null) },
+            't' => { self.expect("true")?; Ok(Value::Bool(true)) },
+            'f' => { self.expect("false")?; Ok(Value::Bool(false)) },
+            '"' => Ok(Value::String(self.string()?)),
+            '[' => self.array(),
+            '{' => self.object(),
+            _ => self.number()
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, Box<dyn Error>> {
+        let start = self.pos;
+        while self.pos < self.chars.len() && matches!(self.chars[self.pos], '0'..='9' | '-' | '+' | '.' | 'e' | 'E') {
+            self.pos += 1;
+        }
+
+        let number: String = self.chars[start..self.pos].iter().collect();
+        Ok(Value::Number(number.parse::<f64>().map_err(|_| format!("invalid JSON value: {}", number))?))
+    }
+
+    fn string(&mut self) -> Result<String, Box<dyn Error>> {
+        self.expect("\"")?;
+        let mut result = String::new();
+        loop {
+            match self.next()? {
+                '"' => { break },
+                '\\' => {
+                    match self.next()? {
+                        'n' => result.push('\n'),
+                        'r' => result.push('\r'),
+                        't' => result.push('\t'),
+                        'b' => result.push('\u{8}'),
+                        'f' => result.push('\u{c}'),
+                        'u' => {
+                            let mut code = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&code) {
+                                self.expect("\\u")?;
+                                let low = self.hex4()?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            result.push(char::from_u32(code).ok_or("invalid unicode escape")?);
+                        },
+                        c => result.push(c)
+                    }
+                },
+                c => result.push(c)
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn hex4(&mut self) -> Result<u32, Box<dyn Error>> {
+        let mut code = 0;
+        for _ in 0..4 {
+            code = code * 16 + self.next()?.to_digit(16).ok_or("invalid unicode escape")?;
+        }
+
+        Ok(code)
+    }
+
+    fn array(&mut self) -> Result<Value, Box<dyn Error>> {
+        self.expect("[")?;
+        let mut values = vec!();
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&']') {
+            self.pos += 1;
+            return Ok(Value::Array(values));
+        }
+
+        loop {
+            values.push(self.value()?);
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => { },
+                ']' => { break },
+                _ => return Err("expected , or ]".into())
+            }
+        }
+
+        Ok(Value::Array(values))
+    }
+
+    fn object(&mut self) -> Result<Value, Box<dyn Error>> {
+        self.expect("{")?;
+        let mut members = vec!();
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&'}') {
+            self.pos += 1;
+            return Ok(Value::Object(members));
+        }
+
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(":")?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.next()? {
+                ',' => { },
+                '}' => { break },
+                _ => return Err("expected , or }".into())
+            }
+        }
+
+        Ok(Value::Object(members))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string() {
+        assert_eq!("\"a\\\"b\\\\c\\r\\n\\u0001\"", string("a\"b\\c\r\n\x01"));
+    }
+
+    #[test]
+    fn test_parse() {
+        let value = parse(" { \"a\" : [1, 2.5, -3], \"b\": {\"c\": null, \"d\": true}, \"e\": \"x\\u00e4\\ud83d\\ude00\\n\" } ").unwrap();
+        assert_eq!(Some(&Value::Array(vec!(Value::Number(1.0), Value::Number(2.5), Value::Number(-3.0)))), value.get("a"));
+        assert_eq!(Some(&Value::Null), value.get("b").and_then(|b| b.get("c")));
+        assert_eq!(Some(&Value::Bool(true)), value.get("b").and_then(|b| b.get("d")));
+        assert_eq!(Some("x\u{e4}\u{1f600}\n"), value.get("e").and_then(Value::as_str));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse("{\"a\": }").is_err());
+        assert!(parse("[1, 2").is_err());
+        assert!(parse("{} x").is_err());
+        assert!(parse("").is_err());
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let value = Value::Object(vec!(
+            ("name".into(), Value::String("a \"quoted\" value".into())),
+            ("list".into(), Value::Array(vec!(Value::Number(42.0), Value::Bool(false), Value::Null))),
+            ("empty".into(), Value::Object(vec!())),
+        ));
+        assert_eq!("{\"name\":\"a \\\"quoted\\\" value\",\"list\":[42,false,null],\"empty\":{}}", value.to_string());
+        assert_eq!(value, parse(&value.to_string()).unwrap());
+    }
+}
@@ -0,0 +1,137 @@
+use std::error::Error;
+use std::io::Write;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+use crate::Pop3MessageMeta;
+use crate::json::string;
+use crate::sink::{self, MessageSink};
+
+/// Encoding of message parts in JSON output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JsonEncoding {
+    /// UTF-8 text; invalid sequences are replaced
+    Text,
+
+    /// base64 encoded octets
+    Base64,
+}
+
+/// Writes message metadata as JSON Lines, one JSON object per message.
+///
+/// Each object contains `message_id`, `unique_id` (or `null`), `message_size`
+/// as reported by LIST and `size` as retrieved. Headers and body are added as
+/// `headers` and `body`, if enabled.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{JsonEncoding, JsonLinesSink, Pop3Connection};
+///
+/// let mut connection = Pop3Connection::new("pop.example.com", 995).unwrap();
+/// connection.login("user@example.com", "secret").unwrap();
+///
+/// let mut sink = JsonLinesSink::new(std::io::stdout()).with_headers(JsonEncoding::Text);
+/// for message in connection.list_meta().unwrap() {
+///     connection.retrieve_to(&message, &mut sink).unwrap();
+/// }
+/// ```
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+    headers: Option<JsonEncoding>,
+    body: Option<JsonEncoding>,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+
+    /// Returns a new sink writing metadata only.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - writer of the JSON Lines
+    pub fn new(writer: W) -> Self {
+        JsonLinesSink { writer, headers: None, body: None }
+    }
+
+    /// Includes the message headers using the given encoding.
+    pub fn with_headers(mut self, encoding: JsonEncoding) -> Self {
+        self.headers = Some(encoding);
+        self
+    }
+
+    /// Includes the message body using the given encoding.
+    pub fn with_body(mut self, encoding: JsonEncoding) -> Self {
+        self.body = Some(encoding);
+        self
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> MessageSink for JsonLinesSink<W> {
+    fn deliver(&mut self, message: &Pop3MessageMeta, content: &[u8]) -> Result<(), Box<dyn Error>> {
+        let unique_id = match &message.unique_id {
+            Some(unique_id) => string(unique_id),
+            None => "null".to_string()
+        };
+
+        let mut line = format!("{{\"message_id\":{},\"unique_id\":{},\"message_size\":{},\"size\":{}",
+            message.message_id, unique_id, message.message_size, content.len());
+
+        let (headers, body) = sink::split_message(content);
+        if let Some(encoding) = self.headers {
+            line.push_str(&format!(",\"headers\":{}", encode(headers, encoding)));
+        }
+        if let Some(encoding) = self.body {
+            line.push_str(&format!(",\"body\":{}", encode(body, encoding)));
+        }
+        line.push_str("}\n");
+
+        self.writer.write_all(line.as_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn encode(value: &[u8], encoding: JsonEncoding) -> String {
+    match encoding {
+        JsonEncoding::Text => string(&String::from_utf8_lossy(value)),
+        JsonEncoding::Base64 => string(&BASE64.encode(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &[u8] = b"Subject: \"hi\"\r\n\r\nbody\r\n";
+
+    fn meta(unique_id: Option<&str>) -> Pop3MessageMeta {
+        Pop3MessageMeta { message_id: 3, message_size: 24, unique_id: unique_id.map(|id| id.to_string()) }
+    }
+
+    fn deliver(sink: JsonLinesSink<Vec<u8>>, message: &Pop3MessageMeta) -> String {
+        let mut sink = sink;
+        sink.deliver(message, MESSAGE).unwrap();
+        String::from_utf8(sink.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn test_metadata_only() {
+        let output = deliver(JsonLinesSink::new(vec!()), &meta(None));
+        assert_eq!("{\"message_id\":3,\"unique_id\":null,\"message_size\":24,\"size\":23}\n", output);
+    }
+
+    #[test]
+    fn test_headers_and_body() {
+        let sink = JsonLinesSink::new(vec!())
+            .with_headers(JsonEncoding::Text)
+            .with_body(JsonEncoding::Base64);
+        let output = deliver(sink, &meta(Some("uid1")));
+        assert_eq!("{\"message_id\":3,\"unique_id\":\"uid1\",\"message_size\":24,\"size\":23,\"headers\":\"Subject: \\\"hi\\\"\\r\\n\",\"body\":\"Ym9keQ0K\"}\n", output);
+    }
+}
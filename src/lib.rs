@@ -1,21 +1,176 @@
-mod line_reader;
+mod accounts;
+mod address;
+mod builder;
+mod clock;
+mod command;
+mod date;
+mod digest;
+mod download;
+mod eml;
+mod error;
+mod engine;
+mod fetcher;
+mod glob;
+mod hash_store;
+mod headers;
+mod health;
+mod interceptor;
+mod json;
+mod jsonl;
+mod logging;
+mod maildir;
+mod mbox;
+mod metrics;
+mod oauth;
+mod parallel;
+mod pool;
+mod proxy;
+mod quarantine;
+mod quota;
+mod report;
+mod retention;
+mod rules;
+mod session;
+mod sink;
+mod state;
+mod stats;
+mod stream;
+mod summary;
+mod sync;
+mod text;
+mod throttle;
+mod trace;
+mod transaction;
+mod transcript;
+mod watcher;
+mod worker;
+
+pub mod backup;
+pub mod csv;
+pub mod encoding;
+pub mod response;
+
+#[cfg(feature = "keyring")]
+pub mod credentials;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+
+mod async_connection;
+#[cfg(feature = "tokio")]
+mod async_pool;
+#[cfg(feature = "tokio")]
+mod async_retry;
+#[cfg(feature = "smol")]
+mod async_smol;
+#[cfg(feature = "tokio")]
+mod async_tokio;
+#[cfg(feature = "dkim")]
+mod dkim;
+#[cfg(feature = "mail-parser")]
+mod mime;
+#[cfg(feature = "lettre")]
+mod resend;
+#[cfg(all(feature = "test-util", feature = "tokio"))]
+mod soak;
+#[cfg(feature = "sqlite")]
+mod sqlite_state;
 
-use std::sync::Arc;
-use std::net::TcpStream;
 use std::error::Error;
+use std::future::Future;
 use std::io::{Write};
-
-use rustls::{RootCertStore, ClientConnection, StreamOwned};
-
-use line_reader::LineReader;
+use std::ops::AddAssign;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::io::AllowStdIo;
+use rustls::RootCertStore;
+
+use clock::ThreadTimer;
+use error::ConnectionLost;
+use stream::Stream;
+
+pub use accounts::{AccountSet, Pop3AccountResult};
+pub use address::Mailbox;
+pub use builder::{Pop3ConnectionBuilder, TlsMode};
+pub use command::CommandSink;
+pub use digest::{MessageDigest, Sha256Digest, to_hex};
+pub use download::Pop3Downloader;
+pub use eml::EmlDirectorySink;
+pub use error::{is_transient, Pop3ErrorExt, Pop3ErrorKind, Pop3NegativeResponse};
+pub use engine::{Pop3Engine, Pop3Event};
+pub use fetcher::{MailFetcher, MailInfo, Pop3MailFetcher};
+pub use hash_store::SharedHashStore;
+pub use headers::Pop3Headers;
+pub use health::{Pop3HealthReport, Pop3HealthState};
+pub use interceptor::Pop3Interceptor;
+pub use jsonl::{JsonEncoding, JsonLinesSink};
+pub use maildir::MaildirSink;
+pub use mbox::MboxSink;
+pub use metrics::MetricsSink;
+pub use oauth::TokenProvider;
+pub use parallel::ParallelFetcher;
+pub use pool::{Pop3AccountKey, Pop3Pool, PooledConnection};
+pub use proxy::Pop3Proxy;
+pub use quarantine::{Quarantine, QuarantineEntry, QuarantineReport, SuspicionFlag};
+pub use quota::{CleanupPlan, CleanupStrategy};
+pub use report::{Pop3SizeBucket, Pop3UsageReport};
+pub use retention::RetentionPolicy;
+pub use rules::{RuleAction, RuleMatcher, RuleSet};
+pub use session::{Pop3SessionEvent, Pop3SessionObserver};
+pub use sink::MessageSink;
+pub use state::{JsonFileStateStore, MemoryStateStore, SyncStateStore, UidState};
+pub use stats::{Pop3CommandStats, Pop3SessionStats};
+pub use summary::Pop3MessageSummary;
+pub use sync::{ContentTypeAction, SyncOptions};
+
+#[cfg(feature = "blake3")]
+pub use digest::Blake3Digest;
+#[cfg(feature = "metrics")]
+pub use metrics::MetricsCrateSink;
+
+pub use async_connection::{AsyncPop3Connection, AsyncResolver, AsyncTimer, Pop3AsyncError, Pop3AsyncMessageReader};
+#[cfg(feature = "tokio")]
+pub use async_pool::{AsyncPop3Pool, Pop3FetchEvent, Pop3FetchFailure, Pop3FetchPhase, Pop3FetchProgress, Pop3FetchReport, Pop3PooledConnection};
+#[cfg(feature = "tokio")]
+pub use async_retry::{OperationClass, Pop3OperationFuture, RetryPolicy, RetryingPop3Connection};
+#[cfg(feature = "smol")]
+pub use async_smol::{SmolPop3Connection, SmolStream, SmolTimer};
+#[cfg(feature = "tokio")]
+pub use async_tokio::{TokioPop3Connection, TokioStream, TokioTimer};
+#[cfg(feature = "dkim")]
+pub use dkim::{DkimSignatureResult, DkimStatus, verify_dkim, verify_dkim_async};
+#[cfg(feature = "mail-parser")]
+pub use mime::{AttachmentFilter, Pop3AttachmentInfo, Pop3ParsedMessage};
+#[cfg(feature = "mock-server")]
+pub use test_util::{AsyncMockServer, MockMismatch, MockScript};
+#[cfg(feature = "lettre")]
+pub use resend::Pop3ResendMessage;
+#[cfg(feature = "sqlite")]
+pub use sqlite_state::SqliteStateStore;
+pub use transaction::DeletionTransaction;
+pub use watcher::Pop3Watcher;
+pub use worker::{Pop3Job, Pop3JobOutput, Pop3Responder, Pop3Worker, Pop3WorkerError};
 
 /// POP3 connection
+///
+/// The blocking connection is a thin wrapper of [`AsyncPop3Connection`]
+/// using a blocking stream, so both share the same protocol implementation.
 pub struct Pop3Connection {    
-    tls: StreamOwned<ClientConnection, TcpStream>,
-    reader: LineReader,
+    inner: AsyncPop3Connection<AllowStdIo<Stream>>,
+}
+
+/// Runs an operation of the async connection on a blocking stream.
+///
+/// Since the stream blocks instead of returning pending, most operations
+/// complete when polled the first time; others, e.g. sleeps of a timer,
+/// block the current thread until they complete.
+fn block_on<T>(operation: impl Future<Output = Result<T, Pop3AsyncError>>) -> Result<T, Box<dyn Error>> {
+    futures_executor::block_on(operation).map_err(|err| err as Box<dyn Error>)
 }
 
 /// POP3 maildrop statistics
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pop3Stat {
     /// count of massages in the maildrop
     pub message_count: u32,
@@ -25,6 +180,7 @@ pub struct Pop3Stat {
 }
 
 /// POP3 message info
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pop3MessageInfo {
     /// numerical Id of the message used for various commands
     pub message_id: u32,
@@ -34,6 +190,7 @@ pub struct Pop3MessageInfo {
 }
 
 /// POP3 message unique id info
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pop3MessageUidInfo {
     /// numerical Id of the message used for various commands
     pub message_id: u32,
@@ -42,6 +199,76 @@ pub struct Pop3MessageUidInfo {
     pub unique_id: String,
 }
 
+/// POP3 message metadata combining LIST and UIDL
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pop3MessageMeta {
+    /// numerical Id of the message used for various commands
+    pub message_id: u32,
+
+    /// size of the message in bytes
+    pub message_size: u32,
+
+    /// unique id of the message; `None` if the server does not support UIDL
+    pub unique_id: Option<String>,
+}
+
+/// Result of comparing the received octets of a message to the size reported by LIST
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pop3SizeCheck {
+    /// size of the message in bytes as reported by LIST
+    pub expected_size: u32,
+
+    /// count of octets received, including byte-stuffing and excluding the termination line
+    pub received_size: u64,
+}
+
+impl Pop3SizeCheck {
+
+    /// Returns true, if the received octets match the reported size.
+    pub fn is_match(&self) -> bool {
+        self.received_size == self.expected_size as u64
+    }
+
+    /// Returns true, if less octets were received than reported, e.g. due to a truncated transfer.
+    pub fn is_truncated(&self) -> bool {
+        self.received_size < self.expected_size as u64
+    }
+}
+
+/// Sizes of a retrieved message in octets
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pop3TransferSize {
+    /// octets received, including byte-stuffing and excluding the termination line
+    pub wire_size: u64,
+
+    /// octets of the message after removing the byte-stuffing, as written by [`Pop3Connection::retrieve_raw`]
+    pub decoded_size: u64,
+
+    /// octets of the message with CRLF line endings replaced by LF, e.g. as stored in Maildir
+    pub normalized_size: u64,
+}
+
+impl AddAssign for Pop3TransferSize {
+    fn add_assign(&mut self, other: Self) {
+        self.wire_size += other.wire_size;
+        self.decoded_size += other.decoded_size;
+        self.normalized_size += other.normalized_size;
+    }
+}
+
+/// POP3 message separated into header section and body
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pop3SplitMessage {
+    /// header section including the line ending of the last header line
+    pub raw_headers: Vec<u8>,
+
+    /// body following the empty line after the header section
+    pub body: Vec<u8>,
+}
+
 impl Pop3Connection {
 
     /// Returns a new POP3 connection.
@@ -51,12 +278,9 @@ impl Pop3Connection {
     /// * `host` - IP-Address or host name of the POP3 server to connect
     /// * `port` - Port of the POP3 server to connect
     pub fn new(host: &str, port: u16) -> Result<Pop3Connection, Box<dyn Error>> {
-        let mut root_store = RootCertStore::empty();
-        for cert in rustls_native_certs::load_native_certs()? {
-            root_store.add(&rustls::Certificate(cert.0))?;
-        }
-
-        Pop3Connection::with_custom_certs(host, port, root_store)
+        Pop3ConnectionBuilder::new(host)
+            .port(port)
+            .connect()
     }
 
     /// Returns a new POP3 connection with custom certificates.
@@ -81,55 +305,170 @@ impl Pop3Connection {
     /// let connection = Pop3Connection::with_custom_certs("", 995, root_store);
     /// ```
     pub fn with_custom_certs(host: &str, port: u16, root_store: RootCertStore) -> Result<Pop3Connection, Box<dyn Error>> {
-        let config = rustls::ClientConfig::builder()
-            .with_safe_defaults()
-            .with_root_certificates(root_store)
-            .with_no_client_auth();
+        Pop3ConnectionBuilder::new(host)
+            .port(port)
+            .root_store(root_store)
+            .connect()
+    }
 
-        let server_name = host.try_into()?;
+    /// Returns a builder to configure a new POP3 connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - IP-Address or host name of the POP3 server to connect
+    pub fn builder(host: &str) -> Pop3ConnectionBuilder {
+        Pop3ConnectionBuilder::new(host)
+    }
 
-        let connection = rustls::ClientConnection::new(Arc::new(config), server_name)?;
-        let stream =  TcpStream::connect(format!("{}:{}", host, port))?;
-        let tls = rustls::StreamOwned::new(connection, stream);
+    /// Returns a new POP3 connection configured by environment variables.
+    ///
+    /// See [`Pop3ConnectionBuilder::from_env`] for the recognized variables.
+    pub fn from_env() -> Result<Pop3Connection, Box<dyn Error>> {
+        Pop3ConnectionBuilder::from_env()?.connect()
+    }
 
-        let mut client = Pop3Connection { 
-            tls: tls,
-            reader: LineReader::new()
-        };
+    pub(crate) fn open(stream: Stream) -> Result<Pop3Connection, Box<dyn Error>> {
+        let mut inner = block_on(AsyncPop3Connection::from_stream(AllowStdIo::new(stream)))?;
+        inner.set_timer(ThreadTimer);
+        Ok(Pop3Connection { inner })
+    }
 
-        client.read_status_line()?;
-        Ok(client)
+    pub(crate) fn start_tls(&mut self, host: &str, root_store: RootCertStore) -> Result<(), Box<dyn Error>> {
+        block_on(self.inner.invoke_single_line("STLS\r\n"))?;
+        let stream = self.inner.stream_mut().ok_or(ConnectionLost("stream closed"))?.get_mut();
+        *stream = std::mem::replace(stream, Stream::Closed).upgrade(host, root_store)?;
+        Ok(())
     }
 
-    fn read_status_line(&mut self) -> Result<String, Box<dyn Error>> {
-        let line = self.reader.read_line(&mut self.tls)?;
+    /// Enables or disables keep-alive.
+    ///
+    /// When enabled and no command was issued for at least the given interval,
+    /// a NOOP is sent transparently before the next command. This prevents
+    /// the server's autologout timer from closing an idle session.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - idle interval after which a NOOP is sent; `None` disables keep-alive
+    pub fn set_keep_alive(&mut self, interval: Option<Duration>) {
+        self.inner.set_keep_alive(interval);
+    }
 
-        match line.starts_with("+OK") {
-            true => Ok(line),
-            _ => Err(line.into())
-        }
+    /// Sends a NOOP, if keep-alive is enabled and the session was idle for the keep-alive interval.
+    ///
+    /// This is called before each command; applications may call it periodically
+    /// to keep an otherwise unused session alive.
+    pub fn keep_alive(&mut self) -> Result<(), Box<dyn Error>> {
+        block_on(self.inner.keep_alive())
     }
 
-    fn invoke_single_line(&mut self, command: &str) -> Result<String, Box<dyn Error>> {
-        self.tls.write(command.as_bytes())?;
-        self.read_status_line()
+    /// Records the session to a transcript, e.g. to reproduce a problem with a specific server.
+    ///
+    /// Each line sent is written as `C: <line>`, each line received as
+    /// `S: <line>`, starting with the greeting. Passwords and authentication
+    /// data are replaced by `***`. Using the `test-util` feature, a transcript
+    /// can be replayed by `test_util::ScriptedTransport::from_transcript`.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - receives the transcript; `None` stops recording
+    pub fn set_transcript(&mut self, writer: Option<Box<dyn Write + Send>>) {
+        self.inner.set_transcript(writer);
     }
 
-    fn invoke_multi_line(&mut self, command: &str) -> Result<Vec<String>, Box<dyn Error>> {
-        self.tls.write(command.as_bytes())?;
-        self.read_status_line()?;
+    /// Writes all further traffic to a wire dump, e.g. to attach a protocol log to a bug report.
+    ///
+    /// Each line sent is written as `C: <line>`, each line received as
+    /// `S: <line>`. Control characters and invalid UTF-8 are escaped and
+    /// lines not terminated by CRLF are marked. Passwords and authentication
+    /// data are replaced by `***`.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - receives the wire dump; `None` stops dumping
+    pub fn set_wire_dump(&mut self, writer: Option<Box<dyn Write + Send>>) {
+        self.inner.set_wire_dump(writer);
+    }
 
-        let mut response : Vec<String> = vec!();
-        loop {
-            let line = self.reader.read_line(&mut self.tls)?;
-            match line {
-                _ if line == "." => { break },
-                _ if line.starts_with(".") => { response.push(line[1..].to_string()); },
-                _ => { response.push(line); }
-            };
-        }
+    /// Sets the observer of the events of the session, e.g. to update a UI.
+    ///
+    /// Connections created by [`Pop3ConnectionBuilder::observer`] report
+    /// their connection and TLS establishment as well. See [`Pop3SessionEvent`].
+    ///
+    /// # Arguments
+    ///
+    /// * `observer` - receives the events; `None` stops observing
+    pub fn set_observer(&mut self, observer: Option<Arc<dyn Pop3SessionObserver>>) {
+        self.inner.set_observer(observer);
+    }
+
+    /// Adds an interceptor, which can veto commands and rewrite responses.
+    ///
+    /// Interceptors are called in the order they were added. See [`Pop3Interceptor`].
+    ///
+    /// # Arguments
+    ///
+    /// * `interceptor` - interceptor of all further commands
+    pub fn add_interceptor(&mut self, interceptor: Arc<dyn Pop3Interceptor>) {
+        self.inner.add_interceptor(interceptor);
+    }
+
+    /// Reports metrics of all further commands, e.g. to monitor a fleet of fetchers.
+    ///
+    /// For each command, its outcome and latency are reported by its name;
+    /// arguments are never reported. Additionally, all bytes sent and
+    /// received are reported. See [`MetricsSink`].
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - receives the metrics; `None` stops reporting
+    pub fn set_metrics(&mut self, sink: Option<Arc<dyn MetricsSink>>) {
+        self.inner.set_metrics(sink);
+    }
 
-        Ok(response)
+    /// Limits the download rate of retrieved messages.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes_per_second` - maximum download rate; `None` disables the limit
+    pub fn set_download_rate(&mut self, bytes_per_second: Option<u64>) {
+        self.inner.set_download_rate(bytes_per_second);
+    }
+
+    /// Enables or disables dry-run mode.
+    ///
+    /// In dry-run mode, deletions are only simulated: DELE is never sent to the
+    /// server, so nothing is removed when the session ends. Simulated deletions
+    /// are reported by [`Pop3Connection::marked_for_deletion`].
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.inner.set_dry_run(dry_run);
+    }
+
+    /// Returns true, if dry-run mode is enabled.
+    pub fn is_dry_run(&self) -> bool {
+        self.inner.is_dry_run()
+    }
+
+    /// Enables or disables pipelining (RFC 2449).
+    ///
+    /// If enabled, [`Pop3Connection::list_meta`] sends LIST and UIDL
+    /// back-to-back without waiting for the first response, which saves a
+    /// round trip. Enable it only if the server announces PIPELINING; this
+    /// is done automatically by [`Pop3Connection::capabilities`] and by
+    /// [`Pop3ConnectionBuilder::pipelining`].
+    pub fn set_pipelining(&mut self, pipelining: bool) {
+        self.inner.set_pipelining(pipelining);
+    }
+
+    /// Returns true, if pipelining is enabled.
+    pub fn is_pipelining(&self) -> bool {
+        self.inner.is_pipelining()
+    }
+
+    /// Returns the ids of messages marked as deleted in this session.
+    ///
+    /// In dry-run mode, these are the messages which would have been deleted.
+    pub fn marked_for_deletion(&self) -> &[u32] {
+        self.inner.marked_for_deletion()
     }
 
     /// Authenticate a POP3 session using username and password.
@@ -142,40 +481,31 @@ impl Pop3Connection {
     /// * `user`     - Name of the user, typically it's e-mail address.
     /// * `password` - Password of the user. 
     pub fn login(&mut self, user: &str, password: &str) -> Result<(), Box<dyn Error>> {
-        self.invoke_single_line(&format!("USER {}\r\n", user))?;
-        self.invoke_single_line(&format!("PASS {}\r\n", password))?;
-        Ok(())
+        block_on(self.inner.login(user, password))
+    }
+
+    /// Authenticate a POP3 session using an OAuth 2.0 access token (XOAUTH2).
+    ///
+    /// If the server rejects the token with an `[AUTH]` response code,
+    /// the provider is asked once for a fresh token and authentication
+    /// is retried.
+    ///
+    /// # Arguments
+    ///
+    /// * `user`     - Name of the user, typically it's e-mail address.
+    /// * `provider` - Provider of the access token.
+    pub fn login_oauth2(&mut self, user: &str, provider: &dyn TokenProvider) -> Result<(), Box<dyn Error>> {
+        block_on(self.inner.login_oauth2_with(user, provider))
     }
 
     /// Returns maildrop statistics.
     pub fn stat(&mut self) -> Result<Pop3Stat, Box<dyn Error>> {
-        let stat = self.invoke_single_line("STAT\r\n")?;
-        let mut stat = stat.split(' ');
-        let _ = stat.next();
-        let message_count = stat.next().ok_or("missing message count")?;
-        let message_count = message_count.parse::<u32>()?;
-        let maildrop_size = stat.next().ok_or("missing maildrop size")?;
-        let maildrop_size = maildrop_size.parse::<u32>()?;
-
-        Ok(Pop3Stat { message_count: message_count, maildrop_size: maildrop_size })
+        block_on(self.inner.stat())
     }
 
     /// Returns id and size of each message.
     pub fn list(&mut self) -> Result<Vec<Pop3MessageInfo>, Box<dyn Error>> {
-        let lines = self.invoke_multi_line("LIST\r\n")?;
-        let mut result = vec!();
-        for line in lines {
-            let mut info = line.split(' ');
-            let message_id = info.next().ok_or("missing id")?.parse::<u32>()?;
-            let message_size = info.next().ok_or("missing size")?.parse::<u32>()?;
-
-            result.push(Pop3MessageInfo { 
-                message_id: message_id, 
-                message_size: message_size
-            });
-        }
-
-        Ok(result)
+        block_on(self.inner.list())
     }
 
     /// Returns the size of a given message.
@@ -184,13 +514,7 @@ impl Pop3Connection {
     ///
     /// * `message_id` - id of the message to query
     pub fn get_message_size(&mut self, message_id: u32) -> Result<u32, Box<dyn Error>> {
-        let line = self.invoke_single_line(&format!("LIST {}\r\n", message_id))?;
-        let mut info = line.split(' ');
-        let _ = info.next();    // skip "+OK"
-        let _ = info.next();    // skip message id
-        let message_size = info.next().ok_or("missing size")?.parse::<u32>()?;
-     
-        Ok(message_size)
+        block_on(self.inner.get_message_size(message_id))
     }
 
     /// Downloads a given message.
@@ -200,13 +524,114 @@ impl Pop3Connection {
     /// * `message_id` - id of the message to download
     /// * `writer`     - writer to store message
     pub fn retrieve(&mut self, message_id: u32, writer: &mut impl Write) -> Result<(), Box<dyn Error>> {
-        let lines = self.invoke_multi_line(&format!("RETR {}\r\n", message_id))?;
-        for line in lines {
-            writer.write(line.as_bytes())?;
-            writer.write(b"\n")?;
-        }
+        block_on(self.inner.retrieve(message_id, &mut AllowStdIo::new(writer)))
+    }
 
-        Ok(())
+    /// Downloads the exact octets of a given message.
+    ///
+    /// In contrast to [`Pop3Connection::retrieve`], lines are neither trimmed
+    /// nor are line endings converted; only the byte-stuffing of the POP3
+    /// protocol is removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to download
+    /// * `writer`     - writer to store message
+    pub fn retrieve_raw(&mut self, message_id: u32, writer: &mut impl Write) -> Result<(), Box<dyn Error>> {
+        block_on(self.inner.retrieve_raw(message_id, &mut AllowStdIo::new(writer)))
+    }
+
+    /// Downloads the exact octets of a given message and returns its sizes.
+    ///
+    /// Unlike the size reported by LIST, the returned sizes are counted
+    /// while receiving the message, so they are exact, e.g. for bandwidth
+    /// and quota accounting.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to download
+    /// * `writer`     - writer to store message
+    pub fn retrieve_sized(&mut self, message_id: u32, writer: &mut impl Write) -> Result<Pop3TransferSize, Box<dyn Error>> {
+        block_on(self.inner.retrieve_sized(message_id, &mut AllowStdIo::new(writer)))
+    }
+
+    /// Enables or disables strict size checks.
+    ///
+    /// When enabled, [`Pop3Connection::retrieve_verified`] fails, if the
+    /// received octets do not match the size reported by LIST.
+    ///
+    /// # Arguments
+    ///
+    /// * `strict` - true to treat size mismatches as errors
+    pub fn set_strict_size_check(&mut self, strict: bool) {
+        self.inner.set_strict_size_check(strict);
+    }
+
+    /// Downloads the exact octets of a given message and compares their count to the size reported by LIST.
+    ///
+    /// The received octets are counted as transferred, i.e. before the
+    /// byte-stuffing is removed. A mismatch, e.g. caused by a truncated
+    /// transfer, is reported by the returned check; in strict mode, it is an
+    /// error (see [`Pop3Connection::set_strict_size_check`]). Note that some
+    /// servers report sizes using different line endings than they transfer.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - metadata of the message to download
+    /// * `writer`  - writer to store message
+    pub fn retrieve_verified(&mut self, message: &Pop3MessageMeta, writer: &mut impl Write) -> Result<Pop3SizeCheck, Box<dyn Error>> {
+        block_on(self.inner.retrieve_verified(message, &mut AllowStdIo::new(writer)))
+    }
+
+    /// Downloads the exact octets of a given message and returns their digest.
+    ///
+    /// The digest is computed while the message is retrieved, so no second
+    /// pass over the data is needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to download
+    /// * `writer`     - writer to store message
+    /// * `digest`     - digest to compute, e.g. [`Sha256Digest`]
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use rust_pop3_client::{Pop3Connection, Sha256Digest, to_hex};
+    ///
+    /// let mut connection = Pop3Connection::new("pop.example.com", 995).unwrap();
+    /// connection.login("user@example.com", "secret").unwrap();
+    ///
+    /// let mut message = vec!();
+    /// let hash = connection.retrieve_with_digest(1, &mut message, &mut Sha256Digest::new()).unwrap();
+    /// println!("sha256: {}", to_hex(&hash));
+    /// ```
+    pub fn retrieve_with_digest(&mut self, message_id: u32, writer: &mut impl Write, digest: &mut dyn MessageDigest) -> Result<Vec<u8>, Box<dyn Error>> {
+        block_on(self.inner.retrieve_with_digest(message_id, &mut AllowStdIo::new(writer), digest))
+    }
+
+    /// Downloads the exact octets of a given message separated into header section and body.
+    ///
+    /// The message is split at the first empty line, which is part of
+    /// neither. Messages without empty line consist of headers only.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message to download
+    pub fn retrieve_split(&mut self, message_id: u32) -> Result<Pop3SplitMessage, Box<dyn Error>> {
+        block_on(self.inner.retrieve_split(message_id))
+    }
+
+    /// Downloads a given message and delivers it to a sink.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - metadata of the message to download
+    /// * `sink`    - target of the message
+    pub fn retrieve_to(&mut self, message: &Pop3MessageMeta, sink: &mut dyn MessageSink) -> Result<(), Box<dyn Error>> {
+        let mut content = vec!();
+        self.retrieve_raw(message.message_id, &mut content)?;
+        sink.deliver(message, &content)
     }
 
     /// Deletes a given message.
@@ -215,14 +640,75 @@ impl Pop3Connection {
     ///
     /// * `message_id` - id of the message to download
     pub fn delete(&mut self, message_id: u32) -> Result<(), Box<dyn Error>> {
-        self.invoke_single_line(&format!("DELE {}\r\n", message_id))?;
-        Ok(())
+        block_on(self.inner.delete(message_id))
+    }
+
+    /// Does nothing but checking the connection.
+    pub fn noop(&mut self) -> Result<(), Box<dyn Error>> {
+        block_on(self.inner.noop())
+    }
+
+    /// Returns true, if a command failed while its response was read, so the
+    /// connection can not be used anymore.
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    /// Returns the latency statistics of the commands of this session by command name.
+    ///
+    /// The statistics show whether slowness comes from authentication,
+    /// listing or retrieval.
+    pub fn stats(&self) -> &Pop3SessionStats {
+        self.inner.stats()
+    }
+
+    /// Checks the connection by NOOP and returns its state and round-trip latency.
+    ///
+    /// The check never fails, so it is suitable for readiness probes of
+    /// services; see [`AsyncPop3Connection::health_check`].
+    pub fn health_check(&mut self) -> Pop3HealthReport {
+        futures_executor::block_on(self.inner.health_check())
+    }
+
+    /// Returns a [`MailFetcher`], which identifies messages by their unique ids.
+    pub fn mail_fetcher(&mut self) -> Pop3MailFetcher<'_> {
+        Pop3MailFetcher::new(self)
+    }
+
+    /// Starts a transaction of deletions, which is rolled back unless committed.
+    pub fn transaction(&mut self) -> DeletionTransaction<'_> {
+        DeletionTransaction::new(self)
+    }
+
+    /// Ends the session.
+    ///
+    /// Messages marked as deleted are removed by the server. In contrast to
+    /// closing the connection on drop, errors are reported, e.g. when the
+    /// server was not able to remove all deleted messages.
+    pub fn quit(mut self) -> Result<(), Box<dyn Error>> {
+        self.close()
+    }
+
+    /// Returns true, if the session was not ended yet.
+    ///
+    /// Sessions are ended by QUIT, e.g. by [`Pop3Connection::fetch_new_messages_with`]
+    /// to commit deletions, or by the server.
+    pub fn is_open(&self) -> bool {
+        self.inner.is_open()
+    }
+
+    pub(crate) fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        block_on(self.inner.close())
+    }
+
+    /// Closes the connection without QUIT, so that deletions are not committed.
+    pub(crate) fn abort(&mut self) {
+        futures_executor::block_on(self.inner.abort());
     }
 
     /// Unmark any messages marked as delete.
     pub fn reset(&mut self) -> Result<(), Box<dyn Error>> {
-        self.invoke_single_line("RSET\r\n")?;
-        Ok(())
+        block_on(self.inner.reset())
     }
 
     /// Returns the message header an a given number of lines from the message.
@@ -232,30 +718,81 @@ impl Pop3Connection {
     /// * `message_id` - id of the message
     /// * `line_count` - count of lines to return from the message body
     pub fn top(&mut self, message_id: u32, line_count: u32) -> Result<String, Box<dyn Error>> {
-        let lines = self.invoke_multi_line(&format!("TOP {} {}\r\n", message_id, line_count))?;
-        let mut message = String::new();
-        for line in lines {
-            message.push_str(&line);
-            message.push('\n');
+        block_on(self.inner.top(message_id, line_count))
+    }
+
+    /// Returns the message header and a given number of lines from the message as exact octets.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    /// * `line_count` - count of lines to return from the message body
+    pub fn top_raw(&mut self, message_id: u32, line_count: u32) -> Result<Vec<u8>, Box<dyn Error>> {
+        block_on(self.inner.top_raw(message_id, line_count))
+    }
+
+    /// Returns the headers of a given message.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    pub fn headers(&mut self, message_id: u32) -> Result<Pop3Headers, Box<dyn Error>> {
+        let content = self.top_raw(message_id, 0)?;
+        Ok(Pop3Headers::parse(&content))
+    }
+
+    /// Returns metadata and headers of each message.
+    pub fn summaries(&mut self) -> Result<Vec<Pop3MessageSummary>, Box<dyn Error>> {
+        let messages = self.list_meta()?;
+        let mut result = vec!();
+        for meta in messages {
+            let headers = self.headers(meta.message_id)?;
+            result.push(Pop3MessageSummary { meta, headers });
         }
 
-        Ok(message)
+        Ok(result)
+    }
+
+    /// Writes id, unique id, size, sender, subject and date of each message as CSV.
+    ///
+    /// See [`csv::write_summaries`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - writer of the CSV data
+    pub fn export_csv(&mut self, writer: &mut impl Write) -> Result<(), Box<dyn Error>> {
+        let summaries = self.summaries()?;
+        csv::write_summaries(writer, &summaries)
     }
 
     /// Returns the unique ids of all messages.
     pub fn list_unique_ids(&mut self) -> Result<Vec<Pop3MessageUidInfo>, Box<dyn Error>> {
-        let lines = self.invoke_multi_line("UIDL\r\n")?;
-        let mut result = vec!();
+        block_on(self.inner.list_unique_ids())
+    }
 
-        for line in lines {
-            let mut info = line.split(' ');
-            let message_id = info.next().ok_or("missing id")?.parse::<u32>()?;
-            let unique_id = info.next().ok_or("missing unique id")?.to_string();
+    /// Returns id, size and unique id of each message.
+    ///
+    /// If the server does not support UIDL, unique ids are omitted.
+    pub fn list_meta(&mut self) -> Result<Vec<Pop3MessageMeta>, Box<dyn Error>> {
+        block_on(self.inner.list_meta())
+    }
 
-            result.push(Pop3MessageUidInfo { message_id: message_id, unique_id: unique_id });
-        }
+    /// Returns the capabilities announced by the server using CAPA (RFC 2449).
+    ///
+    /// Each capability is returned as announced, e.g. `SASL PLAIN XOAUTH2`.
+    /// If the server announces PIPELINING, pipelining is enabled.
+    pub fn capabilities(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
+        block_on(self.inner.capabilities())
+    }
 
-        Ok(result)
+    /// Returns a report about the usage of the maildrop.
+    ///
+    /// The report combines STAT, LIST and UIDL and contains total size,
+    /// message count, the largest messages and a size histogram.
+    pub fn usage_report(&mut self) -> Result<Pop3UsageReport, Box<dyn Error>> {
+        let stat = self.stat()?;
+        let messages = self.list_meta()?;
+        Ok(Pop3UsageReport::new(&stat, &messages))
     }
 
     /// Returns the unique id of a given message.
@@ -264,19 +801,162 @@ impl Pop3Connection {
     ///
     /// * `message_id` - id of the message
     pub fn get_unique_id(&mut self, message_id :u32) -> Result<String, Box<dyn Error>> {
-        let line = self.invoke_single_line(&format!("UIDL {}\r\n", message_id))?;
-        let mut info = line.split(' ');
-        let _ = info.next(); // skip "+OK"
-        let _ = info.next(); // skip message id
-        let unique_id = info.next().ok_or("missing unique id")?.to_string();
-
-        Ok(unique_id)
+        block_on(self.inner.get_unique_id(message_id))
     }
 }
 
 impl Drop for Pop3Connection {
     /// Closes POP3 connection on drop.
     fn drop(&mut self) {
-        let _ = self.invoke_single_line("QUIT\r\n");
+        if self.is_open() {
+            let _ = self.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block_on_pending_operation() {
+        let mut polled = false;
+        let operation = std::future::poll_fn(|cx| match polled {
+            true => std::task::Poll::Ready(Ok(42)),
+            false => {
+                polled = true;
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }
+        });
+        assert_eq!(42, block_on(operation).unwrap());
+    }
+
+    #[test]
+    fn test_retrieve_sized() {
+        let (mut connection, server) = test_util::connect(&[
+            ("RETR 1", "+OK\r\nSubject: a\r\n\r\n..dot\r\nlast\n.\r\n"),
+            ("RETR 1", "+OK\r\nSubject: a\r\n.\r\n"),
+            ("RETR 1", "+OK\r\nSubject: a\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let mut content = vec!();
+        let size = connection.retrieve_sized(1, &mut content).unwrap();
+        assert_eq!(Pop3TransferSize { wire_size: 26, decoded_size: 25, normalized_size: 22 }, size);
+        assert_eq!(25, content.len());
+
+        let message = Pop3MessageMeta { message_id: 1, message_size: 20, unique_id: None };
+        let check = connection.retrieve_verified(&message, &mut vec!()).unwrap();
+        assert!(check.is_truncated());
+        connection.set_strict_size_check(true);
+        assert!(connection.retrieve_verified(&message, &mut vec!()).is_err());
+
+        connection.quit().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_retrieve_split() {
+        let (mut connection, server) = test_util::connect(&[
+            ("RETR 1", "+OK\r\nSubject: a\r\nFrom: b\r\n\r\n..hidden\r\n\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let message = connection.retrieve_split(1).unwrap();
+        assert_eq!(b"Subject: a\r\nFrom: b\r\n".to_vec(), message.raw_headers);
+        assert_eq!(b".hidden\r\n\r\n".to_vec(), message.body);
+
+        connection.quit().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_retrieve_verified() {
+        let (port, server) = test_util::serve(&[
+            ("RETR 1", "+OK\r\nSubject: a\r\n\r\n..dot\r\n.\r\n"),
+            ("RETR 1", "+OK\r\nSubject: a\r\n\r\n..dot\r\n.\r\n"),
+            ("RETR 2", "+OK\r\nSubject: a\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+        let mut connection = Pop3ConnectionBuilder::new("127.0.0.1")
+            .tls_mode(TlsMode::Plain)
+            .port(port)
+            .strict_size_check(true)
+            .connect()
+            .unwrap();
+
+        let message = Pop3MessageMeta { message_id: 1, message_size: 21, unique_id: None };
+        let check = connection.retrieve_verified(&message, &mut vec!()).unwrap();
+        assert_eq!(Pop3SizeCheck { expected_size: 21, received_size: 21 }, check);
+        assert!(check.is_match());
+
+        let message = Pop3MessageMeta { message_id: 1, message_size: 19, unique_id: None };
+        let err = connection.retrieve_verified(&message, &mut vec!()).unwrap_err();
+        assert_eq!("size mismatch of message 1: expected 19 octets, received 21", err.to_string());
+
+        connection.set_strict_size_check(false);
+        let message = Pop3MessageMeta { message_id: 2, message_size: 20, unique_id: None };
+        let check = connection.retrieve_verified(&message, &mut vec!()).unwrap();
+        assert!(!check.is_match());
+        assert!(check.is_truncated());
+
+        connection.quit().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_wire_dump() {
+        let (mut connection, server) = test_util::connect(&[
+            ("PASS secret", "+OK\r\n"),
+            ("RETR 1", "+OK\r\nSubject: a\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dump.txt");
+        connection.set_wire_dump(Some(Box::new(std::fs::File::create(&path).unwrap())));
+        block_on(connection.inner.invoke_single_line("PASS secret\r\n")).unwrap();
+        connection.retrieve_raw(1, &mut vec!()).unwrap();
+        connection.quit().unwrap();
+        server.join().unwrap();
+
+        let dump = std::fs::read_to_string(&path).unwrap();
+        assert_eq!("C: PASS ***\nS: +OK\nC: RETR 1\nS: +OK\nS: Subject: a [LF]\nS: .\nC: QUIT\nS: +OK\n", dump);
+    }
+
+    #[test]
+    fn test_session_events() {
+        let (port, server) = test_util::serve(&[
+            ("USER me", "+OK\r\n"),
+            ("PASS secret", "+OK\r\n"),
+            ("DELE 1", "-ERR no such message\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let events = Arc::new(std::sync::Mutex::new(vec!()));
+        let recorded = events.clone();
+        let mut connection = Pop3ConnectionBuilder::new("127.0.0.1")
+            .tls_mode(TlsMode::Plain)
+            .port(port)
+            .login("me", "secret")
+            .observer(move |event: &Pop3SessionEvent| recorded.lock().unwrap().push(event.clone()))
+            .connect()
+            .unwrap();
+        assert!(connection.delete(1).is_err());
+        connection.quit().unwrap();
+        server.join().unwrap();
+
+        let sent = |command: &str| Pop3SessionEvent::CommandSent { command: command.into() };
+        let received = |command: &str, success| Pop3SessionEvent::ResponseReceived { command: command.into(), success };
+        assert_eq!(vec!(
+            Pop3SessionEvent::Connected { host: "127.0.0.1".into(), port },
+            sent("USER"), received("USER", true),
+            sent("PASS"), received("PASS", true),
+            Pop3SessionEvent::Authenticated { mechanism: "USER".into() },
+            sent("DELE"), received("DELE", false),
+            sent("QUIT"), received("QUIT", true),
+            Pop3SessionEvent::Disconnected { reason: "QUIT".into() },
+        ), *events.lock().unwrap());
     }
 }
@@ -0,0 +1,139 @@
+use std::fmt::Display;
+
+#[cfg(feature = "log")]
+use crate::transcript::Redactor;
+
+/// Target of log records of commands sent.
+#[cfg(feature = "log")]
+const COMMAND_TARGET: &str = "rust_pop3_client::command";
+
+/// Target of log records of response lines received.
+#[cfg(feature = "log")]
+const RESPONSE_TARGET: &str = "rust_pop3_client::response";
+
+/// Target of log records of protocol anomalies.
+#[cfg(feature = "log")]
+const PROTOCOL_TARGET: &str = "rust_pop3_client::protocol";
+
+/// Log of the traffic of a session.
+///
+/// Using the `log` feature, commands are logged at debug level, response
+/// lines at trace level and protocol anomalies at warn level. Passwords and
+/// authentication data are replaced by `***`. Without the feature, nothing
+/// is logged.
+#[derive(Default)]
+pub(crate) struct WireLog {
+    #[cfg(feature = "log")]
+    redactor: Redactor,
+}
+
+#[cfg(feature = "log")]
+impl WireLog {
+
+    /// Logs a line sent by the client.
+    pub(crate) fn client(&mut self, line: &[u8]) {
+        let line = self.redactor.client(line);
+        log::debug!(target: COMMAND_TARGET, "C: {}", line);
+    }
+
+    /// Logs a line received from the server.
+    pub(crate) fn server(&mut self, line: &[u8]) {
+        let line = self.redactor.server(line);
+        log::trace!(target: RESPONSE_TARGET, "S: {}", line);
+    }
+}
+
+#[cfg(not(feature = "log"))]
+impl WireLog {
+
+    pub(crate) fn client(&mut self, _line: &[u8]) {
+    }
+
+    pub(crate) fn server(&mut self, _line: &[u8]) {
+    }
+}
+
+/// Logs a protocol anomaly, e.g. an invalid response or an unexpected disconnect.
+pub(crate) fn anomaly(message: impl Display) {
+    #[cfg(feature = "log")]
+    log::warn!(target: PROTOCOL_TARGET, "{}", message);
+    #[cfg(not(feature = "log"))]
+    let _ = message;
+}
+
+#[cfg(all(test, feature = "log"))]
+mod tests {
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::Mutex;
+    use std::task::{Context, Poll};
+
+    use futures_util::FutureExt;
+    use futures_util::io::{AsyncRead, AsyncWrite, Cursor};
+    use log::{Log, Metadata, Record};
+
+    use crate::AsyncPop3Connection;
+
+    /// Collects all log records as `LEVEL target message` lines.
+    struct Recorder {
+        lines: Mutex<Vec<String>>,
+    }
+
+    impl Log for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record<'_>) {
+            if record.target().starts_with("rust_pop3_client::") {
+                self.lines.lock().unwrap().push(format!("{} {} {}", record.level(), record.target(), record.args()));
+            }
+        }
+
+        fn flush(&self) {
+        }
+    }
+
+    static RECORDER: Recorder = Recorder { lines: Mutex::new(vec!()) };
+
+    /// Stream, which answers with a fixed response.
+    struct Responses(Cursor<Vec<u8>>);
+
+    impl AsyncRead for Responses {
+        fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+            Pin::new(&mut self.get_mut().0).poll_read(cx, buf)
+        }
+    }
+
+    impl AsyncWrite for Responses {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test]
+    fn test_log_records() {
+        log::set_logger(&RECORDER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let stream = Responses(Cursor::new(b"+OK ready\r\n+OK\r\n+OK\r\n* BYE\r\n".to_vec()));
+        let mut connection = AsyncPop3Connection::from_stream(stream).now_or_never().unwrap().unwrap();
+        connection.login("me", "secret").now_or_never().unwrap().unwrap();
+        assert!(connection.stat().now_or_never().unwrap().is_err());
+
+        let lines = RECORDER.lines.lock().unwrap().join("\n");
+        assert!(lines.contains("TRACE rust_pop3_client::response S: +OK ready"));
+        assert!(lines.contains("DEBUG rust_pop3_client::command C: USER me"));
+        assert!(lines.contains("DEBUG rust_pop3_client::command C: PASS ***"));
+        assert!(lines.contains("WARN rust_pop3_client::protocol invalid status line: * BYE"));
+        assert!(!lines.contains("secret"));
+    }
+}
@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::Pop3MessageMeta;
+use crate::sink::{self, MessageSink};
+
+static DELIVERY_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Delivers messages into a Maildir.
+///
+/// Messages are written to `tmp` using a unique file name, synced to disk
+/// and moved to `new` afterwards, as described in the Maildir specification.
+/// Line endings are converted to LF.
+pub struct MaildirSink {
+    path: PathBuf,
+    hostname: String,
+}
+
+impl MaildirSink {
+
+    /// Returns a new Maildir sink. Missing directories are created.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path of the Maildir
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref().to_path_buf();
+        for dir in ["tmp", "new", "cur"] {
+            fs::create_dir_all(path.join(dir))?;
+        }
+
+        Ok(MaildirSink { path, hostname: hostname() })
+    }
+
+    /// Returns the path of the Maildir.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn unique_name(&self, size: usize) -> Result<String, Box<dyn Error>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let counter = DELIVERY_COUNTER.fetch_add(1, Ordering::Relaxed);
+        Ok(format!("{}.M{}P{}Q{}.{},S={}",
+            now.as_secs(), now.subsec_micros(), process::id(), counter, self.hostname, size))
+    }
+}
+
+impl MessageSink for MaildirSink {
+    fn deliver(&mut self, _message: &Pop3MessageMeta, content: &[u8]) -> Result<(), Box<dyn Error>> {
+        let content = sink::to_lf(content);
+        let name = self.unique_name(content.len())?;
+        let tmp_path = self.path.join("tmp").join(&name);
+        let new_path = self.path.join("new").join(&name);
+
+        let result = File::create(&tmp_path)
+            .and_then(|mut file| {
+                file.write_all(&content)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp_path, &new_path));
+        if let Err(err) = result {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(err.into());
+        }
+
+        sync_dir(&self.path.join("new"))
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<(), Box<dyn Error>> {
+    File::open(path)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+/// Returns the host name, escaped as required for Maildir file names.
+fn hostname() -> String {
+    let hostname = std::env::var("HOSTNAME").ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .filter(|hostname| !hostname.is_empty())
+        .unwrap_or_else(|| "localhost".to_string());
+
+    hostname.replace('/', "\\057").replace(':', "\\072")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta() -> Pop3MessageMeta {
+        Pop3MessageMeta { message_id: 1, message_size: 0, unique_id: None }
+    }
+
+    #[test]
+    fn test_creates_maildir() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("inbox");
+        MaildirSink::new(&path).unwrap();
+
+        assert!(path.join("tmp").is_dir());
+        assert!(path.join("new").is_dir());
+        assert!(path.join("cur").is_dir());
+    }
+
+    #[test]
+    fn test_deliver() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = MaildirSink::new(dir.path()).unwrap();
+        sink.deliver(&meta(), b"Subject: one\r\n\r\nbody\r\n").unwrap();
+        sink.deliver(&meta(), b"Subject: two\r\n\r\nbody\r\n").unwrap();
+
+        assert_eq!(0, fs::read_dir(dir.path().join("tmp")).unwrap().count());
+        let mut contents: Vec<Vec<u8>> = fs::read_dir(dir.path().join("new")).unwrap()
+            .map(|entry| fs::read(entry.unwrap().path()).unwrap())
+            .collect();
+        contents.sort();
+        assert_eq!(vec!(b"Subject: one\n\nbody\n".to_vec(), b"Subject: two\n\nbody\n".to_vec()), contents);
+    }
+
+    #[test]
+    fn test_unique_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let sink = MaildirSink::new(dir.path()).unwrap();
+        assert_ne!(sink.unique_name(1).unwrap(), sink.unique_name(1).unwrap());
+        assert!(sink.unique_name(42).unwrap().ends_with(",S=42"));
+    }
+}
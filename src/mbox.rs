@@ -0,0 +1,214 @@
+use std::error::Error;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::date;
+use crate::Pop3MessageMeta;
+use crate::sink::{self, MessageSink};
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+const LOCK_ATTEMPTS: u32 = 100;
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Appends messages to an mbox file.
+///
+/// Each message is preceded by a `From ` separator line. Lines of the
+/// message starting with `From ` (optionally quoted by `>`) are escaped
+/// by another `>` (mboxrd). The file is locked by a dotlock (`<path>.lock`)
+/// while a message is appended.
+/// Line endings are converted to LF.
+pub struct MboxSink {
+    path: PathBuf,
+}
+
+impl MboxSink {
+
+    /// Returns a new mbox sink. The file is created on first delivery.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path of the mbox file
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        MboxSink { path: path.as_ref().to_path_buf() }
+    }
+
+    /// Returns the path of the mbox file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl MessageSink for MboxSink {
+    fn deliver(&mut self, _message: &Pop3MessageMeta, content: &[u8]) -> Result<(), Box<dyn Error>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let entry = mbox_entry(content, now);
+
+        let _lock = DotLock::acquire(&self.path)?;
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        write_entry(&mut file, &entry)
+    }
+}
+
+/// Dotlock of an mbox file, which is released on drop.
+struct DotLock {
+    path: PathBuf,
+}
+
+impl DotLock {
+
+    /// Creates the lock file `<path>.lock`; waits up to 10 seconds, while it exists.
+    fn acquire(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut lock_path = OsString::from(path.as_os_str());
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+
+        for _ in 0..LOCK_ATTEMPTS {
+            match OpenOptions::new().write(true).create_new(true).open(&lock_path) {
+                Ok(_) => return Ok(DotLock { path: lock_path }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => thread::sleep(LOCK_RETRY_INTERVAL),
+                Err(err) => return Err(err.into())
+            }
+        }
+
+        Err(format!("mbox file is locked: {}", lock_path.display()).into())
+    }
+}
+
+impl Drop for DotLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn write_entry(file: &mut File, entry: &[u8]) -> Result<(), Box<dyn Error>> {
+    file.write_all(entry)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+/// Returns a message formatted as mboxrd entry, including separator line.
+fn mbox_entry(content: &[u8], timestamp: u64) -> Vec<u8> {
+    let content = sink::to_lf(content);
+    let mut entry = format!("From {} {}\n", envelope_sender(&content), asctime(timestamp)).into_bytes();
+
+    for line in content.split_inclusive(|&byte| byte == b'\n') {
+        let unquoted = line.iter().position(|&byte| byte != b'>').map(|pos| &line[pos..]).unwrap_or(&[]);
+        if unquoted.starts_with(b"From ") {
+            entry.push(b'>');
+        }
+        entry.extend_from_slice(line);
+    }
+
+    if !entry.ends_with(b"\n") {
+        entry.push(b'\n');
+    }
+    entry.push(b'\n');
+    entry
+}
+
+/// Returns the address of the Return-Path header or `MAILER-DAEMON`.
+fn envelope_sender(content: &[u8]) -> String {
+    for line in content.split(|&byte| byte == b'\n') {
+        if line.is_empty() {
+            break;
+        }
+
+        let line = String::from_utf8_lossy(line);
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Return-Path") {
+                let address = value.trim().trim_start_matches('<').trim_end_matches('>');
+                if !address.is_empty() && !address.contains(char::is_whitespace) {
+                    return address.to_string();
+                }
+            }
+        }
+    }
+
+    "MAILER-DAEMON".to_string()
+}
+
+/// Formats a unix timestamp in asctime format, e.g. `Thu Jan  1 00:00:00 1970`.
+fn asctime(timestamp: u64) -> String {
+    let days = timestamp / 86400;
+    let seconds = timestamp % 86400;
+
+    let (year, month, day) = date::civil_from_days(days as i64);
+
+    format!("{} {} {:>2} {:02}:{:02}:{:02} {}",
+        DAYS[(days % 7) as usize], MONTHS[(month - 1) as usize], day,
+        seconds / 3600, (seconds % 3600) / 60, seconds % 60, year)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_asctime() {
+        assert_eq!("Thu Jan  1 00:00:00 1970", asctime(0));
+        assert_eq!("Tue Feb 29 12:34:56 2000", asctime(951827696));
+    }
+
+    #[test]
+    fn test_envelope_sender() {
+        assert_eq!("bob@example.com", envelope_sender(b"Subject: hi\nReturn-Path: <bob@example.com>\n\nbody\n"));
+        assert_eq!("MAILER-DAEMON", envelope_sender(b"Return-Path: <>\n\nbody\n"));
+        assert_eq!("MAILER-DAEMON", envelope_sender(b"Subject: hi\n\nReturn-Path: <bob@example.com>\n"));
+    }
+
+    #[test]
+    fn test_escaping() {
+        let entry = mbox_entry(b"Subject: hi\r\n\r\nFrom here\r\n>From there\r\nFromage\r\n", 0);
+        assert_eq!(
+            "From MAILER-DAEMON Thu Jan  1 00:00:00 1970\nSubject: hi\n\n>From here\n>>From there\nFromage\n\n",
+            String::from_utf8(entry).unwrap());
+    }
+
+    #[test]
+    fn test_missing_final_newline() {
+        let entry = mbox_entry(b"Subject: hi\r\n\r\nbody", 0);
+        assert!(entry.ends_with(b"body\n\n"));
+    }
+
+    #[test]
+    fn test_deliver_appends() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("archive.mbox");
+        let message = Pop3MessageMeta { message_id: 1, message_size: 0, unique_id: None };
+
+        let mut sink = MboxSink::new(&path);
+        sink.deliver(&message, b"Subject: one\r\n\r\nbody\r\n").unwrap();
+        sink.deliver(&message, b"Subject: two\r\n\r\nbody\r\n").unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("From MAILER-DAEMON "));
+        assert!(content.contains("Subject: one\n\nbody\n\nFrom MAILER-DAEMON"));
+        assert!(content.ends_with("Subject: two\n\nbody\n\n"));
+    }
+
+    #[test]
+    fn test_deliver_waits_for_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("archive.mbox");
+        let lock_path = dir.path().join("archive.mbox.lock");
+        let message = Pop3MessageMeta { message_id: 1, message_size: 0, unique_id: None };
+
+        let lock = DotLock::acquire(&path).unwrap();
+        assert!(lock_path.exists());
+        let writer = thread::spawn(move || MboxSink::new(&path).deliver(&message, b"Subject: one\r\n\r\nbody\r\n").is_ok());
+        thread::sleep(Duration::from_millis(200));
+        assert!(!dir.path().join("archive.mbox").exists());
+
+        drop(lock);
+        assert!(writer.join().unwrap());
+        assert!(dir.path().join("archive.mbox").exists());
+        assert!(!lock_path.exists());
+    }
+}
@@ -0,0 +1,131 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::session;
+
+/// Receiver of metrics of POP3 sessions, e.g. to monitor a fleet of fetchers.
+///
+/// Commands are identified by their name only, e.g. `RETR`, so that
+/// arguments like passwords never reach the metrics backend. Commands
+/// failing at transport level, e.g. by a timeout, are not counted.
+///
+/// Using the `metrics` feature, `MetricsCrateSink` forwards all metrics
+/// to the `metrics` crate.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::time::Duration;
+/// use rust_pop3_client::MetricsSink;
+///
+/// #[derive(Default)]
+/// struct Failures(AtomicU64);
+///
+/// impl MetricsSink for Failures {
+///     fn increment_command(&self, _command: &str, success: bool) {
+///         if !success {
+///             self.0.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }
+///
+///     fn record_latency(&self, _command: &str, _latency: Duration) {
+///     }
+///
+///     fn add_bytes(&self, _sent: u64, _received: u64) {
+///     }
+/// }
+/// ```
+pub trait MetricsSink: Send + Sync {
+
+    /// Counts a completed command.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - name of the command, e.g. `RETR`
+    /// * `success` - true, if the server responded positive
+    fn increment_command(&self, command: &str, success: bool);
+
+    /// Records the latency of a completed command.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - name of the command, e.g. `RETR`
+    /// * `latency` - time from sending the command until its response was received completely
+    fn record_latency(&self, command: &str, latency: Duration);
+
+    /// Adds bytes sent and received.
+    ///
+    /// # Arguments
+    ///
+    /// * `sent` - count of bytes sent to the server
+    /// * `received` - count of bytes received from the server
+    fn add_bytes(&self, sent: u64, received: u64);
+}
+
+/// Forwards metrics to the `metrics` crate.
+///
+/// The following metrics are reported:
+///
+/// * `pop3_commands_total` - counter labeled by `command` and `result` (`ok` or `err`)
+/// * `pop3_command_duration_seconds` - histogram labeled by `command`
+/// * `pop3_bytes_sent_total` - counter
+/// * `pop3_bytes_received_total` - counter
+#[cfg(feature = "metrics")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MetricsCrateSink;
+
+#[cfg(feature = "metrics")]
+impl MetricsSink for MetricsCrateSink {
+    fn increment_command(&self, command: &str, success: bool) {
+        let result = if success { "ok" } else { "err" };
+        metrics::counter!("pop3_commands_total", "command" => command.to_string(), "result" => result).increment(1);
+    }
+
+    fn record_latency(&self, command: &str, latency: Duration) {
+        metrics::histogram!("pop3_command_duration_seconds", "command" => command.to_string()).record(latency.as_secs_f64());
+    }
+
+    fn add_bytes(&self, sent: u64, received: u64) {
+        if sent > 0 {
+            metrics::counter!("pop3_bytes_sent_total").increment(sent);
+        }
+        if received > 0 {
+            metrics::counter!("pop3_bytes_received_total").increment(received);
+        }
+    }
+}
+
+/// Metrics of the command of a connection, which awaits its response.
+pub(crate) struct CommandMetrics {
+    sink: Arc<dyn MetricsSink>,
+    command: Option<(String, Instant)>,
+}
+
+impl CommandMetrics {
+
+    pub(crate) fn new(sink: Arc<dyn MetricsSink>) -> Self {
+        CommandMetrics { sink, command: None }
+    }
+
+    /// Starts measuring a command; only its name is kept.
+    pub(crate) fn start(&mut self, command: &str) {
+        self.command = Some((session::command_name(command), Instant::now()));
+    }
+
+    /// Reports the completed command.
+    pub(crate) fn finish(&mut self, success: bool) {
+        if let Some((name, started)) = self.command.take() {
+            self.sink.increment_command(&name, success);
+            self.sink.record_latency(&name, started.elapsed());
+        }
+    }
+
+    pub(crate) fn sent(&self, bytes: usize) {
+        self.sink.add_bytes(bytes as u64, 0);
+    }
+
+    pub(crate) fn received(&self, bytes: usize) {
+        self.sink.add_bytes(0, bytes as u64);
+    }
+}
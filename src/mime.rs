@@ -0,0 +1,755 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use mail_parser::{MessageParser, MimeHeaders};
+
+use crate::date;
+use crate::encoding::TransferDecoder;
+use crate::glob::glob_matches;
+use crate::{Pop3Connection, Pop3Headers, Pop3MessageMeta};
+
+/// Message parsed into its MIME parts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pop3ParsedMessage {
+    /// headers of the message
+    pub headers: Pop3Headers,
+
+    /// decoded text/plain body parts
+    pub text_parts: Vec<String>,
+
+    /// decoded text/html body parts
+    pub html_parts: Vec<String>,
+
+    /// metadata of the attachments
+    pub attachments: Vec<Pop3AttachmentInfo>,
+}
+
+/// Metadata of an attachment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pop3AttachmentInfo {
+    /// position of the attachment within the message, starting at 0
+    pub index: usize,
+
+    /// file name of the attachment, if specified
+    pub name: Option<String>,
+
+    /// MIME type of the attachment, e.g. `application/pdf`
+    pub content_type: Option<String>,
+
+    /// size of the decoded attachment in octets
+    pub size: usize,
+}
+
+impl Pop3ParsedMessage {
+
+    /// Parses a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - exact octets of the message
+    pub fn parse(content: &[u8]) -> Result<Self, Box<dyn Error>> {
+        let message = MessageParser::default().parse(content).ok_or("failed to parse message")?;
+
+        let text_parts = message.text_bodies()
+            .filter(|part| !part.is_text_html())
+            .filter_map(|part| part.text_contents().map(String::from))
+            .collect();
+        let html_parts = message.html_bodies()
+            .filter(|part| part.is_text_html())
+            .filter_map(|part| part.text_contents().map(String::from))
+            .collect();
+        let mut decoder = AttachmentDecoder::new(AttachmentInfos);
+        decoder.write_all(content)?;
+        let (_, attachments) = decoder.finish()?;
+
+        Ok(Pop3ParsedMessage { headers: Pop3Headers::parse(content), text_parts, html_parts, attachments })
+    }
+
+    /// Returns the value of the first header with the given name (case-insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Returns the parsed Date header; `None` if missing or invalid.
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        self.headers.timestamp()
+    }
+
+    /// Returns the parsed Date header including its offset to UTC; `None` if missing or invalid.
+    #[cfg(feature = "chrono")]
+    pub fn date_time(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        self.headers.date_time()
+    }
+}
+
+/// Selects attachments by file name and MIME type.
+///
+/// Patterns are globs, where `*` matches any sequence of characters and `?`
+/// matches a single character; case is ignored. An attachment matches, if
+/// its name matches any name pattern and its MIME type matches any type
+/// pattern. Without patterns of a kind, any value matches, so an empty
+/// filter matches all attachments.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{AttachmentFilter, Pop3Connection};
+///
+/// let mut connection = Pop3Connection::new("pop.example.com", 995).unwrap();
+/// connection.login("user@example.com", "secret").unwrap();
+/// connection.save_all_attachments(&AttachmentFilter::new().name("*.pdf"), "attachments/{date}").unwrap();
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttachmentFilter {
+    names: Vec<String>,
+    content_types: Vec<String>,
+}
+
+impl AttachmentFilter {
+
+    /// Returns a new filter, which matches all attachments.
+    pub fn new() -> Self {
+        AttachmentFilter::default()
+    }
+
+    /// Adds a pattern of the file name, e.g. `*.pdf`.
+    pub fn name(mut self, pattern: &str) -> Self {
+        self.names.push(pattern.to_string());
+        self
+    }
+
+    /// Adds a pattern of the MIME type, e.g. `image/*`.
+    pub fn content_type(mut self, pattern: &str) -> Self {
+        self.content_types.push(pattern.to_string());
+        self
+    }
+
+    /// Returns true, if the attachment matches the filter.
+    pub fn matches(&self, attachment: &Pop3AttachmentInfo) -> bool {
+        let matches_any = |patterns: &[String], value: Option<&str>| {
+            patterns.is_empty() || value.is_some_and(|value| patterns.iter().any(|pattern| glob_matches(pattern, value)))
+        };
+
+        matches_any(&self.names, attachment.name.as_deref())
+            && matches_any(&self.content_types, attachment.content_type.as_deref())
+    }
+}
+
+/// Returns the directory of a message by replacing the placeholders of a template.
+///
+/// Supported placeholders are `{id}`, `{uid}`, `{from}` (sender address) and
+/// `{date}` (`YYYY-MM-DD` of the Date header). Characters of values, which
+/// are not safe for file names, are replaced by `_`; missing values are
+/// replaced by `unknown`.
+fn render_directory(template: &str, message: &Pop3MessageMeta, headers: &Pop3Headers) -> PathBuf {
+    let from = headers.mailboxes("From").into_iter().next().map(|mailbox| mailbox.address);
+    let values = [
+        ("{id}", Some(message.message_id.to_string())),
+        ("{uid}", message.unique_id.clone()),
+        ("{from}", from),
+        ("{date}", headers.date().and_then(date::format_date)),
+    ];
+
+    let mut directory = template.to_string();
+    for (placeholder, value) in values {
+        if directory.contains(placeholder) {
+            directory = directory.replace(placeholder, &sanitize(value.as_deref().unwrap_or("unknown")));
+        }
+    }
+
+    PathBuf::from(directory)
+}
+
+/// Replaces characters, which are not safe for file names, by `_`.
+fn sanitize(value: &str) -> String {
+    let value: String = value.chars()
+        .map(|c| if c.is_alphanumeric() || "@.-_+ ".contains(c) { c } else { '_' })
+        .collect();
+    match value.trim_start_matches('.') {
+        "" => "_".to_string(),
+        value => value.to_string()
+    }
+}
+
+/// Receives the attachments found by an [`AttachmentDecoder`].
+trait AttachmentHandler {
+    type Writer: Write;
+
+    /// Returns a writer of the decoded content, if the attachment is wanted.
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - headers of the message
+    /// * `info`    - metadata of the attachment; its size is not known yet
+    fn start(&mut self, headers: &Pop3Headers, info: &Pop3AttachmentInfo) -> Result<Option<Self::Writer>, Box<dyn Error>>;
+
+    /// Completes an attachment, whose decoded content was written to the writer.
+    fn finish(&mut self, info: &Pop3AttachmentInfo, writer: Self::Writer) -> Result<(), Box<dyn Error>>;
+}
+
+/// Collects the metadata of the attachments only.
+struct AttachmentInfos;
+
+impl AttachmentHandler for AttachmentInfos {
+    type Writer = io::Sink;
+
+    fn start(&mut self, _headers: &Pop3Headers, _info: &Pop3AttachmentInfo) -> Result<Option<io::Sink>, Box<dyn Error>> {
+        Ok(None)
+    }
+
+    fn finish(&mut self, _info: &Pop3AttachmentInfo, _writer: io::Sink) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Writes a single attachment to a file.
+struct AttachmentFile<'a> {
+    index: usize,
+    path: &'a Path,
+}
+
+impl AttachmentHandler for AttachmentFile<'_> {
+    type Writer = TempFile;
+
+    fn start(&mut self, _headers: &Pop3Headers, info: &Pop3AttachmentInfo) -> Result<Option<TempFile>, Box<dyn Error>> {
+        if info.index != self.index {
+            return Ok(None);
+        }
+
+        Ok(Some(TempFile::create(self.path.to_path_buf())?))
+    }
+
+    fn finish(&mut self, _info: &Pop3AttachmentInfo, writer: TempFile) -> Result<(), Box<dyn Error>> {
+        writer.persist()?;
+        Ok(())
+    }
+}
+
+/// Writes the matching attachments into the directory of a template.
+struct AttachmentDirectory<'a> {
+    meta: &'a Pop3MessageMeta,
+    filter: &'a AttachmentFilter,
+    template: &'a str,
+    paths: Vec<PathBuf>,
+}
+
+impl AttachmentHandler for AttachmentDirectory<'_> {
+    type Writer = TempFile;
+
+    fn start(&mut self, headers: &Pop3Headers, info: &Pop3AttachmentInfo) -> Result<Option<TempFile>, Box<dyn Error>> {
+        if !self.filter.matches(info) {
+            return Ok(None);
+        }
+
+        let directory = render_directory(self.template, self.meta, headers);
+        fs::create_dir_all(&directory)?;
+        let name = sanitize(info.name.as_deref().unwrap_or(&format!("attachment-{}", info.index)));
+        Ok(Some(TempFile::create(unique_path(&directory, &name))?))
+    }
+
+    fn finish(&mut self, _info: &Pop3AttachmentInfo, writer: TempFile) -> Result<(), Box<dyn Error>> {
+        self.paths.push(writer.persist()?);
+        Ok(())
+    }
+}
+
+/// File, which is written to a temporary path first and renamed, when it is complete.
+///
+/// The temporary file is removed, if the file is dropped without being persisted.
+struct TempFile {
+    path: PathBuf,
+    tmp_path: PathBuf,
+    file: Option<BufWriter<File>>,
+}
+
+impl TempFile {
+
+    fn create(path: PathBuf) -> io::Result<Self> {
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+        let file = File::create(&tmp_path)?;
+
+        Ok(TempFile { path, tmp_path, file: Some(BufWriter::new(file)) })
+    }
+
+    /// Renames the temporary file and returns the final path.
+    fn persist(mut self) -> io::Result<PathBuf> {
+        if let Some(file) = self.file.take() {
+            let file = file.into_inner().map_err(|err| err.into_error())?;
+            file.sync_all()?;
+        }
+        fs::rename(&self.tmp_path, &self.path)?;
+
+        Ok(mem::take(&mut self.path))
+    }
+}
+
+impl Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.as_mut().ok_or_else(|| io::Error::other("file already closed"))?.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().ok_or_else(|| io::Error::other("file already closed"))?.flush()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // the path is taken, when the file is persisted
+        if !self.path.as_os_str().is_empty() {
+            self.file = None;
+            let _ = fs::remove_file(&self.tmp_path);
+        }
+    }
+}
+
+/// Splits a message into its MIME parts (RFC 2046) and decodes its attachments.
+///
+/// The message is written line by line, e.g. by [`Pop3Connection::retrieve_raw`],
+/// so it does not need to be buffered. A part is an attachment, if its
+/// Content-Disposition is `attachment`, if it is neither text nor multipart
+/// (including nested messages) or if it has a file name; the body of a
+/// single part message needs an attachment disposition or a non-text type.
+struct AttachmentDecoder<H: AttachmentHandler> {
+    handler: H,
+    headers: Pop3Headers,
+    boundaries: Vec<Vec<u8>>,
+    state: PartState<H::Writer>,
+    line: Vec<u8>,
+    attachments: Vec<Pop3AttachmentInfo>,
+}
+
+enum PartState<W> {
+    /// header section of a part; `root` is true for the headers of the message
+    Headers { data: Vec<u8>, root: bool },
+    Attachment { info: Pop3AttachmentInfo, decoder: TransferDecoder, writer: Option<W> },
+    /// body of a part, which is no attachment, or preamble and epilogue of a multipart
+    Skip,
+}
+
+impl<H: AttachmentHandler> AttachmentDecoder<H> {
+
+    fn new(handler: H) -> Self {
+        AttachmentDecoder {
+            handler,
+            headers: Pop3Headers::default(),
+            boundaries: vec!(),
+            state: PartState::Headers { data: vec!(), root: true },
+            line: vec!(),
+            attachments: vec!(),
+        }
+    }
+
+    /// Completes the message and returns the handler and the metadata of all attachments.
+    fn finish(mut self) -> Result<(H, Vec<Pop3AttachmentInfo>), Box<dyn Error>> {
+        let line = mem::take(&mut self.line);
+        if !line.is_empty() {
+            self.process_line(&line)?;
+        }
+        if let PartState::Headers { data, root } = &mut self.state {
+            let (data, root) = (mem::take(data), *root);
+            self.start_part(data, root)?;
+        }
+        self.finish_part(false)?;
+
+        Ok((self.handler, self.attachments))
+    }
+
+    fn process_line(&mut self, line: &[u8]) -> Result<(), Box<dyn Error>> {
+        if let Some((depth, close)) = self.delimiter(line) {
+            self.finish_part(true)?;
+            self.boundaries.truncate(depth + 1);
+            self.state = if close {
+                self.boundaries.pop();
+                PartState::Skip
+            }
+            else {
+                PartState::Headers { data: vec!(), root: false }
+            };
+            return Ok(());
+        }
+
+        match &mut self.state {
+            PartState::Headers { data, root } => {
+                if line == b"\r\n" || line == b"\n" {
+                    let (data, root) = (mem::take(data), *root);
+                    self.start_part(data, root)?;
+                }
+                else {
+                    data.extend_from_slice(line);
+                }
+            },
+            PartState::Attachment { info, decoder, writer } => {
+                let data = decoder.decode_line(line)?;
+                info.size += data.len();
+                if let Some(writer) = writer {
+                    writer.write_all(&data)?;
+                }
+            },
+            PartState::Skip => {}
+        }
+
+        Ok(())
+    }
+
+    /// Returns the depth of the boundary and whether it closes the multipart, if the line is a delimiter.
+    fn delimiter(&self, line: &[u8]) -> Option<(usize, bool)> {
+        let line = line.strip_prefix(b"--")?;
+        let line = match line.iter().rposition(|byte| !byte.is_ascii_whitespace()) {
+            Some(end) => &line[..=end],
+            None => &b""[..]
+        };
+
+        self.boundaries.iter().rposition(|boundary| line == &boundary[..]).map(|depth| (depth, false))
+            .or_else(|| {
+                let line = line.strip_suffix(b"--")?;
+                self.boundaries.iter().rposition(|boundary| line == &boundary[..]).map(|depth| (depth, true))
+            })
+    }
+
+    fn start_part(&mut self, mut data: Vec<u8>, root: bool) -> Result<(), Box<dyn Error>> {
+        if root {
+            self.headers = Pop3Headers::parse(&data);
+        }
+        data.extend_from_slice(b"\r\n");
+        let message = MessageParser::default().parse_headers(&data[..]);
+        let content_type = message.as_ref().and_then(|message| message.content_type());
+        let ctype = content_type.map(|content_type| content_type.ctype().to_ascii_lowercase());
+
+        if ctype.as_deref() == Some("multipart") {
+            if let Some(boundary) = content_type.and_then(|content_type| content_type.attribute("boundary")) {
+                self.boundaries.push(boundary.as_bytes().to_vec());
+                self.state = PartState::Skip;
+                return Ok(());
+            }
+        }
+
+        let name = message.as_ref().and_then(|message| message.attachment_name()).map(String::from);
+        let is_attachment = message.as_ref()
+            .and_then(|message| message.content_disposition())
+            .is_some_and(|disposition| disposition.is_attachment());
+        let is_text = matches!(ctype.as_deref(), None | Some("text") | Some("multipart"));
+        if !is_attachment && is_text && (root || name.is_none()) {
+            self.state = PartState::Skip;
+            return Ok(());
+        }
+
+        let info = Pop3AttachmentInfo {
+            index: self.attachments.len(),
+            name,
+            content_type: content_type.map(|content_type| match content_type.subtype() {
+                Some(subtype) => format!("{}/{}", content_type.ctype(), subtype),
+                None => content_type.ctype().to_string()
+            }),
+            size: 0,
+        };
+        let writer = self.handler.start(&self.headers, &info)?;
+        let decoder = TransferDecoder::new(message.as_ref().and_then(|message| message.content_transfer_encoding()));
+        self.state = PartState::Attachment { info, decoder, writer };
+
+        Ok(())
+    }
+
+    fn finish_part(&mut self, boundary: bool) -> Result<(), Box<dyn Error>> {
+        if let PartState::Attachment { mut info, mut decoder, mut writer } = mem::replace(&mut self.state, PartState::Skip) {
+            let data = decoder.finish(boundary)?;
+            info.size += data.len();
+            if let Some(mut writer) = writer.take() {
+                writer.write_all(&data)?;
+                self.handler.finish(&info, writer)?;
+            }
+            self.attachments.push(info);
+        }
+
+        Ok(())
+    }
+}
+
+impl<H: AttachmentHandler> Write for AttachmentDecoder<H> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.split_inclusive(|&byte| byte == b'\n') {
+            self.line.extend_from_slice(chunk);
+            if chunk.ends_with(b"\n") {
+                let line = mem::take(&mut self.line);
+                self.process_line(&line).map_err(|err| io::Error::other(err.to_string()))?;
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the decoded content of an attachment of a message to a file.
+///
+/// # Arguments
+///
+/// * `index`    - position of the attachment
+/// * `path`     - path of the file to write
+/// * `retrieve` - writes the exact octets of the message
+fn save_attachment(index: usize, path: &Path, retrieve: impl FnOnce(&mut dyn Write) -> Result<(), Box<dyn Error>>) -> Result<Pop3AttachmentInfo, Box<dyn Error>> {
+    let mut decoder = AttachmentDecoder::new(AttachmentFile { index, path });
+    retrieve(&mut decoder)?;
+    let (_, attachments) = decoder.finish()?;
+
+    Ok(attachments.into_iter().nth(index).ok_or("attachment not found")?)
+}
+
+/// Writes the matching attachments of a message into the directory of the template.
+///
+/// Existing files are not overwritten; a counter is appended to the file
+/// name instead. Returns the paths of the written files.
+fn save_attachments(meta: &Pop3MessageMeta, filter: &AttachmentFilter, template: &str, retrieve: impl FnOnce(&mut dyn Write) -> Result<(), Box<dyn Error>>) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut decoder = AttachmentDecoder::new(AttachmentDirectory { meta, filter, template, paths: vec!() });
+    retrieve(&mut decoder)?;
+    let (directory, _) = decoder.finish()?;
+
+    Ok(directory.paths)
+}
+
+/// Returns a path of a file in the directory, which does not exist yet.
+fn unique_path(directory: &Path, name: &str) -> PathBuf {
+    let path = directory.join(name);
+    if !path.exists() {
+        return path;
+    }
+
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (name, String::new())
+    };
+    (1..).map(|counter| directory.join(format!("{}-{}{}", stem, counter, extension)))
+        .find(|path| !path.exists())
+        .unwrap_or(path)
+}
+
+impl Pop3Connection {
+
+    /// Retrieves a message and parses it into its MIME parts.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    pub fn retrieve_parsed(&mut self, message_id: u32) -> Result<Pop3ParsedMessage, Box<dyn Error>> {
+        let mut content = vec!();
+        self.retrieve_raw(message_id, &mut content)?;
+        Pop3ParsedMessage::parse(&content)
+    }
+
+    /// Returns the metadata of the attachments of a message.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    pub fn attachments(&mut self, message_id: u32) -> Result<Vec<Pop3AttachmentInfo>, Box<dyn Error>> {
+        let mut decoder = AttachmentDecoder::new(AttachmentInfos);
+        self.retrieve_raw(message_id, &mut decoder)?;
+        let (_, attachments) = decoder.finish()?;
+        Ok(attachments)
+    }
+
+    /// Writes the decoded content of an attachment to a file and returns its metadata.
+    ///
+    /// The message is decoded while it is retrieved, so it is not buffered.
+    /// The content is written to a temporary file first, which is renamed
+    /// afterwards, so the file is either complete or missing.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    /// * `index`      - position of the attachment (see [`Pop3AttachmentInfo::index`])
+    /// * `path`       - path of the file to write
+    pub fn save_attachment(&mut self, message_id: u32, index: usize, path: impl AsRef<Path>) -> Result<Pop3AttachmentInfo, Box<dyn Error>> {
+        save_attachment(index, path.as_ref(), |mut writer| self.retrieve_raw(message_id, &mut writer))
+    }
+
+    /// Writes the matching attachments of a message into a directory and returns their paths.
+    ///
+    /// The directory is created, if needed. Its path is given as template,
+    /// which may contain the placeholders `{id}`, `{uid}`, `{from}` (sender
+    /// address) and `{date}` (`YYYY-MM-DD` of the Date header), e.g.
+    /// `attachments/{from}/{date}`. Existing files are not overwritten;
+    /// a counter is appended to the file name instead. The message is
+    /// decoded while it is retrieved, so it is not buffered.
+    ///
+    /// # Arguments
+    ///
+    /// * `message`  - metadata of the message
+    /// * `filter`   - selects the attachments to save
+    /// * `template` - template of the directory path
+    pub fn save_attachments(&mut self, message: &Pop3MessageMeta, filter: &AttachmentFilter, template: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        save_attachments(message, filter, template, |mut writer| self.retrieve_raw(message.message_id, &mut writer))
+    }
+
+    /// Writes the matching attachments of all messages into directories and returns their paths.
+    ///
+    /// See [`Pop3Connection::save_attachments`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter`   - selects the attachments to save
+    /// * `template` - template of the directory path
+    pub fn save_all_attachments(&mut self, filter: &AttachmentFilter, template: &str) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let mut paths = vec!();
+        for message in self.list_meta()? {
+            paths.extend(self.save_attachments(&message, filter, template)?);
+        }
+
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    const MULTIPART: &[u8] = b"From: alice@example.com\r\n\
+Subject: Invoice\r\n\
+MIME-Version: 1.0\r\n\
+Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=\"inner\"\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+\r\n\
+Please find attached.\r\n\
+--inner\r\n\
+Content-Type: text/html; charset=utf-8\r\n\
+\r\n\
+<p>Please find attached.</p>\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: application/pdf; name=\"invoice.pdf\"\r\n\
+Content-Disposition: attachment; filename=\"invoice.pdf\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+JVBERi0xLjQK\r\n\
+--outer--\r\n";
+
+    fn write_multipart(writer: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+        Ok(writer.write_all(MULTIPART)?)
+    }
+
+    #[test]
+    fn test_parse() {
+        let message = Pop3ParsedMessage::parse(MULTIPART).unwrap();
+
+        assert_eq!(Some("Invoice"), message.header("subject"));
+        assert_eq!(vec!["Please find attached."], message.text_parts);
+        assert_eq!(vec!["<p>Please find attached.</p>"], message.html_parts);
+        assert_eq!(vec!(Pop3AttachmentInfo {
+            index: 0,
+            name: Some("invoice.pdf".into()),
+            content_type: Some("application/pdf".into()),
+            size: 9,
+        }), message.attachments);
+    }
+
+    #[test]
+    fn test_save_attachment() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("invoice.pdf");
+
+        let info = save_attachment(0, &path, write_multipart).unwrap();
+        assert_eq!(Some("invoice.pdf"), info.name.as_deref());
+        assert_eq!(b"%PDF-1.4\n".to_vec(), fs::read(&path).unwrap());
+
+        assert!(save_attachment(1, &path, write_multipart).is_err());
+    }
+
+    #[test]
+    fn test_attachment_filter() {
+        let info = |name: Option<&str>, content_type: &str| Pop3AttachmentInfo {
+            index: 0, name: name.map(String::from), content_type: Some(content_type.into()), size: 0
+        };
+        let pdf = info(Some("Invoice.PDF"), "application/pdf");
+        let image = info(None, "image/png");
+
+        assert!(AttachmentFilter::new().matches(&image));
+        assert!(AttachmentFilter::new().name("*.pdf").matches(&pdf));
+        assert!(!AttachmentFilter::new().name("*.pdf").matches(&image));
+        assert!(AttachmentFilter::new().content_type("image/*").content_type("*/pdf").matches(&pdf));
+        assert!(!AttachmentFilter::new().name("inv?ice*").content_type("image/*").matches(&pdf));
+    }
+
+    #[test]
+    fn test_save_attachments() {
+        let dir = tempfile::tempdir().unwrap();
+        let meta = Pop3MessageMeta { message_id: 1, message_size: 0, unique_id: Some("a/b".into()) };
+        let template = format!("{}/{{from}}/{{uid}}", dir.path().display());
+
+        let filter = AttachmentFilter::new().content_type("application/pdf");
+        let first = save_attachments(&meta, &filter, &template, write_multipart).unwrap();
+        let second = save_attachments(&meta, &filter, &template, write_multipart).unwrap();
+
+        let directory = dir.path().join("alice@example.com").join("a_b");
+        assert_eq!(vec!(directory.join("invoice.pdf")), first);
+        assert_eq!(vec!(directory.join("invoice-1.pdf")), second);
+        assert!(save_attachments(&meta, &AttachmentFilter::new().name("*.zip"), &template, write_multipart).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_attachment_parts() {
+        let message = b"Content-Type: multipart/mixed; boundary=b\r\n\
+\r\n\
+preamble\r\n\
+--b\r\n\
+\r\n\
+body\r\n\
+--b \r\n\
+Content-Type: text/plain; name=notes.txt\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+caf=C3=A9 =\r\n\
+ok\r\n\
+--b\r\n\
+Content-Type: message/rfc822\r\n\
+\r\n\
+Subject: nested\r\n\
+\r\n\
+--b--\r\n\
+epilogue\r\n";
+        let attachments = Pop3ParsedMessage::parse(message).unwrap().attachments;
+        assert_eq!(vec!(
+            Pop3AttachmentInfo { index: 0, name: Some("notes.txt".into()), content_type: Some("text/plain".into()), size: 8 },
+            Pop3AttachmentInfo { index: 1, name: None, content_type: Some("message/rfc822".into()), size: 17 },
+        ), attachments);
+
+        let single = b"Content-Type: application/pdf\r\nContent-Transfer-Encoding: base64\r\n\r\nJVBERi0xLjQK\r\n";
+        assert_eq!(9, Pop3ParsedMessage::parse(single).unwrap().attachments[0].size);
+        let text = b"Content-Type: text/plain; name=hello.txt\r\n\r\nHello\r\n";
+        assert!(Pop3ParsedMessage::parse(text).unwrap().attachments.is_empty());
+    }
+
+    #[test]
+    fn test_save_attachment_streamed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("invoice.pdf");
+        let response = format!("+OK\r\n{}.\r\n", String::from_utf8(MULTIPART.to_vec()).unwrap());
+        let (mut connection, server) = test_util::connect(&[
+            ("RETR 1", &response),
+            ("RETR 1", &response),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        assert_eq!(Some("application/pdf"), connection.attachments(1).unwrap()[0].content_type.as_deref());
+        assert_eq!(9, connection.save_attachment(1, 0, &path).unwrap().size);
+        assert_eq!(b"%PDF-1.4\n".to_vec(), fs::read(&path).unwrap());
+        assert_eq!(vec!(path.clone()), fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().path()).collect::<Vec<_>>());
+
+        connection.quit().unwrap();
+        server.join().unwrap();
+    }
+}
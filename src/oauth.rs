@@ -0,0 +1,70 @@
+use std::error::Error;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+use crate::error::error_kind;
+use crate::Pop3ErrorKind;
+
+/// Source of OAuth 2.0 access tokens used for XOAUTH2 authentication.
+///
+/// Long-running applications typically implement this on top of a refresh
+/// token, so that an expired access token can be replaced transparently.
+pub trait TokenProvider {
+    /// Returns the access token to authenticate with.
+    fn access_token(&self) -> Result<String, Box<dyn Error>>;
+
+    /// Returns a fresh access token after the server rejected the previous one.
+    ///
+    /// The default implementation simply asks for [`TokenProvider::access_token`] again.
+    fn refresh_access_token(&self) -> Result<String, Box<dyn Error>> {
+        self.access_token()
+    }
+}
+
+impl<F> TokenProvider for F
+where
+    F: Fn() -> Result<String, Box<dyn Error>>
+{
+    fn access_token(&self) -> Result<String, Box<dyn Error>> {
+        self()
+    }
+}
+
+/// Returns the base64 encoded XOAUTH2 initial client response.
+pub(crate) fn xoauth2_initial_response(user: &str, access_token: &str) -> String {
+    BASE64.encode(format!("user={}\x01auth=Bearer {}\x01\x01", user, access_token))
+}
+
+/// Returns true, if the error was caused by an `[AUTH]` response code (RFC 3206).
+pub(crate) fn is_auth_failure(err: &(dyn Error + 'static)) -> bool {
+    error_kind(err) == Pop3ErrorKind::Auth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pop3NegativeResponse;
+
+    #[test]
+    fn test_xoauth2_initial_response() {
+        let response = xoauth2_initial_response("someuser@example.com", "ya29.vF9dft4qmTc2Nvb3RlckBhdHRhdmlzdGEuY29tCg");
+        assert_eq!("dXNlcj1zb21ldXNlckBleGFtcGxlLmNvbQFhdXRoPUJlYXJlciB5YTI5LnZGOWRmdDRxbVRjMk52YjNSbGNrQmhkSFJoZG1semRHRXVZMjl0Q2cBAQ==", response);
+    }
+
+    #[test]
+    fn test_is_auth_failure() {
+        let err: Box<dyn Error> = Box::new(Pop3NegativeResponse::new("-ERR [AUTH] Invalid credentials"));
+        assert!(is_auth_failure(err.as_ref()));
+
+        let err: Box<dyn Error> = Box::new(Pop3NegativeResponse::new("-ERR [SYS/TEMP] try again later"));
+        assert!(!is_auth_failure(err.as_ref()));
+    }
+
+    #[test]
+    fn test_closure_as_token_provider() {
+        let provider = || -> Result<String, Box<dyn Error>> { Ok("token".into()) };
+        assert_eq!("token", provider.access_token().unwrap());
+        assert_eq!("token", provider.refresh_access_token().unwrap());
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::{HashSet, VecDeque};
+use std::error::Error;
+use std::sync::Mutex;
+use std::thread;
+
+use crate::{Pop3AccountResult, Pop3ConnectionBuilder, Pop3MessageMeta};
+
+struct Account {
+    name: String,
+    builder: Pop3ConnectionBuilder,
+    partitions: usize,
+}
+
+struct Job {
+    account: usize,
+    unique_ids: Option<HashSet<String>>,
+}
+
+/// Downloads messages using multiple worker threads.
+///
+/// Each worker uses its own connection. Work is distributed per account;
+/// accounts of providers which permit concurrent sessions can additionally
+/// be split into partitions, which are fetched in parallel.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{ParallelFetcher, Pop3ConnectionBuilder};
+///
+/// let mut fetcher = ParallelFetcher::new(4);
+/// fetcher.add_account("work", Pop3ConnectionBuilder::new("pop.example.com").login("me@example.com", "secret"));
+/// fetcher.add_account("home", Pop3ConnectionBuilder::new("pop.example.org").login("me@example.org", "secret"));
+///
+/// let results = fetcher.run(|account, message, content| {
+///     println!("{}: message {} ({} bytes)", account, message.message_id, content.len());
+///     Ok(())
+/// });
+/// ```
+pub struct ParallelFetcher {
+    concurrency: usize,
+    accounts: Vec<Account>,
+}
+
+impl ParallelFetcher {
+
+    /// Returns a new fetcher.
+    ///
+    /// # Arguments
+    ///
+    /// * `concurrency` - maximum count of concurrent connections
+    pub fn new(concurrency: usize) -> Self {
+        ParallelFetcher { concurrency: concurrency.max(1), accounts: vec!() }
+    }
+
+    /// Adds an account, whose messages are fetched using a single connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`    - name of the account
+    /// * `builder` - builder used to connect and authenticate the account
+    pub fn add_account(&mut self, name: &str, builder: Pop3ConnectionBuilder) {
+        self.add_partitioned_account(name, builder, 1);
+    }
+
+    /// Adds an account, whose messages are split into partitions fetched concurrently.
+    ///
+    /// Only use this, if the provider permits concurrent sessions of the same
+    /// account. Messages are assigned to partitions by their unique id.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`       - name of the account
+    /// * `builder`    - builder used to connect and authenticate the account
+    /// * `partitions` - count of partitions
+    pub fn add_partitioned_account(&mut self, name: &str, builder: Pop3ConnectionBuilder, partitions: usize) {
+        self.accounts.push(Account { name: name.to_string(), builder, partitions: partitions.max(1) });
+    }
+
+    /// Fetches all messages and returns the count of fetched messages per account.
+    ///
+    /// If a partition of an account fails, the result of the account contains
+    /// the error; other partitions and accounts are not affected. The handler
+    /// may be called from multiple threads concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `handler` - invoked with account name, message metadata and exact message octets
+    pub fn run(&self, handler: impl Fn(&str, &Pop3MessageMeta, &[u8]) -> Result<(), Box<dyn Error>> + Sync) -> Vec<Pop3AccountResult<usize>> {
+        let mut results: Vec<(usize, Vec<String>)> = self.accounts.iter().map(|_| (0, vec!())).collect();
+
+        let mut jobs = VecDeque::new();
+        for (index, account) in self.accounts.iter().enumerate() {
+            if account.partitions == 1 {
+                jobs.push_back(Job { account: index, unique_ids: None });
+                continue;
+            }
+
+            match self.partition(account) {
+                Ok(partitions) => {
+                    for unique_ids in partitions {
+                        jobs.push_back(Job { account: index, unique_ids: Some(unique_ids) });
+                    }
+                },
+                Err(err) => results[index].1.push(err.to_string())
+            }
+        }
+
+        let workers = self.concurrency.min(jobs.len());
+        let jobs = Mutex::new(jobs);
+        let results = Mutex::new(results);
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| {
+                    loop {
+                        // pop in a statement of its own, so that the queue is not locked while fetching
+                        let job = jobs.lock().unwrap_or_else(|err| err.into_inner()).pop_front();
+                        let Some(job) = job else {
+                            break;
+                        };
+                        let (count, error) = self.fetch(&job, &handler);
+                        let mut results = results.lock().unwrap_or_else(|err| err.into_inner());
+                        results[job.account].0 += count;
+                        results[job.account].1.extend(error);
+                    }
+                });
+            }
+        });
+
+        let results = results.into_inner().unwrap_or_else(|err| err.into_inner());
+        self.accounts.iter().zip(results).map(|(account, (count, errors))| {
+            let result = match errors.is_empty() {
+                true => Ok(count),
+                _ => Err(errors.join("; ").into())
+            };
+            Pop3AccountResult { account: account.name.clone(), result }
+        }).collect()
+    }
+
+    fn partition(&self, account: &Account) -> Result<Vec<HashSet<String>>, Box<dyn Error>> {
+        let mut connection = account.builder.clone().connect()?;
+        let messages = connection.list_meta()?;
+        connection.quit()?;
+
+        let mut unique_ids = vec!();
+        for message in messages {
+            unique_ids.push(message.unique_id.ok_or("server does not support UIDL")?);
+        }
+
+        Ok(split(unique_ids, account.partitions))
+    }
+
+    /// Fetches the messages of a job; returns the count of fetched messages and an error, if any.
+    fn fetch(&self, job: &Job, handler: &(impl Fn(&str, &Pop3MessageMeta, &[u8]) -> Result<(), Box<dyn Error>> + Sync)) -> (usize, Option<String>) {
+        let account = &self.accounts[job.account];
+        let mut count = 0;
+        let result = (|| -> Result<(), Box<dyn Error>> {
+            let mut connection = account.builder.clone().connect()?;
+            for message in connection.list_meta()? {
+                if let Some(unique_ids) = &job.unique_ids {
+                    if !matches!(&message.unique_id, Some(unique_id) if unique_ids.contains(unique_id)) {
+                        continue;
+                    }
+                }
+
+                let mut content = vec!();
+                connection.retrieve_raw(message.message_id, &mut content)?;
+                handler(&account.name, &message, &content)?;
+                count += 1;
+            }
+
+            connection.quit()
+        })();
+
+        (count, result.err().map(|err| err.to_string()))
+    }
+}
+
+/// Splits items into the given count of partitions of about equal size.
+fn split(items: Vec<String>, partitions: usize) -> Vec<HashSet<String>> {
+    let mut result: Vec<HashSet<String>> = (0..partitions).map(|_| HashSet::new()).collect();
+    for (index, item) in items.into_iter().enumerate() {
+        result[index % partitions].insert(item);
+    }
+
+    result.retain(|partition| !partition.is_empty());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Condvar;
+    use std::time::Duration;
+    use crate::test_util::{self, unreachable_account};
+    use crate::TlsMode;
+
+    #[test]
+    fn test_split() {
+        let items: Vec<String> = (1..=5).map(|id| id.to_string()).collect();
+        let partitions = split(items, 2);
+        assert_eq!(2, partitions.len());
+        assert_eq!(3, partitions[0].len());
+        assert_eq!(2, partitions[1].len());
+
+        assert_eq!(1, split(vec!("a".into()), 4).len());
+    }
+
+    #[test]
+    fn test_errors_are_aggregated_per_account() {
+        let mut fetcher = ParallelFetcher::new(2);
+        fetcher.add_account("a", unreachable_account());
+        fetcher.add_partitioned_account("b", unreachable_account(), 3);
+        fetcher.add_account("c", unreachable_account());
+
+        let results = fetcher.run(|_, _, _| Ok(()));
+        let names: Vec<&str> = results.iter().map(|result| result.account.as_str()).collect();
+        assert_eq!(vec!("a", "b", "c"), names);
+        assert!(results.iter().all(|result| result.result.is_err()));
+    }
+
+    #[test]
+    fn test_accounts_are_fetched_concurrently() {
+        let script: &[(&str, &str)] = &[
+            ("LIST", "+OK\r\n1 7\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uid1\r\n.\r\n"),
+            ("RETR 1", "+OK\r\nHello\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ];
+        let (port_a, server_a) = test_util::serve(script);
+        let (port_b, server_b) = test_util::serve(script);
+        let mut fetcher = ParallelFetcher::new(2);
+        fetcher.add_account("a", Pop3ConnectionBuilder::new("127.0.0.1").tls_mode(TlsMode::Plain).port(port_a));
+        fetcher.add_account("b", Pop3ConnectionBuilder::new("127.0.0.1").tls_mode(TlsMode::Plain).port(port_b));
+
+        // each handler waits for the other one, which only succeeds if both sessions overlap
+        let arrived = (Mutex::new(0), Condvar::new());
+        let results = fetcher.run(|_, _, _| {
+            let (count, condvar) = &arrived;
+            let mut count = count.lock().unwrap();
+            *count += 1;
+            condvar.notify_all();
+            let (count, _) = condvar.wait_timeout_while(count, Duration::from_secs(5), |count| *count < 2).unwrap();
+            match *count {
+                2 => Ok(()),
+                _ => Err("sessions did not overlap".into())
+            }
+        });
+
+        assert!(results.iter().all(|result| matches!(result.result, Ok(1))));
+        server_a.join().unwrap();
+        server_b.join().unwrap();
+    }
+}
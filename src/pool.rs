@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex, MutexGuard};
+
+use crate::{Pop3Connection, Pop3ConnectionBuilder};
+
+/// Identifies an account of a connection pool.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Pop3AccountKey {
+    /// IP-Address or host name of the POP3 server
+    pub host: String,
+
+    /// Port of the POP3 server
+    pub port: u16,
+
+    /// Name of the user; `None` if connections are not authenticated
+    pub user: Option<String>,
+}
+
+struct Account {
+    builder: Pop3ConnectionBuilder,
+    idle: Vec<Pop3Connection>,
+    active: usize,
+}
+
+/// Thread-safe pool of authenticated POP3 connections.
+///
+/// Connections are created on demand using the builder registered for an
+/// account, up to a maximum count per account. Idle connections are checked
+/// using NOOP before they are handed out; dead connections are replaced.
+/// Connections, which failed while reading a response, are not returned to
+/// the pool. Messages marked as deleted are unmarked by RSET, before a
+/// connection is returned to the pool, so that the next borrower does not
+/// commit deletions it never asked for.
+///
+/// Since most servers lock the maildrop for the duration of a session,
+/// a pool will often manage distinct accounts with a single connection each.
+/// Note that deletions are committed only when a connection is closed,
+/// see [`PooledConnection::close`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{Pop3ConnectionBuilder, Pop3Pool};
+///
+/// let pool = Pop3Pool::new(1);
+/// let account = pool.add_account(Pop3ConnectionBuilder::new("pop.example.com")
+///     .login("user@example.com", "secret"));
+///
+/// let mut connection = pool.get(&account).unwrap();
+/// let stat = connection.stat().unwrap();
+/// ```
+pub struct Pop3Pool {
+    max_connections: usize,
+    accounts: Mutex<HashMap<Pop3AccountKey, Account>>,
+    available: Condvar,
+}
+
+impl Pop3Pool {
+
+    /// Returns a new, empty pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_connections` - maximum count of connections per account
+    pub fn new(max_connections: usize) -> Self {
+        Pop3Pool {
+            max_connections: max_connections.max(1),
+            accounts: Mutex::new(HashMap::new()),
+            available: Condvar::new(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<Pop3AccountKey, Account>> {
+        self.accounts.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Registers an account and returns its key.
+    ///
+    /// If the account is already known, its builder is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `builder` - builder used to create connections of the account
+    pub fn add_account(&self, builder: Pop3ConnectionBuilder) -> Pop3AccountKey {
+        let key = builder.account_key();
+        let mut accounts = self.lock();
+        match accounts.get_mut(&key) {
+            Some(account) => { account.builder = builder; },
+            None => { accounts.insert(key.clone(), Account { builder, idle: vec!(), active: 0 }); }
+        }
+
+        key
+    }
+
+    /// Checks out a connection of the given account.
+    ///
+    /// Blocks while the maximum count of connections of the account is in use.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - key of the account
+    pub fn get(&self, key: &Pop3AccountKey) -> Result<PooledConnection<'_>, Box<dyn Error>> {
+        let mut accounts = self.lock();
+        let (idle, builder) = loop {
+            let account = accounts.get_mut(key).ok_or("unknown account")?;
+            if !account.idle.is_empty() || account.active < self.max_connections {
+                account.active += 1;
+                break (account.idle.pop(), account.builder.clone());
+            }
+
+            accounts = self.available.wait(accounts).unwrap_or_else(|err| err.into_inner());
+        };
+        drop(accounts);
+
+        let connection = match idle.map(Self::check_health) {
+            Some(Ok(connection)) => Ok(connection),
+            _ => builder.connect()
+        };
+
+        match connection {
+            Ok(connection) => Ok(PooledConnection { pool: self, key: key.clone(), connection: Some(connection) }),
+            Err(err) => {
+                self.release(key, None);
+                Err(err)
+            }
+        }
+    }
+
+    fn check_health(mut connection: Pop3Connection) -> Result<Pop3Connection, Box<dyn Error>> {
+        connection.noop()?;
+        Ok(connection)
+    }
+
+    /// Closes all idle connections.
+    ///
+    /// Errors are ignored; use [`PooledConnection::close`] to detect them.
+    pub fn clear(&self) {
+        let idle: Vec<Pop3Connection> = self.lock().values_mut()
+            .flat_map(|account| account.idle.drain(..))
+            .collect();
+        drop(idle);
+    }
+
+    fn release(&self, key: &Pop3AccountKey, connection: Option<Pop3Connection>) {
+        let mut accounts = self.lock();
+        if let Some(account) = accounts.get_mut(key) {
+            account.active -= 1;
+            if let Some(connection) = connection.filter(|connection| !connection.is_poisoned()) {
+                account.idle.push(connection);
+            }
+        }
+        drop(accounts);
+        // waiters of all accounts share the condition variable
+        self.available.notify_all();
+    }
+}
+
+/// Connection checked out of a [`Pop3Pool`].
+///
+/// The connection is returned to the pool on drop.
+pub struct PooledConnection<'a> {
+    pool: &'a Pop3Pool,
+    key: Pop3AccountKey,
+    connection: Option<Pop3Connection>,
+}
+
+impl PooledConnection<'_> {
+
+    /// Removes the connection from the pool and closes it without QUIT.
+    ///
+    /// Since the session does not enter the UPDATE state, messages marked
+    /// as deleted are kept. Should be used when the connection is known to
+    /// be unusable or its deletions must not be committed.
+    pub fn discard(mut self) {
+        if let Some(mut connection) = self.connection.take() {
+            connection.abort();
+        }
+        self.pool.release(&self.key, None);
+    }
+
+    /// Removes the connection from the pool and ends the session using QUIT,
+    /// which commits pending deletions.
+    pub fn close(mut self) -> Result<(), Box<dyn Error>> {
+        let connection = self.connection.take();
+        self.pool.release(&self.key, None);
+        match connection {
+            Some(connection) => connection.quit(),
+            None => Ok(())
+        }
+    }
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Pop3Connection;
+
+    fn deref(&self) -> &Pop3Connection {
+        self.connection.as_ref().expect("connection available")
+    }
+}
+
+impl DerefMut for PooledConnection<'_> {
+    fn deref_mut(&mut self) -> &mut Pop3Connection {
+        self.connection.as_mut().expect("connection available")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    /// Returns the connection to the pool, unless it is poisoned.
+    ///
+    /// Pending deletions are unmarked by RSET; if that fails, the connection
+    /// is closed without QUIT instead.
+    fn drop(&mut self) {
+        if let Some(mut connection) = self.connection.take() {
+            if !connection.marked_for_deletion().is_empty() && connection.reset().is_err() {
+                connection.abort();
+                self.pool.release(&self.key, None);
+                return;
+            }
+            self.pool.release(&self.key, Some(connection));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+    use crate::TlsMode;
+    use crate::test_util::{self, unreachable_account};
+
+    #[test]
+    fn test_account_key() {
+        let pool = Pop3Pool::new(2);
+        let key = pool.add_account(Pop3ConnectionBuilder::new("pop.example.com").login("user", "secret"));
+        assert_eq!(Pop3AccountKey { host: "pop.example.com".into(), port: 995, user: Some("user".into()) }, key);
+
+        let other = pool.add_account(Pop3ConnectionBuilder::new("pop.example.com").login("other", "secret"));
+        assert_ne!(key, other);
+    }
+
+    #[test]
+    fn test_unknown_account() {
+        let pool = Pop3Pool::new(1);
+        let key = Pop3AccountKey { host: "pop.example.com".into(), port: 995, user: None };
+        assert!(pool.get(&key).is_err());
+    }
+
+    #[test]
+    fn test_failed_connect_releases_slot() {
+        let pool = Pop3Pool::new(1);
+        let key = pool.add_account(unreachable_account());
+
+        assert!(pool.get(&key).is_err());
+        assert!(pool.get(&key).is_err());
+    }
+
+    #[test]
+    fn test_poisoned_connection_is_dropped() {
+        let (port, server) = test_util::serve_sessions(&[
+            &[("LIST", "+OK\r\n1 20\r\n")],
+            &[("STAT", "+OK 0 0\r\n")],
+        ]);
+        let pool = Pop3Pool::new(1);
+        let key = pool.add_account(Pop3ConnectionBuilder::new("127.0.0.1").tls_mode(TlsMode::Plain).port(port));
+
+        let mut connection = pool.get(&key).unwrap();
+        assert!(connection.list().is_err());
+        assert!(connection.is_poisoned());
+        drop(connection);
+        assert!(pool.lock()[&key].idle.is_empty());
+
+        let mut connection = pool.get(&key).unwrap();
+        assert_eq!(0, connection.stat().unwrap().message_count);
+        drop(connection);
+        assert_eq!(1, pool.lock()[&key].idle.len());
+
+        assert_eq!(vec!(vec!("LIST".to_string()), vec!("STAT".to_string())), server.join().unwrap());
+    }
+
+    fn account(port: u16) -> Pop3ConnectionBuilder {
+        Pop3ConnectionBuilder::new("127.0.0.1").tls_mode(TlsMode::Plain).port(port)
+    }
+
+    #[test]
+    fn test_release_wakes_waiter_of_account() {
+        let (port_a, server_a) = test_util::serve(&[("NOOP", "+OK\r\n")]);
+        let (port_b, server_b) = test_util::serve(&[("NOOP", "+OK\r\n")]);
+        let pool = Pop3Pool::new(1);
+        let key_a = pool.add_account(account(port_a));
+        let key_b = pool.add_account(account(port_b));
+        let connection_a = pool.get(&key_a).unwrap();
+        let connection_b = pool.get(&key_b).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            // the waiter of the other account waits first, so it would take a single wakeup
+            for key in [&key_b, &key_a] {
+                let (pool, sender) = (&pool, sender.clone());
+                scope.spawn(move || {
+                    let connection = pool.get(key);
+                    sender.send(key.port).unwrap();
+                    drop(connection);
+                });
+                thread::sleep(Duration::from_millis(50));
+            }
+
+            drop(connection_a);
+            assert_eq!(port_a, receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+            drop(connection_b);
+            assert_eq!(port_b, receiver.recv_timeout(Duration::from_secs(5)).unwrap());
+        });
+
+        drop(pool);
+        assert_eq!(vec!("NOOP"), server_a.join().unwrap());
+        assert_eq!(vec!("NOOP"), server_b.join().unwrap());
+    }
+
+    #[test]
+    fn test_pending_deletions_are_reset() {
+        let (port, server) = test_util::serve(&[
+            ("DELE 1", "+OK\r\n"),
+            ("RSET", "+OK\r\n"),
+            ("NOOP", "+OK\r\n"),
+        ]);
+        let pool = Pop3Pool::new(1);
+        let key = pool.add_account(account(port));
+
+        let mut connection = pool.get(&key).unwrap();
+        connection.delete(1).unwrap();
+        drop(connection);
+
+        let connection = pool.get(&key).unwrap();
+        assert!(connection.marked_for_deletion().is_empty());
+        drop(connection);
+        drop(pool);
+        assert_eq!(vec!("DELE 1", "RSET", "NOOP"), server.join().unwrap());
+    }
+
+    #[test]
+    fn test_discard_does_not_quit() {
+        let (port, server) = test_util::serve(&[
+            ("DELE 1", "+OK\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+        let pool = Pop3Pool::new(1);
+        let key = pool.add_account(account(port));
+
+        let mut connection = pool.get(&key).unwrap();
+        connection.delete(1).unwrap();
+        connection.discard();
+
+        assert!(pool.lock()[&key].idle.is_empty());
+        assert_eq!(vec!("DELE 1"), server.join().unwrap());
+    }
+}
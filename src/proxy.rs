@@ -0,0 +1,292 @@
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::{IpAddr, TcpStream};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+/// Proxy tunneling the connection to the POP3 server.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{Pop3ConnectionBuilder, Pop3Proxy};
+///
+/// let connection = Pop3ConnectionBuilder::new("pop.example.com")
+///     .proxy(Pop3Proxy::socks5("127.0.0.1", 1080))
+///     .login("user@example.com", "secret")
+///     .connect();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pop3Proxy {
+    kind: ProxyKind,
+    host: String,
+    port: u16,
+    credentials: Option<(String, String)>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ProxyKind {
+    Socks5,
+    Http,
+}
+
+impl Pop3Proxy {
+
+    /// Returns a SOCKS5 proxy (RFC 1928); host names are resolved by the proxy.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - IP-Address or host name of the proxy
+    /// * `port` - port of the proxy, typically 1080
+    pub fn socks5(host: &str, port: u16) -> Self {
+        Pop3Proxy { kind: ProxyKind::Socks5, host: host.to_string(), port, credentials: None }
+    }
+
+    /// Returns an HTTP proxy, which tunnels the connection using CONNECT.
+    ///
+    /// # Arguments
+    ///
+    /// * `host` - IP-Address or host name of the proxy
+    /// * `port` - port of the proxy, e.g. 3128
+    pub fn http(host: &str, port: u16) -> Self {
+        Pop3Proxy { kind: ProxyKind::Http, host: host.to_string(), port, credentials: None }
+    }
+
+    /// Authenticates at the proxy, using username/password authentication (RFC 1929)
+    /// for SOCKS5 or basic authentication for HTTP.
+    ///
+    /// # Arguments
+    ///
+    /// * `user`     - Name of the user at the proxy
+    /// * `password` - Password of the user at the proxy
+    pub fn credentials(mut self, user: &str, password: &str) -> Self {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self
+    }
+
+    /// Returns a stream tunneled to the given host and port.
+    pub(crate) fn connect(&self, host: &str, port: u16) -> Result<TcpStream, Box<dyn Error>> {
+        let mut stream = TcpStream::connect((self.host.as_str(), self.port))?;
+        match self.kind {
+            ProxyKind::Socks5 => self.socks5_connect(&mut stream, host, port)?,
+            ProxyKind::Http => self.http_connect(&mut stream, host, port)?,
+        }
+        Ok(stream)
+    }
+
+    fn socks5_connect(&self, stream: &mut TcpStream, host: &str, port: u16) -> Result<(), Box<dyn Error>> {
+        match &self.credentials {
+            Some(_) => stream.write_all(&[5, 2, 0, 2])?,
+            None => stream.write_all(&[5, 1, 0])?,
+        }
+        let mut reply = [0u8; 2];
+        stream.read_exact(&mut reply)?;
+        match (reply, &self.credentials) {
+            ([5, 0], _) => { },
+            ([5, 2], Some((user, password))) => {
+                if user.len() > 255 || password.len() > 255 {
+                    return Err("SOCKS5 credentials too long".into());
+                }
+                let mut request = vec![1, user.len() as u8];
+                request.extend_from_slice(user.as_bytes());
+                request.push(password.len() as u8);
+                request.extend_from_slice(password.as_bytes());
+                stream.write_all(&request)?;
+                stream.read_exact(&mut reply)?;
+                if reply[1] != 0 {
+                    return Err("SOCKS5 authentication failed".into());
+                }
+            },
+            ([5, _], _) => return Err("SOCKS5 proxy accepts no supported authentication method".into()),
+            _ => return Err("invalid SOCKS5 response".into())
+        }
+
+        let mut request = vec![5, 1, 0];
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(address)) => {
+                request.push(1);
+                request.extend_from_slice(&address.octets());
+            },
+            Ok(IpAddr::V6(address)) => {
+                request.push(4);
+                request.extend_from_slice(&address.octets());
+            },
+            Err(_) if host.len() <= 255 => {
+                request.extend_from_slice(&[3, host.len() as u8]);
+                request.extend_from_slice(host.as_bytes());
+            },
+            Err(_) => return Err("host name too long".into())
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request)?;
+
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply)?;
+        if reply[0] != 5 {
+            return Err("invalid SOCKS5 response".into());
+        }
+        if reply[1] != 0 {
+            return Err(format!("SOCKS5 proxy failed to connect: {}", socks5_reply(reply[1])).into());
+        }
+        let address_len = match reply[3] {
+            1 => 4,
+            4 => 16,
+            3 => {
+                let mut len = [0u8; 1];
+                stream.read_exact(&mut len)?;
+                len[0] as usize
+            },
+            _ => return Err("invalid SOCKS5 response".into())
+        };
+        // bound address and port are not used
+        let mut bound = vec![0u8; address_len + 2];
+        stream.read_exact(&mut bound)?;
+        Ok(())
+    }
+
+    fn http_connect(&self, stream: &mut TcpStream, host: &str, port: u16) -> Result<(), Box<dyn Error>> {
+        let authority = match host.contains(':') {
+            true => format!("[{}]:{}", host, port),
+            false => format!("{}:{}", host, port)
+        };
+        let mut request = format!("CONNECT {} HTTP/1.1\r\nHost: {}\r\n", authority, authority);
+        if let Some((user, password)) = &self.credentials {
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", BASE64.encode(format!("{}:{}", user, password))));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+
+        // the response is read byte by byte, since the tunnel starts right after it
+        let mut response = vec!();
+        let mut byte = [0u8; 1];
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() > 8192 {
+                return Err("HTTP proxy response too long".into());
+            }
+            stream.read_exact(&mut byte)?;
+            response.push(byte[0]);
+        }
+
+        let response = String::from_utf8_lossy(&response);
+        let status_line = response.lines().next().unwrap_or_default();
+        match status_line.split_whitespace().nth(1) {
+            Some(status) if status.starts_with('2') => Ok(()),
+            _ => Err(format!("HTTP proxy failed to connect: {}", status_line).into())
+        }
+    }
+}
+
+/// Returns the description of a SOCKS5 reply code.
+fn socks5_reply(code: u8) -> &'static str {
+    match code {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    use crate::{Pop3ConnectionBuilder, TlsMode};
+
+    /// Serves a tunnel after the handshake: sends a POP3 greeting and answers QUIT.
+    fn serve(handshake: impl FnOnce(&mut TcpStream) -> Vec<u8> + Send + 'static) -> (u16, JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let received = handshake(&mut stream);
+            stream.write_all(b"+OK ready\r\n").unwrap();
+            let mut line = String::new();
+            BufReader::new(stream.try_clone().unwrap()).read_line(&mut line).unwrap();
+            assert_eq!("QUIT\r\n", line);
+            stream.write_all(b"+OK bye\r\n").unwrap();
+            received
+        });
+        (port, handle)
+    }
+
+    fn read(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+        let mut buffer = vec![0u8; len];
+        stream.read_exact(&mut buffer).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_socks5() {
+        let (port, proxy) = serve(|stream| {
+            let mut received = read(stream, 4);
+            stream.write_all(&[5, 2]).unwrap();
+            received.extend(read(stream, 2 + 2 + 1 + 6));
+            stream.write_all(&[1, 0]).unwrap();
+            received.extend(read(stream, 5 + 15 + 2));
+            stream.write_all(&[5, 0, 0, 1, 127, 0, 0, 1, 0, 110]).unwrap();
+            received
+        });
+
+        let connection = Pop3ConnectionBuilder::new("pop.example.com")
+            .tls_mode(TlsMode::Plain)
+            .proxy(Pop3Proxy::socks5("127.0.0.1", port).credentials("me", "secret"))
+            .connect()
+            .unwrap();
+        connection.quit().unwrap();
+
+        let mut expected = vec![5, 2, 0, 2, 1, 2, b'm', b'e', 6];
+        expected.extend_from_slice(b"secret");
+        expected.extend_from_slice(&[5, 1, 0, 3, 15]);
+        expected.extend_from_slice(b"pop.example.com");
+        expected.extend_from_slice(&[0, 110]);
+        assert_eq!(expected, proxy.join().unwrap());
+    }
+
+    #[test]
+    fn test_socks5_refused() {
+        let (port, _proxy) = serve(|stream| {
+            read(stream, 3);
+            stream.write_all(&[5, 0]).unwrap();
+            read(stream, 10);
+            stream.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0]).unwrap();
+            vec!()
+        });
+
+        let result = Pop3ConnectionBuilder::new("127.0.0.1")
+            .proxy(Pop3Proxy::socks5("127.0.0.1", port))
+            .connect();
+        assert_eq!("SOCKS5 proxy failed to connect: connection refused", result.err().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_http() {
+        let (port, proxy) = serve(|stream| {
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            while !request.ends_with("\r\n\r\n") {
+                reader.read_line(&mut request).unwrap();
+            }
+            stream.write_all(b"HTTP/1.1 200 Connection established\r\n\r\n").unwrap();
+            request.into_bytes()
+        });
+
+        let connection = Pop3ConnectionBuilder::new("pop.example.com")
+            .tls_mode(TlsMode::Plain)
+            .proxy(Pop3Proxy::http("127.0.0.1", port).credentials("me", "secret"))
+            .connect()
+            .unwrap();
+        connection.quit().unwrap();
+
+        let expected = "CONNECT pop.example.com:110 HTTP/1.1\r\nHost: pop.example.com:110\r\nProxy-Authorization: Basic bWU6c2VjcmV0\r\n\r\n";
+        assert_eq!(expected, String::from_utf8(proxy.join().unwrap()).unwrap());
+    }
+}
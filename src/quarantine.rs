@@ -0,0 +1,263 @@
+use std::error::Error;
+
+use crate::{Mailbox, Pop3Connection, Pop3MessageSummary, RuleMatcher};
+
+const EXECUTABLE_EXTENSIONS: [&str; 16] = [
+    "exe", "scr", "com", "pif", "bat", "cmd", "js", "jse", "vbs", "vbe",
+    "wsf", "hta", "jar", "ps1", "lnk", "msi",
+];
+
+/// Reason, why a message is considered suspicious.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SuspicionFlag {
+    /// The headers declare an executable attachment with the given file name.
+    ExecutableAttachment(String),
+
+    /// The domain of the Reply-To or Return-Path address differs from the From domain.
+    SenderMismatch,
+
+    /// The From header contains the given suspicious text.
+    SuspiciousSender(String),
+
+    /// The rule with the given name matched.
+    Rule(String),
+}
+
+/// Headers and suspicion flags of a single message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuarantineEntry {
+    /// metadata and headers of the message
+    pub summary: Pop3MessageSummary,
+
+    /// reasons, why the message is suspicious; empty, if it is not
+    pub flags: Vec<SuspicionFlag>,
+}
+
+/// Result of a quarantine scan of a maildrop.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct QuarantineReport {
+    /// entries of all messages of the maildrop
+    pub entries: Vec<QuarantineEntry>,
+}
+
+impl QuarantineReport {
+
+    /// Returns the entries of messages, which are suspicious.
+    pub fn suspicious(&self) -> impl Iterator<Item = &QuarantineEntry> {
+        self.entries.iter().filter(|entry| !entry.flags.is_empty())
+    }
+}
+
+/// Scans the headers of all messages for suspicious properties without downloading them.
+///
+/// Only the headers are fetched (TOP 0), so a compromised mailbox can be
+/// reviewed before any message is downloaded. By default, the scan flags
+/// executable attachments declared in the headers and Reply-To or
+/// Return-Path domains differing from the From domain.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{Pop3Connection, Quarantine, RuleMatcher};
+///
+/// let mut connection = Pop3Connection::new("pop.example.com", 995).unwrap();
+/// connection.login("user@example.com", "secret").unwrap();
+///
+/// let report = Quarantine::new()
+///     .suspicious_sender("@malicious.example")
+///     .rule("invoice", RuleMatcher::Subject("invoice".into()))
+///     .scan(&mut connection)
+///     .unwrap();
+///
+/// for entry in report.suspicious() {
+///     println!("{}: {:?}", entry.summary.meta.message_id, entry.flags);
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Quarantine {
+    executable_extensions: Vec<String>,
+    suspicious_senders: Vec<String>,
+    check_sender_mismatch: bool,
+    rules: Vec<(String, RuleMatcher)>,
+}
+
+impl Default for Quarantine {
+    fn default() -> Self {
+        Quarantine {
+            executable_extensions: EXECUTABLE_EXTENSIONS.iter().map(|extension| extension.to_string()).collect(),
+            suspicious_senders: vec!(),
+            check_sender_mismatch: true,
+            rules: vec!(),
+        }
+    }
+}
+
+impl Quarantine {
+
+    /// Returns a quarantine scan with the default heuristics.
+    pub fn new() -> Self {
+        Quarantine::default()
+    }
+
+    /// Replaces the file extensions considered as executable, e.g. `["exe", "js"]`.
+    pub fn executable_extensions(mut self, extensions: &[&str]) -> Self {
+        self.executable_extensions = extensions.iter().map(|extension| extension.to_lowercase()).collect();
+        self
+    }
+
+    /// Flags messages, whose From header contains the given text (ignoring case), e.g. a domain.
+    pub fn suspicious_sender(mut self, text: &str) -> Self {
+        self.suspicious_senders.push(text.to_lowercase());
+        self
+    }
+
+    /// Enables or disables the check of Reply-To and Return-Path against the From domain.
+    pub fn check_sender_mismatch(mut self, check_sender_mismatch: bool) -> Self {
+        self.check_sender_mismatch = check_sender_mismatch;
+        self
+    }
+
+    /// Flags messages matching a custom rule.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`    - name of the rule, reported by [`SuspicionFlag::Rule`]
+    /// * `matcher` - condition of the rule
+    pub fn rule(mut self, name: &str, matcher: RuleMatcher) -> Self {
+        self.rules.push((name.to_string(), matcher));
+        self
+    }
+
+    /// Returns the suspicion flags of a message.
+    pub fn inspect(&self, summary: &Pop3MessageSummary) -> Vec<SuspicionFlag> {
+        let mut flags = vec!();
+
+        for (name, value) in summary.headers.iter() {
+            if name.eq_ignore_ascii_case("Content-Type") || name.eq_ignore_ascii_case("Content-Disposition") {
+                for file_name in file_names(value) {
+                    let extension = file_name.rsplit('.').next().unwrap_or_default().to_lowercase();
+                    if file_name.contains('.') && self.executable_extensions.contains(&extension) {
+                        flags.push(SuspicionFlag::ExecutableAttachment(file_name));
+                    }
+                }
+            }
+        }
+
+        let from = summary.from().unwrap_or_default();
+        if self.check_sender_mismatch {
+            let from_domain = domain(from);
+            let mismatch = ["Reply-To", "Return-Path"].iter()
+                .filter_map(|name| summary.header(name))
+                .filter_map(domain)
+                .any(|other| from_domain.as_ref() != Some(&other));
+            if mismatch {
+                flags.push(SuspicionFlag::SenderMismatch);
+            }
+        }
+
+        let from = from.to_lowercase();
+        for sender in &self.suspicious_senders {
+            if from.contains(sender.as_str()) {
+                flags.push(SuspicionFlag::SuspiciousSender(sender.clone()));
+            }
+        }
+
+        for (name, matcher) in &self.rules {
+            if matcher.matches(summary) {
+                flags.push(SuspicionFlag::Rule(name.clone()));
+            }
+        }
+
+        flags
+    }
+
+    /// Fetches the headers of all messages and returns the report.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - connection to the server
+    pub fn scan(&self, connection: &mut Pop3Connection) -> Result<QuarantineReport, Box<dyn Error>> {
+        let entries = connection.summaries()?.into_iter()
+            .map(|summary| {
+                let flags = self.inspect(&summary);
+                QuarantineEntry { summary, flags }
+            })
+            .collect();
+
+        Ok(QuarantineReport { entries })
+    }
+}
+
+/// Returns the values of the `name` and `filename` parameters of a header value.
+fn file_names(value: &str) -> Vec<String> {
+    value.split(';').skip(1)
+        .filter_map(|parameter| parameter.split_once('='))
+        .filter(|(key, _)| {
+            let key = key.trim();
+            key.eq_ignore_ascii_case("name") || key.eq_ignore_ascii_case("filename")
+        })
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+        .collect()
+}
+
+/// Returns the lower case domain of the first address of a header value.
+fn domain(value: &str) -> Option<String> {
+    Mailbox::parse_list(value).first().and_then(Mailbox::domain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Pop3MessageMeta, test_util};
+
+    fn summary(headers: &[(&str, &str)]) -> Pop3MessageSummary {
+        Pop3MessageSummary {
+            meta: Pop3MessageMeta { message_id: 1, message_size: 10, unique_id: None },
+            headers: headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_inspect() {
+        let quarantine = Quarantine::new().suspicious_sender("@bad.example");
+
+        assert!(quarantine.inspect(&summary(&[
+            ("From", "Alice <alice@example.com>"),
+            ("Reply-To", "alice@EXAMPLE.com"),
+            ("Content-Type", "text/plain; charset=utf-8"),
+        ])).is_empty());
+
+        assert_eq!(vec!(
+            SuspicionFlag::ExecutableAttachment("invoice.pdf.exe".into()),
+            SuspicionFlag::SenderMismatch,
+            SuspicionFlag::SuspiciousSender("@bad.example".into()),
+        ), quarantine.inspect(&summary(&[
+            ("From", "Billing <billing@bad.example>"),
+            ("Return-Path", "<bounce@other.example>"),
+            ("Content-Disposition", "attachment; filename=\"invoice.pdf.exe\""),
+        ])));
+    }
+
+    #[test]
+    fn test_scan() {
+        let (mut connection, server) = test_util::connect(&[
+            ("LIST", "+OK\r\n1 10\r\n2 20\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uid1\r\n2 uid2\r\n.\r\n"),
+            ("TOP 1 0", "+OK\r\nSubject: hello\r\n\r\n.\r\n"),
+            ("TOP 2 0", "+OK\r\nSubject: Your invoice\r\n\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let report = Quarantine::new()
+            .rule("invoice", RuleMatcher::Subject("invoice".into()))
+            .scan(&mut connection)
+            .unwrap();
+        drop(connection);
+        server.join().unwrap();
+
+        assert_eq!(2, report.entries.len());
+        let suspicious: Vec<_> = report.suspicious().collect();
+        assert_eq!(1, suspicious.len());
+        assert_eq!(vec!(SuspicionFlag::Rule("invoice".into())), suspicious[0].flags);
+    }
+}
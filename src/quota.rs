@@ -0,0 +1,171 @@
+use std::error::Error;
+
+use futures_util::io::{AsyncRead, AsyncWrite};
+
+use crate::{AsyncPop3Connection, Pop3AsyncError, Pop3Connection, Pop3MessageMeta, block_on};
+
+/// Strategy to choose the messages deleted to free space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CleanupStrategy {
+    /// Deletes the largest messages first.
+    LargestFirst,
+
+    /// Deletes the oldest messages first, according to their Date header.
+    /// Messages without valid Date header are deleted last.
+    OldestFirst,
+}
+
+/// Messages chosen to free space in the maildrop.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CleanupPlan {
+    /// messages to delete, in the order they were chosen
+    pub messages: Vec<Pop3MessageMeta>,
+
+    /// total size of the messages to delete in octets
+    pub freed_size: u64,
+
+    /// total size of the maildrop in octets
+    pub maildrop_size: u64,
+}
+
+impl CleanupPlan {
+
+    /// Returns true, if deleting the messages frees at least the given number of octets.
+    pub fn reaches(&self, target_size: u64) -> bool {
+        self.freed_size >= target_size
+    }
+}
+
+impl Pop3Connection {
+
+    /// Returns the messages to delete in order to free the given number of octets.
+    ///
+    /// No message is deleted, so the plan can be previewed before calling
+    /// [`Pop3Connection::free_space`]. If the maildrop is smaller than the
+    /// target size, all messages are chosen.
+    ///
+    /// # Arguments
+    ///
+    /// * `target_size` - number of octets to free
+    /// * `strategy`    - strategy to choose the messages
+    pub fn plan_free_space(&mut self, target_size: u64, strategy: CleanupStrategy) -> Result<CleanupPlan, Box<dyn Error>> {
+        block_on(self.inner.plan_free_space(target_size, strategy))
+    }
+
+    /// Deletes messages in order to free the given number of octets and returns the plan.
+    ///
+    /// The messages are marked as deleted; they are removed by the server
+    /// when the session ends. See [`Pop3Connection::plan_free_space`].
+    ///
+    /// # Arguments
+    ///
+    /// * `target_size` - number of octets to free
+    /// * `strategy`    - strategy to choose the messages
+    pub fn free_space(&mut self, target_size: u64, strategy: CleanupStrategy) -> Result<CleanupPlan, Box<dyn Error>> {
+        block_on(self.inner.free_space(target_size, strategy))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncPop3Connection<S> {
+
+    /// Returns the messages to delete in order to free the given number of octets.
+    ///
+    /// See [`Pop3Connection::plan_free_space`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `target_size` - number of octets to free
+    /// * `strategy`    - strategy to choose the messages
+    pub async fn plan_free_space(&mut self, target_size: u64, strategy: CleanupStrategy) -> Result<CleanupPlan, Pop3AsyncError> {
+        let mut messages = self.list_meta().await?;
+        let maildrop_size = messages.iter().map(|message| message.message_size as u64).sum();
+
+        match strategy {
+            CleanupStrategy::LargestFirst => {
+                messages.sort_by_key(|message| std::cmp::Reverse(message.message_size));
+            },
+            CleanupStrategy::OldestFirst => {
+                let mut dated = vec!();
+                for message in messages {
+                    let date = self.headers(message.message_id).await?.timestamp();
+                    dated.push((date, message));
+                }
+                dated.sort_by_key(|(date, message)| (date.is_none(), *date, message.message_id));
+                messages = dated.into_iter().map(|(_, message)| message).collect();
+            }
+        }
+
+        let mut plan = CleanupPlan { messages: vec!(), freed_size: 0, maildrop_size };
+        for message in messages {
+            if plan.reaches(target_size) {
+                break;
+            }
+            plan.freed_size += message.message_size as u64;
+            plan.messages.push(message);
+        }
+
+        Ok(plan)
+    }
+
+    /// Deletes messages in order to free the given number of octets and returns the plan.
+    ///
+    /// See [`Pop3Connection::free_space`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `target_size` - number of octets to free
+    /// * `strategy`    - strategy to choose the messages
+    pub async fn free_space(&mut self, target_size: u64, strategy: CleanupStrategy) -> Result<CleanupPlan, Pop3AsyncError> {
+        let plan = self.plan_free_space(target_size, strategy).await?;
+        for message in &plan.messages {
+            self.delete(message.message_id).await?;
+        }
+
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    const LIST: (&str, &str) = ("LIST", "+OK\r\n1 100\r\n2 300\r\n3 200\r\n.\r\n");
+    const UIDL: (&str, &str) = ("UIDL", "+OK\r\n1 uid1\r\n2 uid2\r\n3 uid3\r\n.\r\n");
+
+    #[test]
+    fn test_free_space_largest_first() {
+        let (mut connection, server) = test_util::connect(&[
+            LIST, UIDL,
+            ("DELE 2", "+OK\r\n"),
+            ("DELE 3", "+OK\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let plan = connection.free_space(400, CleanupStrategy::LargestFirst).unwrap();
+        connection.quit().unwrap();
+        server.join().unwrap();
+
+        assert_eq!(vec!(2, 3), plan.messages.iter().map(|message| message.message_id).collect::<Vec<_>>());
+        assert_eq!(500, plan.freed_size);
+        assert_eq!(600, plan.maildrop_size);
+    }
+
+    #[test]
+    fn test_plan_free_space_oldest_first() {
+        let (mut connection, server) = test_util::connect(&[
+            LIST, UIDL,
+            ("TOP 1 0", "+OK\r\nDate: Wed, 2 Jul 2003 10:00:00 +0000\r\n\r\n.\r\n"),
+            ("TOP 2 0", "+OK\r\nSubject: undated\r\n\r\n.\r\n"),
+            ("TOP 3 0", "+OK\r\nDate: Tue, 1 Jul 2003 10:00:00 +0000\r\n\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let plan = connection.plan_free_space(250, CleanupStrategy::OldestFirst).unwrap();
+        drop(connection);
+        server.join().unwrap();
+
+        assert_eq!(vec!(3, 1), plan.messages.iter().map(|message| message.message_id).collect::<Vec<_>>());
+        assert!(plan.reaches(250));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::{Pop3MessageMeta, Pop3Stat};
+
+/// Count of largest messages contained in a usage report.
+const LARGEST_MESSAGES_COUNT: usize = 10;
+
+/// Upper bounds (exclusive) of the size histogram buckets in bytes.
+const BUCKET_BOUNDS: [u32; 4] = [10 * 1024, 100 * 1024, 1024 * 1024, 10 * 1024 * 1024];
+
+/// Bucket of the maildrop size histogram.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pop3SizeBucket {
+    /// lower bound (inclusive) of message sizes in this bucket in bytes
+    pub min_size: u32,
+
+    /// upper bound (exclusive) of message sizes in this bucket in bytes; `None` if unbounded
+    pub max_size: Option<u32>,
+
+    /// count of messages in this bucket
+    pub message_count: u32,
+
+    /// accumulated size of the messages in this bucket in bytes
+    pub total_size: u64,
+}
+
+/// POP3 maildrop usage report
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pop3UsageReport {
+    /// count of messages in the maildrop
+    pub message_count: u32,
+
+    /// size of the maildrop in bytes as reported by STAT
+    pub maildrop_size: u32,
+
+    /// accumulated size of all listed messages in bytes
+    pub total_size: u64,
+
+    /// largest messages, sorted by size in descending order
+    pub largest_messages: Vec<Pop3MessageMeta>,
+
+    /// histogram of message sizes
+    pub size_histogram: Vec<Pop3SizeBucket>,
+}
+
+impl Pop3UsageReport {
+
+    pub(crate) fn new(stat: &Pop3Stat, messages: &[Pop3MessageMeta]) -> Self {
+        let mut size_histogram = vec!();
+        let mut min_size = 0;
+        for max_size in BUCKET_BOUNDS.iter().map(|&bound| Some(bound)).chain(std::iter::once(None)) {
+            size_histogram.push(Pop3SizeBucket { min_size, max_size, message_count: 0, total_size: 0 });
+            min_size = max_size.unwrap_or(0);
+        }
+
+        let mut total_size = 0;
+        for message in messages {
+            total_size += message.message_size as u64;
+            let index = BUCKET_BOUNDS.iter()
+                .position(|&bound| message.message_size < bound)
+                .unwrap_or(BUCKET_BOUNDS.len());
+            let bucket = &mut size_histogram[index];
+            bucket.message_count += 1;
+            bucket.total_size += message.message_size as u64;
+        }
+
+        let mut largest_messages = messages.to_vec();
+        largest_messages.sort_by_key(|message| std::cmp::Reverse(message.message_size));
+        largest_messages.truncate(LARGEST_MESSAGES_COUNT);
+
+        Pop3UsageReport {
+            message_count: stat.message_count,
+            maildrop_size: stat.maildrop_size,
+            total_size,
+            largest_messages,
+            size_histogram,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(message_id: u32, message_size: u32) -> Pop3MessageMeta {
+        Pop3MessageMeta { message_id, message_size, unique_id: Some(format!("uid{}", message_id)) }
+    }
+
+    #[test]
+    fn test_empty_maildrop() {
+        let report = Pop3UsageReport::new(&Pop3Stat { message_count: 0, maildrop_size: 0 }, &[]);
+        assert_eq!(0, report.total_size);
+        assert!(report.largest_messages.is_empty());
+        assert_eq!(5, report.size_histogram.len());
+        assert!(report.size_histogram.iter().all(|bucket| bucket.message_count == 0));
+    }
+
+    #[test]
+    fn test_histogram() {
+        let messages = [meta(1, 100), meta(2, 20 * 1024), meta(3, 50 * 1024), meta(4, 20 * 1024 * 1024)];
+        let report = Pop3UsageReport::new(&Pop3Stat { message_count: 4, maildrop_size: 0 }, &messages);
+
+        let counts: Vec<u32> = report.size_histogram.iter().map(|bucket| bucket.message_count).collect();
+        assert_eq!(vec!(1, 2, 0, 0, 1), counts);
+        assert_eq!(70 * 1024, report.size_histogram[1].total_size);
+        assert_eq!(10 * 1024 * 1024, report.size_histogram[4].min_size);
+        assert_eq!(None, report.size_histogram[4].max_size);
+        assert_eq!(100 + 70 * 1024 + 20 * 1024 * 1024, report.total_size);
+    }
+
+    #[test]
+    fn test_largest_messages() {
+        let messages: Vec<Pop3MessageMeta> = (1..=20).map(|id| meta(id, id * 10)).collect();
+        let report = Pop3UsageReport::new(&Pop3Stat { message_count: 20, maildrop_size: 2100 }, &messages);
+
+        assert_eq!(LARGEST_MESSAGES_COUNT, report.largest_messages.len());
+        assert_eq!(20, report.largest_messages[0].message_id);
+        assert_eq!(11, report.largest_messages[9].message_id);
+    }
+}
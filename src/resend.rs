@@ -0,0 +1,150 @@
+use std::error::Error;
+
+use lettre::Address;
+use lettre::address::Envelope;
+
+use crate::{Mailbox, Pop3Connection, Pop3Headers};
+
+/// Retrieved message prepared to be sent again using lettre.
+///
+/// The content is kept as is, so it can be passed to the `send_raw` method
+/// of a lettre transport along with the envelope. Note that the content
+/// still contains a Bcc header, if the server kept it.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::Pop3Connection;
+///
+/// let mut connection = Pop3Connection::new("pop.example.com", 995).unwrap();
+/// connection.login("user@example.com", "secret").unwrap();
+///
+/// let message = connection.retrieve_resend(1).unwrap()
+///     .with_recipients(vec!("archive@example.com".parse().unwrap()));
+/// let envelope = message.envelope().unwrap();
+/// // transport.send_raw(&envelope, &message.content)
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pop3ResendMessage {
+    /// sender of the envelope; `None` for a null sender or if no valid address was found
+    pub sender: Option<Address>,
+
+    /// recipients of the envelope; may be empty, e.g. for Bcc-only messages
+    pub recipients: Vec<Address>,
+
+    /// exact octets of the message
+    pub content: Vec<u8>,
+}
+
+impl Pop3ResendMessage {
+
+    /// Takes sender and recipients of a message from its headers.
+    ///
+    /// The sender is taken from the Return-Path header, falling back to the
+    /// Sender and From headers; a null Return-Path (`<>`) results in no
+    /// sender. The recipients are taken from the To, Cc and Bcc headers.
+    /// Headers are parsed leniently: malformed addresses and groups without
+    /// members, e.g. `undisclosed-recipients:;`, are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `content` - exact octets of the message
+    pub fn parse(content: Vec<u8>) -> Self {
+        let headers = Pop3Headers::parse(&content);
+        let recipients = ["To", "Cc", "Bcc"].iter()
+            .flat_map(|name| headers.mailboxes(name))
+            .filter_map(|mailbox| mailbox.address.parse::<Address>().ok())
+            .collect();
+
+        Pop3ResendMessage { sender: sender(&headers), recipients, content }
+    }
+
+    /// Replaces the recipients, e.g. to forward the message unchanged to
+    /// another mailbox. The headers are not changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `recipients` - new recipients of the message
+    pub fn with_recipients(self, recipients: Vec<Address>) -> Self {
+        Pop3ResendMessage { recipients, ..self }
+    }
+
+    /// Returns the envelope to send the message with.
+    ///
+    /// Fails, if there are no recipients.
+    pub fn envelope(&self) -> Result<Envelope, Box<dyn Error>> {
+        Ok(Envelope::new(self.sender.clone(), self.recipients.clone())?)
+    }
+}
+
+/// Returns the envelope sender of a message.
+fn sender(headers: &Pop3Headers) -> Option<Address> {
+    if let Some(return_path) = headers.get("Return-Path") {
+        if return_path.trim() == "<>" {
+            return None;
+        }
+    }
+
+    ["Return-Path", "Sender", "From"].iter()
+        .flat_map(|name| headers.mailboxes(name).into_iter().next())
+        .find_map(|Mailbox { address, .. }| address.parse().ok())
+}
+
+impl Pop3Connection {
+
+    /// Retrieves a message and prepares it to be sent again using lettre.
+    ///
+    /// See [`Pop3ResendMessage::parse`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    pub fn retrieve_resend(&mut self, message_id: u32) -> Result<Pop3ResendMessage, Box<dyn Error>> {
+        let mut content = vec!();
+        self.retrieve_raw(message_id, &mut content)?;
+        Ok(Pop3ResendMessage::parse(content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let content = b"Return-Path: <bounces@example.com>\r\nFrom: Alice <alice@example.com>\r\n\
+To: Bob <bob@example.org>, carol@example.org\r\nCc: dave@example.net\r\n\r\nhi\r\n".to_vec();
+        let message = Pop3ResendMessage::parse(content.clone());
+
+        let envelope = message.envelope().unwrap();
+        assert_eq!(Some(&"bounces@example.com".parse().unwrap()), envelope.from());
+        assert_eq!(3, envelope.to().len());
+        assert_eq!(content, message.content);
+
+        let message = message.with_recipients(vec!("archive@example.com".parse().unwrap()));
+        assert_eq!(["archive@example.com".parse::<Address>().unwrap()], message.envelope().unwrap().to());
+    }
+
+    #[test]
+    fn test_parse_null_sender() {
+        let message = Pop3ResendMessage::parse(b"Return-Path: <>\r\nFrom: mailer-daemon@example.com\r\nTo: bob@example.org\r\n\r\n".to_vec());
+        assert_eq!(None, message.sender);
+        assert_eq!(None, message.envelope().unwrap().from());
+    }
+
+    #[test]
+    fn test_parse_lenient() {
+        let message = Pop3ResendMessage::parse(b"From: alice@example.com\r\nTo: undisclosed-recipients:;\r\n\r\n".to_vec());
+        assert_eq!(Some("alice@example.com".parse().unwrap()), message.sender);
+        assert!(message.recipients.is_empty());
+        assert!(message.envelope().is_err());
+
+        let message = Pop3ResendMessage::parse(b"From: not an address\r\nSender: bob@example.org\r\nTo: broken@, carol@example.org\r\n\r\n".to_vec());
+        assert_eq!(Some("bob@example.org".parse().unwrap()), message.sender);
+        assert_eq!(vec!("carol@example.org".parse::<Address>().unwrap()), message.recipients);
+
+        let message = Pop3ResendMessage::parse(b"From: alice@example.com\r\n\r\n".to_vec())
+            .with_recipients(vec!("archive@example.com".parse().unwrap()));
+        assert_eq!(1, message.envelope().unwrap().to().len());
+    }
+}
@@ -0,0 +1,211 @@
+//! Parsing of POP3 responses, shared by the blocking and the async connection.
+//!
+//! The parsers are pure functions without I/O, so they can be fuzzed and
+//! reused, e.g. by a custom transport based on [`crate::Pop3Engine`].
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::num::ParseIntError;
+
+use crate::{Pop3AsyncError, Pop3MessageInfo, Pop3MessageMeta, Pop3MessageUidInfo, Pop3NegativeResponse, Pop3Stat};
+
+/// Malformed response; negative responses are reported as [`Pop3NegativeResponse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError(String);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for ParseError { }
+
+impl From<&str> for ParseError {
+    fn from(message: &str) -> Self {
+        ParseError(message.to_string())
+    }
+}
+
+impl From<ParseIntError> for ParseError {
+    fn from(err: ParseIntError) -> Self {
+        ParseError(err.to_string())
+    }
+}
+
+/// Status line of a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatusLine<'a> {
+    /// positive status (`+OK`) with the text following the status indicator
+    Positive(&'a str),
+
+    /// negative status (`-ERR`) with the text following the status indicator
+    Negative(&'a str),
+}
+
+/// Parses a status line, e.g. `+OK 2 320` or `-ERR no such message`.
+///
+/// # Arguments
+///
+/// * `line` - status line with or without line terminator
+pub fn parse_status_line(line: &str) -> Result<StatusLine<'_>, ParseError> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    if let Some(text) = line.strip_prefix("+OK") {
+        Ok(StatusLine::Positive(text.trim_start()))
+    } else if let Some(text) = line.strip_prefix("-ERR") {
+        Ok(StatusLine::Negative(text.trim_start()))
+    } else {
+        Err(ParseError(format!("invalid status line: {}", line)))
+    }
+}
+
+/// Returns the status line, if it is positive (`+OK`).
+///
+/// Fails with [`Pop3NegativeResponse`] on negative responses and with
+/// [`ParseError`] on malformed status lines.
+pub(crate) fn check_status(line: String) -> Result<String, Pop3AsyncError> {
+    if line.starts_with("+OK") {
+        Ok(line)
+    } else if line.starts_with("-ERR") {
+        Err(Box::new(Pop3NegativeResponse::new(line)))
+    } else {
+        Err(Box::new(ParseError(line)))
+    }
+}
+
+/// Returns the content of a line of a multi-line response without byte-stuffing;
+/// returns `None` for the terminating line.
+///
+/// # Arguments
+///
+/// * `line` - line including its line terminator
+pub fn unstuff_line(line: &[u8]) -> Option<&[u8]> {
+    match line {
+        b".\r\n" | b".\n" => None,
+        [b'.', rest @ ..] => Some(rest),
+        _ => Some(line)
+    }
+}
+
+/// Parses the status line of STAT, e.g. `+OK 2 320`.
+pub fn parse_stat(line: &str) -> Result<Pop3Stat, ParseError> {
+    let mut stat = line.split(' ');
+    let _ = stat.next();
+    let message_count = stat.next().ok_or("missing message count")?;
+    let message_count = message_count.parse::<u32>()?;
+    let maildrop_size = stat.next().ok_or("missing maildrop size")?;
+    let maildrop_size = maildrop_size.parse::<u32>()?;
+
+    Ok(Pop3Stat { message_count, maildrop_size })
+}
+
+/// Parses a line of the LIST response, e.g. `1 120`.
+pub fn parse_list_line(line: &str) -> Result<Pop3MessageInfo, ParseError> {
+    let mut info = line.split(' ');
+    let message_id = info.next().ok_or("missing id")?.parse::<u32>()?;
+    let message_size = info.next().ok_or("missing size")?.parse::<u32>()?;
+
+    Ok(Pop3MessageInfo { message_id, message_size })
+}
+
+/// Parses a line of the UIDL response, e.g. `1 whqtswO00WBw418f9t5JxYwZ`.
+pub fn parse_uidl_line(line: &str) -> Result<Pop3MessageUidInfo, ParseError> {
+    let mut info = line.split(' ');
+    let message_id = info.next().ok_or("missing id")?.parse::<u32>()?;
+    let unique_id = info.next().ok_or("missing unique id")?.to_string();
+
+    Ok(Pop3MessageUidInfo { message_id, unique_id })
+}
+
+/// Parses the status line of LIST for a single message, e.g. `+OK 1 120`, and returns the size.
+pub(crate) fn parse_message_size(line: &str) -> Result<u32, ParseError> {
+    let mut info = line.split(' ');
+    let _ = info.next();    // skip "+OK"
+    let _ = info.next();    // skip message id
+    let message_size = info.next().ok_or("missing size")?.parse::<u32>()?;
+
+    Ok(message_size)
+}
+
+/// Parses the status line of UIDL for a single message, e.g. `+OK 1 whqtswO00WBw418f9t5JxYwZ`,
+/// and returns the unique id.
+pub(crate) fn parse_unique_id(line: &str) -> Result<String, ParseError> {
+    let mut info = line.split(' ');
+    let _ = info.next(); // skip "+OK"
+    let _ = info.next(); // skip message id
+    let unique_id = info.next().ok_or("missing unique id")?.to_string();
+
+    Ok(unique_id)
+}
+
+/// Combines the results of LIST and UIDL.
+pub(crate) fn merge_meta(infos: Vec<Pop3MessageInfo>, unique_ids: Vec<Pop3MessageUidInfo>) -> Vec<Pop3MessageMeta> {
+    let mut unique_ids: HashMap<u32, String> = unique_ids.into_iter()
+        .map(|uid_info| (uid_info.message_id, uid_info.unique_id))
+        .collect();
+
+    infos.into_iter().map(|info| {
+        let unique_id = unique_ids.remove(&info.message_id);
+
+        Pop3MessageMeta {
+            message_id: info.message_id,
+            message_size: info.message_size,
+            unique_id
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_responses() {
+        let stat = parse_stat("+OK 2 320").unwrap();
+        assert_eq!((2, 320), (stat.message_count, stat.maildrop_size));
+        assert_eq!(120, parse_list_line("1 120").unwrap().message_size);
+        assert_eq!("abc", parse_uidl_line("1 abc").unwrap().unique_id);
+        assert_eq!(120, parse_message_size("+OK 1 120").unwrap());
+        assert_eq!("abc", parse_unique_id("+OK 1 abc").unwrap());
+        assert!(parse_list_line("1").is_err());
+        assert!(check_status("-ERR no such message".into()).unwrap_err().is::<Pop3NegativeResponse>());
+        assert!(check_status("* no status".into()).unwrap_err().is::<ParseError>());
+    }
+
+    #[test]
+    fn test_parse_status_line() {
+        assert_eq!(Ok(StatusLine::Positive("2 320")), parse_status_line("+OK 2 320\r\n"));
+        assert_eq!(Ok(StatusLine::Positive("")), parse_status_line("+OK"));
+        assert_eq!(Ok(StatusLine::Negative("no such message")), parse_status_line("-ERR no such message"));
+        assert!(parse_status_line("* BYE").is_err());
+        assert!(parse_status_line("").is_err());
+    }
+
+    #[test]
+    fn test_unstuff_line() {
+        assert_eq!(Some(&b".dot\r\n"[..]), unstuff_line(b"..dot\r\n"));
+        assert_eq!(Some(&b"text\r\n"[..]), unstuff_line(b"text\r\n"));
+        assert_eq!(None, unstuff_line(b".\r\n"));
+        assert_eq!(Some(&b""[..]), unstuff_line(b"."));
+    }
+
+    #[test]
+    fn test_malformed_input_does_not_panic() {
+        for line in ["", " ", "+OK", "+OK  ", "1", "1 ", "x y", "4294967296 1", "-1 2", "+OK 1 2 3"] {
+            let _ = parse_stat(line);
+            let _ = parse_list_line(line);
+            let _ = parse_uidl_line(line);
+            let _ = parse_status_line(line);
+        }
+    }
+
+    #[test]
+    fn test_merge_meta() {
+        let infos = vec!(Pop3MessageInfo { message_id: 1, message_size: 10 }, Pop3MessageInfo { message_id: 2, message_size: 20 });
+        let unique_ids = vec!(Pop3MessageUidInfo { message_id: 2, unique_id: "b".into() });
+        let meta = merge_meta(infos, unique_ids);
+        assert_eq!(None, meta[0].unique_id);
+        assert_eq!(Some("b".into()), meta[1].unique_id);
+    }
+}
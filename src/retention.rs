@@ -0,0 +1,133 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::time::{Duration, SystemTime};
+
+use crate::{Pop3Connection, Pop3MessageMeta, SyncStateStore, UidState};
+
+/// Leave-on-server policy, which deletes messages after a given age.
+///
+/// The age of a message is measured from the time it was first seen on the
+/// server, as recorded in the state store. Only messages which were already
+/// fetched are deleted. Deletions are committed when the session ends.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{JsonFileStateStore, Pop3Connection, RetentionPolicy};
+///
+/// let mut connection = Pop3Connection::new("pop.example.com", 995).unwrap();
+/// connection.login("user@example.com", "secret").unwrap();
+///
+/// let mut state = JsonFileStateStore::new("state.json");
+/// connection.fetch_new_messages(&mut state, |_, _| Ok(())).unwrap();
+/// RetentionPolicy::days(14).apply(&mut connection, &mut state).unwrap();
+/// connection.quit().unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    max_age: Duration,
+}
+
+impl RetentionPolicy {
+
+    /// Returns a policy deleting messages older than the given age.
+    pub fn new(max_age: Duration) -> Self {
+        RetentionPolicy { max_age }
+    }
+
+    /// Returns a policy deleting messages older than the given count of days.
+    pub fn days(days: u64) -> Self {
+        RetentionPolicy::new(Duration::from_secs(days * 24 * 60 * 60))
+    }
+
+    /// Returns the maximum age of messages.
+    pub fn max_age(&self) -> Duration {
+        self.max_age
+    }
+
+    /// Records first-seen times, marks expired messages as deleted and returns them.
+    ///
+    /// The state of messages, which are no longer on the server, is removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - connection to the maildrop
+    /// * `state`      - store of the synchronization state
+    pub fn apply(&self, connection: &mut Pop3Connection, state: &mut dyn SyncStateStore) -> Result<Vec<Pop3MessageMeta>, Box<dyn Error>> {
+        self.apply_at(connection, state, SystemTime::now())
+    }
+
+    fn apply_at(&self, connection: &mut Pop3Connection, state: &mut dyn SyncStateStore, now: SystemTime) -> Result<Vec<Pop3MessageMeta>, Box<dyn Error>> {
+        let messages = connection.list_meta()?;
+        let states = state.load()?;
+
+        let mut updates = vec!();
+        let mut expired = vec!();
+        for message in &messages {
+            let unique_id = message.unique_id.as_ref().ok_or("server does not support UIDL")?;
+            let mut uid_state = states.get(unique_id).cloned().unwrap_or_else(|| UidState::new(now));
+            let delete_after = uid_state.first_seen + self.max_age;
+
+            if uid_state.delete_after != Some(delete_after) {
+                uid_state.delete_after = Some(delete_after);
+                updates.push((unique_id.clone(), uid_state.clone()));
+            }
+
+            if uid_state.fetched_at.is_some() && delete_after <= now {
+                expired.push(message.clone());
+            }
+        }
+        state.save(&updates)?;
+
+        let on_server: HashSet<&String> = messages.iter().filter_map(|message| message.unique_id.as_ref()).collect();
+        let gone: Vec<String> = states.keys().filter(|uid| !on_server.contains(uid)).cloned().collect();
+        if !gone.is_empty() {
+            state.remove(&gone)?;
+        }
+
+        for message in &expired {
+            connection.delete(message.message_id)?;
+        }
+
+        Ok(expired)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryStateStore;
+    use crate::test_util;
+
+    #[test]
+    fn test_apply() {
+        let (mut connection, server) = test_util::connect(&[
+            ("LIST", "+OK\r\n1 10\r\n2 20\r\n3 30\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 old\r\n2 new\r\n3 unfetched\r\n.\r\n"),
+            ("DELE 1", "+OK\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let now = SystemTime::now();
+        let long_ago = now - Duration::from_secs(15 * 24 * 60 * 60);
+        let mut old = UidState::new(long_ago);
+        old.fetched_at = Some(long_ago);
+        let mut state = MemoryStateStore::new();
+        state.save(&[
+            ("old".into(), old),
+            ("unfetched".into(), UidState::new(long_ago)),
+            ("gone".into(), UidState::new(long_ago)),
+        ]).unwrap();
+
+        let expired = RetentionPolicy::days(14).apply_at(&mut connection, &mut state, now).unwrap();
+        connection.quit().unwrap();
+        server.join().unwrap();
+
+        assert_eq!(vec!(1), expired.iter().map(|message| message.message_id).collect::<Vec<_>>());
+        let states = state.load().unwrap();
+        assert_eq!(3, states.len());
+        assert_eq!(now, states["new"].first_seen);
+        assert_eq!(Some(now + Duration::from_secs(14 * 24 * 60 * 60)), states["new"].delete_after);
+        assert!(!states.contains_key("gone"));
+    }
+}
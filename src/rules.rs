@@ -0,0 +1,351 @@
+use std::error::Error;
+use std::time::{Duration, SystemTime};
+
+use crate::glob::glob_matches;
+use crate::{Pop3Connection, Pop3MessageMeta, Pop3MessageSummary};
+
+/// Condition of a rule, evaluated against metadata and headers of a message.
+///
+/// Text matchers check whether the header value contains the given text,
+/// ignoring case.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleMatcher {
+    /// Matches every message.
+    Always,
+
+    /// Matches the value of an arbitrary header.
+    Header { name: String, contains: String },
+
+    /// Matches the From header.
+    From(String),
+
+    /// Matches messages with the given sender address (ignoring case).
+    ///
+    /// A value starting with `@` matches all addresses of the domain, e.g. `@example.com`;
+    /// a value containing `*` or `?` is a glob pattern, e.g. `*@spammer.example`.
+    FromAddress(String),
+
+    /// Matches the To header.
+    To(String),
+
+    /// Matches the Subject header.
+    Subject(String),
+
+    /// Matches the List-Id header of mailing list messages.
+    ListId(String),
+
+    /// Matches messages marked as spam by a filter of the server.
+    ///
+    /// A message is marked as spam, if its X-Spam-Flag header is `YES` or
+    /// its X-Spam-Status header starts with `Yes`, as added by SpamAssassin
+    /// and compatible filters.
+    MarkedAsSpam,
+
+    /// Matches messages whose Authentication-Results header reports the given
+    /// result for the given method (ignoring case), e.g. `dmarc` and `fail`.
+    ///
+    /// Since senders can add this header too, it should only be relied upon,
+    /// if the server removes or replaces foreign Authentication-Results headers.
+    AuthenticationResult { method: String, result: String },
+
+    /// Matches messages whose Authentication-Results header reports a failed
+    /// SPF, DKIM or DMARC check. See [`RuleMatcher::AuthenticationResult`].
+    AuthenticationFailed,
+
+    /// Matches messages larger than the given size in octets.
+    LargerThan(u32),
+
+    /// Matches messages smaller than the given size in octets.
+    SmallerThan(u32),
+
+    /// Matches messages whose Date header is older than the given age.
+    ///
+    /// Messages without a valid Date header do not match.
+    OlderThan(Duration),
+
+    /// Matches, if all matchers match.
+    All(Vec<RuleMatcher>),
+
+    /// Matches, if any matcher matches.
+    Any(Vec<RuleMatcher>),
+
+    /// Matches, if the matcher does not match.
+    Not(Box<RuleMatcher>),
+}
+
+impl RuleMatcher {
+
+    /// Returns true, if the message matches.
+    pub fn matches(&self, summary: &Pop3MessageSummary) -> bool {
+        match self {
+            RuleMatcher::Always => true,
+            RuleMatcher::Header { name, contains } => header_contains(summary, name, contains),
+            RuleMatcher::From(text) => header_contains(summary, "From", text),
+            RuleMatcher::FromAddress(pattern) => summary.from_mailboxes().iter().any(|mailbox| address_matches(&mailbox.address, pattern)),
+            RuleMatcher::To(text) => header_contains(summary, "To", text),
+            RuleMatcher::Subject(text) => header_contains(summary, "Subject", text),
+            RuleMatcher::ListId(text) => header_contains(summary, "List-Id", text),
+            RuleMatcher::MarkedAsSpam => is_marked_as_spam(summary),
+            RuleMatcher::AuthenticationResult { method, result } => has_authentication_result(summary, |other_method, other_result| {
+                other_method.eq_ignore_ascii_case(method) && other_result.eq_ignore_ascii_case(result)
+            }),
+            RuleMatcher::AuthenticationFailed => has_authentication_result(summary, |method, result| {
+                ["spf", "dkim", "dmarc"].iter().any(|name| method.eq_ignore_ascii_case(name)) && result.eq_ignore_ascii_case("fail")
+            }),
+            RuleMatcher::LargerThan(size) => summary.meta.message_size > *size,
+            RuleMatcher::SmallerThan(size) => summary.meta.message_size < *size,
+            RuleMatcher::OlderThan(age) => summary.timestamp()
+                .and_then(|timestamp| SystemTime::now().duration_since(timestamp).ok())
+                .is_some_and(|elapsed| elapsed > *age),
+            RuleMatcher::All(matchers) => matchers.iter().all(|matcher| matcher.matches(summary)),
+            RuleMatcher::Any(matchers) => matchers.iter().any(|matcher| matcher.matches(summary)),
+            RuleMatcher::Not(matcher) => !matcher.matches(summary),
+        }
+    }
+}
+
+fn header_contains(summary: &Pop3MessageSummary, name: &str, text: &str) -> bool {
+    summary.headers.get_all(name)
+        .any(|value| value.to_lowercase().contains(&text.to_lowercase()))
+}
+
+fn is_marked_as_spam(summary: &Pop3MessageSummary) -> bool {
+    summary.headers.get_all("X-Spam-Flag").any(|value| value.trim().eq_ignore_ascii_case("yes"))
+        || summary.headers.get_all("X-Spam-Status").any(|value| value.trim().to_ascii_lowercase().starts_with("yes"))
+}
+
+fn has_authentication_result(summary: &Pop3MessageSummary, predicate: impl Fn(&str, &str) -> bool) -> bool {
+    summary.headers.get_all("Authentication-Results")
+        .flat_map(authentication_results)
+        .any(|(method, result)| predicate(method, result))
+}
+
+/// Returns the method/result pairs of an Authentication-Results header (RFC 8601),
+/// e.g. `("dkim", "pass")`. The authentication service id and properties are ignored.
+fn authentication_results(value: &str) -> Vec<(&str, &str)> {
+    value.split(';')
+        .skip(1)
+        .filter_map(|info| {
+            let token = info.trim().split(|c: char| c.is_whitespace() || c == '(').next()?;
+            let (method, result) = token.split_once('=')?;
+            let method = method.split('/').next().unwrap_or(method);
+            Some((method, result))
+        })
+        .collect()
+}
+
+fn address_matches(address: &str, pattern: &str) -> bool {
+    match pattern.strip_prefix('@') {
+        Some(domain) => address.rsplit_once('@').is_some_and(|(_, other)| other.eq_ignore_ascii_case(domain)),
+        None if pattern.contains(['*', '?']) => glob_matches(pattern, address),
+        None => address.eq_ignore_ascii_case(pattern)
+    }
+}
+
+/// Action taken for a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleAction {
+    /// Retrieves the message and passes it to the handler.
+    Fetch,
+
+    /// Leaves the message untouched.
+    Skip,
+
+    /// Marks the message as deleted without retrieving it.
+    Delete,
+
+    /// Retrieves the message and passes it to the handler along with the given target, e.g. a folder.
+    DeliverTo(String),
+}
+
+/// Ordered list of rules deciding what to do with each message.
+///
+/// The action of the first matching rule applies; if no rule matches, the
+/// default action applies. Rules are evaluated against the headers fetched
+/// by TOP 0, so messages can be triaged before their bodies are downloaded.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{Pop3Connection, RuleAction, RuleMatcher, RuleSet};
+///
+/// let rules = RuleSet::new()
+///     .rule(RuleMatcher::LargerThan(10 * 1024 * 1024), RuleAction::Skip)
+///     .rule(RuleMatcher::MarkedAsSpam, RuleAction::Delete)
+///     .rule(RuleMatcher::ListId("rust-users".into()), RuleAction::DeliverTo("lists".into()));
+///
+/// let mut connection = Pop3Connection::new("pop.example.com", 995).unwrap();
+/// connection.login("user@example.com", "secret").unwrap();
+/// rules.apply(&mut connection, |target, message, content| {
+///     println!("{:?}: message {} ({} octets)", target, message.message_id, content.len());
+///     Ok(())
+/// }).unwrap();
+/// connection.quit().unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct RuleSet {
+    rules: Vec<(RuleMatcher, RuleAction)>,
+    default_action: RuleAction,
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        RuleSet { rules: vec!(), default_action: RuleAction::Fetch }
+    }
+}
+
+impl RuleSet {
+
+    /// Returns an empty rule set, which fetches all messages.
+    pub fn new() -> Self {
+        RuleSet::default()
+    }
+
+    /// Appends a rule.
+    ///
+    /// # Arguments
+    ///
+    /// * `matcher` - condition of the rule
+    /// * `action`  - action taken for matching messages
+    pub fn rule(mut self, matcher: RuleMatcher, action: RuleAction) -> Self {
+        self.rules.push((matcher, action));
+        self
+    }
+
+    /// Sets the action taken for messages, which match no rule.
+    pub fn default_action(mut self, action: RuleAction) -> Self {
+        self.default_action = action;
+        self
+    }
+
+    /// Returns the action for the given message.
+    pub fn evaluate(&self, summary: &Pop3MessageSummary) -> &RuleAction {
+        self.rules.iter()
+            .find(|(matcher, _)| matcher.matches(summary))
+            .map(|(_, action)| action)
+            .unwrap_or(&self.default_action)
+    }
+
+    /// Evaluates the rules for each message of the maildrop and takes the actions.
+    ///
+    /// Messages to fetch are retrieved and passed to the handler; messages to
+    /// delete are marked as deleted, so they are removed when the session ends.
+    /// Returns the action taken for each message. The handler receives the
+    /// target of [`RuleAction::DeliverTo`], if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - connection to the server
+    /// * `handler`    - invoked with target, metadata and exact octets of each fetched message
+    pub fn apply(&self, connection: &mut Pop3Connection, mut handler: impl FnMut(Option<&str>, &Pop3MessageMeta, &[u8]) -> Result<(), Box<dyn Error>>) -> Result<Vec<(Pop3MessageMeta, RuleAction)>, Box<dyn Error>> {
+        let mut result = vec!();
+        for summary in connection.summaries()? {
+            let action = self.evaluate(&summary).clone();
+            match &action {
+                RuleAction::Fetch | RuleAction::DeliverTo(_) => {
+                    let target = match &action {
+                        RuleAction::DeliverTo(target) => Some(target.as_str()),
+                        _ => None
+                    };
+                    let mut content = vec!();
+                    connection.retrieve_raw(summary.meta.message_id, &mut content)?;
+                    handler(target, &summary.meta, &content)?;
+                },
+                RuleAction::Delete => connection.delete(summary.meta.message_id)?,
+                RuleAction::Skip => {}
+            }
+            result.push((summary.meta, action));
+        }
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    fn summary(size: u32, headers: &[(&str, &str)]) -> Pop3MessageSummary {
+        Pop3MessageSummary {
+            meta: Pop3MessageMeta { message_id: 1, message_size: size, unique_id: None },
+            headers: headers.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let rules = RuleSet::new()
+            .rule(RuleMatcher::LargerThan(1000), RuleAction::Skip)
+            .rule(RuleMatcher::All(vec!(
+                RuleMatcher::From("@spam.example".into()),
+                RuleMatcher::Not(Box::new(RuleMatcher::Subject("invoice".into()))),
+            )), RuleAction::Delete)
+            .rule(RuleMatcher::ListId("<rust.lists.example>".into()), RuleAction::DeliverTo("rust".into()));
+
+        assert_eq!(&RuleAction::Skip, rules.evaluate(&summary(2000, &[])));
+        assert_eq!(&RuleAction::Delete, rules.evaluate(&summary(10, &[("From", "x@SPAM.example")])));
+        assert_eq!(&RuleAction::Fetch, rules.evaluate(&summary(10, &[("From", "x@spam.example"), ("Subject", "Your Invoice")])));
+        assert_eq!(&RuleAction::DeliverTo("rust".into()), rules.evaluate(&summary(10, &[("list-id", "Rust <rust.lists.example>")])));
+    }
+
+    #[test]
+    fn test_from_address() {
+        let message = summary(10, &[("From", "\"boss@example.com\" <phisher@evil.example>")]);
+        assert!(!RuleMatcher::FromAddress("boss@example.com".into()).matches(&message));
+        assert!(RuleMatcher::FromAddress("Phisher@Evil.example".into()).matches(&message));
+        assert!(RuleMatcher::FromAddress("@evil.example".into()).matches(&message));
+        assert!(RuleMatcher::FromAddress("*@EVIL.example".into()).matches(&message));
+        assert!(!RuleMatcher::FromAddress("boss*".into()).matches(&message));
+    }
+
+    #[test]
+    fn test_older_than() {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let old = summary(10, &[("Date", "Mon, 1 Jan 2024 10:00:00 +0000")]);
+        assert!(RuleMatcher::OlderThan(day).matches(&old));
+        assert!(!RuleMatcher::OlderThan(day * 365 * 100).matches(&old));
+        assert!(!RuleMatcher::OlderThan(day).matches(&summary(10, &[])));
+    }
+
+    #[test]
+    fn test_spam_matchers() {
+        let spam = summary(10, &[("X-Spam-Status", "Yes, score=7.1 required=5.0")]);
+        let ham = summary(10, &[("X-Spam-Status", "No, score=0.3 required=5.0"), ("X-Spam-Flag", "NO")]);
+        assert!(RuleMatcher::MarkedAsSpam.matches(&spam));
+        assert!(RuleMatcher::MarkedAsSpam.matches(&summary(10, &[("X-Spam-Flag", " YES")])));
+        assert!(!RuleMatcher::MarkedAsSpam.matches(&ham));
+
+        let message = summary(10, &[("Authentication-Results", "mx.example.com; spf=pass smtp.mailfrom=example.com;\
+            dkim=fail (signature mismatch) header.d=example.com; dmarc=none")]);
+        assert!(RuleMatcher::AuthenticationFailed.matches(&message));
+        assert!(RuleMatcher::AuthenticationResult { method: "SPF".into(), result: "pass".into() }.matches(&message));
+        assert!(!RuleMatcher::AuthenticationResult { method: "dmarc".into(), result: "pass".into() }.matches(&message));
+        assert!(!RuleMatcher::AuthenticationFailed.matches(&summary(10, &[("Authentication-Results", "mx.example.com; none")])));
+    }
+
+    #[test]
+    fn test_apply() {
+        let (mut connection, server) = test_util::connect(&[
+            ("LIST", "+OK\r\n1 10\r\n2 20\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uid1\r\n2 uid2\r\n.\r\n"),
+            ("TOP 1 0", "+OK\r\nSubject: hello\r\n\r\n.\r\n"),
+            ("TOP 2 0", "+OK\r\nSubject: buy now\r\n\r\n.\r\n"),
+            ("RETR 1", "+OK\r\nSubject: hello\r\n\r\nbody\r\n.\r\n"),
+            ("DELE 2", "+OK\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let rules = RuleSet::new().rule(RuleMatcher::Subject("buy now".into()), RuleAction::Delete);
+        let mut fetched = vec!();
+        let actions = rules.apply(&mut connection, |target, message, _| {
+            fetched.push((target.map(String::from), message.message_id));
+            Ok(())
+        }).unwrap();
+        connection.quit().unwrap();
+        server.join().unwrap();
+
+        assert_eq!(vec!((None, 1)), fetched);
+        assert_eq!(vec!(RuleAction::Fetch, RuleAction::Delete), actions.into_iter().map(|(_, action)| action).collect::<Vec<_>>());
+    }
+}
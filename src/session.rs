@@ -0,0 +1,91 @@
+/// Event of the lifecycle of a POP3 session.
+///
+/// Commands are identified by their name only, e.g. `RETR`, so that
+/// passwords and tokens are never passed to observers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Pop3SessionEvent {
+    /// the connection to the server was established and its greeting received
+    Connected {
+        /// IP-Address or host name of the server
+        host: String,
+
+        /// port of the server
+        port: u16,
+    },
+
+    /// TLS was established, either implicitly or by STLS
+    TlsEstablished {
+        /// negotiated cipher suite, e.g. `TLS13_AES_256_GCM_SHA384`
+        cipher: String,
+    },
+
+    /// the user was authenticated
+    Authenticated {
+        /// mechanism of the authentication, either `USER` or `XOAUTH2`
+        mechanism: String,
+    },
+
+    /// a command was sent
+    CommandSent {
+        /// name of the command
+        command: String,
+    },
+
+    /// the response to a command was received completely
+    ResponseReceived {
+        /// name of the command
+        command: String,
+
+        /// true, if the server responded positive
+        success: bool,
+    },
+
+    /// the session ended
+    Disconnected {
+        /// reason, e.g. `QUIT` or `connection closed by server`
+        reason: String,
+    },
+}
+
+/// Observer of the events of a session, e.g. to update a UI or a monitor.
+///
+/// Closures taking a [`Pop3SessionEvent`] are observers.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{Pop3ConnectionBuilder, Pop3SessionEvent};
+///
+/// let connection = Pop3ConnectionBuilder::new("pop.example.com")
+///     .observer(|event: &Pop3SessionEvent| println!("{:?}", event))
+///     .login("user@example.com", "secret")
+///     .connect();
+/// ```
+pub trait Pop3SessionObserver: Send + Sync {
+
+    /// Receives an event of the session.
+    fn on_event(&self, event: &Pop3SessionEvent);
+}
+
+impl<F: Fn(&Pop3SessionEvent) + Send + Sync> Pop3SessionObserver for F {
+    fn on_event(&self, event: &Pop3SessionEvent) {
+        self(event)
+    }
+}
+
+/// Stream, which can report its TLS parameters.
+pub(crate) trait TlsInfo {
+
+    /// Returns the negotiated cipher suite; `None`, if TLS is not used.
+    fn cipher_suite(&self) -> Option<String>;
+}
+
+/// Returns the name of a command without its arguments, e.g. `RETR`.
+pub(crate) fn command_name(command: &str) -> String {
+    command.split_whitespace().next().unwrap_or_default().to_ascii_uppercase()
+}
+
+/// Returns the name of a negotiated cipher suite.
+pub(crate) fn cipher_suite_name(suite: rustls::SupportedCipherSuite) -> String {
+    format!("{:?}", suite.suite())
+}
@@ -0,0 +1,62 @@
+use std::error::Error;
+
+use crate::Pop3MessageMeta;
+
+/// Target of retrieved messages, e.g. a Maildir or an mbox file.
+pub trait MessageSink {
+    /// Delivers a retrieved message.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - metadata of the message
+    /// * `content` - exact octets of the message as retrieved by RETR, including CRLF line endings
+    fn deliver(&mut self, message: &Pop3MessageMeta, content: &[u8]) -> Result<(), Box<dyn Error>>;
+}
+
+/// Returns the content with CRLF line endings replaced by LF.
+pub(crate) fn to_lf(content: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(content.len());
+    let mut iter = content.iter().peekable();
+    while let Some(&byte) = iter.next() {
+        if byte == b'\r' && iter.peek() == Some(&&b'\n') {
+            continue;
+        }
+        result.push(byte);
+    }
+
+    result
+}
+
+/// Splits a message into headers and body at the first empty line.
+///
+/// The headers include the line ending of the last header line; the empty
+/// line itself is part of neither. Without empty line, the whole message
+/// is considered as headers.
+pub(crate) fn split_message(content: &[u8]) -> (&[u8], &[u8]) {
+    let mut pos = 0;
+    for line in content.split_inclusive(|&byte| byte == b'\n') {
+        if line == b"\r\n" || line == b"\n" {
+            return (&content[..pos], &content[pos + line.len()..]);
+        }
+        pos += line.len();
+    }
+
+    (content, &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_lf() {
+        assert_eq!(b"a\nb\n\rc\n".to_vec(), to_lf(b"a\r\nb\n\rc\r\n"));
+    }
+
+    #[test]
+    fn test_split_message() {
+        assert_eq!((&b"A: 1\r\nB: 2\r\n"[..], &b"body\r\n\r\nmore"[..]), split_message(b"A: 1\r\nB: 2\r\n\r\nbody\r\n\r\nmore"));
+        assert_eq!((&b""[..], &b"body\n"[..]), split_message(b"\nbody\n"));
+        assert_eq!((&b"A: 1\n"[..], &b""[..]), split_message(b"A: 1\n"));
+    }
+}
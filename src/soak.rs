@@ -0,0 +1,375 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::is_transient;
+use crate::test_util::{MaildropServer, Random};
+use crate::{OperationClass, Pop3AsyncError, Pop3SessionEvent, RetryPolicy, RetryingPop3Connection, TokioPop3Connection};
+
+/// Retries of an operation; enough to wait for the end of a stalled session.
+const MAX_RETRIES: u32 = 20;
+
+/// Report of a [`SoakTest`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SoakReport {
+    /// count of completed operations
+    pub operations: u64,
+
+    /// count of operations, which failed transiently after all retries
+    pub failures: u64,
+
+    /// count of reconnects after transient failures
+    pub reconnects: u32,
+
+    /// count of established sessions
+    pub sessions: u64,
+
+    /// count of NOOP commands sent by keep-alive
+    pub keep_alives: u64,
+
+    /// duration of the test
+    pub elapsed: Duration,
+}
+
+/// Operation of a [`SoakTest`].
+#[derive(Debug)]
+enum Operation {
+    Stat,
+    List,
+    UniqueIds,
+    Retrieve(u32),
+    Top(u32, u32),
+    Noop,
+    Idle,
+}
+
+/// Events of all sessions of a [`SoakTest`].
+#[derive(Default)]
+struct Counters {
+    sessions: AtomicU64,
+    noops: AtomicU64,
+}
+
+/// Harness keeping a session alive for a long time, e.g. hours, to find leaks and desynchronization.
+///
+/// The harness starts a [`MaildropServer`], which injects faults: it closes
+/// connections in the middle of responses and stalls, so that operations
+/// time out. A [`RetryingPop3Connection`](crate::RetryingPop3Connection)
+/// runs a random mix of commands against the server, idles to trigger
+/// keep-alive and verifies each response against the known maildrop.
+///
+/// The test fails,
+///
+/// * if a response does not match the maildrop, e.g. since the client
+///   read the response of another command,
+/// * if an operation fails permanently or
+/// * if sessions are not closed at the end, e.g. since the client leaked
+///   a connection.
+///
+/// Transient failures, which persist after all retries, are only counted.
+/// Requires the `tokio` feature and a tokio runtime with timers enabled.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use rust_pop3_client::test_util::SoakTest;
+///
+/// # async fn run() -> Result<(), rust_pop3_client::Pop3AsyncError> {
+/// let report = SoakTest::new()
+///     .duration(Duration::from_secs(4 * 60 * 60))
+///     .seed(42)
+///     .run()
+///     .await?;
+/// println!("{} operations, {} reconnects", report.operations, report.reconnects);
+/// # Ok(())
+/// # }
+/// ```
+pub struct SoakTest {
+    duration: Duration,
+    seed: u64,
+    messages: usize,
+    keep_alive: Duration,
+    timeout: Duration,
+    disconnects: f64,
+    stalls: f64,
+}
+
+impl SoakTest {
+
+    /// Returns a soak test running for a minute.
+    ///
+    /// By default, the maildrop contains 16 messages, keep-alive is sent
+    /// after 1 second, operations time out after 2 seconds and 1 percent of
+    /// the responses is disturbed by a disconnect and 0.1 percent by a stall.
+    pub fn new() -> Self {
+        SoakTest {
+            duration: Duration::from_secs(60),
+            seed: 0,
+            messages: 16,
+            keep_alive: Duration::from_secs(1),
+            timeout: Duration::from_secs(2),
+            disconnects: 0.01,
+            stalls: 0.001,
+        }
+    }
+
+    /// Sets the duration of the test.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Sets the seed of the pseudo random generators choosing messages, operations and faults.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Sets the count of messages in the maildrop; at least 1.
+    pub fn messages(mut self, count: usize) -> Self {
+        self.messages = count.max(1);
+        self
+    }
+
+    /// Sets the keep-alive interval of the connections.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = interval;
+        self
+    }
+
+    /// Sets the timeout of operations; stalls of the server last twice as long.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the probabilities of faults per response.
+    ///
+    /// # Arguments
+    ///
+    /// * `disconnects` - probability of closing the connection within a response
+    /// * `stalls`      - probability of a response, which is not sent
+    pub fn faults(mut self, disconnects: f64, stalls: f64) -> Self {
+        self.disconnects = disconnects;
+        self.stalls = stalls;
+        self
+    }
+
+    /// Runs the test and returns its report.
+    pub async fn run(self) -> Result<SoakReport, Pop3AsyncError> {
+        let mut random = Random(self.seed);
+        let messages: Vec<Vec<u8>> = (0..self.messages).map(|index| message(index, &mut random)).collect();
+
+        let mut server = MaildropServer::builder()
+            .disconnects(self.disconnects)
+            .stalls(self.stalls, self.timeout * 2)
+            .seed(self.seed);
+        for (index, content) in messages.iter().enumerate() {
+            server = server.message(&unique_id(index), content);
+        }
+        let server = server.start().map_err(|err| err.to_string())?;
+
+        let counters = Arc::new(Counters::default());
+        let observer = {
+            let counters = counters.clone();
+            move |event: &Pop3SessionEvent| match event {
+                Pop3SessionEvent::Connected { .. } => { counters.sessions.fetch_add(1, Ordering::SeqCst); },
+                Pop3SessionEvent::CommandSent { command } if command == "NOOP" => { counters.noops.fetch_add(1, Ordering::SeqCst); },
+                _ => { }
+            }
+        };
+        let builder = server.connection_builder()
+            .login("soak", "secret")
+            .keep_alive(self.keep_alive)
+            .observer(observer);
+        let policy = RetryPolicy::new()
+            .max_retries(MAX_RETRIES)
+            .backoff(Duration::from_millis(10), self.timeout * 4);
+
+        let started = Instant::now();
+        let mut connection = RetryingPop3Connection::connect(builder, policy).await?;
+        let mut operations = 0;
+        let mut failures = 0;
+        let mut explicit_noops = 0;
+        while started.elapsed() < self.duration {
+            let operation = operation(&mut random, messages.len());
+            match self.perform(&mut connection, &operation, &messages, &mut explicit_noops).await {
+                Ok(Ok(())) => operations += 1,
+                Ok(Err(mismatch)) => return Err(format!("desynchronized after {} operations: {:?}: {}", operations, operation, mismatch).into()),
+                Err(err) if is_transient(err.as_ref()) => failures += 1,
+                Err(err) => return Err(format!("{:?} failed after {} operations: {}", operation, operations, err).into())
+            }
+        }
+
+        let reconnects = connection.reconnects();
+        if let Err(err) = connection.quit().await {
+            if !is_transient(err.as_ref()) {
+                return Err(err);
+            }
+        }
+
+        // sessions of stalls end after the stall; other sessions end as soon as the client closes them
+        let deadline = Instant::now() + self.timeout * 2 + Duration::from_secs(1);
+        while server.active_sessions() > 0 {
+            if Instant::now() > deadline {
+                return Err(format!("{} sessions not closed", server.active_sessions()).into());
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        Ok(SoakReport {
+            operations,
+            failures,
+            reconnects,
+            sessions: counters.sessions.load(Ordering::SeqCst),
+            keep_alives: counters.noops.load(Ordering::SeqCst).saturating_sub(explicit_noops),
+            elapsed: started.elapsed(),
+        })
+    }
+
+    /// Performs an operation; returns `Ok(Err(..))`, if the response does not match the maildrop.
+    async fn perform(&self, connection: &mut RetryingPop3Connection, operation: &Operation, messages: &[Vec<u8>], explicit_noops: &mut u64) -> Result<Result<(), String>, Pop3AsyncError> {
+        let timeout = self.timeout;
+        let result = match *operation {
+            Operation::Stat => {
+                let stat = connection.call(OperationClass::Query, |c| Box::pin(timed(c, timeout).stat())).await?;
+                let size: usize = messages.iter().map(Vec::len).sum();
+                check((stat.message_count as usize, stat.maildrop_size as usize), (messages.len(), size))
+            },
+            Operation::List => {
+                let sizes: Vec<usize> = connection.call(OperationClass::Query, |c| Box::pin(timed(c, timeout).list())).await?
+                    .into_iter()
+                    .map(|info| info.message_size as usize)
+                    .collect();
+                check(sizes, messages.iter().map(Vec::len).collect())
+            },
+            Operation::UniqueIds => {
+                let unique_ids: Vec<String> = connection.call(OperationClass::Query, |c| Box::pin(timed(c, timeout).list_unique_ids())).await?
+                    .into_iter()
+                    .map(|info| info.unique_id)
+                    .collect();
+                check(unique_ids, (0..messages.len()).map(unique_id).collect())
+            },
+            Operation::Retrieve(message_id) => {
+                let content = connection.call_message(OperationClass::Retrieve, message_id, |c| Box::pin(async move {
+                    let mut content = vec!();
+                    timed(c, timeout).retrieve_raw(message_id, &mut content).await?;
+                    Ok(content)
+                })).await?;
+                check(content, messages[message_id as usize - 1].clone())
+            },
+            Operation::Top(message_id, line_count) => {
+                let content = connection.call_message(OperationClass::Query, message_id, |c| Box::pin(timed(c, timeout).top_raw(message_id, line_count))).await?;
+                check(content, top(&messages[message_id as usize - 1], line_count as usize).to_vec())
+            },
+            Operation::Noop => {
+                connection.call(OperationClass::Query, |c| {
+                    *explicit_noops += 1;
+                    Box::pin(timed(c, timeout).noop())
+                }).await?;
+                Ok(())
+            },
+            Operation::Idle => {
+                tokio::time::sleep(self.keep_alive + self.keep_alive / 2).await;
+                Ok(())
+            }
+        };
+
+        Ok(result)
+    }
+}
+
+impl Default for SoakTest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sets the timeout of a connection, which may be new after a reconnect.
+fn timed(connection: &mut TokioPop3Connection, timeout: Duration) -> &mut TokioPop3Connection {
+    connection.set_timeout(Some(timeout));
+    connection
+}
+
+fn check<T: PartialEq + std::fmt::Debug>(actual: T, expected: T) -> Result<(), String> {
+    match actual == expected {
+        true => Ok(()),
+        false => Err(format!("expected {:?}, got {:?}", expected, actual))
+    }
+}
+
+fn unique_id(index: usize) -> String {
+    format!("soak-{}", index + 1)
+}
+
+/// Returns a message of random length; some lines require byte-stuffing.
+fn message(index: usize, random: &mut Random) -> Vec<u8> {
+    let mut content = format!("Subject: soak message {}\r\nMessage-ID: <{}@soak.test>\r\n\r\n", index + 1, unique_id(index)).into_bytes();
+    for line in 0..random.up_to(64) {
+        let prefix = if random.chance(0.1) { "." } else { "" };
+        content.extend_from_slice(format!("{}line {} of message {}\r\n", prefix, line, index + 1).as_bytes());
+    }
+    content
+}
+
+/// Returns the header and the given count of body lines of a message.
+fn top(content: &[u8], line_count: usize) -> &[u8] {
+    let mut lines = content.split_inclusive(|&c| c == b'\n');
+    let mut length = 0;
+    for line in lines.by_ref() {
+        length += line.len();
+        if line == b"\r\n" {
+            break;
+        }
+    }
+    length += lines.take(line_count).map(<[u8]>::len).sum::<usize>();
+    &content[..length]
+}
+
+fn operation(random: &mut Random, message_count: usize) -> Operation {
+    let message_id = random.up_to(message_count) as u32;
+    match random.up_to(7) {
+        1 => Operation::Stat,
+        2 => Operation::List,
+        3 => Operation::UniqueIds,
+        4 => Operation::Retrieve(message_id),
+        5 => Operation::Top(message_id, random.up_to(8) as u32 - 1),
+        6 => Operation::Noop,
+        _ => Operation::Idle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn test_top() {
+        let content = b"Subject: a\r\n\r\nline 1\r\nline 2\r\n";
+        assert_eq!(b"Subject: a\r\n\r\n", top(content, 0));
+        assert_eq!(b"Subject: a\r\n\r\nline 1\r\n", top(content, 1));
+        assert_eq!(&content[..], top(content, 5));
+    }
+
+    #[test]
+    fn test_soak() {
+        let report = block_on(SoakTest::new()
+            .duration(Duration::from_secs(1))
+            .seed(42)
+            .messages(4)
+            .keep_alive(Duration::from_millis(20))
+            .timeout(Duration::from_millis(100))
+            .faults(0.05, 0.005)
+            .run()).unwrap();
+
+        assert!(report.operations > 0);
+        assert!(report.reconnects > 0);
+        assert!(report.sessions > 1);
+        assert!(report.keep_alives > 0);
+    }
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use rusqlite::{Connection, params};
+
+use crate::state::{SyncStateStore, UidState};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS uid_state (
+        account TEXT NOT NULL,
+        uid TEXT NOT NULL,
+        first_seen INTEGER NOT NULL,
+        fetched_at INTEGER,
+        delete_after INTEGER,
+        PRIMARY KEY (account, uid)
+    );
+    CREATE TABLE IF NOT EXISTS uid_metadata (
+        account TEXT NOT NULL,
+        uid TEXT NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (account, uid, key)
+    );
+";
+
+/// Synchronization state stored in an SQLite database.
+///
+/// A single database can hold the state of multiple accounts. Updates are
+/// performed in transactions, so the state survives crashes mid-sync.
+pub struct SqliteStateStore {
+    connection: Connection,
+    account: String,
+}
+
+impl SqliteStateStore {
+
+    /// Opens or creates the database at the given path.
+    ///
+    /// # Arguments
+    ///
+    /// * `path`    - path of the database file
+    /// * `account` - name of the account whose state is stored
+    pub fn open(path: impl AsRef<Path>, account: &str) -> Result<Self, Box<dyn Error>> {
+        Self::with_connection(Connection::open(path)?, account)
+    }
+
+    /// Returns a store using an already opened database.
+    ///
+    /// # Arguments
+    ///
+    /// * `connection` - connection to the database
+    /// * `account`    - name of the account whose state is stored
+    pub fn with_connection(connection: Connection, account: &str) -> Result<Self, Box<dyn Error>> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(SqliteStateStore { connection, account: account.to_string() })
+    }
+}
+
+impl SyncStateStore for SqliteStateStore {
+    fn load(&mut self) -> Result<HashMap<String, UidState>, Box<dyn Error>> {
+        let mut states = HashMap::new();
+
+        let mut statement = self.connection.prepare(
+            "SELECT uid, first_seen, fetched_at, delete_after FROM uid_state WHERE account = ?1")?;
+        let rows = statement.query_map(params![self.account], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?, row.get::<_, Option<i64>>(2)?, row.get::<_, Option<i64>>(3)?))
+        })?;
+        for row in rows {
+            let (uid, first_seen, fetched_at, delete_after) = row?;
+            let mut state = UidState::new(from_timestamp(first_seen));
+            state.fetched_at = fetched_at.map(from_timestamp);
+            state.delete_after = delete_after.map(from_timestamp);
+            states.insert(uid, state);
+        }
+
+        let mut statement = self.connection.prepare(
+            "SELECT uid, key, value FROM uid_metadata WHERE account = ?1")?;
+        let rows = statement.query_map(params![self.account], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })?;
+        for row in rows {
+            let (uid, key, value) = row?;
+            if let Some(state) = states.get_mut(&uid) {
+                state.metadata.insert(key, value);
+            }
+        }
+
+        Ok(states)
+    }
+
+    fn save(&mut self, states: &[(String, UidState)]) -> Result<(), Box<dyn Error>> {
+        let transaction = self.connection.transaction()?;
+        for (uid, state) in states {
+            transaction.execute(
+                "INSERT OR REPLACE INTO uid_state (account, uid, first_seen, fetched_at, delete_after) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![self.account, uid, to_timestamp(state.first_seen), state.fetched_at.map(to_timestamp), state.delete_after.map(to_timestamp)])?;
+            transaction.execute(
+                "DELETE FROM uid_metadata WHERE account = ?1 AND uid = ?2",
+                params![self.account, uid])?;
+            for (key, value) in &state.metadata {
+                transaction.execute(
+                    "INSERT INTO uid_metadata (account, uid, key, value) VALUES (?1, ?2, ?3, ?4)",
+                    params![self.account, uid, key, value])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn remove(&mut self, unique_ids: &[String]) -> Result<(), Box<dyn Error>> {
+        let transaction = self.connection.transaction()?;
+        for uid in unique_ids {
+            transaction.execute("DELETE FROM uid_state WHERE account = ?1 AND uid = ?2", params![self.account, uid])?;
+            transaction.execute("DELETE FROM uid_metadata WHERE account = ?1 AND uid = ?2", params![self.account, uid])?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+fn to_timestamp(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64
+}
+
+fn from_timestamp(timestamp: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(timestamp.max(0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut store = SqliteStateStore::with_connection(Connection::open_in_memory().unwrap(), "account").unwrap();
+        let mut state = UidState::new(time(100));
+        state.fetched_at = Some(time(200));
+        state.metadata.insert("sha256".into(), "abc".into());
+        store.save(&[("uid1".into(), state.clone()), ("uid2".into(), UidState::new(time(300)))]).unwrap();
+
+        let states = store.load().unwrap();
+        assert_eq!(2, states.len());
+        assert_eq!(Some(&state), states.get("uid1"));
+        assert_eq!(None, states["uid2"].fetched_at);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut store = SqliteStateStore::with_connection(Connection::open_in_memory().unwrap(), "account").unwrap();
+        store.save(&[("uid1".into(), UidState::new(time(100))), ("uid2".into(), UidState::new(time(100)))]).unwrap();
+        store.remove(&["uid1".into()]).unwrap();
+
+        let states = store.load().unwrap();
+        assert_eq!(vec!("uid2"), states.keys().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_accounts_are_separated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        SqliteStateStore::open(&path, "a").unwrap().save(&[("uid1".into(), UidState::new(time(1)))]).unwrap();
+
+        assert!(SqliteStateStore::open(&path, "b").unwrap().load().unwrap().is_empty());
+        assert_eq!(1, SqliteStateStore::open(&path, "a").unwrap().load().unwrap().len());
+    }
+}
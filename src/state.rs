@@ -0,0 +1,294 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::json::{self, Value};
+
+const FILE_FORMAT_VERSION: u64 = 1;
+
+/// Metadata key of messages, whose fetch was deferred.
+pub(crate) const DEFERRED_KEY: &str = "deferred";
+
+/// Metadata key of messages, which were skipped due to their content type.
+pub(crate) const SKIPPED_KEY: &str = "skipped";
+
+/// Synchronization state of a single message, identified by its unique id.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UidState {
+    /// time the message was seen on the server for the first time
+    pub first_seen: SystemTime,
+
+    /// time the message was fetched; `None` if not fetched yet
+    pub fetched_at: Option<SystemTime>,
+
+    /// time after which the message should be deleted from the server; `None` if not scheduled
+    pub delete_after: Option<SystemTime>,
+
+    /// additional metadata as key/value pairs
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl UidState {
+
+    /// Returns the state of a message seen at the given time.
+    pub fn new(first_seen: SystemTime) -> Self {
+        UidState { first_seen, fetched_at: None, delete_after: None, metadata: BTreeMap::new() }
+    }
+
+    /// Returns true, if fetching the message was deferred since it exceeds the size limit.
+    ///
+    /// See [`crate::SyncOptions::max_message_size`].
+    pub fn is_deferred(&self) -> bool {
+        self.metadata.contains_key(DEFERRED_KEY)
+    }
+
+    /// Returns true, if the message was skipped due to its content type.
+    ///
+    /// See [`crate::SyncOptions::content_type`].
+    pub fn is_skipped(&self) -> bool {
+        self.metadata.contains_key(SKIPPED_KEY)
+    }
+}
+
+/// Persistent store of the synchronization state of a maildrop.
+///
+/// Implementations must apply each call of [`SyncStateStore::save`] and
+/// [`SyncStateStore::remove`] atomically, so that the stored state stays
+/// consistent if the application crashes during synchronization.
+pub trait SyncStateStore {
+    /// Returns the state of all known messages, keyed by unique id.
+    fn load(&mut self) -> Result<HashMap<String, UidState>, Box<dyn Error>>;
+
+    /// Inserts or replaces the state of the given messages.
+    fn save(&mut self, states: &[(String, UidState)]) -> Result<(), Box<dyn Error>>;
+
+    /// Removes the state of the given messages.
+    fn remove(&mut self, unique_ids: &[String]) -> Result<(), Box<dyn Error>>;
+}
+
+/// Synchronization state kept in memory only.
+///
+/// Useful for tests and for applications which persist state otherwise.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStateStore {
+    states: HashMap<String, UidState>,
+}
+
+impl MemoryStateStore {
+
+    /// Returns a new, empty store.
+    pub fn new() -> Self {
+        MemoryStateStore { states: HashMap::new() }
+    }
+}
+
+impl SyncStateStore for MemoryStateStore {
+    fn load(&mut self) -> Result<HashMap<String, UidState>, Box<dyn Error>> {
+        Ok(self.states.clone())
+    }
+
+    fn save(&mut self, states: &[(String, UidState)]) -> Result<(), Box<dyn Error>> {
+        for (uid, state) in states {
+            self.states.insert(uid.clone(), state.clone());
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, unique_ids: &[String]) -> Result<(), Box<dyn Error>> {
+        for uid in unique_ids {
+            self.states.remove(uid);
+        }
+        Ok(())
+    }
+}
+
+/// Synchronization state stored in a JSON file.
+///
+/// The whole file is rewritten on each update. To keep the file consistent,
+/// the state is written to a temporary file first, which replaces the
+/// original file afterwards.
+pub struct JsonFileStateStore {
+    path: PathBuf,
+    states: Option<HashMap<String, UidState>>,
+}
+
+impl JsonFileStateStore {
+
+    /// Returns a new store. The file is created on first update.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - path of the JSON file
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        JsonFileStateStore { path: path.as_ref().to_path_buf(), states: None }
+    }
+
+    /// Returns the path of the JSON file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn states(&mut self) -> Result<&mut HashMap<String, UidState>, Box<dyn Error>> {
+        if self.states.is_none() {
+            let states = match fs::read_to_string(&self.path) {
+                Ok(text) => from_json(&json::parse(&text)?)?,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+                Err(err) => return Err(err.into())
+            };
+            self.states = Some(states);
+        }
+
+        Ok(self.states.get_or_insert_with(HashMap::new))
+    }
+
+    fn write(&mut self) -> Result<(), Box<dyn Error>> {
+        let text = to_json(self.states()?).to_string();
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let result = File::create(&tmp_path)
+            .and_then(|mut file| {
+                file.write_all(text.as_bytes())?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&tmp_path, &self.path));
+        if let Err(err) = result {
+            let _ = fs::remove_file(&tmp_path);
+            self.states = None;
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+}
+
+impl SyncStateStore for JsonFileStateStore {
+    fn load(&mut self) -> Result<HashMap<String, UidState>, Box<dyn Error>> {
+        Ok(self.states()?.clone())
+    }
+
+    fn save(&mut self, states: &[(String, UidState)]) -> Result<(), Box<dyn Error>> {
+        let current = self.states()?;
+        for (uid, state) in states {
+            current.insert(uid.clone(), state.clone());
+        }
+        self.write()
+    }
+
+    fn remove(&mut self, unique_ids: &[String]) -> Result<(), Box<dyn Error>> {
+        let current = self.states()?;
+        for uid in unique_ids {
+            current.remove(uid);
+        }
+        self.write()
+    }
+}
+
+fn to_timestamp(time: SystemTime) -> Value {
+    Value::Number(time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as f64)
+}
+
+fn from_timestamp(value: &Value) -> Result<Option<SystemTime>, Box<dyn Error>> {
+    match value {
+        Value::Null => Ok(None),
+        value => Ok(Some(UNIX_EPOCH + Duration::from_secs(value.as_u64().ok_or("invalid timestamp")?)))
+    }
+}
+
+fn to_json(states: &HashMap<String, UidState>) -> Value {
+    let mut uids: Vec<(&String, &UidState)> = states.iter().collect();
+    uids.sort_by(|a, b| a.0.cmp(b.0));
+
+    let uids = uids.into_iter().map(|(uid, state)| {
+        let metadata = state.metadata.iter()
+            .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+            .collect();
+
+        (uid.clone(), Value::Object(vec!(
+            ("first_seen".into(), to_timestamp(state.first_seen)),
+            ("fetched_at".into(), state.fetched_at.map(to_timestamp).unwrap_or(Value::Null)),
+            ("delete_after".into(), state.delete_after.map(to_timestamp).unwrap_or(Value::Null)),
+            ("metadata".into(), Value::Object(metadata)),
+        )))
+    }).collect();
+
+    Value::Object(vec!(
+        ("version".into(), Value::Number(FILE_FORMAT_VERSION as f64)),
+        ("uids".into(), Value::Object(uids)),
+    ))
+}
+
+fn from_json(value: &Value) -> Result<HashMap<String, UidState>, Box<dyn Error>> {
+    let version = value.get("version").and_then(Value::as_u64).ok_or("missing version")?;
+    if version != FILE_FORMAT_VERSION {
+        return Err(format!("unsupported state file version: {}", version).into());
+    }
+
+    let mut states = HashMap::new();
+    for (uid, entry) in value.get("uids").and_then(Value::as_object).ok_or("missing uids")? {
+        let first_seen = from_timestamp(entry.get("first_seen").ok_or("missing first_seen")?)?
+            .ok_or("missing first_seen")?;
+        let mut state = UidState::new(first_seen);
+        state.fetched_at = from_timestamp(entry.get("fetched_at").unwrap_or(&Value::Null))?;
+        state.delete_after = from_timestamp(entry.get("delete_after").unwrap_or(&Value::Null))?;
+        if let Some(metadata) = entry.get("metadata").and_then(Value::as_object) {
+            for (key, value) in metadata {
+                state.metadata.insert(key.clone(), value.as_str().ok_or("invalid metadata")?.to_string());
+            }
+        }
+        states.insert(uid.clone(), state);
+    }
+
+    Ok(states)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_json_file_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+
+        let mut state = UidState::new(time(100));
+        state.fetched_at = Some(time(200));
+        state.metadata.insert("sha256".into(), "abc".into());
+
+        let mut store = JsonFileStateStore::new(&path);
+        assert!(store.load().unwrap().is_empty());
+        store.save(&[("uid1".into(), state.clone()), ("uid2".into(), UidState::new(time(300)))]).unwrap();
+        store.remove(&["uid2".into()]).unwrap();
+
+        let states = JsonFileStateStore::new(&path).load().unwrap();
+        assert_eq!(1, states.len());
+        assert_eq!(Some(&state), states.get("uid1"));
+        assert!(!dir.path().join("state.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_json_file_store_rejects_invalid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        fs::write(&path, "{\"version\": 2, \"uids\": {}}").unwrap();
+
+        assert!(JsonFileStateStore::new(&path).load().is_err());
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let mut store = MemoryStateStore::new();
+        store.save(&[("uid1".into(), UidState::new(time(1)))]).unwrap();
+        assert_eq!(1, store.load().unwrap().len());
+        store.remove(&["uid1".into()]).unwrap();
+        assert!(store.load().unwrap().is_empty());
+    }
+}
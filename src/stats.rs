@@ -0,0 +1,73 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Latency statistics of a command within a session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pop3CommandStats {
+    /// count of completed commands
+    pub count: u32,
+
+    /// count of commands, which the server responded negative
+    pub failures: u32,
+
+    /// minimum latency
+    pub min: Duration,
+
+    /// maximum latency
+    pub max: Duration,
+
+    /// sum of all latencies
+    pub total: Duration,
+}
+
+impl Pop3CommandStats {
+
+    /// Returns the average latency.
+    pub fn average(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => self.total / count
+        }
+    }
+}
+
+/// Latency statistics of all commands of a session by command name, e.g. `RETR`.
+///
+/// The latency of a command is the time from sending the command until its
+/// response was received completely. Commands failing at transport level,
+/// e.g. by a timeout, are not recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Pop3SessionStats {
+    commands: BTreeMap<String, Pop3CommandStats>,
+}
+
+impl Pop3SessionStats {
+
+    /// Returns the statistics of a command; `None`, if the command was not completed.
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - name of the command, e.g. `RETR`
+    pub fn get(&self, command: &str) -> Option<&Pop3CommandStats> {
+        self.commands.get(&command.to_ascii_uppercase())
+    }
+
+    /// Returns the statistics of all completed commands ordered by command name.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Pop3CommandStats)> {
+        self.commands.iter().map(|(command, stats)| (command.as_str(), stats))
+    }
+
+    /// Records a completed command.
+    pub(crate) fn record(&mut self, command: String, latency: Duration, success: bool) {
+        let failures = if success { 0 } else { 1 };
+        self.commands.entry(command)
+            .and_modify(|stats| {
+                stats.count += 1;
+                stats.failures += failures;
+                stats.min = stats.min.min(latency);
+                stats.max = stats.max.max(latency);
+                stats.total += latency;
+            })
+            .or_insert(Pop3CommandStats { count: 1, failures, min: latency, max: latency, total: latency });
+    }
+}
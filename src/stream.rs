@@ -0,0 +1,97 @@
+use std::error::Error;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+
+use futures_util::io::AllowStdIo;
+use rustls::{ClientConnection, RootCertStore, StreamOwned};
+
+use crate::session::{self, TlsInfo};
+
+use crate::trace::OperationSpan;
+
+/// Returns the TLS configuration of POP3 connections.
+pub(crate) fn client_config(root_store: RootCertStore) -> rustls::ClientConfig {
+    rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth()
+}
+
+/// Network stream of a POP3 connection, either plain or TLS protected.
+pub(crate) enum Stream {
+    Plain(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+    Closed,
+}
+
+impl Stream {
+
+    /// Wraps a TCP stream into TLS and performs the handshake.
+    pub fn tls(mut stream: TcpStream, host: &str, root_store: RootCertStore) -> Result<Stream, Box<dyn Error>> {
+        OperationSpan::tls_handshake(host).run_blocking(|| {
+            let server_name = host.try_into()?;
+            let mut connection = ClientConnection::new(Arc::new(client_config(root_store)), server_name)?;
+            while connection.is_handshaking() {
+                connection.complete_io(&mut stream)?;
+            }
+            Ok(Stream::Tls(Box::new(StreamOwned::new(connection, stream))))
+        })
+    }
+
+    /// Upgrades a plain stream to TLS, e.g. after STLS.
+    pub fn upgrade(self, host: &str, root_store: RootCertStore) -> Result<Stream, Box<dyn Error>> {
+        match self {
+            Stream::Plain(stream) => Stream::tls(stream, host, root_store),
+            Stream::Tls(_) => Err("stream already uses TLS".into()),
+            Stream::Closed => Err("stream closed".into()),
+        }
+    }
+
+    fn closed() -> io::Error {
+        io::Error::new(io::ErrorKind::NotConnected, "stream closed")
+    }
+}
+
+impl TlsInfo for Stream {
+    fn cipher_suite(&self) -> Option<String> {
+        match self {
+            Stream::Tls(stream) => stream.conn.negotiated_cipher_suite().map(session::cipher_suite_name),
+            _ => None
+        }
+    }
+}
+
+impl TlsInfo for AllowStdIo<Stream> {
+    fn cipher_suite(&self) -> Option<String> {
+        self.get_ref().cipher_suite()
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.read(buf),
+            Stream::Tls(stream) => stream.read(buf),
+            Stream::Closed => Err(Stream::closed()),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(stream) => stream.write(buf),
+            Stream::Tls(stream) => stream.write(buf),
+            Stream::Closed => Err(Stream::closed()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(stream) => stream.flush(),
+            Stream::Tls(stream) => stream.flush(),
+            Stream::Closed => Err(Stream::closed()),
+        }
+    }
+}
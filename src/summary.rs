@@ -0,0 +1,71 @@
+use std::time::SystemTime;
+
+use crate::{Mailbox, Pop3Headers, Pop3MessageMeta};
+
+/// POP3 message summary, i.e. metadata and headers of a message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pop3MessageSummary {
+    /// id, size and unique id of the message
+    pub meta: Pop3MessageMeta,
+
+    /// headers of the message
+    pub headers: Pop3Headers,
+}
+
+impl Pop3MessageSummary {
+
+    /// Returns the value of the first header with the given name (case-insensitive).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Returns the From header.
+    pub fn from(&self) -> Option<&str> {
+        self.header("From")
+    }
+
+    /// Returns the mailboxes of all headers with the given name (case-insensitive), e.g. `To`.
+    pub fn mailboxes(&self, name: &str) -> Vec<Mailbox> {
+        self.headers.mailboxes(name)
+    }
+
+    /// Returns the mailboxes of the From header.
+    pub fn from_mailboxes(&self) -> Vec<Mailbox> {
+        self.mailboxes("From")
+    }
+
+    /// Returns the mailboxes of the To headers.
+    pub fn to_mailboxes(&self) -> Vec<Mailbox> {
+        self.mailboxes("To")
+    }
+
+    /// Returns the mailboxes of the Cc headers.
+    pub fn cc_mailboxes(&self) -> Vec<Mailbox> {
+        self.mailboxes("Cc")
+    }
+
+    /// Returns the Subject header.
+    pub fn subject(&self) -> Option<&str> {
+        self.header("Subject")
+    }
+
+    /// Returns the Date header.
+    pub fn date(&self) -> Option<&str> {
+        self.header("Date")
+    }
+
+    /// Returns the parsed Date header; `None` if missing or invalid.
+    ///
+    /// See [`Pop3Headers::timestamp`] for details.
+    pub fn timestamp(&self) -> Option<SystemTime> {
+        self.headers.timestamp()
+    }
+
+    /// Returns the parsed Date header including its offset to UTC; `None` if missing or invalid.
+    ///
+    /// See [`Pop3Headers::timestamp`] for the tolerated deviations.
+    #[cfg(feature = "chrono")]
+    pub fn date_time(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        self.headers.date_time()
+    }
+}
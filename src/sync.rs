@@ -0,0 +1,598 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::time::SystemTime;
+
+use futures_util::io::{AsyncRead, AsyncWrite};
+
+use crate::digest::{self, MessageDigest, Sha256Digest};
+use crate::headers;
+use crate::sink;
+use crate::hash_store::SharedHashStore;
+use crate::state::{DEFERRED_KEY, SKIPPED_KEY};
+
+use crate::{AsyncPop3Connection, Pop3AsyncError, Pop3Connection, Pop3Headers, Pop3MessageMeta, SyncStateStore, UidState, block_on};
+
+/// Metadata key of the SHA-256 hash of a fetched message.
+pub(crate) const SHA256_KEY: &str = "sha256";
+
+/// Metadata key of the Message-ID header of a fetched message.
+pub(crate) const MESSAGE_ID_KEY: &str = "message_id";
+
+/// Metadata key of messages, which were marked as deleted after fetch.
+///
+/// The deletion is committed only when the session ends; if the session
+/// breaks before, the message is marked as deleted again on the next run.
+const DELETE_PENDING_KEY: &str = "delete_pending";
+
+/// Metadata key of the MIME type of a message, recorded if content type policies are used.
+const CONTENT_TYPE_KEY: &str = "content_type";
+
+/// Action applied to new messages of a given content type.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentTypeAction {
+    /// Fetches the message, even if it exceeds the maximum message size.
+    Fetch,
+
+    /// Leaves the message on the server without fetching it.
+    Skip,
+}
+
+/// Options of the synchronization APIs.
+#[derive(Clone, Debug, Default)]
+pub struct SyncOptions {
+    delete_after_fetch: bool,
+    skip_duplicates: bool,
+    skip_duplicate_message_ids: bool,
+    max_message_size: Option<u32>,
+    shared_hashes: Option<SharedHashStore>,
+    content_types: Vec<(String, ContentTypeAction)>,
+}
+
+impl SyncOptions {
+
+    /// Returns the default options.
+    pub fn new() -> Self {
+        SyncOptions::default()
+    }
+
+    /// Deletes each message from the server after it was delivered successfully.
+    ///
+    /// A message is marked as deleted only after the handler returned, i.e. after
+    /// its octets were written and flushed. The deletions are committed by ending
+    /// the session using QUIT, once all messages are fetched.
+    pub fn delete_after_fetch(mut self, delete_after_fetch: bool) -> Self {
+        self.delete_after_fetch = delete_after_fetch;
+        self
+    }
+
+    /// Returns true, if messages are deleted after they were fetched.
+    pub fn is_delete_after_fetch(&self) -> bool {
+        self.delete_after_fetch
+    }
+
+    /// Skips delivery of messages, whose content was already fetched under another unique id.
+    ///
+    /// Some servers assign new unique ids after rebuilding the maildrop.
+    /// To detect such duplicates, the SHA-256 hash of each fetched message is
+    /// recorded in the state store. Duplicates are recorded as fetched without
+    /// invoking the handler.
+    pub fn skip_duplicates(mut self, skip_duplicates: bool) -> Self {
+        self.skip_duplicates = skip_duplicates;
+        self
+    }
+
+    /// Skips messages, whose Message-ID header equals the one of a message fetched before.
+    ///
+    /// Some providers re-expose messages with new unique ids, e.g. after a
+    /// migration, where the content slightly differs. To detect such
+    /// duplicates, the headers of each new message are fetched (TOP 0)
+    /// before it is retrieved. Duplicates are recorded as fetched without
+    /// retrieving them. The Message-ID of each fetched message is recorded
+    /// in the state store, regardless of this option.
+    pub fn skip_duplicate_message_ids(mut self, skip_duplicate_message_ids: bool) -> Self {
+        self.skip_duplicate_message_ids = skip_duplicate_message_ids;
+        self
+    }
+
+    /// Skips delivery of messages, which were already delivered from any account using the same store.
+    ///
+    /// Each delivered message is added to the store after the handler
+    /// succeeded; messages found in the store are recorded as fetched
+    /// without invoking the handler. Note that a message fetched by two
+    /// accounts at the same time may be delivered twice.
+    pub fn shared_hashes(mut self, store: Option<SharedHashStore>) -> Self {
+        self.shared_hashes = store;
+        self
+    }
+
+    /// Returns the store of hashes shared between accounts, if any.
+    pub fn shared_hash_store(&self) -> Option<&SharedHashStore> {
+        self.shared_hashes.as_ref()
+    }
+
+    /// Defers messages larger than the given size in octets.
+    ///
+    /// Instead of retrieving such a message, only its headers are fetched
+    /// (TOP 0). The message is recorded as deferred (see [`UidState::is_deferred`])
+    /// with its From, Subject and Date headers as metadata (keys `from`,
+    /// `subject` and `date`); the handler is not invoked. Deferred messages
+    /// are fetched once they fit the limit, e.g. if the limit is raised.
+    pub fn max_message_size(mut self, max_message_size: Option<u32>) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Applies an action to new messages of the given content type.
+    ///
+    /// The MIME type of the Content-Type header is fetched (TOP 0) before a
+    /// message is retrieved and recorded as metadata (key `content_type`).
+    /// The pattern is either a MIME type, e.g. `multipart/report`, or a type
+    /// with wildcard, e.g. `text/*`; case is ignored. The action of the first
+    /// matching pattern applies.
+    ///
+    /// Skipped messages are recorded as skipped (see [`UidState::is_skipped`])
+    /// and are fetched once no longer skipped by the options. Messages with
+    /// [`ContentTypeAction::Fetch`] are not deferred due to their size
+    /// (see [`SyncOptions::max_message_size`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - MIME type to match, e.g. `text/calendar` or `text/*`
+    /// * `action`  - action applied to matching messages
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rust_pop3_client::{ContentTypeAction, SyncOptions};
+    ///
+    /// let options = SyncOptions::new()
+    ///     .max_message_size(Some(1024 * 1024))
+    ///     .content_type("multipart/report", ContentTypeAction::Skip)
+    ///     .content_type("text/calendar", ContentTypeAction::Fetch);
+    /// ```
+    pub fn content_type(mut self, pattern: &str, action: ContentTypeAction) -> Self {
+        self.content_types.push((pattern.to_ascii_lowercase(), action));
+        self
+    }
+
+    /// Returns the action of the first pattern matching the given MIME type, if any.
+    fn content_type_action(&self, mime_type: &str) -> Option<ContentTypeAction> {
+        self.content_types.iter()
+            .find(|(pattern, _)| match pattern.strip_suffix("/*") {
+                Some(main_type) => mime_type.split('/').next() == Some(main_type) || main_type == "*",
+                None => pattern == mime_type
+            })
+            .map(|(_, action)| *action)
+    }
+}
+
+impl Pop3Connection {
+
+    /// Retrieves messages, which were not fetched before, and returns their count.
+    ///
+    /// The unique ids reported by UIDL are compared against the state store.
+    /// Each new message is retrieved and passed to the handler. The state of
+    /// a message is updated only after the handler succeeded, so each message
+    /// is delivered at least once, even if the application crashes.
+    ///
+    /// If the handler fails, fetching stops and the error is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `state`   - store of the synchronization state
+    /// * `handler` - invoked with metadata and exact octets of each new message
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use rust_pop3_client::{JsonFileStateStore, MaildirSink, MessageSink, Pop3Connection};
+    ///
+    /// let mut connection = Pop3Connection::new("pop.example.com", 995).unwrap();
+    /// connection.login("user@example.com", "secret").unwrap();
+    ///
+    /// let mut state = JsonFileStateStore::new("state.json");
+    /// let mut maildir = MaildirSink::new("Maildir").unwrap();
+    /// connection.fetch_new_messages(&mut state, |message, content| maildir.deliver(message, content)).unwrap();
+    /// ```
+    pub fn fetch_new_messages(&mut self, state: &mut dyn SyncStateStore, handler: impl FnMut(&Pop3MessageMeta, &[u8]) -> Result<(), Box<dyn Error>>) -> Result<usize, Box<dyn Error>> {
+        self.fetch_new_messages_with(state, &SyncOptions::default(), handler)
+    }
+
+    /// Retrieves messages, which were not fetched before, using the given options
+    /// and returns their count.
+    ///
+    /// See [`Pop3Connection::fetch_new_messages`] for details. If messages are
+    /// deleted after fetch, the session is ended using QUIT to commit the
+    /// deletions, so the connection can not be used afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// * `state`   - store of the synchronization state
+    /// * `options` - options of the synchronization
+    /// * `handler` - invoked with metadata and exact octets of each new message
+    pub fn fetch_new_messages_with(&mut self, state: &mut dyn SyncStateStore, options: &SyncOptions, mut handler: impl FnMut(&Pop3MessageMeta, &[u8]) -> Result<(), Box<dyn Error>>) -> Result<usize, Box<dyn Error>> {
+        let mut handler_error = None;
+        let result = block_on(self.inner.fetch_new_messages_with(state, options, |message, content| {
+            handler(message, content).map_err(|err| {
+                let message = err.to_string();
+                handler_error = Some(err);
+                message.into()
+            })
+        }));
+
+        match handler_error {
+            Some(err) => Err(err),
+            None => result
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncPop3Connection<S> {
+
+    /// Retrieves messages, which were not fetched before, and returns their count.
+    ///
+    /// See [`Pop3Connection::fetch_new_messages`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `state`   - store of the synchronization state
+    /// * `handler` - invoked with metadata and exact octets of each new message
+    pub async fn fetch_new_messages<T>(&mut self, state: &mut T, handler: impl FnMut(&Pop3MessageMeta, &[u8]) -> Result<(), Pop3AsyncError>) -> Result<usize, Pop3AsyncError>
+    where
+        T: SyncStateStore + ?Sized
+    {
+        self.fetch_new_messages_with(state, &SyncOptions::default(), handler).await
+    }
+
+    /// Retrieves messages, which were not fetched before, using the given options
+    /// and returns their count.
+    ///
+    /// See [`Pop3Connection::fetch_new_messages_with`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `state`   - store of the synchronization state
+    /// * `options` - options of the synchronization
+    /// * `handler` - invoked with metadata and exact octets of each new message
+    pub async fn fetch_new_messages_with<T>(&mut self, state: &mut T, options: &SyncOptions, mut handler: impl FnMut(&Pop3MessageMeta, &[u8]) -> Result<(), Pop3AsyncError>) -> Result<usize, Pop3AsyncError>
+    where
+        T: SyncStateStore + ?Sized
+    {
+        let messages = self.list_meta().await?;
+        let states = state.load().map_err(|err| err.to_string())?;
+
+        let mut hashes: HashSet<String> = states.values()
+            .filter_map(|uid_state| uid_state.metadata.get(SHA256_KEY).cloned())
+            .collect();
+        let mut message_ids: HashSet<String> = states.values()
+            .filter(|uid_state| uid_state.fetched_at.is_some())
+            .filter_map(|uid_state| uid_state.metadata.get(MESSAGE_ID_KEY).cloned())
+            .collect();
+
+        let mut count = 0;
+        for message in messages {
+            let unique_id = message.unique_id.clone().ok_or("server does not support UIDL")?;
+            let mut uid_state = match states.get(&unique_id) {
+                Some(uid_state) if uid_state.fetched_at.is_some() => {
+                    if options.delete_after_fetch && uid_state.metadata.contains_key(DELETE_PENDING_KEY) {
+                        self.delete(message.message_id).await?;
+                    }
+                    continue;
+                },
+                Some(uid_state) => uid_state.clone(),
+                None => UidState::new(SystemTime::now())
+            };
+
+            let mut top = None;
+            let mut action = None;
+            if !options.content_types.is_empty() {
+                let mime_type = match uid_state.metadata.get(CONTENT_TYPE_KEY) {
+                    Some(mime_type) => mime_type.clone(),
+                    None => Pop3Headers::parse(self.top_cached(message.message_id, &mut top).await?).mime_type()
+                };
+                action = options.content_type_action(&mime_type);
+                uid_state.metadata.insert(CONTENT_TYPE_KEY.to_string(), mime_type);
+            }
+
+            if action == Some(ContentTypeAction::Skip) {
+                if !uid_state.is_skipped() {
+                    uid_state.metadata.insert(SKIPPED_KEY.to_string(), "true".to_string());
+                    state.save(&[(unique_id, uid_state)]).map_err(|err| err.to_string())?;
+                }
+                continue;
+            }
+
+            if action != Some(ContentTypeAction::Fetch) && options.max_message_size.is_some_and(|max_size| message.message_size > max_size) {
+                if !uid_state.is_deferred() {
+                    let headers = Pop3Headers::parse(self.top_cached(message.message_id, &mut top).await?);
+                    for name in ["From", "Subject", "Date"] {
+                        if let Some(value) = headers.get(name) {
+                            uid_state.metadata.insert(name.to_lowercase(), value.to_string());
+                        }
+                    }
+                    uid_state.metadata.insert(DEFERRED_KEY.to_string(), "true".to_string());
+                    state.save(&[(unique_id, uid_state)]).map_err(|err| err.to_string())?;
+                }
+                continue;
+            }
+
+            let mut message_id = None;
+            if options.skip_duplicate_message_ids {
+                message_id = message_id_of(self.top_cached(message.message_id, &mut top).await?);
+            }
+
+            if !message_id.as_ref().is_some_and(|message_id| message_ids.contains(message_id)) {
+                let mut content = vec!();
+                self.retrieve_raw(message.message_id, &mut content).await?;
+                let hash = sha256_hex(&content);
+                let duplicate = (options.skip_duplicates && hashes.contains(&hash))
+                    || options.shared_hashes.as_ref().is_some_and(|store| store.contains(&hash));
+                if !duplicate {
+                    handler(&message, &content)?;
+                    count += 1;
+                }
+                if let Some(store) = &options.shared_hashes {
+                    store.insert(&hash).map_err(|err| err.to_string())?;
+                }
+
+                uid_state.metadata.insert(SHA256_KEY.to_string(), hash.clone());
+                hashes.insert(hash);
+                if message_id.is_none() {
+                    message_id = message_id_of(&content);
+                }
+            }
+
+            uid_state.fetched_at = Some(SystemTime::now());
+            uid_state.metadata.remove(DEFERRED_KEY);
+            uid_state.metadata.remove(SKIPPED_KEY);
+            if let Some(message_id) = message_id {
+                uid_state.metadata.insert(MESSAGE_ID_KEY.to_string(), message_id.clone());
+                message_ids.insert(message_id);
+            }
+            if options.delete_after_fetch {
+                uid_state.metadata.insert(DELETE_PENDING_KEY.to_string(), "true".to_string());
+            }
+            state.save(&[(unique_id, uid_state)]).map_err(|err| err.to_string())?;
+
+            if options.delete_after_fetch {
+                self.delete(message.message_id).await?;
+            }
+        }
+
+        if options.delete_after_fetch {
+            self.close().await?;
+        }
+
+        Ok(count)
+    }
+
+    /// Returns the headers of a message fetched by TOP 0, fetching them only once.
+    async fn top_cached<'a>(&mut self, message_id: u32, top: &'a mut Option<Vec<u8>>) -> Result<&'a [u8], Pop3AsyncError> {
+        if top.is_none() {
+            *top = Some(self.top_raw(message_id, 0).await?);
+        }
+
+        Ok(top.as_deref().unwrap_or_default())
+    }
+}
+
+/// Returns the Message-ID header of a message, if any.
+fn message_id_of(content: &[u8]) -> Option<String> {
+    let (header_data, _) = sink::split_message(content);
+    let headers = headers::parse_headers(header_data);
+    headers::find(&headers, "Message-ID")
+        .map(|message_id| message_id.trim().to_string())
+        .filter(|message_id| !message_id.is_empty())
+}
+
+/// Returns the SHA-256 hash of the content as lower case hex string.
+pub(crate) fn sha256_hex(content: &[u8]) -> String {
+    let mut hasher = Sha256Digest::new();
+    hasher.update(content);
+    digest::to_hex(&hasher.finalize_reset())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryStateStore;
+    use crate::test_util;
+
+    const LIST: (&str, &str) = ("LIST", "+OK\r\n1 10\r\n2 20\r\n.\r\n");
+    const UIDL: (&str, &str) = ("UIDL", "+OK\r\n1 uid1\r\n2 uid2\r\n.\r\n");
+
+    #[test]
+    fn test_fetch_new_messages() {
+        let (mut connection, server) = test_util::connect(&[
+            LIST, UIDL,
+            ("RETR 2", "+OK\r\nSubject: two\r\n\r\n..dot\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let mut state = MemoryStateStore::new();
+        let mut fetched = UidState::new(SystemTime::now());
+        fetched.fetched_at = Some(SystemTime::now());
+        state.save(&[("uid1".into(), fetched)]).unwrap();
+
+        let mut messages = vec!();
+        let count = connection.fetch_new_messages(&mut state, |message, content| {
+            messages.push((message.message_id, content.to_vec()));
+            Ok(())
+        }).unwrap();
+        drop(connection);
+        server.join().unwrap();
+
+        assert_eq!(1, count);
+        assert_eq!(vec!((2, b"Subject: two\r\n\r\n.dot\r\n".to_vec())), messages);
+        assert!(state.load().unwrap()["uid2"].fetched_at.is_some());
+    }
+
+    #[test]
+    fn test_defer_large_messages() {
+        let (mut connection, server) = test_util::connect(&[
+            LIST, UIDL,
+            ("RETR 1", "+OK\r\nSubject: one\r\n.\r\n"),
+            ("TOP 2 0", "+OK\r\nSubject: two\r\nFrom: a@example.com\r\n\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let mut state = MemoryStateStore::new();
+        let options = SyncOptions::new().max_message_size(Some(15));
+        let count = connection.fetch_new_messages_with(&mut state, &options, |_, _| Ok(())).unwrap();
+        drop(connection);
+        server.join().unwrap();
+
+        assert_eq!(1, count);
+        let states = state.load().unwrap();
+        assert!(!states["uid1"].is_deferred());
+        assert!(states["uid2"].is_deferred());
+        assert!(states["uid2"].fetched_at.is_none());
+        assert_eq!(Some("two"), states["uid2"].metadata.get("subject").map(String::as_str));
+    }
+
+    #[test]
+    fn test_content_type_policy() {
+        let (mut connection, server) = test_util::connect(&[
+            ("LIST", "+OK\r\n1 10\r\n2 20\r\n3 30\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uid1\r\n2 uid2\r\n3 uid3\r\n.\r\n"),
+            ("TOP 1 0", "+OK\r\nContent-Type: multipart/report; report-type=delivery-status\r\n\r\n.\r\n"),
+            ("TOP 2 0", "+OK\r\nContent-Type: Text/Calendar; method=REQUEST\r\n\r\n.\r\n"),
+            ("RETR 2", "+OK\r\nSubject: meeting\r\n.\r\n"),
+            ("TOP 3 0", "+OK\r\nSubject: large\r\n\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let mut state = MemoryStateStore::new();
+        let options = SyncOptions::new()
+            .max_message_size(Some(15))
+            .content_type("multipart/report", ContentTypeAction::Skip)
+            .content_type("text/calendar", ContentTypeAction::Fetch);
+        let count = connection.fetch_new_messages_with(&mut state, &options, |_, _| Ok(())).unwrap();
+        drop(connection);
+        server.join().unwrap();
+
+        assert_eq!(1, count);
+        let states = state.load().unwrap();
+        assert!(states["uid1"].is_skipped());
+        assert!(states["uid2"].fetched_at.is_some());
+        assert_eq!(Some("text/calendar"), states["uid2"].metadata.get(CONTENT_TYPE_KEY).map(String::as_str));
+        assert!(states["uid3"].is_deferred());
+        assert_eq!(Some("large"), states["uid3"].metadata.get("subject").map(String::as_str));
+
+        let options = SyncOptions::new().content_type("Text/*", ContentTypeAction::Skip);
+        assert_eq!(Some(ContentTypeAction::Skip), options.content_type_action("text/html"));
+        assert_eq!(None, options.content_type_action("multipart/mixed"));
+    }
+
+    #[test]
+    fn test_shared_hashes() {
+        let store = SharedHashStore::new();
+        let options = SyncOptions::new().shared_hashes(Some(store.clone()));
+
+        let mut delivered = 0;
+        for _ in 0..2 {
+            let (mut connection, server) = test_util::connect(&[
+                ("LIST", "+OK\r\n1 10\r\n.\r\n"),
+                ("UIDL", "+OK\r\n1 uid1\r\n.\r\n"),
+                ("RETR 1", "+OK\r\nSubject: list\r\n.\r\n"),
+                ("QUIT", "+OK\r\n"),
+            ]);
+            let mut state = MemoryStateStore::new();
+            delivered += connection.fetch_new_messages_with(&mut state, &options, |_, _| Ok(())).unwrap();
+            assert!(state.load().unwrap()["uid1"].fetched_at.is_some());
+            drop(connection);
+            server.join().unwrap();
+        }
+
+        assert_eq!(1, delivered);
+        assert_eq!(1, store.len());
+    }
+
+    #[test]
+    fn test_skip_duplicate_message_ids() {
+        let (mut connection, server) = test_util::connect(&[
+            LIST, UIDL,
+            ("TOP 1 0", "+OK\r\nMessage-ID: <1@example.com>\r\n\r\n.\r\n"),
+            ("RETR 1", "+OK\r\nMessage-ID: <1@example.com>\r\n\r\none\r\n.\r\n"),
+            ("TOP 2 0", "+OK\r\nMessage-ID:  <1@example.com>\r\n\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let mut state = MemoryStateStore::new();
+        let options = SyncOptions::new().skip_duplicate_message_ids(true);
+        let count = connection.fetch_new_messages_with(&mut state, &options, |_, _| Ok(())).unwrap();
+        drop(connection);
+        server.join().unwrap();
+
+        assert_eq!(1, count);
+        let states = state.load().unwrap();
+        assert!(states["uid2"].fetched_at.is_some());
+        assert_eq!(Some("<1@example.com>"), states["uid1"].metadata.get(MESSAGE_ID_KEY).map(String::as_str));
+    }
+
+    #[test]
+    fn test_delete_after_fetch() {
+        let (mut connection, server) = test_util::connect(&[
+            LIST, UIDL,
+            ("RETR 1", "+OK\r\nSubject: one\r\n.\r\n"),
+            ("DELE 1", "+OK\r\n"),
+            ("RETR 2", "+OK\r\nSubject: two\r\n.\r\n"),
+            ("DELE 2", "+OK\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let mut state = MemoryStateStore::new();
+        let options = SyncOptions::new().delete_after_fetch(true);
+        let count = connection.fetch_new_messages_with(&mut state, &options, |_, _| Ok(())).unwrap();
+        let commands = server.join().unwrap();
+
+        assert_eq!(2, count);
+        assert_eq!(Some("QUIT"), commands.last().map(String::as_str));
+        assert!(connection.noop().is_err());
+    }
+
+    #[test]
+    fn test_skip_duplicates() {
+        let (mut connection, server) = test_util::connect(&[
+            LIST, UIDL,
+            ("RETR 1", "+OK\r\nSubject: same\r\n.\r\n"),
+            ("RETR 2", "+OK\r\nSubject: same\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let mut state = MemoryStateStore::new();
+        let options = SyncOptions::new().skip_duplicates(true);
+        let mut delivered = 0;
+        let count = connection.fetch_new_messages_with(&mut state, &options, |_, _| { delivered += 1; Ok(()) }).unwrap();
+        drop(connection);
+        server.join().unwrap();
+
+        assert_eq!(1, count);
+        assert_eq!(1, delivered);
+        let states = state.load().unwrap();
+        assert!(states["uid2"].fetched_at.is_some());
+        assert_eq!(Some(&sha256_hex(b"Subject: same\r\n")), states["uid2"].metadata.get(SHA256_KEY));
+    }
+
+    #[test]
+    fn test_sha256_hex() {
+        assert_eq!("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855", sha256_hex(b""));
+    }
+
+    #[test]
+    fn test_state_is_not_updated_on_handler_failure() {
+        let (mut connection, server) = test_util::connect(&[
+            LIST, UIDL,
+            ("RETR 1", "+OK\r\nSubject: one\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let mut state = MemoryStateStore::new();
+        let result = connection.fetch_new_messages(&mut state, |_, _| Err("disk full".into()));
+        drop(connection);
+        server.join().unwrap();
+
+        assert!(result.is_err());
+        assert!(state.load().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+use std::io;
+
+use rustls::RootCertStore;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+
+use super::{self_signed_config, MockMismatch, MockScript};
+use crate::{Pop3AsyncError, TlsMode};
+
+/// In-process POP3 server for integration tests of async applications.
+///
+/// The server accepts one connection per script on localhost and runs the
+/// sessions one after another. TLS uses a self-signed certificate for the
+/// host name `localhost`, which is generated on start and trusted by
+/// [`AsyncMockServer::root_store`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{AsyncMockServer, MockScript, Pop3ConnectionBuilder, TlsMode};
+///
+/// # async fn run() -> Result<(), rust_pop3_client::Pop3AsyncError> {
+/// let server = AsyncMockServer::start(TlsMode::Implicit, vec!(MockScript::new()
+///     .expect("USER me", "+OK\r\n")
+///     .expect("PASS secret", "+OK\r\n")
+///     .expect("STAT", "+OK 0 0\r\n")
+///     .expect("QUIT", "+OK\r\n"))).await?;
+///
+/// let mut connection = Pop3ConnectionBuilder::new("localhost")
+///     .port(server.port())
+///     .root_store(server.root_store())
+///     .login("me", "secret")
+///     .connect_async()
+///     .await?;
+/// assert_eq!(0, connection.stat().await?.message_count);
+/// connection.quit().await?;
+///
+/// server.finish().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncMockServer {
+    port: u16,
+    root_store: RootCertStore,
+    task: JoinHandle<Result<Vec<Vec<String>>, Pop3AsyncError>>,
+}
+
+impl AsyncMockServer {
+
+    /// Starts the server in the background; requires a tokio runtime.
+    ///
+    /// # Arguments
+    ///
+    /// * `tls_mode` - transport layer security mode of the server
+    /// * `scripts`  - script of each session
+    pub async fn start(tls_mode: TlsMode, scripts: Vec<MockScript>) -> Result<AsyncMockServer, Pop3AsyncError> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let port = listener.local_addr()?.port();
+        let (config, root_store, _) = self_signed_config().map_err(|err| err.to_string())?;
+        let acceptor = match tls_mode {
+            TlsMode::Plain => None,
+            _ => Some(TlsAcceptor::from(config))
+        };
+
+        let task = tokio::spawn(async move {
+            let mut sessions = vec!();
+            for (session, script) in scripts.into_iter().enumerate() {
+                let (stream, _) = listener.accept().await?;
+                sessions.push(run_session(session, stream, tls_mode, acceptor.as_ref(), &script).await?);
+            }
+
+            Ok(sessions)
+        });
+
+        Ok(AsyncMockServer { port, root_store, task })
+    }
+
+    /// Returns the port the server listens on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns a store trusting the generated certificate of the server.
+    pub fn root_store(&self) -> RootCertStore {
+        self.root_store.clone()
+    }
+
+    /// Waits until all sessions are done and returns the received commands of each session.
+    ///
+    /// Fails with [`MockMismatch`], if a command was not expected by the script.
+    pub async fn finish(self) -> Result<Vec<Vec<String>>, Pop3AsyncError> {
+        self.task.await?
+    }
+}
+
+async fn run_session(session: usize, stream: TcpStream, tls_mode: TlsMode, acceptor: Option<&TlsAcceptor>, script: &MockScript) -> Result<Vec<String>, Pop3AsyncError> {
+    let mut received = vec!();
+    match (tls_mode, acceptor) {
+        (TlsMode::Implicit, Some(acceptor)) => {
+            let mut stream = BufReader::new(acceptor.accept(stream).await?);
+            stream.write_all(script.greeting.as_bytes()).await?;
+            run_steps(session, &mut stream, &script.steps, false, &mut received).await?;
+        },
+        _ => {
+            let mut stream = BufReader::new(stream);
+            stream.write_all(script.greeting.as_bytes()).await?;
+            let start_tls = tls_mode == TlsMode::StartTls;
+            let position = run_steps(session, &mut stream, &script.steps, start_tls, &mut received).await?;
+            if let (Some(acceptor), true) = (acceptor, position < script.steps.len()) {
+                let mut stream = BufReader::new(acceptor.accept(stream.into_inner()).await?);
+                run_steps(session, &mut stream, &script.steps[position..], false, &mut received).await?;
+            }
+        }
+    }
+
+    Ok(received)
+}
+
+/// Runs the steps of a script; returns the position after STLS, if `start_tls` is set.
+async fn run_steps<S: AsyncRead + AsyncWrite + Unpin>(session: usize, stream: &mut BufReader<S>, steps: &[(String, String)], start_tls: bool, received: &mut Vec<String>) -> Result<usize, Pop3AsyncError> {
+    for (position, (expected, response)) in steps.iter().enumerate() {
+        let mut line = String::new();
+        stream.read_line(&mut line).await?;
+        let line = line.trim_end().to_string();
+        if &line != expected {
+            let _ = stream.write_all(b"-ERR unexpected command\r\n").await;
+            return Err(MockMismatch { session, expected: expected.clone(), received: line }.into());
+        }
+
+        received.push(line);
+        stream.write_all(response.as_bytes()).await?;
+        stream.flush().await?;
+        if start_tls && expected == "STLS" {
+            return Ok(position + 1);
+        }
+    }
+
+    stream.flush().await?;
+    match stream.get_mut().shutdown().await {
+        Err(err) if err.kind() != io::ErrorKind::NotConnected => Err(err.into()),
+        _ => Ok(steps.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Pop3ConnectionBuilder;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    fn script() -> MockScript {
+        MockScript::new()
+            .expect("USER me", "+OK\r\n")
+            .expect("PASS secret", "+OK\r\n")
+            .expect("STAT", "+OK 2 40\r\n")
+            .expect("QUIT", "+OK\r\n")
+    }
+
+    #[test]
+    fn test_tls_modes() {
+        block_on(async {
+            for tls_mode in [TlsMode::Plain, TlsMode::Implicit, TlsMode::StartTls] {
+                let script = match tls_mode {
+                    TlsMode::StartTls => MockScript::new().expect("STLS", "+OK begin TLS\r\n")
+                        .expect("USER me", "+OK\r\n")
+                        .expect("PASS secret", "+OK\r\n")
+                        .expect("STAT", "+OK 2 40\r\n")
+                        .expect("QUIT", "+OK\r\n"),
+                    _ => script()
+                };
+                let server = AsyncMockServer::start(tls_mode, vec!(script)).await.unwrap();
+
+                let mut connection = Pop3ConnectionBuilder::new("localhost")
+                    .tls_mode(tls_mode)
+                    .port(server.port())
+                    .root_store(server.root_store())
+                    .login("me", "secret")
+                    .connect_async()
+                    .await
+                    .unwrap();
+                assert_eq!(2, connection.stat().await.unwrap().message_count);
+                connection.quit().await.unwrap();
+
+                let sessions = server.finish().await.unwrap();
+                assert_eq!("QUIT", sessions[0].last().unwrap());
+            }
+        });
+    }
+
+    #[test]
+    fn test_mismatch() {
+        block_on(async {
+            let server = AsyncMockServer::start(TlsMode::Plain, vec!(script())).await.unwrap();
+
+            let connection = Pop3ConnectionBuilder::new("127.0.0.1")
+                .tls_mode(TlsMode::Plain)
+                .port(server.port())
+                .login("other", "secret")
+                .connect_async()
+                .await;
+            assert!(connection.is_err());
+
+            let err = server.finish().await.unwrap_err();
+            let mismatch = err.downcast_ref::<MockMismatch>().unwrap();
+            assert_eq!("USER other", mismatch.received);
+        });
+    }
+}
@@ -0,0 +1,675 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+
+use super::{self_signed_config, read_line, Random, ReadWrite};
+use crate::{Pop3ConnectionBuilder, TlsMode};
+
+/// Capabilities of a [`MaildropServer`], unless configured otherwise.
+const DEFAULT_CAPABILITIES: [&str; 3] = ["USER", "UIDL", "TOP"];
+
+/// Message of a maildrop.
+#[derive(Clone)]
+struct Message {
+    unique_id: String,
+    content: Vec<u8>,
+}
+
+impl Message {
+
+    /// Returns a message, whose lines are terminated by CRLF.
+    fn new(unique_id: &str, content: &[u8]) -> Self {
+        let mut normalized = Vec::with_capacity(content.len());
+        for line in content.split_inclusive(|&c| c == b'\n') {
+            let line = line.strip_suffix(b"\n").unwrap_or(line);
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            normalized.extend_from_slice(line);
+            normalized.extend_from_slice(b"\r\n");
+        }
+
+        Message { unique_id: unique_id.to_string(), content: normalized }
+    }
+
+    /// Returns the header and the given count of body lines.
+    fn top(&self, line_count: usize) -> &[u8] {
+        let mut lines = self.content.split_inclusive(|&c| c == b'\n');
+        let mut length = 0;
+        for line in lines.by_ref() {
+            length += line.len();
+            if line == b"\r\n" {
+                break;
+            }
+        }
+        length += lines.take(line_count).map(<[u8]>::len).sum::<usize>();
+
+        &self.content[..length]
+    }
+}
+
+/// Messages of a [`MaildropServer`], shared by all sessions.
+struct Maildrop {
+    messages: Vec<Message>,
+    locked: bool,
+}
+
+/// Builder of a [`MaildropServer`].
+pub struct MaildropServerBuilder {
+    tls_mode: TlsMode,
+    port: u16,
+    credentials: Option<(String, String)>,
+    capabilities: Vec<String>,
+    failures: HashMap<String, String>,
+    disconnect_after: Option<usize>,
+    disconnects: f64,
+    stalls: f64,
+    stall: Duration,
+    seed: u64,
+    messages: Vec<Message>,
+}
+
+impl MaildropServerBuilder {
+
+    /// Sets the TLS mode of the server. Defaults to [`TlsMode::Plain`].
+    pub fn tls_mode(mut self, tls_mode: TlsMode) -> Self {
+        self.tls_mode = tls_mode;
+        self
+    }
+
+    /// Sets the port of localhost to listen on. Defaults to a free port.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Accepts only the given credentials. By default, all credentials are accepted.
+    pub fn credentials(mut self, user: &str, password: &str) -> Self {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self
+    }
+
+    /// Sets the capabilities announced by CAPA. Defaults to `USER`, `UIDL` and `TOP`.
+    ///
+    /// The optional commands UIDL and TOP are answered by `-ERR`, if their
+    /// capability is missing. `STLS` is announced in [`TlsMode::StartTls`].
+    pub fn capabilities(mut self, capabilities: &[&str]) -> Self {
+        self.capabilities = capabilities.iter().map(|capability| capability.to_ascii_uppercase()).collect();
+        self
+    }
+
+    /// Answers a command always by the given status line, e.g. to simulate failures.
+    ///
+    /// # Arguments
+    ///
+    /// * `command`  - command name, e.g. `RETR`
+    /// * `response` - status line without line terminator, e.g. `-ERR [SYS/TEMP] try again later`
+    pub fn fail(mut self, command: &str, response: &str) -> Self {
+        self.failures.insert(command.to_ascii_uppercase(), response.to_string());
+        self
+    }
+
+    /// Closes each connection without response after the given count of commands.
+    pub fn disconnect_after(mut self, commands: usize) -> Self {
+        self.disconnect_after = Some(commands);
+        self
+    }
+
+    /// Closes connections at random instead of responding completely.
+    ///
+    /// Before a response is sent, the connection is closed with the given
+    /// probability after a random count of complete lines of the response.
+    pub fn disconnects(mut self, probability: f64) -> Self {
+        self.disconnects = probability;
+        self
+    }
+
+    /// Stops responding at random, e.g. to test timeouts.
+    ///
+    /// # Arguments
+    ///
+    /// * `probability` - probability of a stall before a response
+    /// * `duration`    - time without response, before the connection is closed
+    pub fn stalls(mut self, probability: f64, duration: Duration) -> Self {
+        self.stalls = probability;
+        self.stall = duration;
+        self
+    }
+
+    /// Sets the seed of the pseudo random generator choosing faults. Defaults to 0.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Adds a message to the maildrop; line terminators are converted to CRLF.
+    ///
+    /// # Arguments
+    ///
+    /// * `unique_id` - unique id reported by UIDL
+    /// * `content`   - message in RFC 5322 format
+    pub fn message(mut self, unique_id: &str, content: &[u8]) -> Self {
+        self.messages.push(Message::new(unique_id, content));
+        self
+    }
+
+    /// Adds all `.eml` files of a directory to the maildrop, ordered by file name.
+    ///
+    /// The file name without extension is used as unique id.
+    pub fn load_dir(mut self, path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let mut paths = vec!();
+        for entry in fs::read_dir(path)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("eml")) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        for path in paths {
+            let unique_id = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+            self.messages.push(Message::new(&unique_id, &fs::read(&path)?));
+        }
+
+        Ok(self)
+    }
+
+    /// Starts the server on localhost.
+    ///
+    /// TLS uses a self-signed certificate for the host name `localhost`,
+    /// which is generated on start.
+    pub fn start(self) -> Result<MaildropServer, Box<dyn Error>> {
+        let (config, root_store, certificate_pem) = self_signed_config()?;
+        let listener = TcpListener::bind(("127.0.0.1", self.port))?;
+        let port = listener.local_addr()?.port();
+        let maildrop = Arc::new(Mutex::new(Maildrop { messages: self.messages, locked: false }));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let sessions = Arc::new(AtomicUsize::new(0));
+
+        let server = Arc::new(Server {
+            tls_mode: self.tls_mode,
+            config,
+            credentials: self.credentials,
+            capabilities: self.capabilities,
+            failures: self.failures,
+            disconnect_after: self.disconnect_after,
+            disconnects: self.disconnects,
+            stalls: self.stalls,
+            stall: self.stall,
+            random: Mutex::new(Random(self.seed)),
+            maildrop: maildrop.clone(),
+            sessions: sessions.clone(),
+        });
+        let thread = {
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let server = server.clone();
+                        thread::spawn(move || server.serve(stream));
+                    }
+                }
+            })
+        };
+
+        Ok(MaildropServer { port, tls_mode: self.tls_mode, root_store, certificate_pem, maildrop, sessions, stopped, thread: Some(thread) })
+    }
+}
+
+/// State of a session of a [`MaildropServer`].
+#[derive(Default)]
+struct Session {
+    user: Option<String>,
+    authenticated: bool,
+    deleted: HashSet<usize>,
+    tls: bool,
+}
+
+/// Fault injected instead of a response.
+enum Fault {
+    /// closes the connection after the given count of response lines
+    Disconnect(usize),
+
+    /// closes the connection after a stall
+    Stall,
+}
+
+/// Configuration of a [`MaildropServer`], shared by all sessions.
+struct Server {
+    tls_mode: TlsMode,
+    config: Arc<ServerConfig>,
+    credentials: Option<(String, String)>,
+    capabilities: Vec<String>,
+    failures: HashMap<String, String>,
+    disconnect_after: Option<usize>,
+    disconnects: f64,
+    stalls: f64,
+    stall: Duration,
+    random: Mutex<Random>,
+    maildrop: Arc<Mutex<Maildrop>>,
+    sessions: Arc<AtomicUsize>,
+}
+
+impl Server {
+
+    fn tls(&self, stream: TcpStream) -> Result<Box<dyn ReadWrite>, Box<dyn Error>> {
+        let connection = ServerConnection::new(self.config.clone())?;
+        Ok(Box::new(StreamOwned::new(connection, stream)))
+    }
+
+    /// Serves a connection; the maildrop is unlocked before the connection is closed.
+    fn serve(&self, stream: TcpStream) {
+        self.sessions.fetch_add(1, Ordering::SeqCst);
+        let mut session = Session::default();
+        let _ = self.run(&stream, &mut session);
+        if session.authenticated {
+            if let Ok(mut maildrop) = self.maildrop.lock() {
+                maildrop.locked = false;
+            }
+        }
+        drop(stream);
+        self.sessions.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Returns the fault to inject instead of a response, if any.
+    fn fault(&self, line_count: usize) -> Option<Fault> {
+        let mut random = self.random.lock().ok()?;
+        if random.chance(self.stalls) {
+            return Some(Fault::Stall);
+        }
+        match random.chance(self.disconnects) {
+            true => Some(Fault::Disconnect(random.up_to(line_count) - 1)),
+            false => None
+        }
+    }
+
+    fn run(&self, plain: &TcpStream, session: &mut Session) -> Result<(), Box<dyn Error>> {
+        let mut stream = match self.tls_mode {
+            TlsMode::Implicit => { session.tls = true; self.tls(plain.try_clone()?)? },
+            _ => Box::new(plain.try_clone()?)
+        };
+        stream.write_all(b"+OK POP3 test server ready\r\n")?;
+        stream.flush()?;
+
+        let mut count = 0;
+        while let Some(command) = read_line(&mut stream)? {
+            count += 1;
+            if self.disconnect_after.is_some_and(|limit| count > limit) {
+                break;
+            }
+
+            let mut words = command.split_whitespace();
+            let name = words.next().unwrap_or_default().to_ascii_uppercase();
+            let args: Vec<&str> = words.collect();
+            let response = match self.failures.get(&name) {
+                Some(response) => format!("{}\r\n", response).into_bytes(),
+                None => self.respond(session, &name, &args)?
+            };
+            match self.fault(response.split_inclusive(|&c| c == b'\n').count()) {
+                Some(Fault::Disconnect(line_count)) => {
+                    for line in response.split_inclusive(|&c| c == b'\n').take(line_count) {
+                        stream.write_all(line)?;
+                    }
+                    stream.flush()?;
+                    break;
+                },
+                Some(Fault::Stall) => {
+                    thread::sleep(self.stall);
+                    break;
+                },
+                None => { }
+            }
+            stream.write_all(&response)?;
+            stream.flush()?;
+
+            match name.as_str() {
+                "QUIT" => break,
+                "STLS" if response.starts_with(b"+OK") => {
+                    session.tls = true;
+                    stream = self.tls(plain.try_clone()?)?;
+                },
+                _ => { }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn has_capability(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|item| item.split_whitespace().next() == Some(capability))
+    }
+
+    /// Returns the response to a command.
+    fn respond(&self, session: &mut Session, name: &str, args: &[&str]) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut maildrop = self.maildrop.lock().map_err(|_| "lock poisoned")?;
+        let response = match (session.authenticated, name) {
+            (_, "CAPA") => {
+                let mut response = String::from("+OK capability list follows\r\n");
+                for capability in &self.capabilities {
+                    response.push_str(&format!("{}\r\n", capability));
+                }
+                if self.tls_mode == TlsMode::StartTls && !session.tls {
+                    response.push_str("STLS\r\n");
+                }
+                response.push_str(".\r\n");
+                response
+            },
+            (_, "NOOP") => "+OK\r\n".to_string(),
+            (_, "QUIT") => {
+                if session.authenticated {
+                    let mut index = 0;
+                    maildrop.messages.retain(|_| { index += 1; !session.deleted.contains(&index) });
+                    maildrop.locked = false;
+                    session.authenticated = false;
+                }
+                "+OK bye\r\n".to_string()
+            },
+            (false, "STLS") if self.tls_mode == TlsMode::StartTls && !session.tls => "+OK begin TLS negotiation\r\n".to_string(),
+            (false, "USER") if args.len() == 1 => {
+                session.user = Some(args[0].to_string());
+                "+OK\r\n".to_string()
+            },
+            (false, "PASS") if session.user.is_some() => {
+                let valid = match &self.credentials {
+                    Some((user, password)) => session.user.as_deref() == Some(user.as_str()) && args.join(" ") == *password,
+                    None => true
+                };
+                match (valid, maildrop.locked) {
+                    (false, _) => "-ERR [AUTH] invalid credentials\r\n".to_string(),
+                    (true, true) => "-ERR [IN-USE] maildrop locked\r\n".to_string(),
+                    (true, false) => {
+                        maildrop.locked = true;
+                        session.authenticated = true;
+                        "+OK maildrop locked and ready\r\n".to_string()
+                    }
+                }
+            },
+            (true, "STAT") => {
+                let messages = Self::messages(&maildrop, session);
+                let size: usize = messages.iter().map(|(_, message)| message.content.len()).sum();
+                format!("+OK {} {}\r\n", messages.len(), size)
+            },
+            (true, "LIST") | (true, "UIDL") if name == "LIST" || self.has_capability("UIDL") => {
+                let describe = |message: &Message| match name {
+                    "LIST" => message.content.len().to_string(),
+                    _ => message.unique_id.clone()
+                };
+                match args.first() {
+                    Some(arg) => match Self::message(&maildrop, session, arg) {
+                        Some((id, message)) => format!("+OK {} {}\r\n", id, describe(message)),
+                        None => "-ERR no such message\r\n".to_string()
+                    },
+                    None => {
+                        let mut response = String::from("+OK\r\n");
+                        for (id, message) in Self::messages(&maildrop, session) {
+                            response.push_str(&format!("{} {}\r\n", id, describe(message)));
+                        }
+                        response.push_str(".\r\n");
+                        response
+                    }
+                }
+            },
+            (true, "RETR") | (true, "TOP") if name == "RETR" || self.has_capability("TOP") => {
+                let line_count = args.get(1).and_then(|count| count.parse::<usize>().ok());
+                match (Self::message(&maildrop, session, args.first().unwrap_or(&"")), name, line_count) {
+                    (Some((_, message)), "RETR", _) => return Ok(multi_line(&message.content)),
+                    (Some((_, message)), _, Some(line_count)) => return Ok(multi_line(message.top(line_count))),
+                    (Some(_), _, None) => "-ERR invalid line count\r\n".to_string(),
+                    (None, _, _) => "-ERR no such message\r\n".to_string()
+                }
+            },
+            (true, "DELE") => match Self::message(&maildrop, session, args.first().unwrap_or(&"")) {
+                Some((id, _)) => {
+                    session.deleted.insert(id);
+                    format!("+OK message {} deleted\r\n", id)
+                },
+                None => "-ERR no such message\r\n".to_string()
+            },
+            (true, "RSET") => {
+                session.deleted.clear();
+                "+OK\r\n".to_string()
+            },
+            _ => "-ERR unknown command\r\n".to_string()
+        };
+
+        Ok(response.into_bytes())
+    }
+
+    /// Returns the messages not deleted in the session with their ids.
+    fn messages<'a>(maildrop: &'a Maildrop, session: &Session) -> Vec<(usize, &'a Message)> {
+        maildrop.messages.iter()
+            .enumerate()
+            .map(|(index, message)| (index + 1, message))
+            .filter(|(id, _)| !session.deleted.contains(id))
+            .collect()
+    }
+
+    /// Returns a message not deleted in the session by its id.
+    fn message<'a>(maildrop: &'a Maildrop, session: &Session, id: &str) -> Option<(usize, &'a Message)> {
+        let id = id.parse::<usize>().ok()?;
+        match id >= 1 && !session.deleted.contains(&id) {
+            true => maildrop.messages.get(id - 1).map(|message| (id, message)),
+            false => None
+        }
+    }
+}
+
+/// Returns a positive multi-line response of the given content using byte-stuffing.
+fn multi_line(content: &[u8]) -> Vec<u8> {
+    let mut response = b"+OK\r\n".to_vec();
+    for line in content.split_inclusive(|&c| c == b'\n') {
+        if line.starts_with(b".") {
+            response.push(b'.');
+        }
+        response.extend_from_slice(line);
+    }
+    response.extend_from_slice(b".\r\n");
+    response
+}
+
+/// POP3 server on localhost serving a maildrop, e.g. for end-to-end tests.
+///
+/// In contrast to [`super::MockServer`], the server implements the
+/// commands of RFC 1939: messages can be listed, retrieved and deleted.
+/// Deletions are applied to the maildrop when a session ends by QUIT, so
+/// that following sessions see them. Only one session at a time can access
+/// the maildrop; further logins are refused by `-ERR [IN-USE]`.
+///
+/// The `pop3-test-server` binary serves a directory of `.eml` files using
+/// this server.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::test_util::MaildropServer;
+///
+/// let server = MaildropServer::builder()
+///     .message("first", b"Subject: hello\r\n\r\nHello\r\n")
+///     .start()
+///     .unwrap();
+///
+/// let mut connection = server.connection_builder().login("me", "secret").connect().unwrap();
+/// assert_eq!(1, connection.stat().unwrap().message_count);
+/// connection.delete(1).unwrap();
+/// connection.quit().unwrap();
+///
+/// assert!(server.unique_ids().is_empty());
+/// ```
+pub struct MaildropServer {
+    port: u16,
+    tls_mode: TlsMode,
+    root_store: RootCertStore,
+    certificate_pem: String,
+    maildrop: Arc<Mutex<Maildrop>>,
+    sessions: Arc<AtomicUsize>,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MaildropServer {
+
+    /// Returns a builder of a server using plain text with an empty maildrop.
+    pub fn builder() -> MaildropServerBuilder {
+        MaildropServerBuilder {
+            tls_mode: TlsMode::Plain,
+            port: 0,
+            credentials: None,
+            capabilities: DEFAULT_CAPABILITIES.iter().map(ToString::to_string).collect(),
+            failures: HashMap::new(),
+            disconnect_after: None,
+            disconnects: 0.0,
+            stalls: 0.0,
+            stall: Duration::ZERO,
+            seed: 0,
+            messages: vec!(),
+        }
+    }
+
+    /// Returns the port the server listens on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns a store trusting the generated certificate of the server.
+    pub fn root_store(&self) -> RootCertStore {
+        self.root_store.clone()
+    }
+
+    /// Returns the generated certificate of the server in PEM format.
+    pub fn certificate_pem(&self) -> &str {
+        &self.certificate_pem
+    }
+
+    /// Returns a connection builder configured to connect to this server.
+    pub fn connection_builder(&self) -> Pop3ConnectionBuilder {
+        Pop3ConnectionBuilder::new("localhost")
+            .port(self.port)
+            .tls_mode(self.tls_mode)
+            .root_store(self.root_store())
+    }
+
+    /// Returns the unique ids of the messages of the maildrop.
+    pub fn unique_ids(&self) -> Vec<String> {
+        self.maildrop.lock()
+            .map(|maildrop| maildrop.messages.iter().map(|message| message.unique_id.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Returns the count of open connections.
+    pub fn active_sessions(&self) -> usize {
+        self.sessions.load(Ordering::SeqCst)
+    }
+
+    /// Serves connections until the process is terminated.
+    pub fn wait(mut self) {
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for MaildropServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // wake up the accepting thread
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server() -> MaildropServerBuilder {
+        MaildropServer::builder()
+            .message("a", b"Subject: first\n\n.hidden\nbody\n")
+            .message("b", b"Subject: second\r\n\r\nline 1\r\nline 2\r\n")
+    }
+
+    #[test]
+    fn test_session() {
+        let server = server().tls_mode(TlsMode::StartTls).credentials("me", "secret").start().unwrap();
+
+        let mut connection = server.connection_builder().login("me", "secret").connect().unwrap();
+        assert_eq!(2, connection.stat().unwrap().message_count);
+        let unique_ids: Vec<String> = connection.list_unique_ids().unwrap().into_iter().map(|info| info.unique_id).collect();
+        assert_eq!(vec!("a", "b"), unique_ids);
+
+        let mut content = vec!();
+        connection.retrieve_raw(1, &mut content).unwrap();
+        assert_eq!(b"Subject: first\r\n\r\n.hidden\r\nbody\r\n".to_vec(), content);
+        assert_eq!(b"Subject: second\r\n\r\nline 1\r\n".to_vec(), connection.top_raw(2, 1).unwrap());
+
+        assert!(server.connection_builder().login("me", "secret").connect().is_err());
+
+        connection.delete(1).unwrap();
+        connection.quit().unwrap();
+        assert_eq!(vec!("b"), server.unique_ids());
+
+        assert!(server.connection_builder().login("me", "wrong").connect().is_err());
+    }
+
+    #[test]
+    fn test_capabilities_and_failures() {
+        let server = server()
+            .capabilities(&["USER"])
+            .fail("RETR", "-ERR [SYS/TEMP] try again later")
+            .start()
+            .unwrap();
+
+        let mut connection = server.connection_builder().login("me", "secret").connect().unwrap();
+        assert!(connection.list_unique_ids().is_err());
+        let err = connection.retrieve_raw(1, &mut vec!()).unwrap_err();
+        assert_eq!("-ERR [SYS/TEMP] try again later", err.to_string());
+        connection.reset().unwrap();
+    }
+
+    #[test]
+    fn test_disconnect_after() {
+        let server = server().disconnect_after(3).start().unwrap();
+
+        let mut connection = server.connection_builder().login("me", "secret").connect().unwrap();
+        connection.noop().unwrap();
+        assert!(connection.noop().is_err());
+
+        let connection = server.connection_builder().login("me", "secret").connect();
+        assert!(connection.is_ok(), "maildrop not unlocked after disconnect");
+    }
+
+    #[test]
+    fn test_disconnects() {
+        let server = server().disconnects(1.0).seed(7).start().unwrap();
+
+        assert!(server.connection_builder().login("me", "secret").connect().is_err());
+        for _ in 0..100 {
+            if server.active_sessions() == 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(0, server.active_sessions());
+    }
+
+    #[test]
+    fn test_load_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("2.eml"), "Subject: 2\n\n").unwrap();
+        fs::write(dir.path().join("1.eml"), "Subject: 1\n\n").unwrap();
+        fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let server = MaildropServer::builder().load_dir(dir.path()).unwrap().start().unwrap();
+        assert_eq!(vec!("1", "2"), server.unique_ids());
+    }
+}
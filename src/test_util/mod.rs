@@ -0,0 +1,1014 @@
+//! Utilities for integration tests of applications using this crate.
+//!
+//! Requires the `test-util` feature. [`MockServer`] serves real sockets,
+//! [`MaildropServer`] serves a maildrop of messages, [`ScriptedTransport`]
+//! replays a transcript entirely in memory and [`FaultInjector`] disturbs
+//! any transport to test error handling. With the `mock-server` feature,
+//! `AsyncMockServer` runs a [`MockScript`] per session on a tokio runtime
+//! and with the `tokio` feature, `SoakTest` runs a long-lived session
+//! against a faulty [`MaildropServer`]. All servers use a self-signed
+//! certificate for `localhost`, which is generated on start.
+
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::future::Future;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use futures_util::io::{AsyncRead, AsyncWrite};
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+
+#[cfg(feature = "mock-server")]
+mod async_mock;
+mod maildrop;
+mod script;
+
+use crate::transcript;
+#[cfg(feature = "mock-server")]
+pub use async_mock::AsyncMockServer;
+pub use maildrop::{MaildropServer, MaildropServerBuilder};
+pub use script::{MockMismatch, MockScript};
+#[cfg(test)]
+pub(crate) use script::{connect, serve, serve_sessions, unreachable_account};
+#[cfg(all(feature = "test-util", feature = "tokio"))]
+pub use crate::soak::{SoakReport, SoakTest};
+use crate::{AsyncTimer, Pop3ConnectionBuilder, TlsMode};
+
+pub(crate) trait ReadWrite: Read + Write + Send { }
+
+impl<T: Read + Write + Send> ReadWrite for T { }
+
+/// Canned responses of a [`MockServer`], shared by all sessions.
+struct Responses {
+    greeting: String,
+    responses: HashMap<String, VecDeque<String>>,
+}
+
+impl Responses {
+
+    /// Returns the response to a command.
+    ///
+    /// Responses of the exact command line take precedence over responses
+    /// of the command name. If multiple responses were configured, they are
+    /// used in order and the last one is repeated.
+    fn respond(&mut self, command: &str) -> Option<String> {
+        let name = command.split_whitespace().next().unwrap_or_default().to_ascii_uppercase();
+        let queue = match self.responses.contains_key(command) {
+            true => self.responses.get_mut(command),
+            false => self.responses.get_mut(&name)
+        }?;
+
+        match queue.len() {
+            1 => queue.front().cloned(),
+            _ => queue.pop_front()
+        }
+    }
+}
+
+/// Builder of a [`MockServer`].
+pub struct MockServerBuilder {
+    tls_mode: TlsMode,
+    responses: Responses,
+    latency: Duration,
+    byte_delay: Duration,
+}
+
+impl MockServerBuilder {
+
+    /// Sets the TLS mode of the server. Defaults to [`TlsMode::Plain`].
+    pub fn tls_mode(mut self, tls_mode: TlsMode) -> Self {
+        self.tls_mode = tls_mode;
+        self
+    }
+
+    /// Sets the greeting sent after connecting, including its line terminator.
+    pub fn greeting(mut self, greeting: &str) -> Self {
+        self.responses.greeting = greeting.to_string();
+        self
+    }
+
+    /// Adds the response to a command.
+    ///
+    /// Responses are sent as is, so they must contain the line terminators
+    /// and, for multi-line responses, the terminating line. Multiple
+    /// responses of the same command are sent in order; the last one is
+    /// repeated. Commands without response are answered by `-ERR`, except
+    /// `QUIT`, which ends the session, and `STLS`, which upgrades the
+    /// session to TLS in [`TlsMode::StartTls`].
+    ///
+    /// # Arguments
+    ///
+    /// * `command`  - command name, e.g. `STAT`, or exact command line, e.g. `RETR 1`
+    /// * `response` - response sent to the client
+    pub fn respond(mut self, command: &str, response: &str) -> Self {
+        self.responses.responses.entry(command.to_string()).or_default().push_back(response.to_string());
+        self
+    }
+
+    /// Simulates a slow link by throttling the greeting and all responses.
+    ///
+    /// Each response is sent byte by byte, so that clients receive it in
+    /// pieces, e.g. to verify timeouts and progress reports.
+    ///
+    /// # Arguments
+    ///
+    /// * `latency`    - delay before the first byte of a response
+    /// * `byte_delay` - delay before each further byte
+    pub fn throttle(mut self, latency: Duration, byte_delay: Duration) -> Self {
+        self.latency = latency;
+        self.byte_delay = byte_delay;
+        self
+    }
+
+    /// Starts the server on a free port of localhost.
+    ///
+    /// TLS uses a self-signed certificate for the host name `localhost`,
+    /// which is generated on start.
+    pub fn start(self) -> Result<MockServer, Box<dyn Error>> {
+        let (config, root_store, _) = self_signed_config()?;
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        let commands = Arc::new(Mutex::new(vec!()));
+        let stopped = Arc::new(AtomicBool::new(false));
+
+        let session = Arc::new(Session {
+            tls_mode: self.tls_mode,
+            config,
+            responses: Mutex::new(self.responses),
+            commands: commands.clone(),
+            latency: self.latency,
+            byte_delay: self.byte_delay,
+        });
+        let thread = {
+            let stopped = stopped.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let session = session.clone();
+                        thread::spawn(move || { let _ = session.run(stream); });
+                    }
+                }
+            })
+        };
+
+        Ok(MockServer { port, tls_mode: self.tls_mode, root_store, commands, stopped, thread: Some(thread) })
+    }
+}
+
+/// Returns a TLS configuration using a self-signed certificate for `localhost`,
+/// a store trusting the certificate and the certificate in PEM format.
+pub(crate) fn self_signed_config() -> Result<(Arc<ServerConfig>, RootCertStore, String), Box<dyn Error>> {
+    let certificate = rcgen::generate_simple_self_signed(vec!("localhost".to_string()))?;
+    let certificate_der = certificate.serialize_der()?;
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(vec!(Certificate(certificate_der.clone())), PrivateKey(certificate.serialize_private_key_der()))?;
+    let mut root_store = RootCertStore::empty();
+    root_store.add(&Certificate(certificate_der))?;
+
+    Ok((Arc::new(config), root_store, certificate.serialize_pem()?))
+}
+
+/// Configuration and state shared by the sessions of a [`MockServer`].
+struct Session {
+    tls_mode: TlsMode,
+    config: Arc<ServerConfig>,
+    responses: Mutex<Responses>,
+    commands: Arc<Mutex<Vec<String>>>,
+    latency: Duration,
+    byte_delay: Duration,
+}
+
+impl Session {
+
+    fn tls(&self, stream: TcpStream) -> Result<Box<dyn ReadWrite>, Box<dyn Error>> {
+        let connection = ServerConnection::new(self.config.clone())?;
+        Ok(Box::new(StreamOwned::new(connection, stream)))
+    }
+
+    /// Sends a response; byte by byte, if the server is throttled.
+    fn send(&self, stream: &mut impl Write, response: &[u8]) -> io::Result<()> {
+        thread::sleep(self.latency);
+        match self.byte_delay.is_zero() {
+            true => stream.write_all(response)?,
+            false => for (index, byte) in response.iter().enumerate() {
+                if index > 0 {
+                    thread::sleep(self.byte_delay);
+                }
+                stream.write_all(&[*byte])?;
+                stream.flush()?;
+            }
+        }
+        stream.flush()
+    }
+
+    fn run(&self, plain: TcpStream) -> Result<(), Box<dyn Error>> {
+        let mut stream = match self.tls_mode {
+            TlsMode::Implicit => self.tls(plain.try_clone()?)?,
+            _ => Box::new(plain.try_clone()?)
+        };
+
+        let greeting = self.responses.lock().map_err(|_| "lock poisoned")?.greeting.clone();
+        self.send(&mut stream, greeting.as_bytes())?;
+
+        while let Some(command) = read_line(&mut stream)? {
+            self.commands.lock().map_err(|_| "lock poisoned")?.push(command.clone());
+            let response = self.responses.lock().map_err(|_| "lock poisoned")?.respond(&command);
+            let name = command.split_whitespace().next().unwrap_or_default().to_ascii_uppercase();
+            let response = match (response, name.as_str()) {
+                (Some(response), _) => response,
+                (None, "QUIT") => "+OK bye\r\n".to_string(),
+                (None, "STLS") if self.tls_mode == TlsMode::StartTls => "+OK begin TLS negotiation\r\n".to_string(),
+                (None, _) => "-ERR unknown command\r\n".to_string()
+            };
+            self.send(&mut stream, response.as_bytes())?;
+
+            match name.as_str() {
+                "QUIT" => break,
+                "STLS" if self.tls_mode == TlsMode::StartTls && response.starts_with("+OK") => {
+                    stream = self.tls(plain.try_clone()?)?;
+                },
+                _ => { }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads a line without its line terminator; returns `None` if the client closed the connection.
+pub(crate) fn read_line(stream: &mut impl Read) -> io::Result<Option<String>> {
+    let mut line = vec!();
+    let mut byte = [0u8; 1];
+    loop {
+        match stream.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) if byte[0] == b'\n' => break,
+            Ok(_) => line.push(byte[0]),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err)
+        }
+    }
+
+    Ok(Some(String::from_utf8_lossy(&line).trim_end_matches('\r').to_string()))
+}
+
+/// POP3 server on localhost with canned responses for integration tests.
+///
+/// The server accepts any number of connections until it is dropped and
+/// records the commands received by all sessions.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::test_util::MockServer;
+/// use rust_pop3_client::TlsMode;
+///
+/// let server = MockServer::builder()
+///     .tls_mode(TlsMode::Implicit)
+///     .respond("USER", "+OK\r\n")
+///     .respond("PASS", "+OK\r\n")
+///     .respond("STAT", "+OK 1 120\r\n")
+///     .start()
+///     .unwrap();
+///
+/// let mut connection = server.connection_builder().login("me", "secret").connect().unwrap();
+/// assert_eq!(1, connection.stat().unwrap().message_count);
+/// connection.quit().unwrap();
+///
+/// assert_eq!(vec!("USER me", "PASS secret", "STAT", "QUIT"), server.commands());
+/// ```
+pub struct MockServer {
+    port: u16,
+    tls_mode: TlsMode,
+    root_store: RootCertStore,
+    commands: Arc<Mutex<Vec<String>>>,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+
+    /// Returns a builder of a server using plain text, which answers all commands by `-ERR`.
+    pub fn builder() -> MockServerBuilder {
+        MockServerBuilder {
+            tls_mode: TlsMode::Plain,
+            responses: Responses { greeting: "+OK mock server ready\r\n".into(), responses: HashMap::new() },
+            latency: Duration::ZERO,
+            byte_delay: Duration::ZERO,
+        }
+    }
+
+    /// Returns the port the server listens on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns a store trusting the generated certificate of the server.
+    pub fn root_store(&self) -> RootCertStore {
+        self.root_store.clone()
+    }
+
+    /// Returns a connection builder configured to connect to this server.
+    pub fn connection_builder(&self) -> Pop3ConnectionBuilder {
+        Pop3ConnectionBuilder::new("localhost")
+            .port(self.port)
+            .tls_mode(self.tls_mode)
+            .root_store(self.root_store())
+    }
+
+    /// Returns the commands received so far by all sessions, without line terminators.
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.lock().map(|commands| commands.clone()).unwrap_or_default()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // wake up the accepting thread
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// State of a [`ScriptedTransport`], shared by its clones.
+#[derive(Debug, Default)]
+struct Transcript {
+    steps: VecDeque<(String, String)>,
+    output: VecDeque<u8>,
+    line: Vec<u8>,
+    failure: Option<String>,
+}
+
+/// In-memory transport, which replays a transcript of commands and responses.
+///
+/// The transport implements the `futures` I/O traits used by
+/// [`crate::AsyncPop3Connection::from_stream`] as well as [`Read`] and
+/// [`Write`], e.g. to drive a [`crate::Pop3Engine`]. It is always ready, so
+/// futures using it complete on first poll without any runtime.
+///
+/// Each command written must match the next expected command of the
+/// transcript; otherwise writing fails and all further I/O fails as well.
+/// Reading fails, if the client waits for a response although the
+/// transcript expects another command first. Clones share the transcript,
+/// so that a clone can be checked after the transport was moved into a
+/// connection.
+///
+/// # Examples
+///
+/// ```
+/// use futures_util::FutureExt;
+/// use rust_pop3_client::AsyncPop3Connection;
+/// use rust_pop3_client::test_util::ScriptedTransport;
+///
+/// let transport = ScriptedTransport::new()
+///     .expect("STAT", "+OK 2 320\r\n")
+///     .expect("QUIT", "+OK bye\r\n");
+///
+/// let mut connection = AsyncPop3Connection::from_stream(transport.clone()).now_or_never().unwrap().unwrap();
+/// assert_eq!(2, connection.stat().now_or_never().unwrap().unwrap().message_count);
+/// connection.quit().now_or_never().unwrap().unwrap();
+/// transport.assert_done();
+/// ```
+#[derive(Clone, Debug)]
+pub struct ScriptedTransport {
+    transcript: Arc<Mutex<Transcript>>,
+}
+
+impl ScriptedTransport {
+
+    /// Returns a new transport, which sends the default greeting.
+    pub fn new() -> Self {
+        Self::with_greeting("+OK scripted server ready\r\n")
+    }
+
+    /// Returns a new transport, which sends the given greeting including its line terminator.
+    pub fn with_greeting(greeting: &str) -> Self {
+        let transcript = Transcript { output: greeting.bytes().collect(), ..Transcript::default() };
+        ScriptedTransport { transcript: Arc::new(Mutex::new(transcript)) }
+    }
+
+    /// Returns a transport replaying a transcript recorded by [`crate::Pop3Connection::set_transcript`].
+    ///
+    /// Redacted secrets (`***`) match any value, so that the client can
+    /// send arbitrary credentials.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - content of the transcript
+    pub fn from_transcript(text: &str) -> Result<Self, Box<dyn Error>> {
+        let (greeting, steps) = transcript::parse(text)?;
+        Ok(steps.iter().fold(Self::with_greeting(&greeting), |transport, (command, response)| transport.expect(command, response)))
+    }
+
+    /// Returns a transport replaying a transcript file.
+    ///
+    /// See [`ScriptedTransport::from_transcript`].
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Self::from_transcript(&std::fs::read_to_string(path)?)
+    }
+
+    /// Adds an expected command and its response.
+    ///
+    /// # Arguments
+    ///
+    /// * `command`  - expected command without line terminator, e.g. `RETR 1`; a trailing `***` matches any value
+    /// * `response` - response including line terminators, sent after the command was received
+    pub fn expect(self, command: &str, response: &str) -> Self {
+        self.lock().steps.push_back((command.to_string(), response.to_string()));
+        self
+    }
+
+    /// Returns true, if all expected commands were received and all responses were read.
+    pub fn is_done(&self) -> bool {
+        let transcript = self.lock();
+        transcript.steps.is_empty() && transcript.output.is_empty() && transcript.failure.is_none()
+    }
+
+    /// Panics, if the transcript deviated or was not replayed completely.
+    pub fn assert_done(&self) {
+        let mut transcript = self.lock();
+        if let Some(failure) = &transcript.failure {
+            panic!("scripted transport failed: {}", failure);
+        }
+        if let Some((command, _)) = transcript.steps.front() {
+            panic!("scripted transport: expected command \"{}\" was not received", command);
+        }
+        if !transcript.output.is_empty() {
+            panic!("scripted transport: response was not read: {:?}", String::from_utf8_lossy(transcript.output.make_contiguous()));
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Transcript> {
+        self.transcript.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Default for ScriptedTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Transcript {
+
+    fn check(&self) -> io::Result<()> {
+        match &self.failure {
+            Some(failure) => Err(io::Error::new(io::ErrorKind::InvalidData, failure.clone())),
+            None => Ok(())
+        }
+    }
+
+    fn fail(&mut self, failure: String) -> io::Error {
+        self.failure = Some(failure.clone());
+        io::Error::new(io::ErrorKind::InvalidData, failure)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check()?;
+        if self.output.is_empty() && !buf.is_empty() {
+            return match self.steps.front() {
+                Some((command, _)) => Err(self.fail(format!("read while expecting command \"{}\"", command))),
+                None => Ok(0)
+            };
+        }
+
+        let count = buf.len().min(self.output.len());
+        for (target, byte) in buf.iter_mut().zip(self.output.drain(..count)) {
+            *target = byte;
+        }
+        Ok(count)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.check()?;
+        for &byte in buf {
+            self.line.push(byte);
+            if byte != b'\n' {
+                continue;
+            }
+
+            let line = String::from_utf8_lossy(&self.line).trim_end_matches(['\r', '\n']).to_string();
+            self.line.clear();
+            match self.steps.pop_front() {
+                Some((command, response)) if transcript::matches(&command, &line) => self.output.extend(response.bytes()),
+                Some((command, _)) => return Err(self.fail(format!("expected \"{}\", received \"{}\"", command, line))),
+                None => return Err(self.fail(format!("unexpected command \"{}\"", line)))
+            }
+        }
+        Ok(buf.len())
+    }
+}
+
+impl Read for ScriptedTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.lock().read(buf)
+    }
+}
+
+impl Write for ScriptedTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for ScriptedTransport {
+    fn poll_read(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.lock().read(buf))
+    }
+}
+
+impl AsyncWrite for ScriptedTransport {
+    fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.lock().write(buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Deterministic pseudo random numbers (SplitMix64).
+#[derive(Clone, Debug)]
+pub(crate) struct Random(pub(crate) u64);
+
+impl Random {
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut value = self.0;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        value ^ (value >> 31)
+    }
+
+    /// Returns true with the given probability.
+    pub(crate) fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    /// Returns a number in `1..=max`; `max` must not be 0.
+    pub(crate) fn up_to(&mut self, max: usize) -> usize {
+        1 + (self.next() % max as u64) as usize
+    }
+}
+
+/// Transport, which wraps another transport and injects faults.
+///
+/// Faults are chosen by a pseudo random generator, so that a seed always
+/// produces the same faults for the same traffic. Each fault is configured
+/// by its probability per read or write:
+///
+/// * short reads return only a part of the available data
+/// * split writes accept only a part of the data, so that it is written in pieces
+/// * delays postpone reads; without a timer, reads only yield to the executor
+/// * disconnects close the transport, e.g. in the middle of a response
+/// * garbage injects random bytes into the received data
+///
+/// # Examples
+///
+/// ```
+/// use futures_util::FutureExt;
+/// use rust_pop3_client::AsyncPop3Connection;
+/// use rust_pop3_client::test_util::{FaultInjector, ScriptedTransport};
+///
+/// let transport = ScriptedTransport::new().expect("STAT", "+OK 2 320\r\n");
+/// let transport = FaultInjector::new(transport, 42).short_reads(0.5).split_writes(0.5);
+///
+/// let mut connection = AsyncPop3Connection::from_stream(transport).now_or_never().unwrap().unwrap();
+/// assert_eq!(2, connection.stat().now_or_never().unwrap().unwrap().message_count);
+/// ```
+pub struct FaultInjector<S> {
+    inner: S,
+    random: Random,
+    short_reads: f64,
+    split_writes: f64,
+    delays: f64,
+    delay: Duration,
+    timer: Option<Arc<dyn AsyncTimer>>,
+    pending_delay: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    delayed: bool,
+    disconnects: f64,
+    disconnected: bool,
+    garbage: f64,
+}
+
+impl<S> FaultInjector<S> {
+
+    /// Returns a transport, which does not inject any faults yet.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - wrapped transport
+    /// * `seed`  - seed of the pseudo random generator
+    pub fn new(inner: S, seed: u64) -> Self {
+        FaultInjector {
+            inner,
+            random: Random(seed),
+            short_reads: 0.0,
+            split_writes: 0.0,
+            delays: 0.0,
+            delay: Duration::ZERO,
+            timer: None,
+            pending_delay: None,
+            delayed: false,
+            disconnects: 0.0,
+            disconnected: false,
+            garbage: 0.0,
+        }
+    }
+
+    /// Sets the probability of short reads.
+    pub fn short_reads(mut self, probability: f64) -> Self {
+        self.short_reads = probability;
+        self
+    }
+
+    /// Sets the probability of split writes.
+    pub fn split_writes(mut self, probability: f64) -> Self {
+        self.split_writes = probability;
+        self
+    }
+
+    /// Sets the probability and duration of delays.
+    ///
+    /// # Arguments
+    ///
+    /// * `probability` - probability of a delay before a read
+    /// * `delay`       - duration of a delay
+    /// * `timer`       - timer of the runtime; `None` to only yield to the executor
+    pub fn delays(mut self, probability: f64, delay: Duration, timer: Option<Arc<dyn AsyncTimer>>) -> Self {
+        self.delays = probability;
+        self.delay = delay;
+        self.timer = timer;
+        self
+    }
+
+    /// Sets the probability of disconnects.
+    pub fn disconnects(mut self, probability: f64) -> Self {
+        self.disconnects = probability;
+        self
+    }
+
+    /// Sets the probability of garbage bytes.
+    pub fn garbage(mut self, probability: f64) -> Self {
+        self.garbage = probability;
+        self
+    }
+
+    /// Returns the wrapped transport.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns `Pending` while a delay is active.
+    fn poll_delay(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.pending_delay.is_none() && !self.delayed && self.random.chance(self.delays) {
+            self.delayed = true;
+            match &self.timer {
+                Some(timer) => self.pending_delay = Some(timer.sleep(self.delay)),
+                None => {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+            }
+        }
+
+        if let Some(delay) = self.pending_delay.as_mut() {
+            if delay.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            self.pending_delay = None;
+        }
+        self.delayed = false;
+        Poll::Ready(())
+    }
+}
+
+fn disconnected() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "connection closed by fault injection")
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for FaultInjector<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.disconnected {
+            return Poll::Ready(Ok(0));
+        }
+        if this.poll_delay(cx).is_pending() {
+            return Poll::Pending;
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        if this.random.chance(this.disconnects) {
+            this.disconnected = true;
+            return Poll::Ready(Ok(0));
+        }
+        if this.random.chance(this.garbage) {
+            let count = this.random.up_to(buf.len().min(16));
+            for byte in buf[..count].iter_mut() {
+                *byte = this.random.next() as u8;
+            }
+            return Poll::Ready(Ok(count));
+        }
+
+        let length = match this.random.chance(this.short_reads) {
+            true => this.random.up_to(buf.len()),
+            false => buf.len()
+        };
+        Pin::new(&mut this.inner).poll_read(cx, &mut buf[..length])
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for FaultInjector<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if this.disconnected {
+            return Poll::Ready(Err(disconnected()));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let length = match this.random.chance(this.split_writes) {
+            true => this.random.up_to(buf.len()),
+            false => buf.len()
+        };
+        Pin::new(&mut this.inner).poll_write(cx, &buf[..length])
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.disconnected {
+            true => Poll::Ready(Err(disconnected())),
+            false => Pin::new(&mut this.inner).poll_flush(cx)
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_close(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::FutureExt;
+    use crate::AsyncPop3Connection;
+
+    fn run_session(tls_mode: TlsMode) {
+        let server = MockServer::builder()
+            .tls_mode(tls_mode)
+            .respond("USER", "+OK\r\n")
+            .respond("PASS", "+OK\r\n")
+            .respond("RETR 1", "+OK\r\nHello\r\n.\r\n")
+            .start()
+            .unwrap();
+
+        let mut connection = server.connection_builder().login("me", "secret").connect().unwrap();
+        let mut content = vec!();
+        connection.retrieve_raw(1, &mut content).unwrap();
+        assert_eq!(b"Hello\r\n", content.as_slice());
+        assert!(connection.retrieve_raw(2, &mut vec!()).is_err());
+        connection.quit().unwrap();
+
+        let commands = server.commands();
+        assert_eq!(vec!("USER me", "PASS secret", "RETR 1", "RETR 2", "QUIT"), commands[commands.len() - 5..]);
+    }
+
+    #[test]
+    fn test_plain() {
+        run_session(TlsMode::Plain);
+    }
+
+    #[test]
+    fn test_implicit_tls() {
+        run_session(TlsMode::Implicit);
+    }
+
+    #[test]
+    fn test_start_tls() {
+        run_session(TlsMode::StartTls);
+    }
+
+    #[test]
+    fn test_session_events_report_tls() {
+        let server = MockServer::builder().tls_mode(TlsMode::StartTls).start().unwrap();
+        let events = Arc::new(Mutex::new(vec!()));
+        let recorded = events.clone();
+        let connection = server.connection_builder()
+            .observer(move |event: &crate::Pop3SessionEvent| recorded.lock().unwrap().push(event.clone()))
+            .connect()
+            .unwrap();
+        connection.quit().unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(crate::Pop3SessionEvent::Connected { host: "localhost".into(), port: server.port() }, events[0]);
+        assert!(matches!(&events[1], crate::Pop3SessionEvent::TlsEstablished { cipher } if cipher.starts_with("TLS")));
+    }
+
+    #[test]
+    fn test_throttle() {
+        let server = MockServer::builder()
+            .greeting("+OK\r\n")
+            .respond("RETR 1", "+OK\r\nHello\r\n.\r\n")
+            .throttle(Duration::from_millis(10), Duration::from_millis(2))
+            .start()
+            .unwrap();
+
+        let started = std::time::Instant::now();
+        let mut connection = server.connection_builder().connect().unwrap();
+        let mut content = vec!();
+        connection.retrieve_raw(1, &mut content).unwrap();
+        assert_eq!(b"Hello\r\n", content.as_slice());
+        // greeting and response: 2 latencies and 4 + 14 byte delays
+        assert!(started.elapsed() >= Duration::from_millis(56));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_throttle_exceeds_timeout() {
+        let server = MockServer::builder()
+            .greeting("+OK\r\n")
+            .respond("STAT", "+OK 1 120\r\n")
+            .throttle(Duration::ZERO, Duration::from_millis(20))
+            .start()
+            .unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async {
+            let mut connection = server.connection_builder().connect_async().await.unwrap();
+            connection.set_timeout(Some(Duration::from_millis(100)));
+            let err = connection.stat().await.unwrap_err();
+            assert_eq!(Some(io::ErrorKind::TimedOut), err.downcast_ref::<io::Error>().map(io::Error::kind));
+            assert!(connection.is_poisoned());
+        });
+    }
+
+    #[test]
+    fn test_responses_in_order() {
+        let server = MockServer::builder()
+            .greeting("+OK hello\r\n")
+            .respond("STAT", "+OK 2 20\r\n")
+            .respond("STAT", "+OK 1 10\r\n")
+            .start()
+            .unwrap();
+
+        let mut connection = server.connection_builder().connect().unwrap();
+        assert_eq!(2, connection.stat().unwrap().message_count);
+        assert_eq!(1, connection.stat().unwrap().message_count);
+        assert_eq!(1, connection.stat().unwrap().message_count);
+    }
+
+    #[test]
+    fn test_scripted_transport() {
+        let transport = ScriptedTransport::new()
+            .expect("LIST", "+OK\r\n1 20\r\n.\r\n")
+            .expect("RETR 1", "+OK\r\n..dot\r\n.\r\n");
+
+        let mut connection = AsyncPop3Connection::from_stream(transport.clone()).now_or_never().unwrap().unwrap();
+        assert_eq!(1, connection.list().now_or_never().unwrap().unwrap().len());
+        let mut content = vec!();
+        connection.retrieve_raw(1, &mut content).now_or_never().unwrap().unwrap();
+        assert_eq!(b".dot\r\n", content.as_slice());
+        assert!(transport.is_done());
+    }
+
+    #[test]
+    fn test_scripted_transport_fails_on_deviation() {
+        let transport = ScriptedTransport::new().expect("STAT", "+OK 0 0\r\n");
+
+        let mut connection = AsyncPop3Connection::from_stream(transport.clone()).now_or_never().unwrap().unwrap();
+        let err = connection.list_unique_ids().now_or_never().unwrap().err().unwrap();
+        assert_eq!("expected \"STAT\", received \"UIDL\"", err.to_string());
+        assert!(!transport.is_done());
+    }
+
+    #[test]
+    #[should_panic(expected = "expected command \"QUIT\" was not received")]
+    fn test_scripted_transport_incomplete() {
+        let transport = ScriptedTransport::new().expect("QUIT", "+OK\r\n");
+        AsyncPop3Connection::from_stream(transport.clone()).now_or_never().unwrap().unwrap();
+        transport.assert_done();
+    }
+
+    #[test]
+    fn test_replay_recorded_transcript() {
+        let server = MockServer::builder()
+            .respond("USER", "+OK\r\n")
+            .respond("PASS", "+OK\r\n")
+            .respond("RETR 1", "+OK\r\n..dot\r\n.\r\n")
+            .start()
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.txt");
+        let mut connection = server.connection_builder().record_transcript(&path).login("me", "secret").connect().unwrap();
+        connection.retrieve_raw(1, &mut vec!()).unwrap();
+        drop(connection);
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("secret"));
+
+        let transport = ScriptedTransport::load(&path).unwrap();
+        let mut connection = AsyncPop3Connection::from_stream(transport.clone()).now_or_never().unwrap().unwrap();
+        connection.login("other", "password").now_or_never().unwrap().unwrap_err();
+        let transport = ScriptedTransport::load(&path).unwrap();
+        let mut connection = AsyncPop3Connection::from_stream(transport.clone()).now_or_never().unwrap().unwrap();
+        connection.login("me", "password").now_or_never().unwrap().unwrap();
+        let mut content = vec!();
+        connection.retrieve_raw(1, &mut content).now_or_never().unwrap().unwrap();
+        assert_eq!(b".dot\r\n", content.as_slice());
+        connection.quit().now_or_never().unwrap().unwrap();
+        transport.assert_done();
+    }
+
+    fn session() -> ScriptedTransport {
+        ScriptedTransport::new()
+            .expect("LIST", "+OK\r\n1 20\r\n2 30\r\n.\r\n")
+            .expect("RETR 1", "+OK\r\nSubject: hi\r\n\r\n..dot\r\n.\r\n")
+            .expect("QUIT", "+OK\r\n")
+    }
+
+    /// Runs a session by polling without timer; returns the retrieved message and whether a failed connection is poisoned.
+    fn run_faulty_session<S: AsyncRead + AsyncWrite + Unpin>(transport: S) -> (Result<Vec<u8>, crate::Pop3AsyncError>, bool) {
+        let future = async {
+            let mut connection = match AsyncPop3Connection::from_stream(transport).await {
+                Ok(connection) => connection,
+                Err(err) => return (Err(err), true)
+            };
+            let result = async {
+                assert_eq!(2, connection.list().await?.len());
+                let mut content = vec!();
+                connection.retrieve_raw(1, &mut content).await?;
+                Ok(content)
+            }.await;
+            match result {
+                // quit consumes the connection, which is not used afterwards
+                Ok(content) => (connection.quit().await.map(|_| content), true),
+                Err(err) => (Err(err), connection.is_poisoned())
+            }
+        };
+
+        let mut future = std::pin::pin!(future);
+        let mut cx = Context::from_waker(futures_util::task::noop_waker_ref());
+        loop {
+            if let Poll::Ready(result) = future.as_mut().poll(&mut cx) {
+                return result;
+            }
+        }
+    }
+
+    #[test]
+    fn test_fault_injector_benign_faults() {
+        for seed in 0..50 {
+            let transport = FaultInjector::new(session(), seed)
+                .short_reads(0.5)
+                .split_writes(0.5)
+                .delays(0.3, Duration::ZERO, None);
+            assert_eq!(b"Subject: hi\r\n\r\n.dot\r\n".to_vec(), run_faulty_session(transport).0.unwrap());
+        }
+    }
+
+    #[test]
+    fn test_fault_injector_disconnects() {
+        let mut failures = 0;
+        for seed in 0..50 {
+            let transport = FaultInjector::new(session(), seed).short_reads(0.5).disconnects(0.1);
+            match run_faulty_session(transport) {
+                (Ok(content), _) => assert_eq!(b"Subject: hi\r\n\r\n.dot\r\n".to_vec(), content),
+                (Err(_), poisoned) => { assert!(poisoned); failures += 1; }
+            }
+        }
+        assert!(failures > 0);
+    }
+
+    #[test]
+    fn test_fault_injector_garbage() {
+        let transport = FaultInjector::new(session(), 7).garbage(1.0);
+        assert!(run_faulty_session(transport).0.is_err());
+    }
+}
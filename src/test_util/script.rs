@@ -0,0 +1,152 @@
+use std::error::Error;
+use std::fmt;
+#[cfg(test)]
+use std::io::Write;
+#[cfg(test)]
+use std::net::TcpListener;
+#[cfg(test)]
+use std::thread::{self, JoinHandle};
+
+#[cfg(test)]
+use super::read_line;
+#[cfg(test)]
+use crate::{Pop3Connection, Pop3ConnectionBuilder, TlsMode};
+
+/// Script of a single session of a scripted server, e.g. of an `AsyncMockServer`.
+///
+/// After the greeting, the server expects the commands of the script in
+/// order and answers each with the given response. Responses are sent as
+/// is, so they must contain the line terminators and, for multi-line
+/// responses, the terminating line.
+#[derive(Clone, Debug)]
+pub struct MockScript {
+    pub(super) greeting: String,
+    pub(super) steps: Vec<(String, String)>,
+}
+
+impl MockScript {
+
+    /// Returns a new script, which only sends the default greeting.
+    pub fn new() -> Self {
+        MockScript { greeting: "+OK mock server ready\r\n".into(), steps: vec!() }
+    }
+
+    /// Sets the greeting sent after connecting.
+    pub fn greeting(mut self, greeting: &str) -> Self {
+        self.greeting = greeting.to_string();
+        self
+    }
+
+    /// Adds an expected command and its response.
+    ///
+    /// If the server runs with [`crate::TlsMode::StartTls`], the connection is
+    /// upgraded to TLS after the response to the `STLS` command.
+    ///
+    /// # Arguments
+    ///
+    /// * `command`  - expected command without line terminator, e.g. `USER me`
+    /// * `response` - response sent to the client
+    pub fn expect(mut self, command: &str, response: &str) -> Self {
+        self.steps.push((command.to_string(), response.to_string()));
+        self
+    }
+}
+
+impl Default for MockScript {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Unexpected command received by a scripted server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MockMismatch {
+    /// index of the session
+    pub session: usize,
+
+    /// expected command
+    pub expected: String,
+
+    /// received command; empty if the client closed the connection
+    pub received: String,
+}
+
+impl fmt::Display for MockMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "session {}: expected \"{}\", received \"{}\"", self.session, self.expected, self.received)
+    }
+}
+
+impl Error for MockMismatch { }
+
+
+/// Scripted POP3 server for tests.
+///
+/// Sends a greeting, then expects each command of the script in order and
+/// answers with the given response. Returns the received commands.
+#[cfg(test)]
+pub(crate) fn serve(script: &[(&str, &str)]) -> (u16, JoinHandle<Vec<String>>) {
+    let (port, handle) = serve_sessions(&[script]);
+    let handle = thread::spawn(move || handle.join().unwrap().remove(0));
+    (port, handle)
+}
+
+/// Scripted POP3 server for tests, which accepts a connection for each script.
+///
+/// A session ends when its script is done; the connection is closed then,
+/// even if the client sends further commands. The server panics on
+/// unexpected commands, but not if the client closes the connection early.
+#[cfg(test)]
+pub(crate) fn serve_sessions(scripts: &[&[(&str, &str)]]) -> (u16, JoinHandle<Vec<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let scripts: Vec<MockScript> = scripts.iter()
+        .map(|script| script.iter()
+            .fold(MockScript::new().greeting("+OK ready\r\n"), |script, (command, response)| script.expect(command, response)))
+        .collect();
+
+    let handle = thread::spawn(move || {
+        let mut sessions = vec!();
+        for (session, script) in scripts.into_iter().enumerate() {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream.write_all(script.greeting.as_bytes()).unwrap();
+
+            let mut received = vec!();
+            for (expected, response) in script.steps {
+                let Some(line) = read_line(&mut stream).unwrap() else {
+                    break;
+                };
+                if line != expected {
+                    panic!("{}", MockMismatch { session, expected, received: line });
+                }
+                received.push(line);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            sessions.push(received);
+        }
+
+        sessions
+    });
+
+    (port, handle)
+}
+
+/// Connects to a scripted server.
+#[cfg(test)]
+pub(crate) fn connect(script: &[(&str, &str)]) -> (Pop3Connection, JoinHandle<Vec<String>>) {
+    let (port, handle) = serve(script);
+    let connection = Pop3ConnectionBuilder::new("127.0.0.1")
+        .tls_mode(TlsMode::Plain)
+        .port(port)
+        .connect()
+        .unwrap();
+
+    (connection, handle)
+}
+
+/// Returns a builder of an account, whose server refuses connections.
+#[cfg(test)]
+pub(crate) fn unreachable_account() -> Pop3ConnectionBuilder {
+    let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    Pop3ConnectionBuilder::new("127.0.0.1").tls_mode(TlsMode::Plain).port(port)
+}
@@ -0,0 +1,377 @@
+use std::error::Error;
+
+use futures_util::io::{AsyncRead, AsyncWrite};
+
+use crate::{AsyncPop3Connection, Pop3AsyncError, Pop3Connection, block_on};
+use crate::encoding;
+use crate::headers;
+use crate::sink;
+
+/// Maximum nesting depth of multipart messages searched for text parts.
+const MAX_DEPTH: usize = 8;
+
+/// Lines requested by TOP in addition to the preview lines, covering
+/// multipart preambles, boundaries and part headers.
+const PREVIEW_EXTRA_LINES: u32 = 50;
+
+/// Returns the text of the first text/plain and the first text/html part of a message.
+///
+/// The text is decoded according to transfer encoding and charset.
+/// Attachments are ignored. Truncated messages, e.g. retrieved by TOP, are
+/// supported; an unterminated last part is considered complete.
+pub(crate) fn text_parts(content: &[u8]) -> (Option<String>, Option<String>) {
+    let mut plain = None;
+    let mut html = None;
+    find_text_parts(content, &mut plain, &mut html, 0);
+    (plain, html)
+}
+
+fn find_text_parts(content: &[u8], plain: &mut Option<String>, html: &mut Option<String>, depth: usize) {
+    let (header_data, body) = sink::split_message(content);
+    let headers = headers::parse_headers(header_data);
+    let (mime_type, parameters) = parse_content_type(headers::find(&headers, "Content-Type").unwrap_or("text/plain"));
+
+    let is_attachment = headers::find(&headers, "Content-Disposition")
+        .is_some_and(|disposition| disposition.trim().to_ascii_lowercase().starts_with("attachment"));
+    if is_attachment {
+        return;
+    }
+
+    if mime_type.starts_with("multipart/") {
+        if let (Some(boundary), true) = (parameter(&parameters, "boundary"), depth < MAX_DEPTH) {
+            for part in split_multipart(body, boundary) {
+                find_text_parts(part, plain, html, depth + 1);
+            }
+        }
+        return;
+    }
+
+    let target = match mime_type.as_str() {
+        "text/plain" => plain,
+        "text/html" => html,
+        _ => return
+    };
+    if target.is_some() {
+        return;
+    }
+
+    if let Ok(data) = encoding::decode_transfer_encoding(headers::find(&headers, "Content-Transfer-Encoding"), body) {
+        let text = encoding::decode_charset(&data, parameter(&parameters, "charset"));
+        *target = Some(text);
+    }
+}
+
+/// Splits a Content-Type value into the lower case MIME type and its parameters.
+pub(crate) fn parse_content_type(value: &str) -> (String, Vec<(String, String)>) {
+    let mut items = value.split(';');
+    let mime_type = items.next().unwrap_or_default().trim().to_ascii_lowercase();
+    let parameters = items
+        .filter_map(|item| item.split_once('='))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().trim_matches('"').to_string()))
+        .collect();
+
+    (mime_type, parameters)
+}
+
+fn parameter<'a>(parameters: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    parameters.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+}
+
+/// Returns the parts of a multipart body.
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = vec!();
+    let mut start: Option<usize> = None;
+    let mut pos = 0;
+
+    for line in body.split_inclusive(|&byte| byte == b'\n') {
+        let trimmed = line.trim_ascii_end();
+        if trimmed.starts_with(delimiter.as_bytes()) {
+            if let Some(start) = start {
+                parts.push(strip_line_break(&body[start..pos]));
+            }
+            if trimmed[delimiter.len()..].starts_with(b"--") {
+                return parts;
+            }
+            start = Some(pos + line.len());
+        }
+        pos += line.len();
+    }
+
+    if let Some(start) = start {
+        parts.push(&body[start.min(body.len())..]);
+    }
+
+    parts
+}
+
+fn strip_line_break(data: &[u8]) -> &[u8] {
+    data.strip_suffix(b"\r\n").or_else(|| data.strip_suffix(b"\n")).unwrap_or(data)
+}
+
+/// Returns the readable text of an HTML document.
+///
+/// Tags, comments, scripts and styles are removed, entities are decoded
+/// and whitespace is collapsed.
+pub(crate) fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&decode_entities(&rest[..start]));
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map(|end| &comment[end + 3..]).unwrap_or("");
+            continue;
+        }
+
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => { rest = ""; break; }
+        };
+        let is_closing = rest[1..end].starts_with('/');
+        let tag = rest[1..end].trim_start_matches('/').split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or_default().to_ascii_lowercase();
+        rest = &rest[end + 1..];
+
+        if !is_closing && matches!(tag.as_str(), "script" | "style" | "head" | "title") {
+            let closing = format!("</{}", tag);
+            rest = match rest.to_ascii_lowercase().find(&closing) {
+                Some(pos) => rest[pos..].find('>').map(|end| &rest[pos + end + 1..]).unwrap_or(""),
+                None => ""
+            };
+        }
+        text.push(' ');
+    }
+    text.push_str(&decode_entities(rest));
+
+    collapse_whitespace(&text)
+}
+
+/// Decodes HTML character references, e.g. `&amp;` or `&#228;`.
+fn decode_entities(text: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let decoded = rest[1..].find(';').filter(|&end| end <= 10).and_then(|end| {
+            let name = &rest[1..end + 1];
+            let c = match name {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                "nbsp" => ' ',
+                _ => {
+                    let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                        None => name.strip_prefix('#')?.parse().ok()?
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end + 2))
+        });
+
+        match decoded {
+            Some((c, len)) => { result.push(c); rest = &rest[len..]; },
+            None => { result.push('&'); rest = &rest[1..]; }
+        }
+    }
+    result.push_str(rest);
+
+    result
+}
+
+/// Replaces each sequence of whitespace by a single space and trims the text.
+pub(crate) fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Returns at most `max_chars` characters of the text.
+fn truncate(text: String, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((pos, _)) => text[..pos].trim_end().to_string(),
+        None => text
+    }
+}
+
+/// Returns a short readable snippet of a message.
+///
+/// The first text/plain part is preferred; for HTML-only messages, the
+/// text of the first text/html part is used.
+pub(crate) fn preview(content: &[u8], max_chars: usize) -> String {
+    let text = match text_parts(content) {
+        (Some(plain), _) => collapse_whitespace(&plain),
+        (None, Some(html)) => html_to_text(&html),
+        (None, None) => String::new()
+    };
+
+    truncate(text, max_chars)
+}
+
+/// Returns up to `line_count` non-empty lines of the first text part of a message.
+///
+/// Trailing whitespace is removed. For HTML-only messages, the readable
+/// text of the first text/html part is returned as a single line.
+pub(crate) fn preview_lines(content: &[u8], line_count: usize) -> Vec<String> {
+    let text = match text_parts(content) {
+        (Some(plain), _) => plain,
+        (None, Some(html)) => html_to_text(&html),
+        (None, None) => String::new()
+    };
+
+    text.lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .take(line_count)
+        .map(str::to_string)
+        .collect()
+}
+
+impl Pop3Connection {
+
+    /// Retrieves a message as UTF-8 text.
+    ///
+    /// The message is decoded using the charset of its Content-Type header.
+    /// Using the `charset` feature, all common charsets are supported and the
+    /// charset of messages without declaration is guessed; otherwise invalid
+    /// UTF-8 sequences are replaced. Transfer encodings are not decoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    pub fn retrieve_as_string(&mut self, message_id: u32) -> Result<String, Box<dyn Error>> {
+        block_on(self.inner.retrieve_as_string(message_id))
+    }
+
+    /// Returns a short readable snippet of a message, e.g. for notifications.
+    ///
+    /// The text of the first text/plain part is used; for HTML-only
+    /// messages, tags are stripped and entities decoded. Whitespace is
+    /// collapsed. Messages without text part yield an empty snippet.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    /// * `max_chars`  - maximum count of characters of the snippet
+    pub fn preview_text(&mut self, message_id: u32, max_chars: usize) -> Result<String, Box<dyn Error>> {
+        block_on(self.inner.preview_text(message_id, max_chars))
+    }
+
+    /// Returns the first lines of the body of a message, e.g. for preview panes.
+    ///
+    /// In contrast to [`Pop3Connection::preview_text`], only the beginning
+    /// of the message is retrieved using TOP. The first text part is decoded
+    /// according to transfer encoding and charset; empty lines are skipped.
+    /// Since encoded lines may be shorter than decoded ones, fewer lines
+    /// may be returned for long text lines.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    /// * `line_count` - maximum count of lines to return
+    pub fn body_preview(&mut self, message_id: u32, line_count: u32) -> Result<Vec<String>, Box<dyn Error>> {
+        block_on(self.inner.body_preview(message_id, line_count))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncPop3Connection<S> {
+
+    /// Retrieves a message as UTF-8 text.
+    ///
+    /// See [`Pop3Connection::retrieve_as_string`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    pub async fn retrieve_as_string(&mut self, message_id: u32) -> Result<String, Pop3AsyncError> {
+        let mut content = vec!();
+        self.retrieve_raw(message_id, &mut content).await?;
+        Ok(encoding::message_to_string(&content))
+    }
+
+    /// Returns a short readable snippet of a message, e.g. for notifications.
+    ///
+    /// See [`Pop3Connection::preview_text`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    /// * `max_chars`  - maximum count of characters of the snippet
+    pub async fn preview_text(&mut self, message_id: u32, max_chars: usize) -> Result<String, Pop3AsyncError> {
+        let mut content = vec!();
+        self.retrieve_raw(message_id, &mut content).await?;
+        Ok(preview(&content, max_chars))
+    }
+
+    /// Returns the first lines of the body of a message, e.g. for preview panes.
+    ///
+    /// See [`Pop3Connection::body_preview`] for details.
+    ///
+    /// # Arguments
+    ///
+    /// * `message_id` - id of the message
+    /// * `line_count` - maximum count of lines to return
+    pub async fn body_preview(&mut self, message_id: u32, line_count: u32) -> Result<Vec<String>, Pop3AsyncError> {
+        let top_lines = line_count.saturating_mul(2).saturating_add(PREVIEW_EXTRA_LINES);
+        let content = self.top_raw(message_id, top_lines).await?;
+        Ok(preview_lines(&content, line_count as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALTERNATIVE: &[u8] = b"Subject: hi\r\n\
+Content-Type: multipart/alternative; boundary=\"b\"\r\n\
+\r\n\
+preamble\r\n\
+--b\r\n\
+Content-Type: text/plain; charset=iso-8859-1\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+Gr=FC=DFe,\r\n\
+world\r\n\
+--b\r\n\
+Content-Type: text/html\r\n\
+\r\n\
+<p>Hello</p>\r\n\
+--b--\r\n";
+
+    #[test]
+    fn test_text_parts() {
+        let (plain, html) = text_parts(ALTERNATIVE);
+        assert_eq!(Some("Grüße,\r\nworld".into()), plain);
+        assert_eq!(Some("<p>Hello</p>".into()), html);
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><title>T</title><style>p { color: red; }</style></head>\
+            <body><!-- hidden --><p>Fish &amp; Chips</p><br/>&#8364;&#x31;0&nbsp;only &unknown;</body></html>";
+        assert_eq!("Fish & Chips €10 only &unknown;", html_to_text(html));
+    }
+
+    #[test]
+    fn test_preview() {
+        assert_eq!("Grüße, world", preview(ALTERNATIVE, 100));
+        assert_eq!("Grüße,", preview(ALTERNATIVE, 7));
+
+        let html_only = b"Content-Type: text/html; charset=utf-8\r\n\r\n<div>Your <b>order</b> has shipped</div>\r\n";
+        assert_eq!("Your order has shipped", preview(html_only, 100));
+    }
+
+    #[test]
+    fn test_preview_lines() {
+        assert_eq!(vec!("Grüße,", "world"), preview_lines(ALTERNATIVE, 5));
+        assert_eq!(vec!("Grüße,"), preview_lines(ALTERNATIVE, 1));
+
+        let base64 = b"Content-Transfer-Encoding: base64\r\n\r\nZmlyc3QKCnNlY29uZAp0aGlyZAo=\r\n";
+        assert_eq!(vec!("first", "second"), preview_lines(base64, 2));
+    }
+}
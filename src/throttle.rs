@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+/// Limits the rate of transferred bytes by waiting when ahead of schedule.
+pub(crate) struct Throttle {
+    bytes_per_second: u64,
+    start: Instant,
+    bytes: u64,
+}
+
+impl Throttle {
+
+    pub fn new(bytes_per_second: u64) -> Self {
+        Throttle { bytes_per_second, start: Instant::now(), bytes: 0 }
+    }
+
+    /// Accounts transferred bytes and returns how long to wait until the rate is met.
+    pub fn consume(&mut self, bytes: usize) -> Duration {
+        self.bytes += bytes as u64;
+        self.delay(self.start.elapsed())
+    }
+
+    /// Returns how long to wait, given the time elapsed since the transfer started.
+    fn delay(&self, elapsed: Duration) -> Duration {
+        if self.bytes_per_second == 0 {
+            return Duration::ZERO;
+        }
+
+        let expected = Duration::from_secs_f64(self.bytes as f64 / self.bytes_per_second as f64);
+        expected.saturating_sub(elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_no_delay_when_behind_schedule() {
+        let mut throttle = Throttle::new(1000);
+        throttle.bytes = 500;
+        assert_eq!(Duration::ZERO, throttle.delay(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_delay_when_ahead_of_schedule() {
+        let mut throttle = Throttle::new(1000);
+        throttle.bytes = 2000;
+        assert_eq!(Duration::from_millis(1500), throttle.delay(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn test_zero_rate_is_unlimited() {
+        let mut throttle = Throttle::new(0);
+        throttle.bytes = 1_000_000;
+        assert_eq!(Duration::ZERO, throttle.delay(Duration::ZERO));
+    }
+}
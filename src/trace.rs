@@ -0,0 +1,211 @@
+use std::fmt::Display;
+use std::future::Future;
+#[cfg(feature = "tracing")]
+use std::time::Instant;
+
+#[cfg(feature = "tracing")]
+use tracing::field::Empty;
+#[cfg(feature = "tracing")]
+use tracing::Instrument;
+
+/// Commands, whose argument is a message id, which is recorded.
+#[cfg(feature = "tracing")]
+const MESSAGE_ID_COMMANDS: [&str; 5] = ["DELE", "LIST", "RETR", "TOP", "UIDL"];
+
+/// Span of a connection, TLS handshake, authentication or command.
+///
+/// Using the `tracing` feature, each span records its duration, the count
+/// of received bytes and the error, if the operation failed. Arguments of
+/// commands are never recorded, except message ids, so that passwords and
+/// tokens do not leak into logs. Without the feature, spans are no-ops.
+pub(crate) struct OperationSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    #[cfg(feature = "tracing")]
+    started: Instant,
+    #[cfg(feature = "tracing")]
+    bytes: u64,
+}
+
+#[cfg(feature = "tracing")]
+impl OperationSpan {
+
+    fn new(span: tracing::Span) -> Self {
+        OperationSpan { span, started: Instant::now(), bytes: 0 }
+    }
+
+    /// Returns the span of a connection to the given server.
+    pub(crate) fn connect(host: &str, port: u16) -> Self {
+        Self::new(tracing::info_span!("pop3.connect", host, port, duration_ms = Empty, error = Empty))
+    }
+
+    /// Returns the span of a TLS handshake.
+    pub(crate) fn tls_handshake(host: &str) -> Self {
+        Self::new(tracing::info_span!("pop3.tls_handshake", host, duration_ms = Empty, error = Empty))
+    }
+
+    /// Returns the span of an authentication using the given mechanism.
+    pub(crate) fn auth(mechanism: &'static str) -> Self {
+        Self::new(tracing::info_span!("pop3.auth", mechanism, duration_ms = Empty, error = Empty))
+    }
+
+    /// Returns the span of a command; only the command name and message ids are recorded.
+    pub(crate) fn command(command: &str) -> Self {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or_default().to_ascii_uppercase();
+        let span = tracing::debug_span!("pop3.command", command = %name, message_id = Empty, bytes = Empty, duration_ms = Empty, error = Empty);
+        if MESSAGE_ID_COMMANDS.contains(&name.as_str()) {
+            if let Some(message_id) = words.next().and_then(|word| word.parse::<u32>().ok()) {
+                span.record("message_id", message_id);
+            }
+        }
+
+        Self::new(span)
+    }
+
+    /// Adds received bytes.
+    pub(crate) fn add_bytes(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+
+    /// Records duration, received bytes and error of the completed operation.
+    pub(crate) fn finish<T, E: Display>(self, result: &Result<T, E>) {
+        self.span.record("duration_ms", self.started.elapsed().as_millis() as u64);
+        if self.bytes > 0 {
+            self.span.record("bytes", self.bytes);
+        }
+        if let Err(err) = result {
+            self.span.record("error", tracing::field::display(err));
+        }
+    }
+
+    /// Runs an async operation within the span.
+    pub(crate) async fn run<T, E: Display>(self, operation: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+        let result = operation.instrument(self.span.clone()).await;
+        self.finish(&result);
+        result
+    }
+
+    /// Runs a blocking operation within the span.
+    pub(crate) fn run_blocking<T, E: Display>(self, operation: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        let result = self.span.in_scope(operation);
+        self.finish(&result);
+        result
+    }
+}
+
+#[cfg(not(feature = "tracing"))]
+impl OperationSpan {
+
+    pub(crate) fn connect(_host: &str, _port: u16) -> Self {
+        OperationSpan { }
+    }
+
+    pub(crate) fn tls_handshake(_host: &str) -> Self {
+        OperationSpan { }
+    }
+
+    pub(crate) fn auth(_mechanism: &'static str) -> Self {
+        OperationSpan { }
+    }
+
+    pub(crate) fn command(_command: &str) -> Self {
+        OperationSpan { }
+    }
+
+    pub(crate) fn add_bytes(&mut self, _bytes: usize) {
+    }
+
+    pub(crate) fn finish<T, E: Display>(self, _result: &Result<T, E>) {
+    }
+
+    pub(crate) async fn run<T, E: Display>(self, operation: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+        operation.await
+    }
+
+    pub(crate) fn run_blocking<T, E: Display>(self, operation: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        operation()
+    }
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use std::fmt::Debug;
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Metadata, Subscriber};
+
+    use crate::test_util;
+
+    /// Collects span names and recorded fields as `name field=value` lines.
+    #[derive(Clone, Default)]
+    struct Recorder {
+        lines: Arc<Mutex<Vec<String>>>,
+        next_id: Arc<AtomicU64>,
+    }
+
+    impl Visit for Recorder {
+        fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+            self.lines.lock().unwrap().push(format!("{}={:?}", field.name(), value));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            self.lines.lock().unwrap().push(span.metadata().name().to_string());
+            span.record(&mut self.clone());
+            Id::from_u64(self.next_id.fetch_add(1, Ordering::SeqCst) + 1)
+        }
+
+        fn record(&self, _span: &Id, values: &Record<'_>) {
+            values.record(&mut self.clone());
+        }
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {
+        }
+
+        fn event(&self, _event: &Event<'_>) {
+        }
+
+        fn enter(&self, _span: &Id) {
+        }
+
+        fn exit(&self, _span: &Id) {
+        }
+    }
+
+    #[test]
+    fn test_records_commands_without_secrets() {
+        let recorder = Recorder::default();
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let (mut connection, server) = test_util::connect(&[
+                ("USER user", "+OK\r\n"),
+                ("PASS secret", "+OK\r\n"),
+                ("RETR 1", "+OK\r\nHello\r\n.\r\n"),
+                ("DELE 2", "-ERR no such message\r\n"),
+                ("QUIT", "+OK\r\n"),
+            ]);
+            connection.login("user", "secret").unwrap();
+            connection.retrieve_raw(1, &mut vec!()).unwrap();
+            assert!(connection.delete(2).is_err());
+            connection.quit().unwrap();
+            server.join().unwrap();
+        });
+
+        let lines = recorder.lines.lock().unwrap().join("\n");
+        assert!(lines.contains("pop3.auth\nmechanism=\"USER\""));
+        assert!(lines.contains("command=PASS"));
+        assert!(lines.contains("command=RETR\nmessage_id=1"));
+        assert!(lines.contains("bytes=15"));
+        assert!(lines.contains("error=-ERR no such message"));
+        assert!(!lines.contains("secret"));
+        assert!(!lines.contains("user\""));
+    }
+}
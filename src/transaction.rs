@@ -0,0 +1,134 @@
+use std::error::Error;
+use std::ops::{Deref, DerefMut};
+
+use crate::Pop3Connection;
+
+/// Guard for a batch of deletions.
+///
+/// Deletions performed through the guard are only committed by
+/// [`DeletionTransaction::commit`], which ends the session using QUIT.
+/// If the guard is dropped without being committed, all deletions are
+/// rolled back using RSET.
+///
+/// Note that RSET also unmarks messages deleted before the transaction
+/// was started.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::Pop3Connection;
+///
+/// let mut connection = Pop3Connection::new("pop.example.com", 995).unwrap();
+/// connection.login("user@example.com", "secret").unwrap();
+///
+/// let mut transaction = connection.transaction();
+/// for info in transaction.list().unwrap() {
+///     transaction.delete(info.message_id).unwrap();
+/// }
+/// transaction.commit().unwrap();
+/// ```
+pub struct DeletionTransaction<'a> {
+    connection: &'a mut Pop3Connection,
+    committed: bool,
+}
+
+impl<'a> DeletionTransaction<'a> {
+
+    pub(crate) fn new(connection: &'a mut Pop3Connection) -> Self {
+        DeletionTransaction { connection, committed: false }
+    }
+
+    /// Commits all deletions by ending the session using QUIT.
+    ///
+    /// The connection can not be used for further commands afterwards.
+    pub fn commit(mut self) -> Result<(), Box<dyn Error>> {
+        self.committed = true;
+        self.connection.close()
+    }
+
+    /// Rolls back all deletions using RSET.
+    pub fn rollback(mut self) -> Result<(), Box<dyn Error>> {
+        self.committed = true;
+        self.connection.reset()
+    }
+}
+
+impl Deref for DeletionTransaction<'_> {
+    type Target = Pop3Connection;
+
+    fn deref(&self) -> &Pop3Connection {
+        self.connection
+    }
+}
+
+impl DerefMut for DeletionTransaction<'_> {
+    fn deref_mut(&mut self) -> &mut Pop3Connection {
+        self.connection
+    }
+}
+
+impl Drop for DeletionTransaction<'_> {
+    /// Rolls back deletions, unless committed.
+    fn drop(&mut self) {
+        if !self.committed && !self.connection.marked_for_deletion().is_empty() {
+            let _ = self.connection.reset();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{self, MaildropServer};
+
+    fn server() -> MaildropServer {
+        MaildropServer::builder()
+            .message("a", b"Subject: first\r\n\r\nbody\r\n")
+            .message("b", b"Subject: second\r\n\r\nbody\r\n")
+            .start()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_drop_rolls_back() {
+        let (mut connection, server) = test_util::connect(&[
+            ("DELE 1", "+OK\r\n"),
+            ("RSET", "+OK\r\n"),
+            ("NOOP", "+OK\r\n"),
+        ]);
+
+        let mut transaction = connection.transaction();
+        transaction.delete(1).unwrap();
+        drop(transaction);
+        assert!(connection.marked_for_deletion().is_empty());
+        connection.noop().unwrap();
+
+        assert_eq!(vec!("DELE 1", "RSET", "NOOP"), server.join().unwrap());
+    }
+
+    #[test]
+    fn test_commit() {
+        let server = server();
+        let mut connection = server.connection_builder().login("me", "secret").connect().unwrap();
+
+        let mut transaction = connection.transaction();
+        transaction.delete(1).unwrap();
+        transaction.commit().unwrap();
+        assert!(!connection.is_open());
+
+        assert_eq!(vec!("b"), server.unique_ids());
+    }
+
+    #[test]
+    fn test_rollback() {
+        let server = server();
+        let mut connection = server.connection_builder().login("me", "secret").connect().unwrap();
+
+        let mut transaction = connection.transaction();
+        transaction.delete(1).unwrap();
+        transaction.rollback().unwrap();
+        assert_eq!(2, connection.stat().unwrap().message_count);
+        connection.quit().unwrap();
+
+        assert_eq!(vec!("a", "b"), server.unique_ids());
+    }
+}
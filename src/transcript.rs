@@ -0,0 +1,206 @@
+use std::io::Write;
+
+/// Replacement of secrets in transcripts.
+pub(crate) const REDACTED: &str = "***";
+
+/// Replaces passwords and authentication data of a session by `***`.
+#[derive(Default)]
+pub(crate) struct Redactor {
+    authenticating: bool,
+}
+
+impl Redactor {
+
+    /// Returns a line sent by the client without line terminator and secrets.
+    pub(crate) fn client(&mut self, line: &[u8]) -> String {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches(['\r', '\n']);
+        let mut words = line.splitn(3, ' ');
+        let name = words.next().unwrap_or_default().to_ascii_uppercase();
+        match (name.as_str(), words.next(), words.next()) {
+            ("PASS", Some(_), _) => format!("PASS {}", REDACTED),
+            ("AUTH", Some(mechanism), Some(_)) => { self.authenticating = true; format!("AUTH {} {}", mechanism, REDACTED) },
+            ("AUTH", _, _) => { self.authenticating = true; line.to_string() },
+            _ if self.authenticating && !line.is_empty() => REDACTED.to_string(),
+            _ => line.to_string()
+        }
+    }
+
+    /// Returns a line received from the server without line terminator.
+    pub(crate) fn server(&mut self, line: &[u8]) -> String {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.starts_with("+OK") || line.starts_with("-ERR") {
+            self.authenticating = false;
+        }
+        line.to_string()
+    }
+}
+
+/// Writer of a session transcript.
+///
+/// Each line sent by the client is written as `C: <line>`, each line
+/// received from the server as `S: <line>`, both without line terminators.
+/// Passwords and authentication data are replaced by `***`. Errors of the
+/// writer are ignored, so that recording never affects the session.
+///
+/// A wire dump uses the same format, but shows the exact traffic: control
+/// characters and invalid UTF-8 are escaped and lines not terminated by
+/// CRLF are marked.
+pub(crate) struct Transcript {
+    writer: Box<dyn Write + Send>,
+    redactor: Redactor,
+    exact: bool,
+}
+
+impl Transcript {
+
+    pub(crate) fn new(writer: Box<dyn Write + Send>) -> Self {
+        Transcript { writer, redactor: Redactor::default(), exact: false }
+    }
+
+    pub(crate) fn wire_dump(writer: Box<dyn Write + Send>) -> Self {
+        Transcript { writer, redactor: Redactor::default(), exact: true }
+    }
+
+    /// Records a line sent by the client.
+    pub(crate) fn client(&mut self, line: &[u8]) {
+        let (content, marker) = self.describe(line);
+        let content = self.redactor.client(&content);
+        self.write("C: ", &content, marker);
+    }
+
+    /// Records a line received from the server.
+    pub(crate) fn server(&mut self, line: &[u8]) {
+        let (content, marker) = self.describe(line);
+        let content = self.redactor.server(&content);
+        self.write("S: ", &content, marker);
+    }
+
+    /// Returns the line to record and the marker of its line terminator.
+    fn describe(&self, line: &[u8]) -> (Vec<u8>, &'static str) {
+        if !self.exact {
+            return (line.to_vec(), "");
+        }
+
+        let (content, marker) = match line {
+            [content @ .., b'\r', b'\n'] => (content, ""),
+            [content @ .., b'\n'] => (content, " [LF]"),
+            content => (content, " [no line terminator]")
+        };
+        let content = match std::str::from_utf8(content) {
+            Ok(text) => text.chars()
+                .map(|c| match c.is_control() && c != '\t' {
+                    true => c.escape_default().to_string(),
+                    false => c.to_string()
+                })
+                .collect(),
+            Err(_) => content.escape_ascii().to_string()
+        };
+
+        (content.into_bytes(), marker)
+    }
+
+    fn write(&mut self, prefix: &str, line: &str, marker: &str) {
+        let _ = writeln!(self.writer, "{}{}{}", prefix, line, marker);
+        let _ = self.writer.flush();
+    }
+}
+
+/// Parses a transcript into the greeting and the commands with their responses.
+///
+/// Responses contain line terminators.
+#[cfg(any(test, feature = "test-util"))]
+pub(crate) fn parse(text: &str) -> Result<(String, Vec<(String, String)>), String> {
+    let mut greeting = String::new();
+    let mut steps: Vec<(String, String)> = vec!();
+    for (index, line) in text.lines().enumerate() {
+        if let Some(command) = line.strip_prefix("C:") {
+            steps.push((command.strip_prefix(' ').unwrap_or(command).to_string(), String::new()));
+        } else if let Some(content) = line.strip_prefix("S:") {
+            let response = steps.last_mut().map_or(&mut greeting, |(_, response)| response);
+            response.push_str(content.strip_prefix(' ').unwrap_or(content));
+            response.push_str("\r\n");
+        } else if !line.is_empty() {
+            return Err(format!("invalid transcript line {}: {}", index + 1, line));
+        }
+    }
+
+    Ok((greeting, steps))
+}
+
+/// Returns true, if a command matches a command of a transcript, whose secrets may be redacted.
+#[cfg(any(test, feature = "test-util"))]
+pub(crate) fn matches(expected: &str, command: &str) -> bool {
+    match expected.strip_suffix(REDACTED) {
+        Some(prefix) => command.starts_with(prefix),
+        None => expected == command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_redacts_secrets() {
+        let buffer = SharedBuffer::default();
+        let mut transcript = Transcript::new(Box::new(buffer.clone()));
+        transcript.server(b"+OK ready\r\n");
+        transcript.client(b"USER me\r\n");
+        transcript.server(b"+OK\r\n");
+        transcript.client(b"PASS secret\r\n");
+        transcript.server(b"+OK\r\n");
+        transcript.client(b"AUTH XOAUTH2 dG9rZW4=\r\n");
+        transcript.server(b"+ eyJzdGF0dXMiOiI0MDEifQ==\r\n");
+        transcript.client(b"\r\n");
+        transcript.server(b"-ERR invalid\r\n");
+
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!("S: +OK ready\nC: USER me\nS: +OK\nC: PASS ***\nS: +OK\n\
+            C: AUTH XOAUTH2 ***\nS: + eyJzdGF0dXMiOiI0MDEifQ==\nC: \nS: -ERR invalid\n", text);
+    }
+
+    #[test]
+    fn test_parse() {
+        let (greeting, steps) = parse("S: +OK ready\nC: PASS ***\nS: +OK\nC: RETR 1\nS: +OK\nS: Hello\nS: .\n").unwrap();
+        assert_eq!("+OK ready\r\n", greeting);
+        assert_eq!(vec!(
+            ("PASS ***".to_string(), "+OK\r\n".to_string()),
+            ("RETR 1".to_string(), "+OK\r\nHello\r\n.\r\n".to_string()),
+        ), steps);
+        assert!(parse("X: what\n").is_err());
+
+        assert!(matches("PASS ***", "PASS secret"));
+        assert!(!matches("PASS ***", "USER me"));
+        assert!(matches("STAT", "STAT"));
+    }
+
+    #[test]
+    fn test_wire_dump() {
+        let buffer = SharedBuffer::default();
+        let mut dump = Transcript::wire_dump(Box::new(buffer.clone()));
+        dump.client(b"PASS secret\r\n");
+        dump.server(b"+OK\n");
+        dump.server(b"Subject: \xe4\x01\r\n");
+        dump.server(b"Subject: \xc3\xa4\r\n");
+        dump.server(b"partial");
+
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!("C: PASS ***\nS: +OK [LF]\nS: Subject: \\xe4\\x01\nS: Subject: \u{e4}\nS: partial [no line terminator]\n", text);
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::ops::ControlFlow;
+use std::thread;
+use std::time::Duration;
+
+use crate::{Pop3Connection, Pop3ConnectionBuilder, Pop3MessageSummary};
+
+/// Polls a maildrop for new messages.
+///
+/// Messages are identified by their unique id (UIDL). The first check
+/// records the messages already present; each later check reports the
+/// messages that were not seen before.
+///
+/// Note that most servers do not show messages arriving during a session,
+/// so by default a new session is opened for each check. Use
+/// [`Pop3Watcher::keep_session`] for servers that do. Since each session
+/// authenticates with the credentials of the builder, long-running
+/// watchers using OAuth should pass a token provider by
+/// [`Pop3ConnectionBuilder::login_oauth2_with`] instead of a single access
+/// token, which expires.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::ops::ControlFlow;
+/// use std::time::Duration;
+/// use rust_pop3_client::{Pop3ConnectionBuilder, Pop3Watcher};
+///
+/// let mut watcher = Pop3Watcher::new(Pop3ConnectionBuilder::new("pop.example.com")
+///     .login("user@example.com", "secret"));
+///
+/// watcher.poll(Duration::from_secs(60), |messages| {
+///     for message in messages {
+///         println!("new mail: {}", message.subject().unwrap_or(""));
+///     }
+///     ControlFlow::Continue(())
+/// }).unwrap();
+/// ```
+pub struct Pop3Watcher {
+    builder: Pop3ConnectionBuilder,
+    keep_session: bool,
+    connection: Option<Pop3Connection>,
+    known: Option<HashSet<String>>,
+}
+
+impl Pop3Watcher {
+
+    /// Creates a new watcher.
+    ///
+    /// # Arguments
+    ///
+    /// * `builder` - builder used to connect to the server
+    pub fn new(builder: Pop3ConnectionBuilder) -> Self {
+        Pop3Watcher { builder, keep_session: false, connection: None, known: None }
+    }
+
+    /// Keeps the session open between checks and verifies it using NOOP.
+    ///
+    /// If the session is broken, a new one is opened.
+    pub fn keep_session(mut self, keep_session: bool) -> Self {
+        self.keep_session = keep_session;
+        self
+    }
+
+    /// Checks for new messages once and returns their summaries.
+    ///
+    /// The first check returns no messages, since there is nothing to compare with.
+    pub fn check(&mut self) -> Result<Vec<Pop3MessageSummary>, Box<dyn Error>> {
+        let mut connection = match self.connection.take() {
+            Some(mut connection) => match connection.noop() {
+                Ok(()) => connection,
+                Err(_) => self.builder.clone().connect()?,
+            },
+            None => self.builder.clone().connect()?,
+        };
+
+        let messages = connection.list_meta()?;
+        let mut current = HashSet::new();
+        let mut result = vec!();
+        for meta in messages {
+            let unique_id = match &meta.unique_id {
+                Some(unique_id) => unique_id.clone(),
+                None => continue,
+            };
+            let is_new = self.known.as_ref().map(|known| !known.contains(&unique_id)).unwrap_or(false);
+            current.insert(unique_id);
+            if is_new {
+                let headers = connection.headers(meta.message_id)?;
+                result.push(Pop3MessageSummary { meta, headers });
+            }
+        }
+        self.known = Some(current);
+
+        if self.keep_session {
+            self.connection = Some(connection);
+        }
+        else {
+            connection.quit()?;
+        }
+
+        Ok(result)
+    }
+
+    /// Checks for new messages periodically.
+    ///
+    /// The callback is invoked with the summaries of new messages, if there are any.
+    /// Polling stops when the callback returns [`ControlFlow::Break`] or a check fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - time to wait between checks
+    /// * `callback` - callback invoked with new messages
+    pub fn poll<F>(&mut self, interval: Duration, mut callback: F) -> Result<(), Box<dyn Error>>
+    where
+        F: FnMut(&[Pop3MessageSummary]) -> ControlFlow<()>
+    {
+        loop {
+            let messages = self.check()?;
+            if !messages.is_empty() && callback(&messages).is_break() {
+                return Ok(());
+            }
+            thread::sleep(interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::{TlsMode, test_util};
+
+    #[test]
+    fn test_poll_reports_new_messages() {
+        let (port, server) = test_util::serve(&[
+            ("LIST", "+OK\r\n1 10\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uid1\r\n.\r\n"),
+            ("NOOP", "+OK\r\n"),
+            ("LIST", "+OK\r\n1 10\r\n2 20\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uid1\r\n2 uid2\r\n.\r\n"),
+            ("TOP 2 0", "+OK\r\nSubject: hello\r\n\r\n.\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+        let builder = Pop3ConnectionBuilder::new("127.0.0.1").tls_mode(TlsMode::Plain).port(port);
+        let mut watcher = Pop3Watcher::new(builder).keep_session(true);
+
+        let mut subjects = vec!();
+        watcher.poll(Duration::ZERO, |messages| {
+            subjects.extend(messages.iter().map(|message| message.subject().unwrap().to_string()));
+            ControlFlow::Break(())
+        }).unwrap();
+
+        assert_eq!(vec!["hello"], subjects);
+        drop(watcher);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_reconnect_refreshes_access_token() {
+        let commands: Vec<String> = ["token1", "token2"].iter()
+            .map(|token| format!("AUTH XOAUTH2 {}", crate::oauth::xoauth2_initial_response("user", token)))
+            .collect();
+        let (port, server) = test_util::serve_sessions(&[
+            &[
+                (&commands[0], "+OK\r\n"),
+                ("LIST", "+OK\r\n.\r\n"),
+                ("UIDL", "+OK\r\n.\r\n"),
+                ("QUIT", "+OK\r\n"),
+            ],
+            &[
+                (&commands[1], "+OK\r\n"),
+                ("LIST", "+OK\r\n1 10\r\n.\r\n"),
+                ("UIDL", "+OK\r\n1 uid1\r\n.\r\n"),
+                ("TOP 1 0", "+OK\r\nSubject: hello\r\n\r\n.\r\n"),
+                ("QUIT", "+OK\r\n"),
+            ],
+        ]);
+
+        // each token expires after a single session
+        let issued = Arc::new(AtomicUsize::new(0));
+        let provider = move || -> Result<String, Box<dyn Error>> {
+            Ok(format!("token{}", issued.fetch_add(1, Ordering::SeqCst) + 1))
+        };
+        let builder = Pop3ConnectionBuilder::new("127.0.0.1").tls_mode(TlsMode::Plain).port(port)
+            .login_oauth2_with("user", Arc::new(provider));
+        let mut watcher = Pop3Watcher::new(builder);
+
+        assert!(watcher.check().unwrap().is_empty());
+        assert_eq!(Some("hello"), watcher.check().unwrap()[0].subject());
+        assert_eq!(2, server.join().unwrap().len());
+    }
+}
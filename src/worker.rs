@@ -0,0 +1,205 @@
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use crate::{Pop3Connection, Pop3ConnectionBuilder, Pop3MessageMeta, Pop3Stat};
+
+/// Error reported by a [`Pop3Worker`].
+pub type Pop3WorkerError = Box<dyn Error + Send + Sync>;
+
+/// Job executed by a [`Pop3Worker`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Pop3Job {
+    /// Returns maildrop statistics (STAT).
+    Stat,
+
+    /// Returns id, size and unique id of each message (LIST and UIDL).
+    List,
+
+    /// Downloads the exact octets of a message (RETR).
+    Retrieve { message_id: u32 },
+
+    /// Returns the headers and a given number of lines of a message (TOP).
+    Top { message_id: u32, line_count: u32 },
+
+    /// Marks a message as deleted (DELE).
+    Delete { message_id: u32 },
+
+    /// Unmarks all messages marked as deleted (RSET).
+    Reset,
+
+    /// Checks the connection (NOOP).
+    Noop,
+
+    /// Ends the session, committing deletions (QUIT). Further jobs fail.
+    Quit,
+}
+
+/// Output of a successfully executed [`Pop3Job`].
+#[derive(Debug)]
+pub enum Pop3JobOutput {
+    /// Output of [`Pop3Job::Stat`]
+    Stat(Pop3Stat),
+
+    /// Output of [`Pop3Job::List`]
+    List(Vec<Pop3MessageMeta>),
+
+    /// Output of [`Pop3Job::Retrieve`] and [`Pop3Job::Top`]
+    Message(Vec<u8>),
+
+    /// Output of jobs without result
+    Done,
+}
+
+/// Receives the result of a job submitted to a [`Pop3Worker`].
+pub struct Pop3Responder {
+    receiver: Receiver<Result<Pop3JobOutput, Pop3WorkerError>>,
+}
+
+impl Pop3Responder {
+
+    /// Blocks until the job is done and returns its result.
+    pub fn wait(self) -> Result<Pop3JobOutput, Pop3WorkerError> {
+        self.receiver.recv().map_err(|_| "worker stopped")?
+    }
+
+    /// Returns the result of the job, if it is done; never blocks.
+    pub fn try_wait(&self) -> Option<Result<Pop3JobOutput, Pop3WorkerError>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err("worker stopped".into()))
+        }
+    }
+}
+
+type Request = (Pop3Job, Sender<Result<Pop3JobOutput, Pop3WorkerError>>);
+
+/// Executes POP3 jobs on a dedicated thread.
+///
+/// The worker owns the connection; jobs are submitted over a channel and
+/// executed in order. This gives applications, e.g. with a GUI, a
+/// non-blocking interface without adopting async.
+///
+/// # Examples
+///
+/// ```no_run
+/// use rust_pop3_client::{Pop3ConnectionBuilder, Pop3Job, Pop3JobOutput, Pop3Worker};
+///
+/// let worker = Pop3Worker::spawn(Pop3ConnectionBuilder::new("pop.example.com")
+///     .login("user@example.com", "secret"));
+///
+/// let responder = worker.submit(Pop3Job::List);
+/// // ... do something else ...
+/// if let Ok(Pop3JobOutput::List(messages)) = responder.wait() {
+///     println!("{} messages", messages.len());
+/// }
+/// ```
+pub struct Pop3Worker {
+    sender: Option<Sender<Request>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Pop3Worker {
+
+    /// Starts a worker, which connects using the given builder.
+    ///
+    /// If the connection fails, all jobs fail with the connection error.
+    pub fn spawn(builder: Pop3ConnectionBuilder) -> Self {
+        Self::start(move || builder.connect().map_err(|err| err.to_string().into()))
+    }
+
+    /// Starts a worker using an established connection.
+    pub fn with_connection(connection: Pop3Connection) -> Self {
+        Self::start(move || Ok(connection))
+    }
+
+    fn start(connect: impl FnOnce() -> Result<Pop3Connection, Pop3WorkerError> + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel::<Request>();
+        let handle = thread::spawn(move || {
+            let mut connection = connect();
+            for (job, responder) in receiver {
+                let result = match connection.as_mut() {
+                    Ok(connection) => execute(connection, job),
+                    Err(err) => Err(err.to_string().into())
+                };
+                let _ = responder.send(result);
+            }
+        });
+
+        Pop3Worker { sender: Some(sender), handle: Some(handle) }
+    }
+
+    /// Submits a job and returns a responder to receive its result.
+    pub fn submit(&self, job: Pop3Job) -> Pop3Responder {
+        let (responder, receiver) = mpsc::channel();
+        if let Some(sender) = &self.sender {
+            if let Err(mpsc::SendError((_, responder))) = sender.send((job, responder)) {
+                let _ = responder.send(Err("worker stopped".into()));
+            }
+        }
+
+        Pop3Responder { receiver }
+    }
+}
+
+fn execute(connection: &mut Pop3Connection, job: Pop3Job) -> Result<Pop3JobOutput, Pop3WorkerError> {
+    let result = match job {
+        Pop3Job::Stat => connection.stat().map(Pop3JobOutput::Stat),
+        Pop3Job::List => connection.list_meta().map(Pop3JobOutput::List),
+        Pop3Job::Retrieve { message_id } => {
+            let mut content = vec!();
+            connection.retrieve_raw(message_id, &mut content).map(|_| Pop3JobOutput::Message(content))
+        },
+        Pop3Job::Top { message_id, line_count } => connection.top_raw(message_id, line_count).map(Pop3JobOutput::Message),
+        Pop3Job::Delete { message_id } => connection.delete(message_id).map(|_| Pop3JobOutput::Done),
+        Pop3Job::Reset => connection.reset().map(|_| Pop3JobOutput::Done),
+        Pop3Job::Noop => connection.noop().map(|_| Pop3JobOutput::Done),
+        Pop3Job::Quit => connection.close().map(|_| Pop3JobOutput::Done),
+    };
+
+    result.map_err(|err| err.to_string().into())
+}
+
+impl Drop for Pop3Worker {
+    /// Stops the worker after all submitted jobs are done and closes the connection.
+    fn drop(&mut self) {
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util;
+
+    #[test]
+    fn test_jobs() {
+        let (connection, server) = test_util::connect(&[
+            ("LIST", "+OK\r\n1 10\r\n.\r\n"),
+            ("UIDL", "+OK\r\n1 uid1\r\n.\r\n"),
+            ("RETR 1", "+OK\r\nSubject: hi\r\n.\r\n"),
+            ("DELE 1", "+OK\r\n"),
+            ("QUIT", "+OK\r\n"),
+        ]);
+
+        let worker = Pop3Worker::with_connection(connection);
+        let list = worker.submit(Pop3Job::List);
+        let retrieve = worker.submit(Pop3Job::Retrieve { message_id: 1 });
+        let delete = worker.submit(Pop3Job::Delete { message_id: 1 });
+        let quit = worker.submit(Pop3Job::Quit);
+        let after_quit = worker.submit(Pop3Job::Noop);
+
+        assert!(matches!(list.wait(), Ok(Pop3JobOutput::List(messages)) if messages.len() == 1));
+        assert!(matches!(retrieve.wait(), Ok(Pop3JobOutput::Message(content)) if content == b"Subject: hi\r\n"));
+        assert!(matches!(delete.wait(), Ok(Pop3JobOutput::Done)));
+        assert!(matches!(quit.wait(), Ok(Pop3JobOutput::Done)));
+        assert!(after_quit.wait().is_err());
+
+        drop(worker);
+        server.join().unwrap();
+    }
+}